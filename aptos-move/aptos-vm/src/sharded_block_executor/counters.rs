@@ -3,8 +3,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use aptos_metrics_core::{
-    register_histogram, register_histogram_vec, register_int_gauge, Histogram, HistogramVec,
-    IntGauge,
+    exponential_buckets, register_histogram, register_histogram_vec, register_int_counter,
+    register_int_gauge, Histogram, HistogramVec, IntCounter, IntGauge,
 };
 use once_cell::sync::Lazy;
 
@@ -58,3 +58,37 @@ pub static SHARDED_BLOCK_EXECUTOR_TXN_COUNT: Lazy<HistogramVec> = Lazy::new(|| {
     )
     .unwrap()
 });
+
+pub static SHARDED_BLOCK_EXECUTOR_UNSHARDED_FALLBACK_COUNT: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "sharded_block_executor_unsharded_fallback_count",
+        "Count of blocks too small to shard that were executed unsharded"
+    )
+    .unwrap()
+});
+
+pub static SHARDED_BLOCK_EXECUTOR_DEADLINE_EXCEEDED_COUNT: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "sharded_block_executor_deadline_exceeded_count",
+        "Count of blocks whose sharded execution did not finish before the deadline"
+    )
+    .unwrap()
+});
+
+pub static SHARDED_BLOCK_EXECUTOR_RESOLUTION_FAILURE_FALLBACK_COUNT: Lazy<IntCounter> =
+    Lazy::new(|| {
+        register_int_counter!(
+            "sharded_block_executor_resolution_failure_fallback_count",
+            "Count of blocks re-executed unsharded because their sharded execution failed"
+        )
+        .unwrap()
+    });
+
+pub static SHARDED_BLOCK_EXECUTOR_CRITICAL_PATH_LENGTH: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "sharded_block_executor_critical_path_length",
+        "Number of txns in the longest chain of cross-shard dependencies of the blocks executed by the shards",
+        exponential_buckets(/*start=*/ 1.0, /*factor=*/ 2.0, /*count=*/ 20).unwrap(),
+    )
+    .unwrap()
+});
@@ -18,8 +18,12 @@ use aptos_types::{
     write_set::TransactionWrite,
 };
 use std::{
+    cmp::Reverse,
     collections::{HashMap, HashSet},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
 };
 
 pub struct CrossShardCommitReceiver {}
@@ -47,6 +51,46 @@ impl CrossShardCommitReceiver {
     }
 }
 
+/// The order in which the writes of a committed transaction are sent to the sub-blocks depending on
+/// them. Both orders are deterministic, so that the sub-blocks are unblocked in the same order on
+/// every run.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DependencyResolutionOrder {
+    /// Dependent sub-blocks are resolved in execution order, i.e. by round and then by shard id,
+    /// so the sub-blocks that run first are unblocked first.
+    #[default]
+    Topological,
+    /// Dependent sub-blocks waiting on more writes of the sub-block are resolved first, ties are
+    /// broken in topological order.
+    PriorityWeighted,
+}
+
+impl DependencyResolutionOrder {
+    /// Ranks the dependent sub-blocks given the number of edges each of them has on the sub-block,
+    /// a lower rank is resolved first.
+    fn rank(
+        &self,
+        num_edges_by_dependent: HashMap<(ShardId, RoundId), usize>,
+    ) -> HashMap<(ShardId, RoundId), usize> {
+        let mut dependents: Vec<_> = num_edges_by_dependent.into_iter().collect();
+        match self {
+            DependencyResolutionOrder::Topological => {
+                dependents.sort_by_key(|((shard_id, round_id), _)| (*round_id, *shard_id))
+            },
+            DependencyResolutionOrder::PriorityWeighted => {
+                dependents.sort_by_key(|((shard_id, round_id), num_edges)| {
+                    (Reverse(*num_edges), *round_id, *shard_id)
+                })
+            },
+        }
+        dependents
+            .into_iter()
+            .enumerate()
+            .map(|(rank, (dependent, _))| (dependent, rank))
+            .collect()
+    }
+}
+
 pub struct CrossShardCommitSender {
     shard_id: ShardId,
     cross_shard_client: Arc<dyn CrossShardClient>,
@@ -55,9 +99,13 @@ pub struct CrossShardCommitSender {
     // global indices, so we need to convert the local index received from the parallel execution to
     // the global index.
     dependent_edges: HashMap<TxnIndex, HashMap<StateKey, HashSet<(ShardId, RoundId)>>>,
+    // The rank of each dependent shard id and round id in the dependency resolution order.
+    dependent_ranks: HashMap<(ShardId, RoundId), usize>,
     // The offset of the first transaction in the sub-block. This is used to convert the local index
     // in parallel execution to the global index.
     index_offset: TxnIndex,
+    // Number of speculative aborts in the shard, shared across the sub-blocks it executes.
+    num_speculative_aborts: Arc<AtomicU64>,
 }
 
 impl CrossShardCommitSender {
@@ -65,8 +113,11 @@ impl CrossShardCommitSender {
         shard_id: ShardId,
         cross_shard_client: Arc<dyn CrossShardClient>,
         sub_block: &SubBlock<AnalyzedTransaction>,
+        num_speculative_aborts: Arc<AtomicU64>,
+        resolution_order: DependencyResolutionOrder,
     ) -> Self {
         let mut dependent_edges = HashMap::new();
+        let mut num_edges_by_dependent = HashMap::new();
         let mut num_dependent_edges = 0;
         for (txn_idx, txn_with_deps) in sub_block.txn_with_index_iter() {
             let mut storage_locations_to_target = HashMap::new();
@@ -80,6 +131,9 @@ impl CrossShardCommitSender {
                         .entry(storage_location.clone().into_state_key())
                         .or_insert_with(HashSet::new)
                         .insert((txn_id_with_shard.shard_id, txn_id_with_shard.round_id));
+                    *num_edges_by_dependent
+                        .entry((txn_id_with_shard.shard_id, txn_id_with_shard.round_id))
+                        .or_insert(0) += 1;
                     num_dependent_edges += 1;
                 }
             }
@@ -98,7 +152,9 @@ impl CrossShardCommitSender {
             shard_id,
             cross_shard_client,
             dependent_edges,
+            dependent_ranks: resolution_order.rank(num_edges_by_dependent),
             index_offset: sub_block.start_index as TxnIndex,
+            num_speculative_aborts,
         }
     }
 
@@ -111,26 +167,34 @@ impl CrossShardCommitSender {
         let output = txn_output.committed_output();
         let write_set = output.write_set();
 
+        let mut updates = vec![];
         for (state_key, write_op) in write_set.iter() {
             if let Some(dependent_shard_ids) = edges.get(state_key) {
-                for (dependent_shard_id, round_id) in dependent_shard_ids.iter() {
-                    trace!("Sending remote update for success for shard id {:?} and txn_idx: {:?}, state_key: {:?}, dependent shard id: {:?}", self.shard_id, txn_idx, state_key, dependent_shard_id);
-                    let message = RemoteTxnWriteMsg(RemoteTxnWrite::new(
-                        state_key.clone(),
-                        Some(write_op.clone()),
+                for dependent in dependent_shard_ids.iter() {
+                    updates.push((
+                        self.dependent_ranks[dependent],
+                        *dependent,
+                        state_key,
+                        write_op,
                     ));
-                    if *round_id == GLOBAL_ROUND_ID {
-                        self.cross_shard_client.send_global_msg(message);
-                    } else {
-                        self.cross_shard_client.send_cross_shard_msg(
-                            *dependent_shard_id,
-                            *round_id,
-                            message,
-                        );
-                    }
                 }
             }
         }
+        // The write set is ordered by state key, so sorting by rank is deterministic.
+        updates.sort_by_key(|(rank, ..)| *rank);
+        for (_, (dependent_shard_id, round_id), state_key, write_op) in updates {
+            trace!("Sending remote update for success for shard id {:?} and txn_idx: {:?}, state_key: {:?}, dependent shard id: {:?}", self.shard_id, txn_idx, state_key, dependent_shard_id);
+            let message = RemoteTxnWriteMsg(RemoteTxnWrite::new(
+                state_key.clone(),
+                Some(write_op.clone()),
+            ));
+            if round_id == GLOBAL_ROUND_ID {
+                self.cross_shard_client.send_global_msg(message);
+            } else {
+                self.cross_shard_client
+                    .send_cross_shard_msg(dependent_shard_id, round_id, message);
+            }
+        }
     }
 }
 
@@ -147,6 +211,10 @@ impl TransactionCommitHook for CrossShardCommitSender {
     fn on_execution_aborted(&self, _txn_idx: TxnIndex) {
         todo!("on_transaction_aborted not supported for sharded execution yet")
     }
+
+    fn on_speculative_abort(&self, _txn_idx: TxnIndex) {
+        self.num_speculative_aborts.fetch_add(1, Ordering::Relaxed);
+    }
 }
 
 // CrossShardClient is a trait that defines the interface for sending and receiving messages across
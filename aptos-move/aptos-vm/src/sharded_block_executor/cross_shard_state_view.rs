@@ -1,7 +1,7 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 use crate::sharded_block_executor::remote_state_value::RemoteStateValue;
-use anyhow::Result;
+use anyhow::{anyhow, ensure, Result};
 use aptos_logger::trace;
 use aptos_state_view::{StateView, TStateView};
 use aptos_types::{
@@ -11,7 +11,14 @@ use aptos_types::{
     },
     transaction::analyzed_transaction::AnalyzedTransaction,
 };
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
 /// A state view for reading cross shard state values. It is backed by a state view
 /// and a hashmap of cross shard state keys. When a cross shard state value is not
@@ -20,6 +27,11 @@ use std::collections::{HashMap, HashSet};
 pub struct CrossShardStateView<'a, S> {
     cross_shard_data: HashMap<StateKey, RemoteStateValue>,
     base_view: &'a S,
+    /// Accumulates the nanoseconds spent waiting for the cross shard state values, if set.
+    wait_nanos: Option<Arc<AtomicU64>>,
+    /// Once set, reads fail instead of waiting for the cross shard state values, so that the
+    /// execution of an aborted block finishes early.
+    abort: Option<Arc<AtomicBool>>,
 }
 
 impl<'a, S: StateView + Sync + Send> CrossShardStateView<'a, S> {
@@ -35,9 +47,27 @@ impl<'a, S: StateView + Sync + Send> CrossShardStateView<'a, S> {
         Self {
             cross_shard_data,
             base_view,
+            wait_nanos: None,
+            abort: None,
         }
     }
 
+    pub fn with_wait_nanos(mut self, wait_nanos: Option<Arc<AtomicU64>>) -> Self {
+        self.wait_nanos = wait_nanos;
+        self
+    }
+
+    pub fn with_abort(mut self, abort: Option<Arc<AtomicBool>>) -> Self {
+        self.abort = abort;
+        self
+    }
+
+    fn is_aborted(&self) -> bool {
+        self.abort
+            .as_ref()
+            .map_or(false, |abort| abort.load(Ordering::Relaxed))
+    }
+
     #[cfg(test)]
     fn waiting_count(&self) -> usize {
         self.cross_shard_data
@@ -75,8 +105,19 @@ impl<'a, S: StateView + Sync + Send> TStateView for CrossShardStateView<'a, S> {
     type Key = StateKey;
 
     fn get_state_value(&self, state_key: &StateKey) -> Result<Option<StateValue>> {
+        ensure!(!self.is_aborted(), "execution of the block was aborted");
         if let Some(value) = self.cross_shard_data.get(state_key) {
-            return Ok(value.get_value());
+            let start = (!value.is_ready()).then(Instant::now);
+            let value = match &self.abort {
+                Some(abort) => value
+                    .get_value_unless_aborted(abort)
+                    .ok_or_else(|| anyhow!("execution of the block was aborted"))?,
+                None => value.get_value(),
+            };
+            if let (Some(wait_nanos), Some(start)) = (&self.wait_nanos, start) {
+                wait_nanos.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+            }
+            return Ok(value);
         }
         self.base_view.get_state_value(state_key)
     }
@@ -0,0 +1,138 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_types::{
+    block_executor::partitioner::{
+        PartitionedTransactions, RoundId, ShardId, ShardedTxnIndex, TransactionWithDependencies,
+        TxnIndex,
+    },
+    transaction::{
+        analyzed_transaction::{AnalyzedTransaction, StorageLocation},
+        TransactionOutput, TransactionStatus,
+    },
+};
+
+/// Where a transaction of a sharded block was executed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExecutedBy {
+    Shard {
+        shard_id: ShardId,
+        round: RoundId,
+    },
+    /// The global executor, which executes the global txns after all the rounds of the shards.
+    Global,
+}
+
+/// How one transaction of a sharded block was executed, to debug divergences between the sharded
+/// and unsharded outputs of a block.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TxnExecutionTrace {
+    pub txn_index: TxnIndex,
+    pub executed_by: ExecutedBy,
+    /// The locations the transaction read from the writes of other shards, with the transactions
+    /// that wrote them.
+    pub cross_shard_reads: Vec<(ShardedTxnIndex, StorageLocation)>,
+    pub status: TransactionStatus,
+}
+
+/// The trace of every transaction of a block executed by the shards, in the order of the outputs
+/// of the block.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ShardedExecutionTrace {
+    txns: Vec<TxnExecutionTrace>,
+}
+
+impl ShardedExecutionTrace {
+    pub fn txns(&self) -> &[TxnExecutionTrace] {
+        &self.txns
+    }
+
+    pub fn num_txns(&self) -> usize {
+        self.txns.len()
+    }
+
+    pub fn get(&self, txn_index: TxnIndex) -> Option<&TxnExecutionTrace> {
+        self.txns.iter().find(|txn| txn.txn_index == txn_index)
+    }
+}
+
+/// The part of the trace known before the block executes, recorded before the transactions are
+/// handed to the shards.
+pub(crate) struct PendingExecutionTrace {
+    txns: Vec<(
+        TxnIndex,
+        ExecutedBy,
+        Vec<(ShardedTxnIndex, StorageLocation)>,
+    )>,
+}
+
+impl PendingExecutionTrace {
+    /// Records the transactions in the order `merge_sharded_outputs` returns their outputs: the
+    /// transactions of every round in shard id order, followed by the global txns.
+    pub fn new(transactions: &PartitionedTransactions) -> Self {
+        let num_rounds = transactions
+            .sharded_txns()
+            .iter()
+            .map(|sub_blocks| sub_blocks.num_sub_blocks())
+            .max()
+            .unwrap_or(0);
+        let mut txns = Vec::with_capacity(transactions.num_txns());
+        for round in 0..num_rounds {
+            for (shard_id, sub_blocks) in transactions.sharded_txns().iter().enumerate() {
+                if let Some(sub_block) = sub_blocks.get_sub_block(round) {
+                    txns.extend(sub_block.txn_with_index_iter().map(|(txn_index, txn)| {
+                        (
+                            txn_index,
+                            ExecutedBy::Shard { shard_id, round },
+                            cross_shard_reads(txn),
+                        )
+                    }));
+                }
+            }
+        }
+        let num_sharded_txns = transactions.num_sharded_txns();
+        txns.extend(transactions.global_txns.iter().enumerate().map(|(i, txn)| {
+            (
+                num_sharded_txns + i,
+                ExecutedBy::Global,
+                cross_shard_reads(txn),
+            )
+        }));
+        Self { txns }
+    }
+
+    pub fn finish(self, outputs: &[TransactionOutput]) -> ShardedExecutionTrace {
+        assert_eq!(
+            self.txns.len(),
+            outputs.len(),
+            "Every transaction of the block must have an output"
+        );
+        let txns = self
+            .txns
+            .into_iter()
+            .zip(outputs)
+            .map(
+                |((txn_index, executed_by, cross_shard_reads), output)| TxnExecutionTrace {
+                    txn_index,
+                    executed_by,
+                    cross_shard_reads,
+                    status: output.status().clone(),
+                },
+            )
+            .collect();
+        ShardedExecutionTrace { txns }
+    }
+}
+
+fn cross_shard_reads(
+    txn: &TransactionWithDependencies<AnalyzedTransaction>,
+) -> Vec<(ShardedTxnIndex, StorageLocation)> {
+    txn.cross_shard_dependencies()
+        .required_edges_iter()
+        .flat_map(|(required_txn_idx, locations)| {
+            locations
+                .iter()
+                .map(|location| (*required_txn_idx, location.clone()))
+        })
+        .collect()
+}
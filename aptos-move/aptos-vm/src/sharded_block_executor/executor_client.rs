@@ -5,12 +5,71 @@ use aptos_state_view::StateView;
 use aptos_types::{
     block_executor::partitioner::PartitionedTransactions, transaction::TransactionOutput,
 };
-use move_core_types::vm_status::VMStatus;
-use std::sync::Arc;
+use move_core_types::vm_status::{StatusCode, VMStatus};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug)]
+pub enum ShardedExecutionError {
+    /// The shards did not finish executing the block before the deadline, the caller can fall back
+    /// to executing the block unsharded.
+    DeadlineExceeded,
+    VMStatus(VMStatus),
+}
+
+impl From<VMStatus> for ShardedExecutionError {
+    fn from(status: VMStatus) -> Self {
+        Self::VMStatus(status)
+    }
+}
+
+impl From<ShardedExecutionError> for VMStatus {
+    fn from(error: ShardedExecutionError) -> Self {
+        match error {
+            ShardedExecutionError::DeadlineExceeded => VMStatus::error(
+                StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR,
+                Some("Sharded block execution exceeded its deadline".to_string()),
+            ),
+            ShardedExecutionError::VMStatus(status) => status,
+        }
+    }
+}
+
+/// Statistics of the execution of a block by the shards.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShardedExecutionStats {
+    /// Number of speculative executions, in each shard, that were aborted because of a conflict and
+    /// re-executed. High abort rates signal a poor partitioning of the block.
+    pub speculative_aborts_per_shard: Vec<u64>,
+    /// Time each shard sat idle during the block: waiting for the cross-shard dependencies of its
+    /// transactions, summed over its executor threads, and waiting for its next block once it
+    /// finished its sub-blocks before the slowest shard. High idle times signal a poorly balanced
+    /// partitioning of the block.
+    pub idle_time_per_shard: Vec<Duration>,
+    /// Number of transactions in the longest chain of cross-shard dependencies of the block. The
+    /// transactions of the chain execute one after the other, whatever the number of shards.
+    pub critical_path_length: usize,
+    /// Number of transactions in the block.
+    pub num_txns: usize,
+}
+
+impl ShardedExecutionStats {
+    /// Ratio of the number of transactions to the critical path length, i.e. the parallelism the
+    /// partitioning of the block allows at most. 0 if the block is empty.
+    pub fn achievable_parallelism(&self) -> f64 {
+        if self.critical_path_length == 0 {
+            return 0.0;
+        }
+        self.num_txns as f64 / self.critical_path_length as f64
+    }
+}
 
 pub struct ShardedExecutionOutput {
     pub sharded_output: Vec<Vec<Vec<TransactionOutput>>>,
     pub global_output: Vec<TransactionOutput>,
+    pub stats: ShardedExecutionStats,
 }
 
 impl ShardedExecutionOutput {
@@ -21,12 +80,22 @@ impl ShardedExecutionOutput {
         Self {
             sharded_output,
             global_output,
+            stats: ShardedExecutionStats::default(),
         }
     }
 
+    pub fn with_stats(mut self, stats: ShardedExecutionStats) -> Self {
+        self.stats = stats;
+        self
+    }
+
     pub fn into_inner(self) -> (Vec<Vec<Vec<TransactionOutput>>>, Vec<TransactionOutput>) {
         (self.sharded_output, self.global_output)
     }
+
+    pub fn stats(&self) -> &ShardedExecutionStats {
+        &self.stats
+    }
 }
 
 // Interface to communicate from the block executor coordinator to the executor shards.
@@ -42,4 +111,26 @@ pub trait ExecutorClient<S: StateView + Sync + Send + 'static>: Send + Sync {
         concurrency_level_per_shard: usize,
         maybe_block_gas_limit: Option<u64>,
     ) -> Result<ShardedExecutionOutput, VMStatus>;
+
+    // Same as `execute_block`, but returns `DeadlineExceeded` if the shards don't finish before the
+    // deadline. Clients that can't stop waiting for the shards only check the deadline once they finish.
+    fn execute_block_with_deadline(
+        &self,
+        state_view: Arc<S>,
+        transactions: PartitionedTransactions,
+        concurrency_level_per_shard: usize,
+        maybe_block_gas_limit: Option<u64>,
+        deadline: Instant,
+    ) -> Result<ShardedExecutionOutput, ShardedExecutionError> {
+        let output = self.execute_block(
+            state_view,
+            transactions,
+            concurrency_level_per_shard,
+            maybe_block_gas_limit,
+        )?;
+        if Instant::now() > deadline {
+            return Err(ShardedExecutionError::DeadlineExceeded);
+        }
+        Ok(output)
+    }
 }
@@ -54,6 +54,8 @@ impl<S: StateView + Sync + Send + 'static> GlobalExecutor<S> {
             transactions,
             self.global_cross_shard_client.clone(),
             None,
+            None,
+            None,
             GLOBAL_ROUND_ID,
             state_view,
             self.concurrency_level,
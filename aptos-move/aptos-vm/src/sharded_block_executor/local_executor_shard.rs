@@ -4,15 +4,18 @@
 use crate::sharded_block_executor::{
     coordinator_client::CoordinatorClient,
     counters::WAIT_FOR_SHARDED_OUTPUT_SECONDS,
-    cross_shard_client::CrossShardClient,
-    executor_client::{ExecutorClient, ShardedExecutionOutput},
+    cross_shard_client::{CrossShardClient, DependencyResolutionOrder},
+    executor_client::{
+        ExecutorClient, ShardedExecutionError, ShardedExecutionOutput, ShardedExecutionStats,
+    },
     global_executor::GlobalExecutor,
     messages::CrossShardMsg,
     sharded_aggregator_service,
     sharded_executor_service::ShardedExecutorService,
     ExecutorShardCommand,
 };
-use aptos_logger::trace;
+use aptos_infallible::Mutex;
+use aptos_logger::{trace, warn};
 use aptos_state_view::StateView;
 use aptos_types::{
     block_executor::partitioner::{
@@ -20,14 +23,23 @@ use aptos_types::{
     },
     transaction::TransactionOutput,
 };
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crossbeam_channel::{unbounded, Receiver, Select, Sender};
 use move_core_types::vm_status::VMStatus;
-use std::{sync::Arc, thread};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 /// Executor service that runs on local machine and waits for commands from the coordinator and executes
 /// them in parallel.
 pub struct LocalExecutorService<S: StateView + Sync + Send + 'static> {
     join_handle: Option<thread::JoinHandle<()>>,
+    num_speculative_aborts: Arc<AtomicU64>,
+    dependency_wait_nanos: Arc<AtomicU64>,
     phantom: std::marker::PhantomData<S>,
 }
 
@@ -39,21 +51,29 @@ impl<S: StateView + Sync + Send + 'static> LocalExecutorService<S> {
         command_rx: Receiver<ExecutorShardCommand<S>>,
         result_tx: Sender<Result<Vec<Vec<TransactionOutput>>, VMStatus>>,
         cross_shard_client: LocalCrossShardClient,
+        dependency_resolution_order: DependencyResolutionOrder,
     ) -> Self {
         let coordinator_client = Arc::new(LocalCoordinatorClient::new(command_rx, result_tx));
-        let executor_service = Arc::new(ShardedExecutorService::new(
-            shard_id,
-            num_shards,
-            num_threads,
-            coordinator_client,
-            Arc::new(cross_shard_client),
-        ));
+        let executor_service = Arc::new(
+            ShardedExecutorService::new(
+                shard_id,
+                num_shards,
+                num_threads,
+                coordinator_client,
+                Arc::new(cross_shard_client),
+            )
+            .with_dependency_resolution_order(dependency_resolution_order),
+        );
+        let num_speculative_aborts = executor_service.num_speculative_aborts();
+        let dependency_wait_nanos = executor_service.dependency_wait_nanos();
         let join_handle = thread::Builder::new()
             .name(format!("executor-shard-{}", shard_id))
             .spawn(move || executor_service.start())
             .unwrap();
         Self {
             join_handle: Some(join_handle),
+            num_speculative_aborts,
+            dependency_wait_nanos,
             phantom: std::marker::PhantomData,
         }
     }
@@ -73,6 +93,20 @@ impl<S: StateView + Sync + Send + 'static> LocalExecutorService<S> {
     pub fn setup_local_executor_shards(
         num_shards: usize,
         num_threads: Option<usize>,
+    ) -> LocalExecutorClient<S> {
+        Self::setup_local_executor_shards_with_dependency_resolution_order(
+            num_shards,
+            num_threads,
+            DependencyResolutionOrder::default(),
+        )
+    }
+
+    /// Same as `setup_local_executor_shards`, with the order in which the shards resolve the
+    /// cross-shard dependencies of the sub-blocks they execute.
+    pub fn setup_local_executor_shards_with_dependency_resolution_order(
+        num_shards: usize,
+        num_threads: Option<usize>,
+        dependency_resolution_order: DependencyResolutionOrder,
     ) -> LocalExecutorClient<S> {
         let (global_executor, global_cross_shard_tx) = Self::setup_global_executor();
         let num_threads = num_threads
@@ -98,6 +132,7 @@ impl<S: StateView + Sync + Send + 'static> LocalExecutorService<S> {
                     .unzip()
             })
             .unzip();
+        let client_cross_shard_msg_rxs = cross_shard_msg_rxs.clone();
         let executor_shards = command_rxs
             .into_iter()
             .zip(result_txs)
@@ -116,10 +151,17 @@ impl<S: StateView + Sync + Send + 'static> LocalExecutorService<S> {
                     command_rx,
                     result_tx,
                     cross_shard_client,
+                    dependency_resolution_order,
                 )
             })
             .collect();
-        LocalExecutorClient::new(command_txs, result_rxs, executor_shards, global_executor)
+        LocalExecutorClient::new(
+            command_txs,
+            result_rxs,
+            executor_shards,
+            global_executor,
+            client_cross_shard_msg_rxs,
+        )
     }
 }
 
@@ -130,6 +172,12 @@ pub struct LocalExecutorClient<S: StateView + Sync + Send + 'static> {
     result_rxs: Vec<Receiver<Result<Vec<Vec<TransactionOutput>>, VMStatus>>>,
     executor_services: Vec<LocalExecutorService<S>>,
     global_executor: GlobalExecutor<S>,
+    // Number of results, per shard, of blocks that exceeded their deadline and have not been received
+    // yet. They are discarded before executing the next block.
+    pending_discarded_results: Mutex<Vec<usize>>,
+    // Receivers of the cross-shard messages of every shard and round, to drop the messages that the
+    // cancelled blocks left over.
+    cross_shard_msg_rxs: Vec<Vec<Receiver<CrossShardMsg>>>,
 }
 
 impl<S: StateView + Sync + Send + 'static> LocalExecutorClient<S> {
@@ -138,42 +186,129 @@ impl<S: StateView + Sync + Send + 'static> LocalExecutorClient<S> {
         result_rx: Vec<Receiver<Result<Vec<Vec<TransactionOutput>>, VMStatus>>>,
         executor_shards: Vec<LocalExecutorService<S>>,
         global_executor: GlobalExecutor<S>,
+        cross_shard_msg_rxs: Vec<Vec<Receiver<CrossShardMsg>>>,
     ) -> Self {
+        let num_shards = command_tx.len();
         Self {
             command_txs: command_tx,
             result_rxs: result_rx,
             executor_services: executor_shards,
             global_executor,
+            pending_discarded_results: Mutex::new(vec![0; num_shards]),
+            cross_shard_msg_rxs,
         }
     }
 
-    fn get_output_from_shards(&self) -> Result<Vec<Vec<Vec<TransactionOutput>>>, VMStatus> {
+    /// Receives the results in the order the shards complete and returns them by shard id, with the
+    /// instants they were received. All the results are received before returning an error, so that
+    /// none is left over for the next block. If the deadline passes first, the shards that are still
+    /// executing are cancelled through `abort`, and their results are discarded once they arrive.
+    fn get_output_from_shards(
+        &self,
+        deadline: Option<Instant>,
+        abort: &AtomicBool,
+    ) -> Result<(Vec<Vec<Vec<TransactionOutput>>>, Vec<Instant>), ShardedExecutionError> {
         let _timer = WAIT_FOR_SHARDED_OUTPUT_SECONDS.start_timer();
         trace!("LocalExecutorClient Waiting for results");
-        let mut results = vec![];
-        for (i, rx) in self.result_rxs.iter().enumerate() {
-            results.push(
-                rx.recv()
-                    .unwrap_or_else(|_| panic!("Did not receive output from shard {}", i))?,
-            );
+        let mut select = Select::new();
+        for rx in &self.result_rxs {
+            select.recv(rx);
         }
-        Ok(results)
+        let mut results: Vec<_> = (0..self.result_rxs.len()).map(|_| None).collect();
+        for _ in 0..self.result_rxs.len() {
+            let operation = match deadline {
+                Some(deadline) => match select.select_deadline(deadline) {
+                    Ok(operation) => operation,
+                    Err(_) => {
+                        abort.store(true, Ordering::Relaxed);
+                        let mut pending_discarded_results = self.pending_discarded_results.lock();
+                        for (shard_id, result) in results.iter().enumerate() {
+                            if result.is_none() {
+                                warn!("Shard {} did not finish before the deadline", shard_id);
+                                pending_discarded_results[shard_id] += 1;
+                            }
+                        }
+                        return Err(ShardedExecutionError::DeadlineExceeded);
+                    },
+                },
+                None => select.select(),
+            };
+            let shard_id = operation.index();
+            let result = operation
+                .recv(&self.result_rxs[shard_id])
+                .unwrap_or_else(|_| panic!("Did not receive output from shard {}", shard_id));
+            select.remove(shard_id);
+            results[shard_id] = Some((Instant::now(), result));
+        }
+        let (finish_times, results): (Vec<_>, Vec<_>) =
+            results.into_iter().map(Option::unwrap).unzip();
+        Ok((results.into_iter().collect::<Result<_, _>>()?, finish_times))
     }
-}
 
-impl<S: StateView + Sync + Send + 'static> ExecutorClient<S> for LocalExecutorClient<S> {
-    fn num_shards(&self) -> usize {
-        self.command_txs.len()
+    /// Waits for the shards that were cancelled in a previous block to stop, and discards their
+    /// results and the cross-shard messages left over, so that the shards are idle and their
+    /// channels empty when the next block is dispatched.
+    fn discard_pending_results(&self) {
+        let mut pending_discarded_results = self.pending_discarded_results.lock();
+        if pending_discarded_results.iter().sum::<usize>() == 0 {
+            return;
+        }
+        for (shard_id, num_pending) in pending_discarded_results.iter_mut().enumerate() {
+            for _ in 0..*num_pending {
+                let _ = self.result_rxs[shard_id].recv();
+            }
+            if *num_pending > 0 {
+                let service = &self.executor_services[shard_id];
+                service.num_speculative_aborts.store(0, Ordering::Relaxed);
+                service.dependency_wait_nanos.store(0, Ordering::Relaxed);
+            }
+            *num_pending = 0;
+        }
+        // all the shards stopped, nothing is sent anymore
+        for rx in self.cross_shard_msg_rxs.iter().flatten() {
+            rx.try_iter().for_each(drop);
+        }
     }
 
-    fn execute_block(
+    /// Takes the stats accumulated by the shards since the last block, must be called after all the
+    /// shards sent their results, at `finish_times`.
+    fn take_stats_from_shards(&self, finish_times: &[Instant]) -> ShardedExecutionStats {
+        let last_finish_time = finish_times.iter().max().copied();
+        ShardedExecutionStats {
+            speculative_aborts_per_shard: self
+                .executor_services
+                .iter()
+                .map(|service| service.num_speculative_aborts.swap(0, Ordering::Relaxed))
+                .collect(),
+            idle_time_per_shard: self
+                .executor_services
+                .iter()
+                .enumerate()
+                .map(|(shard_id, service)| {
+                    let dependency_wait = Duration::from_nanos(
+                        service.dependency_wait_nanos.swap(0, Ordering::Relaxed),
+                    );
+                    let assignment_wait = last_finish_time.map_or(Duration::ZERO, |last| {
+                        last.duration_since(finish_times[shard_id])
+                    });
+                    dependency_wait + assignment_wait
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn execute_block_with_optional_deadline(
         &self,
         state_view: Arc<S>,
         transactions: PartitionedTransactions,
         concurrency_level_per_shard: usize,
         maybe_block_gas_limit: Option<u64>,
-    ) -> Result<ShardedExecutionOutput, VMStatus> {
+        deadline: Option<Instant>,
+    ) -> Result<ShardedExecutionOutput, ShardedExecutionError> {
         assert_eq!(transactions.num_shards(), self.num_shards());
+        self.discard_pending_results();
+        let abort = Arc::new(AtomicBool::new(false));
         let (sub_blocks, global_txns) = transactions.into();
         for (i, sub_blocks_for_shard) in sub_blocks.into_iter().enumerate() {
             self.command_txs[i]
@@ -182,6 +317,7 @@ impl<S: StateView + Sync + Send + 'static> ExecutorClient<S> for LocalExecutorCl
                     sub_blocks_for_shard,
                     concurrency_level_per_shard,
                     maybe_block_gas_limit,
+                    abort.clone(),
                 ))
                 .unwrap();
         }
@@ -190,13 +326,24 @@ impl<S: StateView + Sync + Send + 'static> ExecutorClient<S> for LocalExecutorCl
         // global transactions will be blocked for cross shard transaction results. This hopefully will help with
         // finishing the global transactions faster but we need to evaluate if this causes thread contention. If it
         // does, then we can simply move this call to the end of the function.
-        let mut global_output = self.global_executor.execute_global_txns(
-            global_txns,
-            state_view.as_ref(),
-            maybe_block_gas_limit,
-        )?;
+        let mut global_output = self
+            .global_executor
+            .execute_global_txns(global_txns, state_view.as_ref(), maybe_block_gas_limit)
+            .map_err(|status| {
+                // The shards are cancelled and their results discarded before the next block.
+                abort.store(true, Ordering::Relaxed);
+                for num_pending in self.pending_discarded_results.lock().iter_mut() {
+                    *num_pending += 1;
+                }
+                status
+            })?;
 
-        let mut sharded_output = self.get_output_from_shards()?;
+        let sharded_output = self.get_output_from_shards(deadline, &abort);
+        let finish_times = sharded_output
+            .as_ref()
+            .map_or(&[][..], |(_, finish_times)| finish_times.as_slice());
+        let stats = self.take_stats_from_shards(finish_times);
+        let (mut sharded_output, _) = sharded_output?;
 
         sharded_aggregator_service::aggregate_and_update_total_supply(
             &mut sharded_output,
@@ -205,7 +352,46 @@ impl<S: StateView + Sync + Send + 'static> ExecutorClient<S> for LocalExecutorCl
             self.global_executor.get_executor_thread_pool(),
         );
 
-        Ok(ShardedExecutionOutput::new(sharded_output, global_output))
+        Ok(ShardedExecutionOutput::new(sharded_output, global_output).with_stats(stats))
+    }
+}
+
+impl<S: StateView + Sync + Send + 'static> ExecutorClient<S> for LocalExecutorClient<S> {
+    fn num_shards(&self) -> usize {
+        self.command_txs.len()
+    }
+
+    fn execute_block(
+        &self,
+        state_view: Arc<S>,
+        transactions: PartitionedTransactions,
+        concurrency_level_per_shard: usize,
+        maybe_block_gas_limit: Option<u64>,
+    ) -> Result<ShardedExecutionOutput, VMStatus> {
+        Ok(self.execute_block_with_optional_deadline(
+            state_view,
+            transactions,
+            concurrency_level_per_shard,
+            maybe_block_gas_limit,
+            None,
+        )?)
+    }
+
+    fn execute_block_with_deadline(
+        &self,
+        state_view: Arc<S>,
+        transactions: PartitionedTransactions,
+        concurrency_level_per_shard: usize,
+        maybe_block_gas_limit: Option<u64>,
+        deadline: Instant,
+    ) -> Result<ShardedExecutionOutput, ShardedExecutionError> {
+        self.execute_block_with_optional_deadline(
+            state_view,
+            transactions,
+            concurrency_level_per_shard,
+            maybe_block_gas_limit,
+            Some(deadline),
+        )
     }
 }
 
@@ -2,27 +2,43 @@
 // Parts of the project are originally copyright © Meta Platforms, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::sharded_block_executor::{
-    counters::{
-        NUM_EXECUTOR_SHARDS, SHARDED_BLOCK_EXECUTION_SECONDS,
-        SHARDED_EXECUTION_RESULT_AGGREGATION_SECONDS,
+use crate::{
+    sharded_block_executor::{
+        counters::{
+            NUM_EXECUTOR_SHARDS, SHARDED_BLOCK_EXECUTION_SECONDS,
+            SHARDED_BLOCK_EXECUTOR_CRITICAL_PATH_LENGTH,
+            SHARDED_BLOCK_EXECUTOR_DEADLINE_EXCEEDED_COUNT,
+            SHARDED_BLOCK_EXECUTOR_RESOLUTION_FAILURE_FALLBACK_COUNT,
+            SHARDED_BLOCK_EXECUTOR_UNSHARDED_FALLBACK_COUNT,
+            SHARDED_EXECUTION_RESULT_AGGREGATION_SECONDS,
+        },
+        execution_trace::{PendingExecutionTrace, ShardedExecutionTrace},
+        executor_client::{ExecutorClient, ShardedExecutionError, ShardedExecutionStats},
+        versioned_state_view::{PinnedStateView, VersionedStateSource},
     },
-    executor_client::ExecutorClient,
+    AptosVM, VMExecutor,
 };
-use aptos_logger::{info, trace};
+use aptos_infallible::Mutex;
+use aptos_logger::{error, info, trace};
 use aptos_state_view::StateView;
 use aptos_types::{
-    block_executor::partitioner::{PartitionedTransactions, SubBlocksForShard},
-    transaction::{analyzed_transaction::AnalyzedTransaction, TransactionOutput},
+    block_executor::partitioner::{PartitionedTransactions, ShardId, SubBlocksForShard, TxnIndex},
+    transaction::{analyzed_transaction::AnalyzedTransaction, TransactionOutput, Version},
+    write_set::{WriteSet, WriteSetMut},
+};
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant},
 };
-use move_core_types::vm_status::VMStatus;
-use std::{marker::PhantomData, sync::Arc};
 
 pub mod aggr_overridden_state_view;
 pub mod coordinator_client;
 mod counters;
 pub mod cross_shard_client;
 mod cross_shard_state_view;
+pub mod execution_trace;
 pub mod executor_client;
 pub mod global_executor;
 pub mod local_executor_shard;
@@ -30,19 +46,43 @@ pub mod messages;
 pub mod remote_state_value;
 pub mod sharded_aggregator_service;
 pub mod sharded_executor_service;
+pub mod versioned_state_view;
+
+/// What to do when the shards fail to execute a block, e.g. because the cross-shard dependencies
+/// of its transactions could not be resolved.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CrossShardFailurePolicy {
+    /// Return the error of the shards.
+    #[default]
+    ReturnError,
+    /// Re-execute the whole block with `AptosVM::execute_block` and return its outputs.
+    ExecuteUnsharded,
+}
 
 /// Coordinator for sharded block executors that manages multiple shards and aggregates the results.
 pub struct ShardedBlockExecutor<S: StateView + Sync + Send + 'static, C: ExecutorClient<S>> {
     executor_client: C,
+    /// Blocks with fewer txns than this are executed unsharded, since the sharding overhead dominates.
+    min_txns_for_sharding: usize,
+    /// Maximum time the shards have to execute a block, unbounded if not set.
+    execution_deadline: Option<Duration>,
+    cross_shard_failure_policy: CrossShardFailurePolicy,
+    /// Whether to record how every transaction is executed, see `last_execution_trace`.
+    execution_trace_enabled: bool,
+    last_execution_stats: Mutex<ShardedExecutionStats>,
+    last_execution_trace: Mutex<Option<ShardedExecutionTrace>>,
     phantom: PhantomData<S>,
 }
 
 pub enum ExecutorShardCommand<S> {
+    /// The last field is set by the coordinator once it stops waiting for the block, e.g. when the
+    /// deadline passes, the shard then cancels the rest of its execution.
     ExecuteSubBlocks(
         Arc<S>,
         SubBlocksForShard<AnalyzedTransaction>,
         usize,
         Option<u64>,
+        Arc<AtomicBool>,
     ),
     Stop,
 }
@@ -55,23 +95,94 @@ impl<S: StateView + Sync + Send + 'static, C: ExecutorClient<S>> ShardedBlockExe
         );
         Self {
             executor_client,
+            min_txns_for_sharding: 0,
+            execution_deadline: None,
+            cross_shard_failure_policy: CrossShardFailurePolicy::default(),
+            execution_trace_enabled: false,
+            last_execution_stats: Mutex::new(ShardedExecutionStats::default()),
+            last_execution_trace: Mutex::new(None),
             phantom: PhantomData,
         }
     }
 
+    pub fn with_min_txns_for_sharding(mut self, min_txns_for_sharding: usize) -> Self {
+        self.min_txns_for_sharding = min_txns_for_sharding;
+        self
+    }
+
+    pub fn with_execution_deadline(mut self, execution_deadline: Duration) -> Self {
+        self.execution_deadline = Some(execution_deadline);
+        self
+    }
+
+    pub fn with_cross_shard_failure_policy(
+        mut self,
+        cross_shard_failure_policy: CrossShardFailurePolicy,
+    ) -> Self {
+        self.cross_shard_failure_policy = cross_shard_failure_policy;
+        self
+    }
+
+    pub fn with_execution_trace(mut self, execution_trace_enabled: bool) -> Self {
+        self.execution_trace_enabled = execution_trace_enabled;
+        self
+    }
+
     pub fn num_shards(&self) -> usize {
         self.executor_client.num_shards()
     }
 
+    /// Stats of the last block executed by the shards, empty if it was executed unsharded.
+    pub fn last_execution_stats(&self) -> ShardedExecutionStats {
+        self.last_execution_stats.lock().clone()
+    }
+
+    /// Trace of the last block executed by the shards, in the order of its outputs. `None` if
+    /// tracing is disabled or the block was executed unsharded.
+    pub fn last_execution_trace(&self) -> Option<ShardedExecutionTrace> {
+        self.last_execution_trace.lock().clone()
+    }
+
+    /// The net write set of a block: the write sets of its kept transactions applied in transaction
+    /// order, so the last write to a key wins. This is the state the unsharded execution of the
+    /// block leaves behind.
+    pub fn merge_outputs(&self, outputs: &[TransactionOutput]) -> WriteSet {
+        let mut write_set = WriteSetMut::default();
+        for output in outputs {
+            if !output.status().is_discarded() {
+                write_set.extend(
+                    output
+                        .write_set()
+                        .iter()
+                        .map(|(state_key, write_op)| (state_key.clone(), write_op.clone())),
+                );
+            }
+        }
+        write_set
+            .freeze()
+            .expect("Freezing the merged write set should succeed")
+    }
+
+    /// Whether the block is too small to be worth sharding and is executed with `AptosVM::execute_block` instead.
+    pub fn should_execute_unsharded(&self, transactions: &PartitionedTransactions) -> bool {
+        transactions.num_txns() < self.min_txns_for_sharding
+    }
+
     /// Execute a block of transactions in parallel by splitting the block into num_remote_executors partitions and
-    /// dispatching each partition to a remote executor shard.
+    /// dispatching each partition to a remote executor shard. If the shards don't finish before the execution
+    /// deadline, `DeadlineExceeded` is returned and the caller can fall back to unsharded execution. If
+    /// the shards fail, the block is executed unsharded instead depending on the
+    /// `CrossShardFailurePolicy`.
     pub fn execute_block(
         &self,
         state_view: Arc<S>,
         transactions: PartitionedTransactions,
         concurrency_level_per_shard: usize,
         maybe_block_gas_limit: Option<u64>,
-    ) -> Result<Vec<TransactionOutput>, VMStatus> {
+    ) -> Result<Vec<TransactionOutput>, ShardedExecutionError> {
+        let deadline = self
+            .execution_deadline
+            .map(|execution_deadline| Instant::now() + execution_deadline);
         let _timer = SHARDED_BLOCK_EXECUTION_SECONDS.start_timer();
         let num_executor_shards = self.executor_client.num_shards();
         NUM_EXECUTOR_SHARDS.set(num_executor_shards as i64);
@@ -81,35 +192,174 @@ impl<S: StateView + Sync + Send + 'static, C: ExecutorClient<S>> ShardedBlockExe
             "Block must be partitioned into {} sub-blocks",
             num_executor_shards
         );
-        let (sharded_output, global_output) = self
-            .executor_client
-            .execute_block(
-                state_view,
+        if self.should_execute_unsharded(&transactions) {
+            SHARDED_BLOCK_EXECUTOR_UNSHARDED_FALLBACK_COUNT.inc();
+            *self.last_execution_stats.lock() = ShardedExecutionStats::default();
+            *self.last_execution_trace.lock() = None;
+            let transactions = PartitionedTransactions::flatten(transactions)
+                .into_iter()
+                .map(|txn| txn.into_txn())
+                .collect();
+            return Ok(AptosVM::execute_block(
+                transactions,
+                state_view.as_ref(),
+                maybe_block_gas_limit,
+            )?);
+        }
+        let num_txns = transactions.num_txns();
+        let fallback_transactions = (self.cross_shard_failure_policy
+            == CrossShardFailurePolicy::ExecuteUnsharded)
+            .then(|| transactions.clone());
+        let critical_path_length = critical_path_length(&transactions);
+        let pending_trace = self
+            .execution_trace_enabled
+            .then(|| PendingExecutionTrace::new(&transactions));
+        SHARDED_BLOCK_EXECUTOR_CRITICAL_PATH_LENGTH.observe(critical_path_length as f64);
+        let output = match deadline {
+            Some(deadline) => self.executor_client.execute_block_with_deadline(
+                state_view.clone(),
                 transactions,
                 concurrency_level_per_shard,
                 maybe_block_gas_limit,
-            )?
-            .into_inner();
-        // wait for all remote executors to send the result back and append them in order by shard id
+                deadline,
+            ),
+            None => self
+                .executor_client
+                .execute_block(
+                    state_view.clone(),
+                    transactions,
+                    concurrency_level_per_shard,
+                    maybe_block_gas_limit,
+                )
+                .map_err(ShardedExecutionError::from),
+        };
+        if let Err(ShardedExecutionError::DeadlineExceeded) = output {
+            SHARDED_BLOCK_EXECUTOR_DEADLINE_EXCEEDED_COUNT.inc();
+        }
+        let output = match (output, fallback_transactions) {
+            (Err(ShardedExecutionError::VMStatus(status)), Some(fallback_transactions)) => {
+                error!(
+                    "Sharded execution of a block of {} txns failed with {:?}, executing it unsharded",
+                    num_txns, status
+                );
+                SHARDED_BLOCK_EXECUTOR_RESOLUTION_FAILURE_FALLBACK_COUNT.inc();
+                *self.last_execution_stats.lock() = ShardedExecutionStats::default();
+                *self.last_execution_trace.lock() = None;
+                let transactions = PartitionedTransactions::flatten(fallback_transactions)
+                    .into_iter()
+                    .map(|txn| txn.into_txn())
+                    .collect();
+                return Ok(AptosVM::execute_block(
+                    transactions,
+                    state_view.as_ref(),
+                    maybe_block_gas_limit,
+                )?);
+            },
+            (output, _) => output?,
+        };
+        let stats = ShardedExecutionStats {
+            critical_path_length,
+            num_txns,
+            ..output.stats().clone()
+        };
+        info!(
+            "Sharded execution of {} txns with a critical path of {} txns, achievable parallelism {:.2}",
+            num_txns,
+            critical_path_length,
+            stats.achievable_parallelism()
+        );
+        *self.last_execution_stats.lock() = stats;
+        let (sharded_output, global_output) = output.into_inner();
         trace!("ShardedBlockExecutor Received all results");
         let _aggregation_timer = SHARDED_EXECUTION_RESULT_AGGREGATION_SECONDS.start_timer();
-        let num_rounds = sharded_output[0].len();
-        let mut aggregated_results = vec![];
-        let mut ordered_results = vec![vec![]; num_executor_shards * num_rounds];
-        // Append the output from individual shards in the round order
-        for (shard_id, results_from_shard) in sharded_output.into_iter().enumerate() {
-            for (round, result) in results_from_shard.into_iter().enumerate() {
-                ordered_results[round * num_executor_shards + shard_id] = result;
-            }
-        }
+        let output = merge_sharded_outputs(sharded_output.into_iter().enumerate(), global_output);
+        *self.last_execution_trace.lock() =
+            pending_trace.map(|pending_trace| pending_trace.finish(&output));
+        Ok(output)
+    }
+}
 
-        for result in ordered_results.into_iter() {
-            aggregated_results.extend(result);
-        }
+impl<V: VersionedStateSource + 'static, C: ExecutorClient<PinnedStateView<V>>>
+    ShardedBlockExecutor<PinnedStateView<V>, C>
+{
+    /// Executes the block against the state at `version` rather than the latest one, e.g. to
+    /// re-execute it on another branch during a reorg. Every shard reads the state at `version`.
+    pub fn execute_block_at_version(
+        &self,
+        state_source: Arc<V>,
+        version: Version,
+        transactions: PartitionedTransactions,
+        concurrency_level_per_shard: usize,
+        maybe_block_gas_limit: Option<u64>,
+    ) -> Result<Vec<TransactionOutput>, ShardedExecutionError> {
+        info!("Executing a sharded block at version {}", version);
+        self.execute_block(
+            Arc::new(PinnedStateView::new(state_source, version)),
+            transactions,
+            concurrency_level_per_shard,
+            maybe_block_gas_limit,
+        )
+    }
+}
 
-        // Lastly append the global output
-        aggregated_results.extend(global_output);
+/// Number of transactions in the longest chain of cross-shard dependencies of the block, i.e. the
+/// number of transactions that execute one after the other however many shards execute the block.
+pub fn critical_path_length(transactions: &PartitionedTransactions) -> usize {
+    let num_sharded_txns = transactions.num_sharded_txns();
+    let mut txns_with_index: Vec<_> = transactions
+        .sharded_txns()
+        .iter()
+        .flat_map(|sub_blocks| sub_blocks.sub_block_iter())
+        .flat_map(|sub_block| sub_block.txn_with_index_iter())
+        .chain(
+            transactions
+                .global_txns
+                .iter()
+                .enumerate()
+                .map(|(i, txn)| (num_sharded_txns + i, txn)),
+        )
+        .collect();
+    // A transaction only requires transactions with a lower index, so their chain lengths are
+    // known by the time it is visited.
+    txns_with_index.sort_by_key(|(txn_idx, _)| *txn_idx);
+    let mut chain_lengths: HashMap<TxnIndex, usize> = HashMap::new();
+    for (txn_idx, txn) in txns_with_index {
+        let longest_required_chain = txn
+            .cross_shard_dependencies
+            .required_edges_iter()
+            .filter_map(|(required_txn_idx, _)| chain_lengths.get(&required_txn_idx.txn_index))
+            .max()
+            .copied()
+            .unwrap_or(0);
+        chain_lengths.insert(txn_idx, longest_required_chain + 1);
+    }
+    chain_lengths.into_values().max().unwrap_or(0)
+}
 
-        Ok(aggregated_results)
+/// Merges the outputs of the shards into the output of the block: the outputs of every round in
+/// shard id order, followed by the global output. The shard outputs can be passed in any order, e.g.
+/// the order the shards completed in, as the merged output only depends on their shard ids.
+pub fn merge_sharded_outputs(
+    shard_outputs: impl IntoIterator<Item = (ShardId, Vec<Vec<TransactionOutput>>)>,
+    global_output: Vec<TransactionOutput>,
+) -> Vec<TransactionOutput> {
+    let mut shard_outputs: Vec<_> = shard_outputs.into_iter().collect();
+    shard_outputs.sort_by_key(|(shard_id, _)| *shard_id);
+    let num_shards = shard_outputs.len();
+    let num_rounds = shard_outputs
+        .first()
+        .map_or(0, |(_, results_from_shard)| results_from_shard.len());
+    let mut ordered_results = vec![vec![]; num_shards * num_rounds];
+    // Append the output from individual shards in the round order
+    for (shard_index, (_, results_from_shard)) in shard_outputs.into_iter().enumerate() {
+        for (round, result) in results_from_shard.into_iter().enumerate() {
+            ordered_results[round * num_shards + shard_index] = result;
+        }
     }
+    // Lastly append the global output
+    ordered_results
+        .into_iter()
+        .flatten()
+        .chain(global_output)
+        .collect()
 }
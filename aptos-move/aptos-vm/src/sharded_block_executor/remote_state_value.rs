@@ -2,7 +2,16 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use aptos_types::state_store::state_value::StateValue;
-use std::sync::{Arc, Condvar, Mutex};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::Duration,
+};
+
+/// How often a wait for a remote state value checks whether it was aborted.
+const ABORT_CHECK_INTERVAL: Duration = Duration::from_millis(10);
 
 #[derive(Clone)]
 // This struct is used to store the status of a remote state value. It provides semantics for
@@ -38,6 +47,22 @@ impl RemoteStateValue {
         }
     }
 
+    /// Same as `get_value`, but stops waiting once `abort` is set, returning `None`.
+    pub fn get_value_unless_aborted(&self, abort: &AtomicBool) -> Option<Option<StateValue>> {
+        let (lock, cvar) = &*self.value_condition;
+        let mut status = lock.lock().unwrap();
+        while let RemoteValueStatus::Waiting = *status {
+            if abort.load(Ordering::Relaxed) {
+                return None;
+            }
+            status = cvar.wait_timeout(status, ABORT_CHECK_INTERVAL).unwrap().0;
+        }
+        match &*status {
+            RemoteValueStatus::Ready(value) => Some(value.clone()),
+            RemoteValueStatus::Waiting => unreachable!(),
+        }
+    }
+
     pub fn is_ready(&self) -> bool {
         let (lock, _cvar) = &*self.value_condition;
         let status = lock.lock().unwrap();
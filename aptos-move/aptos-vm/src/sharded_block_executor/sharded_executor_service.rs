@@ -6,8 +6,12 @@ use crate::{
         aggr_overridden_state_view::{AggregatorOverriddenStateView, TOTAL_SUPPLY_AGGR_BASE_VAL},
         coordinator_client::CoordinatorClient,
         counters::{SHARDED_BLOCK_EXECUTION_BY_ROUNDS_SECONDS, SHARDED_BLOCK_EXECUTOR_TXN_COUNT},
-        cross_shard_client::{CrossShardClient, CrossShardCommitReceiver, CrossShardCommitSender},
+        cross_shard_client::{
+            CrossShardClient, CrossShardCommitReceiver, CrossShardCommitSender,
+            DependencyResolutionOrder,
+        },
         cross_shard_state_view::CrossShardStateView,
+        executor_client::ShardedExecutionError,
         messages::CrossShardMsg,
         ExecutorShardCommand,
     },
@@ -23,7 +27,10 @@ use aptos_types::{
 use aptos_vm_logging::disable_speculative_logging;
 use futures::{channel::oneshot, executor::block_on};
 use move_core_types::vm_status::VMStatus;
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+};
 
 pub struct ShardedExecutorService<S: StateView + Sync + Send + 'static> {
     shard_id: ShardId,
@@ -31,6 +38,9 @@ pub struct ShardedExecutorService<S: StateView + Sync + Send + 'static> {
     executor_thread_pool: Arc<rayon::ThreadPool>,
     coordinator_client: Arc<dyn CoordinatorClient<S>>,
     cross_shard_client: Arc<dyn CrossShardClient>,
+    num_speculative_aborts: Arc<AtomicU64>,
+    dependency_wait_nanos: Arc<AtomicU64>,
+    dependency_resolution_order: DependencyResolutionOrder,
 }
 
 impl<S: StateView + Sync + Send + 'static> ShardedExecutorService<S> {
@@ -56,9 +66,35 @@ impl<S: StateView + Sync + Send + 'static> ShardedExecutorService<S> {
             executor_thread_pool,
             coordinator_client,
             cross_shard_client,
+            num_speculative_aborts: Arc::new(AtomicU64::new(0)),
+            dependency_wait_nanos: Arc::new(AtomicU64::new(0)),
+            dependency_resolution_order: DependencyResolutionOrder::default(),
         }
     }
 
+    /// Sets the order in which the cross-shard writes of the sub-blocks executed by this shard are
+    /// sent to the sub-blocks depending on them.
+    pub fn with_dependency_resolution_order(
+        mut self,
+        dependency_resolution_order: DependencyResolutionOrder,
+    ) -> Self {
+        self.dependency_resolution_order = dependency_resolution_order;
+        self
+    }
+
+    /// Number of speculative executions aborted, because of a conflict, in the sub-blocks executed
+    /// by this shard. It is updated before the execution result is sent to the coordinator.
+    pub fn num_speculative_aborts(&self) -> Arc<AtomicU64> {
+        self.num_speculative_aborts.clone()
+    }
+
+    /// Nanoseconds the executor threads of this shard were blocked waiting for the cross-shard
+    /// dependencies of the sub-blocks it executed, summed over the threads. It is updated before the
+    /// execution result is sent to the coordinator.
+    pub fn dependency_wait_nanos(&self) -> Arc<AtomicU64> {
+        self.dependency_wait_nanos.clone()
+    }
+
     fn execute_sub_block(
         &self,
         sub_block: SubBlock<AnalyzedTransaction>,
@@ -66,6 +102,7 @@ impl<S: StateView + Sync + Send + 'static> ShardedExecutorService<S> {
         state_view: &S,
         concurrency_level: usize,
         maybe_block_gas_limit: Option<u64>,
+        abort: &Arc<AtomicBool>,
     ) -> Result<Vec<TransactionOutput>, VMStatus> {
         disable_speculative_logging();
         trace!(
@@ -73,14 +110,21 @@ impl<S: StateView + Sync + Send + 'static> ShardedExecutorService<S> {
             self.shard_id,
             round
         );
-        let cross_shard_commit_sender =
-            CrossShardCommitSender::new(self.shard_id, self.cross_shard_client.clone(), &sub_block);
+        let cross_shard_commit_sender = CrossShardCommitSender::new(
+            self.shard_id,
+            self.cross_shard_client.clone(),
+            &sub_block,
+            self.num_speculative_aborts.clone(),
+            self.dependency_resolution_order,
+        );
         Self::execute_transactions_with_dependencies(
             Some(self.shard_id),
             self.executor_thread_pool.clone(),
             sub_block.into_transactions_with_deps(),
             self.cross_shard_client.clone(),
             Some(cross_shard_commit_sender),
+            Some(self.dependency_wait_nanos.clone()),
+            Some(abort.clone()),
             round,
             state_view,
             concurrency_level,
@@ -94,6 +138,8 @@ impl<S: StateView + Sync + Send + 'static> ShardedExecutorService<S> {
         transactions: Vec<TransactionWithDependencies<AnalyzedTransaction>>,
         cross_shard_client: Arc<dyn CrossShardClient>,
         cross_shard_commit_sender: Option<CrossShardCommitSender>,
+        dependency_wait_nanos: Option<Arc<AtomicU64>>,
+        abort: Option<Arc<AtomicBool>>,
         round: usize,
         state_view: &S,
         concurrency_level: usize,
@@ -101,10 +147,11 @@ impl<S: StateView + Sync + Send + 'static> ShardedExecutorService<S> {
     ) -> Result<Vec<TransactionOutput>, VMStatus> {
         let (callback, callback_receiver) = oneshot::channel();
 
-        let cross_shard_state_view = Arc::new(CrossShardStateView::create_cross_shard_state_view(
-            state_view,
-            &transactions,
-        ));
+        let cross_shard_state_view = Arc::new(
+            CrossShardStateView::create_cross_shard_state_view(state_view, &transactions)
+                .with_wait_nanos(dependency_wait_nanos)
+                .with_abort(abort),
+        );
 
         let cross_shard_state_view_clone = cross_shard_state_view.clone();
         let cross_shard_client_clone = cross_shard_client.clone();
@@ -163,9 +210,14 @@ impl<S: StateView + Sync + Send + 'static> ShardedExecutorService<S> {
         state_view: &S,
         concurrency_level: usize,
         maybe_block_gas_limit: Option<u64>,
+        abort: &Arc<AtomicBool>,
     ) -> Result<Vec<Vec<TransactionOutput>>, VMStatus> {
         let mut result = vec![];
         for (round, sub_block) in transactions.into_sub_blocks().into_iter().enumerate() {
+            // the coordinator stopped waiting for the block, skip the remaining rounds
+            if abort.load(Ordering::Relaxed) {
+                return Err(ShardedExecutionError::DeadlineExceeded.into());
+            }
             let _timer = SHARDED_BLOCK_EXECUTION_BY_ROUNDS_SECONDS
                 .with_label_values(&[&self.shard_id.to_string(), &round.to_string()])
                 .start_timer();
@@ -184,6 +236,7 @@ impl<S: StateView + Sync + Send + 'static> ShardedExecutorService<S> {
                 state_view,
                 concurrency_level,
                 maybe_block_gas_limit,
+                abort,
             )?);
             trace!(
                 "Finished executing sub block for shard {} and round {}",
@@ -208,6 +261,7 @@ impl<S: StateView + Sync + Send + 'static> ShardedExecutorService<S> {
                     transactions,
                     concurrency_level_per_shard,
                     maybe_block_gas_limit,
+                    abort,
                 ) => {
                     trace!(
                         "Shard {} received ExecuteBlock command of block size {} ",
@@ -219,6 +273,7 @@ impl<S: StateView + Sync + Send + 'static> ShardedExecutorService<S> {
                         state_view.as_ref(),
                         concurrency_level_per_shard,
                         maybe_block_gas_limit,
+                        &abort,
                     );
                     drop(state_view);
                     self.coordinator_client.send_execution_result(ret);
@@ -0,0 +1,55 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Result;
+use aptos_state_view::TStateView;
+use aptos_types::{
+    state_store::{
+        state_key::StateKey, state_storage_usage::StateStorageUsage, state_value::StateValue,
+    },
+    transaction::Version,
+};
+use std::sync::Arc;
+
+/// A source of the state at past versions as well as the latest one, e.g. a DB that hasn't pruned
+/// them yet.
+pub trait VersionedStateSource: Send + Sync {
+    fn get_state_value_at_version(
+        &self,
+        state_key: &StateKey,
+        version: Version,
+    ) -> Result<Option<StateValue>>;
+
+    fn get_usage_at_version(&self, version: Version) -> Result<StateStorageUsage>;
+}
+
+/// A state view of a `VersionedStateSource` pinned to one version. The shards executing a block
+/// all read through the same pinned view, so they see the state at that version however the
+/// source moves on while the block executes.
+pub struct PinnedStateView<V> {
+    source: Arc<V>,
+    version: Version,
+}
+
+impl<V: VersionedStateSource> PinnedStateView<V> {
+    pub fn new(source: Arc<V>, version: Version) -> Self {
+        Self { source, version }
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+}
+
+impl<V: VersionedStateSource> TStateView for PinnedStateView<V> {
+    type Key = StateKey;
+
+    fn get_state_value(&self, state_key: &StateKey) -> Result<Option<StateValue>> {
+        self.source
+            .get_state_value_at_version(state_key, self.version)
+    }
+
+    fn get_usage(&self) -> Result<StateStorageUsage> {
+        self.source.get_usage_at_version(self.version)
+    }
+}
@@ -9,6 +9,7 @@
 /// error.
 use aptos_block_partitioner::{
     pre_partition::{
+        account_sharding::config::AccountShardingPartitionerConfig,
         connected_component::config::ConnectedComponentPartitionerConfig,
         uniform_partitioner::config::UniformPartitionerConfig,
     },
@@ -16,9 +17,12 @@ use aptos_block_partitioner::{
     PartitionerConfig,
 };
 use aptos_vm::sharded_block_executor::{
-    local_executor_shard::LocalExecutorService, ShardedBlockExecutor,
+    cross_shard_client::DependencyResolutionOrder, local_executor_shard::LocalExecutorService,
+    ShardedBlockExecutor,
 };
+use move_core_types::account_address::AccountAddress;
 use rand::{rngs::OsRng, Rng};
+use std::sync::Arc;
 
 #[test]
 fn test_partitioner_v2_uniform_sharded_block_executor_no_conflict() {
@@ -185,26 +189,190 @@ fn test_partitioner_v2_connected_component_sharded_block_executor_with_random_tr
     }
 }
 
+#[test]
+fn test_partitioner_v2_account_sharding_sharded_block_executor_with_conflict() {
+    // Sends all the accounts with an even last byte to shard 0, regardless of conflicts.
+    let sharding_fn = |address: &AccountAddress, num_shards: usize| {
+        let last_byte = address.into_bytes()[AccountAddress::LENGTH - 1] as usize;
+        if last_byte % 2 == 0 {
+            0
+        } else {
+            last_byte % num_shards
+        }
+    };
+    for merge_discard in [false, true] {
+        let num_shards = 4;
+        let client = LocalExecutorService::setup_local_executor_shards(num_shards, Some(2));
+        let sharded_block_executor = ShardedBlockExecutor::new(client);
+        let partitioner = PartitionerV2Config::default()
+            .partition_last_round(merge_discard)
+            .pre_partitioner_config(Box::new(AccountShardingPartitionerConfig::new(Arc::new(
+                sharding_fn,
+            ))))
+            .build();
+        test_utils::sharded_block_executor_with_conflict(partitioner, sharded_block_executor, 2);
+    }
+}
+
+#[test]
+fn test_sharded_block_executor_unsharded_fallback_for_small_block() {
+    let num_shards = 4;
+    let client = LocalExecutorService::setup_local_executor_shards(num_shards, Some(2));
+    let sharded_block_executor = ShardedBlockExecutor::new(client).with_min_txns_for_sharding(10);
+    let partitioner = PartitionerV2Config::default().build();
+    test_utils::sharded_block_executor_unsharded_fallback(partitioner, sharded_block_executor);
+}
+
+#[test]
+fn test_sharded_block_executor_merge_order_independent_of_completion_order() {
+    let num_shards = 4;
+    let client = LocalExecutorService::setup_local_executor_shards(num_shards, Some(2));
+    let partitioner = PartitionerV2Config::default().build();
+    test_utils::sharded_block_executor_merge_order_independent_of_completion_order(
+        partitioner,
+        client,
+    );
+}
+
+#[test]
+fn test_sharded_block_executor_speculative_abort_stats() {
+    let num_shards = 4;
+    let client = LocalExecutorService::setup_local_executor_shards(num_shards, Some(4));
+    let sharded_block_executor = ShardedBlockExecutor::new(client);
+    let partitioner = PartitionerV2Config::default()
+        .partition_last_round(true)
+        .build();
+    test_utils::sharded_block_executor_speculative_abort_stats(partitioner, sharded_block_executor);
+}
+
+#[test]
+fn test_sharded_block_executor_deadline_exceeded() {
+    test_utils::sharded_block_executor_deadline_exceeded();
+}
+
+#[test]
+fn test_sharded_block_executor_cancels_stragglers() {
+    test_utils::sharded_block_executor_cancels_stragglers();
+}
+
+#[test]
+fn test_sharded_block_executor_deterministic() {
+    let num_shards = 4;
+    let client = LocalExecutorService::setup_local_executor_shards(num_shards, Some(4));
+    let sharded_block_executor = ShardedBlockExecutor::new(client);
+    let partitioner = PartitionerV2Config::default().build();
+    test_utils::sharded_block_executor_deterministic(partitioner, sharded_block_executor);
+}
+
+#[test]
+fn test_sharded_block_executor_cross_shard_dependency_chain() {
+    for dependency_resolution_order in [
+        DependencyResolutionOrder::Topological,
+        DependencyResolutionOrder::PriorityWeighted,
+    ] {
+        let num_shards = 4;
+        let client =
+            LocalExecutorService::setup_local_executor_shards_with_dependency_resolution_order(
+                num_shards,
+                Some(2),
+                dependency_resolution_order,
+            );
+        let sharded_block_executor = ShardedBlockExecutor::new(client);
+        test_utils::sharded_block_executor_cross_shard_dependency_chain(sharded_block_executor);
+    }
+}
+
+#[test]
+fn test_sharded_block_executor_critical_path_stats() {
+    let num_shards = 4;
+    let client = LocalExecutorService::setup_local_executor_shards(num_shards, Some(2));
+    let sharded_block_executor = ShardedBlockExecutor::new(client);
+    let partitioner = PartitionerV2Config::default().build();
+    test_utils::sharded_block_executor_critical_path_stats(partitioner, sharded_block_executor);
+}
+
+#[test]
+fn test_sharded_block_executor_idle_time_stats() {
+    test_utils::sharded_block_executor_idle_time_stats();
+}
+
+#[test]
+fn test_sharded_block_executor_cross_shard_failure_fallback() {
+    test_utils::sharded_block_executor_cross_shard_failure_fallback();
+}
+
+#[test]
+fn test_sharded_block_executor_at_version() {
+    let num_shards = 4;
+    let client = LocalExecutorService::setup_local_executor_shards(num_shards, Some(2));
+    let sharded_block_executor = ShardedBlockExecutor::new(client);
+    let partitioner = PartitionerV2Config::default().build();
+    test_utils::sharded_block_executor_at_version(partitioner, sharded_block_executor);
+}
+
+#[test]
+fn test_sharded_block_executor_execution_trace() {
+    let num_shards = 4;
+    let client = LocalExecutorService::setup_local_executor_shards(num_shards, Some(2));
+    let sharded_block_executor = ShardedBlockExecutor::new(client).with_execution_trace(true);
+    test_utils::sharded_block_executor_execution_trace(sharded_block_executor);
+}
+
+#[test]
+fn test_sharded_block_executor_merge_outputs() {
+    let num_shards = 4;
+    let client = LocalExecutorService::setup_local_executor_shards(num_shards, Some(2));
+    let sharded_block_executor = ShardedBlockExecutor::new(client);
+    let partitioner = PartitionerV2Config::default().build();
+    test_utils::sharded_block_executor_merge_outputs(partitioner, sharded_block_executor);
+}
+
 mod test_utils {
-    use aptos_block_partitioner::BlockPartitioner;
+    use aptos_block_partitioner::{
+        v2::config::PartitionerV2Config, BlockPartitioner, PartitionerConfig,
+    };
     use aptos_crypto::hash::CryptoHash;
     use aptos_language_e2e_tests::{
         account::AccountData, common_transactions::peer_to_peer_txn, data_store::FakeDataStore,
         executor::FakeExecutor,
     };
+    use aptos_state_view::TStateView;
     use aptos_types::{
-        block_executor::partitioner::PartitionedTransactions,
-        transaction::{analyzed_transaction::AnalyzedTransaction, Transaction, TransactionOutput},
+        block_executor::partitioner::{
+            CrossShardDependencies, PartitionedTransactions, ShardedTxnIndex, SubBlock,
+            SubBlocksForShard, TransactionWithDependencies,
+        },
+        state_store::{
+            state_key::StateKey, state_storage_usage::StateStorageUsage, state_value::StateValue,
+        },
+        transaction::{
+            analyzed_transaction::{AnalyzedTransaction, StorageLocation},
+            ExecutionStatus, Transaction, TransactionOutput, TransactionStatus, Version,
+        },
+        write_set::WriteSetMut,
     };
     use aptos_vm::{
-        sharded_block_executor::{executor_client::ExecutorClient, ShardedBlockExecutor},
+        sharded_block_executor::{
+            critical_path_length,
+            execution_trace::ExecutedBy,
+            executor_client::{ExecutorClient, ShardedExecutionError, ShardedExecutionOutput},
+            local_executor_shard::LocalExecutorService,
+            merge_sharded_outputs,
+            versioned_state_view::{PinnedStateView, VersionedStateSource},
+            CrossShardFailurePolicy, ShardedBlockExecutor,
+        },
         AptosVM, VMExecutor,
     };
-    use move_core_types::account_address::AccountAddress;
-    use rand::{rngs::OsRng, Rng};
+    use move_core_types::{
+        account_address::AccountAddress,
+        vm_status::{StatusCode, VMStatus},
+    };
+    use rand::{rngs::OsRng, seq::SliceRandom, Rng};
     use std::{
-        collections::HashMap,
+        collections::{BTreeMap, HashMap},
         sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
     };
 
     pub fn generate_account_at(
@@ -405,4 +573,752 @@ mod test_utils {
             AptosVM::execute_block(execution_ordered_txns, executor.data_store(), None).unwrap();
         compare_txn_outputs(unsharded_txn_output, sharded_txn_output);
     }
+
+    pub fn sharded_block_executor_unsharded_fallback<E: ExecutorClient<FakeDataStore>>(
+        partitioner: Box<dyn BlockPartitioner>,
+        sharded_block_executor: ShardedBlockExecutor<FakeDataStore, E>,
+    ) {
+        let num_txns = 3;
+        let num_shards = sharded_block_executor.num_shards();
+        let mut executor = FakeExecutor::from_head_genesis();
+        let transactions = (0..num_txns)
+            .map(|_| generate_non_conflicting_p2p(&mut executor).0)
+            .collect();
+        let partitioned_txns = partitioner.partition(transactions, num_shards);
+        assert!(sharded_block_executor.should_execute_unsharded(&partitioned_txns));
+
+        let sharded_txn_output = sharded_block_executor
+            .execute_block(
+                Arc::new(executor.data_store().clone()),
+                partitioned_txns.clone(),
+                2,
+                None,
+            )
+            .unwrap();
+
+        let ordered_txns: Vec<Transaction> = PartitionedTransactions::flatten(partitioned_txns)
+            .into_iter()
+            .map(|t| t.into_txn())
+            .collect();
+        let unsharded_txn_output =
+            AptosVM::execute_block(ordered_txns, executor.data_store(), None).unwrap();
+        compare_txn_outputs(unsharded_txn_output, sharded_txn_output);
+    }
+
+    pub fn sharded_block_executor_merge_order_independent_of_completion_order<
+        E: ExecutorClient<FakeDataStore>,
+    >(
+        partitioner: Box<dyn BlockPartitioner>,
+        executor_client: E,
+    ) {
+        let num_txns = 40;
+        let num_shards = executor_client.num_shards();
+        let mut executor = FakeExecutor::from_head_genesis();
+        let transactions = (0..num_txns)
+            .map(|_| generate_non_conflicting_p2p(&mut executor).0)
+            .collect();
+        let partitioned_txns = partitioner.partition(transactions, num_shards);
+        let (sharded_output, global_output) = executor_client
+            .execute_block(
+                Arc::new(executor.data_store().clone()),
+                partitioned_txns.clone(),
+                2,
+                None,
+            )
+            .unwrap()
+            .into_inner();
+
+        // Feed the shard outputs to the merge in different completion orders.
+        let mut completion_orders: Vec<Vec<usize>> =
+            vec![(0..num_shards).collect(), (0..num_shards).rev().collect()];
+        for _ in 0..4 {
+            let mut order: Vec<usize> = (0..num_shards).collect();
+            order.shuffle(&mut OsRng);
+            completion_orders.push(order);
+        }
+        let merged_outputs: Vec<Vec<TransactionOutput>> = completion_orders
+            .into_iter()
+            .map(|order| {
+                merge_sharded_outputs(
+                    order
+                        .into_iter()
+                        .map(|shard_id| (shard_id, sharded_output[shard_id].clone())),
+                    global_output.clone(),
+                )
+            })
+            .collect();
+        for merged_output in &merged_outputs[1..] {
+            compare_txn_outputs(merged_outputs[0].clone(), merged_output.clone());
+        }
+
+        let ordered_txns: Vec<Transaction> = PartitionedTransactions::flatten(partitioned_txns)
+            .into_iter()
+            .map(|t| t.into_txn())
+            .collect();
+        let unsharded_txn_output =
+            AptosVM::execute_block(ordered_txns, executor.data_store(), None).unwrap();
+        compare_txn_outputs(unsharded_txn_output, merged_outputs[0].clone());
+    }
+
+    pub fn sharded_block_executor_speculative_abort_stats<E: ExecutorClient<FakeDataStore>>(
+        partitioner: Box<dyn BlockPartitioner>,
+        sharded_block_executor: ShardedBlockExecutor<FakeDataStore, E>,
+    ) {
+        let num_txns = 400;
+        let num_shards = sharded_block_executor.num_shards();
+        let mut executor = FakeExecutor::from_head_genesis();
+
+        // Conflict-free transactions are never aborted.
+        let transactions = (0..num_txns)
+            .map(|_| generate_non_conflicting_p2p(&mut executor).0)
+            .collect();
+        let partitioned_txns = partitioner.partition(transactions, num_shards);
+        sharded_block_executor
+            .execute_block(
+                Arc::new(executor.data_store().clone()),
+                partitioned_txns,
+                4,
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            sharded_block_executor
+                .last_execution_stats()
+                .speculative_aborts_per_shard,
+            vec![0; num_shards]
+        );
+
+        // All the transactions transfer to the same receiver, so they conflict with each other.
+        let receiver = generate_account_at(&mut executor, AccountAddress::random());
+        let transactions = (0..num_txns)
+            .map(|_| {
+                let mut sender = generate_account_at(&mut executor, AccountAddress::random());
+                generate_p2p_txn(&mut sender, &receiver, 1_000)
+            })
+            .collect();
+        let partitioned_txns = partitioner.partition(transactions, num_shards);
+        sharded_block_executor
+            .execute_block(
+                Arc::new(executor.data_store().clone()),
+                partitioned_txns,
+                4,
+                None,
+            )
+            .unwrap();
+        let stats = sharded_block_executor.last_execution_stats();
+        assert_eq!(stats.speculative_aborts_per_shard.len(), num_shards);
+        assert!(stats.speculative_aborts_per_shard.iter().sum::<u64>() > 0);
+    }
+
+    /// A state view that delays the reads made by the executor threads of one shard.
+    struct SlowShardStateView {
+        data_store: FakeDataStore,
+        slow_thread_name_prefix: String,
+        delay: Duration,
+    }
+
+    impl TStateView for SlowShardStateView {
+        type Key = StateKey;
+
+        fn get_state_value(&self, state_key: &StateKey) -> anyhow::Result<Option<StateValue>> {
+            let is_slow_shard = thread::current().name().map_or(false, |name| {
+                name.starts_with(&self.slow_thread_name_prefix)
+            });
+            if is_slow_shard {
+                thread::sleep(self.delay);
+            }
+            self.data_store.get_state_value(state_key)
+        }
+
+        fn get_usage(&self) -> anyhow::Result<StateStorageUsage> {
+            self.data_store.get_usage()
+        }
+    }
+
+    pub fn sharded_block_executor_deadline_exceeded() {
+        let num_txns = 40;
+        let num_shards = 4;
+        let execution_deadline = Duration::from_millis(200);
+        let client = LocalExecutorService::setup_local_executor_shards(num_shards, Some(2));
+        let sharded_block_executor =
+            ShardedBlockExecutor::new(client).with_execution_deadline(execution_deadline);
+        let partitioner = PartitionerV2Config::default().build();
+        let mut executor = FakeExecutor::from_head_genesis();
+        let transactions = (0..num_txns)
+            .map(|_| generate_non_conflicting_p2p(&mut executor).0)
+            .collect();
+        let partitioned_txns = partitioner.partition(transactions, num_shards);
+        let state_view = Arc::new(SlowShardStateView {
+            data_store: executor.data_store().clone(),
+            slow_thread_name_prefix: "sharded-executor-shard-0-".to_string(),
+            delay: Duration::from_millis(50),
+        });
+
+        let start = Instant::now();
+        let result = sharded_block_executor.execute_block(state_view, partitioned_txns, 2, None);
+        assert!(matches!(
+            result,
+            Err(ShardedExecutionError::DeadlineExceeded)
+        ));
+        // The executor stops waiting for the slow shard at the deadline, not once it finishes.
+        assert!(start.elapsed() < execution_deadline * 10);
+
+        // Dropping the executor waits for the slow shard to finish and stops all the shards.
+        drop(sharded_block_executor);
+    }
+
+    pub fn sharded_block_executor_cancels_stragglers() {
+        let num_txns = 40;
+        let num_shards = 4;
+        let execution_deadline = Duration::from_secs(1);
+        let client = LocalExecutorService::setup_local_executor_shards(num_shards, Some(2));
+        let sharded_block_executor =
+            ShardedBlockExecutor::new(client).with_execution_deadline(execution_deadline);
+        let partitioner = PartitionerV2Config::default().build();
+        let mut executor = FakeExecutor::from_head_genesis();
+        let transactions = (0..num_txns)
+            .map(|_| generate_non_conflicting_p2p(&mut executor).0)
+            .collect();
+        let partitioned_txns = partitioner.partition(transactions, num_shards);
+        // Without cancellation, the slow shard would execute its txns for tens of seconds.
+        let state_view = Arc::new(SlowShardStateView {
+            data_store: executor.data_store().clone(),
+            slow_thread_name_prefix: "sharded-executor-shard-0-".to_string(),
+            delay: Duration::from_millis(500),
+        });
+        let start = Instant::now();
+        let result = sharded_block_executor.execute_block(state_view, partitioned_txns, 2, None);
+        assert!(matches!(
+            result,
+            Err(ShardedExecutionError::DeadlineExceeded)
+        ));
+
+        // The slow shard is cancelled at the deadline, so the next block isn't delayed by it.
+        let transactions = (0..num_txns)
+            .map(|_| generate_non_conflicting_p2p(&mut executor).0)
+            .collect();
+        let partitioned_txns = partitioner.partition(transactions, num_shards);
+        let sharded_txn_output = sharded_block_executor
+            .execute_block(
+                Arc::new(executor.data_store().clone()),
+                partitioned_txns.clone(),
+                2,
+                None,
+            )
+            .unwrap();
+        assert!(start.elapsed() < execution_deadline * 5);
+
+        let ordered_txns: Vec<Transaction> = PartitionedTransactions::flatten(partitioned_txns)
+            .into_iter()
+            .map(|t| t.into_txn())
+            .collect();
+        let unsharded_txn_output =
+            AptosVM::execute_block(ordered_txns, executor.data_store(), None).unwrap();
+        compare_txn_outputs(unsharded_txn_output, sharded_txn_output);
+    }
+
+    pub fn sharded_block_executor_idle_time_stats() {
+        let num_txns = 10;
+        let num_shards = 4;
+        let delay = Duration::from_millis(2);
+        let client = LocalExecutorService::setup_local_executor_shards(num_shards, Some(2));
+        let sharded_block_executor = ShardedBlockExecutor::new(client);
+        let mut executor = FakeExecutor::from_head_genesis();
+
+        // All the transactions are assigned to the slow shard 0, the other shards have nothing to
+        // execute and wait for it to finish.
+        let transactions = (0..num_txns)
+            .map(|_| {
+                let txn = generate_non_conflicting_p2p(&mut executor).0;
+                TransactionWithDependencies::new(txn, CrossShardDependencies::default())
+            })
+            .collect();
+        let mut sharded_txns: Vec<_> = (0..num_shards).map(SubBlocksForShard::empty).collect();
+        sharded_txns[0].add_sub_block(SubBlock::new(0, transactions));
+        for sub_blocks in &mut sharded_txns[1..] {
+            sub_blocks.add_sub_block(SubBlock::new(num_txns, vec![]));
+        }
+        let partitioned_txns = PartitionedTransactions::new(sharded_txns, vec![]);
+        let state_view = Arc::new(SlowShardStateView {
+            data_store: executor.data_store().clone(),
+            slow_thread_name_prefix: "sharded-executor-shard-0-".to_string(),
+            delay,
+        });
+        sharded_block_executor
+            .execute_block(state_view, partitioned_txns, 2, None)
+            .unwrap();
+
+        let idle_time_per_shard = sharded_block_executor
+            .last_execution_stats()
+            .idle_time_per_shard;
+        assert_eq!(idle_time_per_shard.len(), num_shards);
+        // Every transaction reads the state at least once, on one of the 2 threads of shard 0.
+        let min_busy_time = delay * num_txns as u32 / 2;
+        for idle_time in &idle_time_per_shard[1..] {
+            assert!(*idle_time >= min_busy_time);
+            assert!(*idle_time > idle_time_per_shard[0]);
+        }
+    }
+
+    /// Executes the block through the sharded path `runs` times and asserts that the outputs of
+    /// every run are byte-identical, to catch nondeterminism from the scheduling of the shards.
+    pub fn assert_sharded_deterministic<E: ExecutorClient<FakeDataStore>>(
+        sharded_block_executor: &ShardedBlockExecutor<FakeDataStore, E>,
+        state_view: Arc<FakeDataStore>,
+        block: PartitionedTransactions,
+        runs: usize,
+    ) {
+        let mut expected_output = None;
+        for run in 0..runs {
+            let output = sharded_block_executor
+                .execute_block(state_view.clone(), block.clone(), 4, None)
+                .unwrap();
+            let output_bytes = bcs::to_bytes(&output).unwrap();
+            match &expected_output {
+                None => expected_output = Some(output_bytes),
+                Some(expected_output) => assert!(
+                    *expected_output == output_bytes,
+                    "Output of run {} differs from the first run",
+                    run
+                ),
+            }
+        }
+    }
+
+    pub fn sharded_block_executor_deterministic<E: ExecutorClient<FakeDataStore>>(
+        partitioner: Box<dyn BlockPartitioner>,
+        sharded_block_executor: ShardedBlockExecutor<FakeDataStore, E>,
+    ) {
+        let num_txns = 400;
+        let num_accounts = 40;
+        let num_shards = sharded_block_executor.num_shards();
+        let mut executor = FakeExecutor::from_head_genesis();
+        let accounts: Vec<_> = (0..num_accounts)
+            .map(|_| generate_account_at(&mut executor, AccountAddress::random()))
+            .map(Mutex::new)
+            .collect();
+        let transactions = (0..num_txns)
+            .map(|i| {
+                let sender = &mut accounts[i % num_accounts].lock().unwrap();
+                let receiver = &accounts[(i * 7 + 1) % num_accounts].lock().unwrap();
+                generate_p2p_txn(sender, receiver, 1_000)
+            })
+            .collect();
+        let partitioned_txns = partitioner.partition(transactions, num_shards);
+        assert_sharded_deterministic(
+            &sharded_block_executor,
+            Arc::new(executor.data_store().clone()),
+            partitioned_txns,
+            3,
+        );
+    }
+
+    /// The locations written by `writer` that `reader` reads or writes, i.e. the locations on which
+    /// `reader` depends if it's executed after `writer`.
+    fn conflicting_locations(
+        writer: &AnalyzedTransaction,
+        reader: &AnalyzedTransaction,
+    ) -> Vec<StorageLocation> {
+        writer
+            .write_hints()
+            .iter()
+            .filter(|location| {
+                reader.read_hints().contains(location) || reader.write_hints().contains(location)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Generates a block of `chain_length` transfers, each sent by the receiver of the previous one,
+    /// where transfer i is executed by shard i in round i. Every link of the chain crosses shards and
+    /// rounds, so a transfer can only execute once the previous one is resolved.
+    fn generate_cross_shard_dependency_chain(
+        executor: &mut FakeExecutor,
+        chain_length: usize,
+        num_shards: usize,
+    ) -> PartitionedTransactions {
+        assert!(num_shards >= chain_length);
+        let mut accounts: Vec<_> = (0..=chain_length)
+            .map(|_| generate_account_at(executor, AccountAddress::random()))
+            .collect();
+        // Each transfer depends on the previous transfer's write to the coin store of its sender.
+        let transactions: Vec<_> = (0..chain_length)
+            .map(|i| {
+                let (senders, receivers) = accounts.split_at_mut(i + 1);
+                generate_p2p_txn(&mut senders[i], &receivers[0], 1_000)
+            })
+            .collect();
+
+        let mut sharded_txns: Vec<_> = (0..num_shards).map(SubBlocksForShard::empty).collect();
+        for round_id in 0..chain_length {
+            for (shard_id, sub_blocks) in sharded_txns.iter_mut().enumerate() {
+                let mut sub_block_txns = vec![];
+                if shard_id == round_id {
+                    let txn = &transactions[round_id];
+                    let mut cross_shard_dependencies = CrossShardDependencies::default();
+                    if round_id > 0 {
+                        let prev_idx = round_id - 1;
+                        for location in conflicting_locations(&transactions[prev_idx], txn) {
+                            cross_shard_dependencies.add_required_edge(
+                                ShardedTxnIndex::new(prev_idx, prev_idx, prev_idx),
+                                location,
+                            );
+                        }
+                    }
+                    if round_id + 1 < chain_length {
+                        let next_idx = round_id + 1;
+                        cross_shard_dependencies.add_dependent_edge(
+                            ShardedTxnIndex::new(next_idx, next_idx, next_idx),
+                            conflicting_locations(txn, &transactions[next_idx]),
+                        );
+                    }
+                    sub_block_txns.push(TransactionWithDependencies::new(
+                        txn.clone(),
+                        cross_shard_dependencies,
+                    ));
+                }
+                let start_index = if shard_id <= round_id {
+                    round_id
+                } else {
+                    round_id + 1
+                };
+                sub_blocks.add_sub_block(SubBlock::new(start_index, sub_block_txns));
+            }
+        }
+        let partitioned_txns = PartitionedTransactions::new(sharded_txns, vec![]);
+        assert_eq!(
+            PartitionedTransactions::flatten(partitioned_txns.clone())
+                .into_iter()
+                .map(|t| t.into_txn())
+                .collect::<Vec<_>>(),
+            transactions
+                .into_iter()
+                .map(|t| t.into_txn())
+                .collect::<Vec<_>>()
+        );
+        partitioned_txns
+    }
+
+    pub fn sharded_block_executor_cross_shard_dependency_chain<E: ExecutorClient<FakeDataStore>>(
+        sharded_block_executor: ShardedBlockExecutor<FakeDataStore, E>,
+    ) {
+        let num_shards = sharded_block_executor.num_shards();
+        let mut executor = FakeExecutor::from_head_genesis();
+        let partitioned_txns = generate_cross_shard_dependency_chain(&mut executor, 3, num_shards);
+
+        let sharded_txn_output = sharded_block_executor
+            .execute_block(
+                Arc::new(executor.data_store().clone()),
+                partitioned_txns.clone(),
+                2,
+                None,
+            )
+            .unwrap();
+        assert!(sharded_txn_output
+            .iter()
+            .all(|output| output.status() == &TransactionStatus::Keep(ExecutionStatus::Success)));
+
+        let ordered_txns: Vec<Transaction> = PartitionedTransactions::flatten(partitioned_txns)
+            .into_iter()
+            .map(|t| t.into_txn())
+            .collect();
+        let unsharded_txn_output =
+            AptosVM::execute_block(ordered_txns, executor.data_store(), None).unwrap();
+        compare_txn_outputs(unsharded_txn_output, sharded_txn_output);
+    }
+
+    pub fn sharded_block_executor_critical_path_stats<E: ExecutorClient<FakeDataStore>>(
+        partitioner: Box<dyn BlockPartitioner>,
+        sharded_block_executor: ShardedBlockExecutor<FakeDataStore, E>,
+    ) {
+        let num_shards = sharded_block_executor.num_shards();
+        let mut executor = FakeExecutor::from_head_genesis();
+
+        // The transactions of a chain of cross-shard dependencies can't execute in parallel.
+        let chain_length = 3;
+        let partitioned_txns =
+            generate_cross_shard_dependency_chain(&mut executor, chain_length, num_shards);
+        assert_eq!(critical_path_length(&partitioned_txns), chain_length);
+        sharded_block_executor
+            .execute_block(
+                Arc::new(executor.data_store().clone()),
+                partitioned_txns,
+                2,
+                None,
+            )
+            .unwrap();
+        let stats = sharded_block_executor.last_execution_stats();
+        assert_eq!(stats.critical_path_length, chain_length);
+        assert_eq!(stats.num_txns, chain_length);
+        assert_eq!(stats.achievable_parallelism(), 1.0);
+
+        // Conflict-free transactions have no cross-shard dependencies.
+        let num_txns = 40;
+        let transactions = (0..num_txns)
+            .map(|_| generate_non_conflicting_p2p(&mut executor).0)
+            .collect();
+        let partitioned_txns = partitioner.partition(transactions, num_shards);
+        sharded_block_executor
+            .execute_block(
+                Arc::new(executor.data_store().clone()),
+                partitioned_txns,
+                2,
+                None,
+            )
+            .unwrap();
+        let stats = sharded_block_executor.last_execution_stats();
+        assert_eq!(stats.critical_path_length, 1);
+        assert_eq!(stats.num_txns, num_txns);
+        assert_eq!(stats.achievable_parallelism(), num_txns as f64);
+    }
+
+    pub fn sharded_block_executor_execution_trace<E: ExecutorClient<FakeDataStore>>(
+        sharded_block_executor: ShardedBlockExecutor<FakeDataStore, E>,
+    ) {
+        let num_shards = sharded_block_executor.num_shards();
+        let mut executor = FakeExecutor::from_head_genesis();
+        let chain_length = 3;
+        let partitioned_txns =
+            generate_cross_shard_dependency_chain(&mut executor, chain_length, num_shards);
+
+        let sharded_txn_output = sharded_block_executor
+            .execute_block(
+                Arc::new(executor.data_store().clone()),
+                partitioned_txns,
+                2,
+                None,
+            )
+            .unwrap();
+        let trace = sharded_block_executor.last_execution_trace().unwrap();
+        assert_eq!(trace.num_txns(), chain_length);
+        for (txn_index, (txn_trace, output)) in
+            trace.txns().iter().zip(&sharded_txn_output).enumerate()
+        {
+            assert_eq!(txn_trace.txn_index, txn_index);
+            // Transfer i is executed by shard i in round i.
+            let executed_by = ExecutedBy::Shard {
+                shard_id: txn_index,
+                round: txn_index,
+            };
+            assert_eq!(txn_trace.executed_by, executed_by);
+            assert_eq!(&txn_trace.status, output.status());
+            assert_eq!(
+                txn_trace.status,
+                TransactionStatus::Keep(ExecutionStatus::Success)
+            );
+            // Every transfer but the first reads the coin store written by the previous one.
+            if txn_index == 0 {
+                assert!(txn_trace.cross_shard_reads.is_empty());
+            } else {
+                assert!(!txn_trace.cross_shard_reads.is_empty());
+                assert!(txn_trace
+                    .cross_shard_reads
+                    .iter()
+                    .all(|(required_txn_idx, _)| required_txn_idx.txn_index == txn_index - 1));
+            }
+        }
+
+        // Blocks executed unsharded have no trace.
+        let sharded_block_executor = sharded_block_executor.with_min_txns_for_sharding(10);
+        let partitioned_txns =
+            generate_cross_shard_dependency_chain(&mut executor, chain_length, num_shards);
+        sharded_block_executor
+            .execute_block(
+                Arc::new(executor.data_store().clone()),
+                partitioned_txns,
+                2,
+                None,
+            )
+            .unwrap();
+        assert!(sharded_block_executor.last_execution_trace().is_none());
+    }
+
+    pub fn sharded_block_executor_merge_outputs<E: ExecutorClient<FakeDataStore>>(
+        partitioner: Box<dyn BlockPartitioner>,
+        sharded_block_executor: ShardedBlockExecutor<FakeDataStore, E>,
+    ) {
+        let num_shards = sharded_block_executor.num_shards();
+        let num_accounts = 10;
+        let mut executor = FakeExecutor::from_head_genesis();
+        let mut accounts: Vec<_> = (0..num_accounts)
+            .map(|_| generate_account_at(&mut executor, AccountAddress::random()))
+            .collect();
+        // Every account both sends and receives several times, so the same keys are written by
+        // transactions on different shards.
+        let mut transactions = Vec::new();
+        for i in 1..5 {
+            for j in 0..num_accounts {
+                let receiver = accounts[(j + i) % num_accounts].clone();
+                transactions.push(generate_p2p_txn(&mut accounts[j], &receiver, 1_000));
+            }
+        }
+        let partitioned_txns = partitioner.partition(transactions, num_shards);
+        let execution_ordered_txns = PartitionedTransactions::flatten(partitioned_txns.clone())
+            .into_iter()
+            .map(|t| t.into_txn())
+            .collect();
+
+        let sharded_txn_output = sharded_block_executor
+            .execute_block(
+                Arc::new(executor.data_store().clone()),
+                partitioned_txns,
+                2,
+                None,
+            )
+            .unwrap();
+        let merged_write_set = sharded_block_executor.merge_outputs(&sharded_txn_output);
+
+        let unsharded_txn_output =
+            AptosVM::execute_block(execution_ordered_txns, executor.data_store(), None).unwrap();
+        let mut cumulative_write_ops = BTreeMap::new();
+        for output in &unsharded_txn_output {
+            for (state_key, write_op) in output.write_set().iter() {
+                cumulative_write_ops.insert(state_key.clone(), write_op.clone());
+            }
+        }
+        let cumulative_write_set = WriteSetMut::new(cumulative_write_ops).freeze().unwrap();
+        assert_eq!(merged_write_set, cumulative_write_set);
+        // Keys written by several transactions appear once.
+        let num_writes: usize = unsharded_txn_output
+            .iter()
+            .map(|output| output.write_set().iter().count())
+            .sum();
+        assert!(merged_write_set.iter().count() < num_writes);
+    }
+
+    /// Keeps a snapshot of the whole state per version.
+    struct VersionedDataStore {
+        versions: Vec<FakeDataStore>,
+    }
+
+    impl VersionedStateSource for VersionedDataStore {
+        fn get_state_value_at_version(
+            &self,
+            state_key: &StateKey,
+            version: Version,
+        ) -> anyhow::Result<Option<StateValue>> {
+            self.versions[version as usize].get_state_value(state_key)
+        }
+
+        fn get_usage_at_version(&self, version: Version) -> anyhow::Result<StateStorageUsage> {
+            self.versions[version as usize].get_usage()
+        }
+    }
+
+    pub fn sharded_block_executor_at_version<
+        E: ExecutorClient<PinnedStateView<VersionedDataStore>>,
+    >(
+        partitioner: Box<dyn BlockPartitioner>,
+        sharded_block_executor: ShardedBlockExecutor<PinnedStateView<VersionedDataStore>, E>,
+    ) {
+        let num_txns = 40;
+        let num_shards = sharded_block_executor.num_shards();
+        let mut executor = FakeExecutor::from_head_genesis();
+        let transactions = (0..num_txns)
+            .map(|_| generate_non_conflicting_p2p(&mut executor).0)
+            .collect();
+        let partitioned_txns = partitioner.partition(transactions, num_shards);
+        let ordered_txns: Vec<Transaction> =
+            PartitionedTransactions::flatten(partitioned_txns.clone())
+                .into_iter()
+                .map(|t| t.into_txn())
+                .collect();
+
+        // version 1 is the state after the block committed at version 0, where its txns are stale
+        let state_v0 = executor.data_store().clone();
+        let mut state_v1 = state_v0.clone();
+        for output in AptosVM::execute_block(ordered_txns.clone(), &state_v0, None).unwrap() {
+            state_v1.add_write_set(output.write_set());
+        }
+        let state_source = Arc::new(VersionedDataStore {
+            versions: vec![state_v0, state_v1],
+        });
+
+        let mut outputs = vec![];
+        for version in 0..2 {
+            let sharded_txn_output = sharded_block_executor
+                .execute_block_at_version(
+                    state_source.clone(),
+                    version,
+                    partitioned_txns.clone(),
+                    2,
+                    None,
+                )
+                .unwrap();
+            let unsharded_txn_output = AptosVM::execute_block(
+                ordered_txns.clone(),
+                &state_source.versions[version as usize],
+                None,
+            )
+            .unwrap();
+            compare_txn_outputs(unsharded_txn_output, sharded_txn_output.clone());
+            outputs.push(sharded_txn_output);
+        }
+        let is_discarded = |output: &TransactionOutput| output.status().is_discarded();
+        assert!(!outputs[0].iter().any(is_discarded));
+        assert!(outputs[1].iter().all(is_discarded));
+    }
+
+    /// An executor client whose shards fail every block, as if the cross-shard dependencies of the
+    /// transactions could never be resolved.
+    struct FailingExecutorClient {
+        num_shards: usize,
+    }
+
+    impl ExecutorClient<FakeDataStore> for FailingExecutorClient {
+        fn num_shards(&self) -> usize {
+            self.num_shards
+        }
+
+        fn execute_block(
+            &self,
+            _state_view: Arc<FakeDataStore>,
+            _transactions: PartitionedTransactions,
+            _concurrency_level_per_shard: usize,
+            _maybe_block_gas_limit: Option<u64>,
+        ) -> Result<ShardedExecutionOutput, VMStatus> {
+            Err(VMStatus::error(
+                StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR,
+                Some("cross-shard dependencies not resolved".to_string()),
+            ))
+        }
+    }
+
+    pub fn sharded_block_executor_cross_shard_failure_fallback() {
+        let num_txns = 40;
+        let num_shards = 4;
+        let partitioner = PartitionerV2Config::default().build();
+        let mut executor = FakeExecutor::from_head_genesis();
+        let transactions = (0..num_txns)
+            .map(|_| generate_non_conflicting_p2p(&mut executor).0)
+            .collect();
+        let partitioned_txns = partitioner.partition(transactions, num_shards);
+        let state_view = Arc::new(executor.data_store().clone());
+
+        // by default the failure of the shards is returned
+        let sharded_block_executor =
+            ShardedBlockExecutor::new(FailingExecutorClient { num_shards });
+        let result = sharded_block_executor.execute_block(
+            state_view.clone(),
+            partitioned_txns.clone(),
+            2,
+            None,
+        );
+        assert!(matches!(result, Err(ShardedExecutionError::VMStatus(_))));
+
+        let sharded_block_executor =
+            ShardedBlockExecutor::new(FailingExecutorClient { num_shards })
+                .with_cross_shard_failure_policy(CrossShardFailurePolicy::ExecuteUnsharded);
+        let fallback_txn_output = sharded_block_executor
+            .execute_block(state_view, partitioned_txns.clone(), 2, None)
+            .unwrap();
+        let ordered_txns: Vec<Transaction> = PartitionedTransactions::flatten(partitioned_txns)
+            .into_iter()
+            .map(|t| t.into_txn())
+            .collect();
+        let unsharded_txn_output =
+            AptosVM::execute_block(ordered_txns, executor.data_store(), None).unwrap();
+        assert_eq!(fallback_txn_output.len(), num_txns);
+        compare_txn_outputs(unsharded_txn_output, fallback_txn_output);
+    }
 }
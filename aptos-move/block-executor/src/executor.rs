@@ -212,6 +212,7 @@ where
     }
 
     fn validate(
+        &self,
         idx_to_validate: TxnIndex,
         incarnation: Incarnation,
         validation_wave: Wave,
@@ -232,6 +233,9 @@ where
 
         if aborted {
             counters::SPECULATIVE_ABORT_COUNT.inc();
+            if let Some(txn_commit_listener) = &self.transaction_commit_hook {
+                txn_commit_listener.on_speculative_abort(idx_to_validate);
+            }
 
             // Any logs from the aborted execution should be cleared and not reported.
             clear_speculative_txn_logs(idx_to_validate as usize);
@@ -460,7 +464,7 @@ where
             }
 
             scheduler_task = match scheduler_task {
-                SchedulerTask::ValidationTask(txn_idx, incarnation, wave) => Self::validate(
+                SchedulerTask::ValidationTask(txn_idx, incarnation, wave) => self.validate(
                     txn_idx,
                     incarnation,
                     wave,
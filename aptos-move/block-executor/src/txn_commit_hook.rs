@@ -12,6 +12,10 @@ pub trait TransactionCommitHook: Send + Sync {
     fn on_transaction_committed(&self, txn_idx: TxnIndex, output: &Self::Output);
 
     fn on_execution_aborted(&self, txn_idx: TxnIndex);
+
+    /// Called when a speculative execution of the transaction fails validation and is aborted,
+    /// i.e. the transaction conflicted with a lower transaction and will be re-executed.
+    fn on_speculative_abort(&self, _txn_idx: TxnIndex) {}
 }
 
 pub struct NoOpTransactionCommitHook<T, E> {
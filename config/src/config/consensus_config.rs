@@ -57,6 +57,9 @@ pub struct ConsensusConfig {
     //   and others are creating large blocks, so we can wait as well.
     pub wait_for_full_blocks_above_pending_blocks: usize,
     pub wait_for_full_blocks_above_recent_fill_threshold: f32,
+    // Limits the number of concurrent pulls against the payload client shared by consensus
+    // components, unlimited if not set. Must be positive if set.
+    pub max_concurrent_payload_pulls: Option<usize>,
     pub intra_consensus_channel_buffer_size: usize,
     pub quorum_store: QuorumStoreConfig,
     pub vote_back_pressure_limit: u64,
@@ -65,6 +68,58 @@ pub struct ConsensusConfig {
     // must match one of the CHAIN_HEALTH_WINDOW_SIZES values.
     pub window_for_chain_health: usize,
     pub chain_health_backoff: Vec<ChainHealthBackoffValues>,
+    // Where the DAG consensus stores its nodes and votes.
+    pub dag_storage_backend: DagStorageBackend,
+    // Number of signers of a DAG node its missing parents are fetched from at once.
+    pub dag_fetch_fanout: u32,
+    // The first reliable broadcast retry of a DAG message is sent after
+    // dag_rb_backoff_base_ms * dag_rb_backoff_factor ms, every retry multiplies the delay by
+    // dag_rb_backoff_base_ms.
+    pub dag_rb_backoff_base_ms: u64,
+    pub dag_rb_backoff_factor: u64,
+    // Caps on the delay between reliable broadcast retries and on each broadcast rpc, scaled
+    // with the validator set if not set.
+    pub dag_rb_backoff_max_delay_ms: Option<u64>,
+    pub dag_rb_rpc_timeout_ms: Option<u64>,
+    // Limits the reliable broadcast rpcs in flight, unlimited if not set.
+    pub dag_rb_max_concurrent_sends: Option<usize>,
+    // Threads verifying the DAG nodes recovered from storage at startup, the nodes are trusted
+    // if not set.
+    pub dag_startup_verification_threads: Option<usize>,
+    // Certified nodes buffered while the DAG state syncs and replayed after, 0 drops them.
+    pub dag_live_node_buffer_size: usize,
+    // Recently committed payloads excluded from new DAG nodes, 0 disables it.
+    pub dag_recent_commits_dedup_size: usize,
+    // How long to wait for the payload of an authored DAG node, and the limits of the payload.
+    pub dag_payload_pull_timeout_ms: u64,
+    pub dag_payload_max_txns: u64,
+    pub dag_payload_max_bytes: u64,
+    // Checkpoints the signatures collected by the broadcast of our DAG node every this many new
+    // signatures, to resume it after a restart. Not checkpointed if not set.
+    pub dag_broadcast_checkpoint_interval: Option<usize>,
+    // Rounds the DAG can be ahead of the highest committed anchor before no new round is
+    // entered, unbounded if not set.
+    pub dag_max_commit_lag_rounds: Option<u64>,
+    // Re-broadcasts the pending DAG node whenever the current round hasn't advanced for this
+    // long. Disabled if not set.
+    pub dag_round_timeout_ms: Option<u64>,
+    // Buffers the payloads of up to this many added DAG nodes to prefetch their data in one
+    // batch. Each payload is prefetched right away if not set.
+    pub dag_payload_prefetch_batch_size: Option<usize>,
+    // Alerts once this many consecutive broadcasts of our DAG nodes were replaced before
+    // collecting a signature quorum. Disabled if not set.
+    pub dag_broadcast_failure_alert_threshold: Option<u64>,
+}
+
+/// The storage backend of the DAG consensus.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DagStorageBackend {
+    /// Keep the DAG in memory only, it is lost on restart. Meant for ephemeral test networks.
+    InMemory,
+    /// Persist the DAG in the consensus db.
+    #[default]
+    OnDisk,
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
@@ -124,6 +179,7 @@ impl Default for ConsensusConfig {
             wait_for_full_blocks_above_pending_blocks: 100,
             // Max is 1, so 1.1 disables it.
             wait_for_full_blocks_above_recent_fill_threshold: 1.1,
+            max_concurrent_payload_pulls: None,
             intra_consensus_channel_buffer_size: 10,
             quorum_store: QuorumStoreConfig::default(),
 
@@ -205,6 +261,24 @@ impl Default for ConsensusConfig {
                     backoff_proposal_delay_ms: 300,
                 },
             ],
+            dag_storage_backend: DagStorageBackend::default(),
+            dag_fetch_fanout: 1,
+            dag_rb_backoff_base_ms: 2,
+            dag_rb_backoff_factor: 50,
+            dag_rb_backoff_max_delay_ms: None,
+            dag_rb_rpc_timeout_ms: None,
+            dag_rb_max_concurrent_sends: None,
+            dag_startup_verification_threads: None,
+            dag_live_node_buffer_size: 100,
+            dag_recent_commits_dedup_size: 100,
+            dag_payload_pull_timeout_ms: 1000,
+            dag_payload_max_txns: 1000,
+            dag_payload_max_bytes: 10 * 1024 * 1024,
+            dag_broadcast_checkpoint_interval: None,
+            dag_max_commit_lag_rounds: None,
+            dag_round_timeout_ms: None,
+            dag_payload_prefetch_batch_size: None,
+            dag_broadcast_failure_alert_threshold: None,
         }
     }
 }
@@ -347,6 +421,72 @@ impl ConsensusConfig {
         }
         Ok(())
     }
+
+    fn sanitize_positive_limits(
+        sanitizer_name: &str,
+        config: &ConsensusConfig,
+    ) -> Result<(), Error> {
+        let must_be_positive = [
+            (
+                config.max_concurrent_payload_pulls.unwrap_or(1) as u64,
+                "max_concurrent_payload_pulls",
+            ),
+            (config.dag_fetch_fanout as u64, "dag_fetch_fanout"),
+            (config.dag_rb_backoff_base_ms, "dag_rb_backoff_base_ms"),
+            (config.dag_rb_backoff_factor, "dag_rb_backoff_factor"),
+            (
+                config.dag_rb_backoff_max_delay_ms.unwrap_or(1),
+                "dag_rb_backoff_max_delay_ms",
+            ),
+            (
+                config.dag_rb_rpc_timeout_ms.unwrap_or(1),
+                "dag_rb_rpc_timeout_ms",
+            ),
+            (
+                config.dag_rb_max_concurrent_sends.unwrap_or(1) as u64,
+                "dag_rb_max_concurrent_sends",
+            ),
+            (
+                config.dag_startup_verification_threads.unwrap_or(1) as u64,
+                "dag_startup_verification_threads",
+            ),
+            (
+                config.dag_payload_pull_timeout_ms,
+                "dag_payload_pull_timeout_ms",
+            ),
+            (config.dag_payload_max_txns, "dag_payload_max_txns"),
+            (config.dag_payload_max_bytes, "dag_payload_max_bytes"),
+            (
+                config.dag_broadcast_checkpoint_interval.unwrap_or(1) as u64,
+                "dag_broadcast_checkpoint_interval",
+            ),
+            (
+                config.dag_max_commit_lag_rounds.unwrap_or(1),
+                "dag_max_commit_lag_rounds",
+            ),
+            (
+                config.dag_round_timeout_ms.unwrap_or(1),
+                "dag_round_timeout_ms",
+            ),
+            (
+                config.dag_payload_prefetch_batch_size.unwrap_or(1) as u64,
+                "dag_payload_prefetch_batch_size",
+            ),
+            (
+                config.dag_broadcast_failure_alert_threshold.unwrap_or(1),
+                "dag_broadcast_failure_alert_threshold",
+            ),
+        ];
+        for (value, label) in &must_be_positive {
+            if *value == 0 {
+                return Err(Error::ConfigSanitizerFailed(
+                    sanitizer_name.to_owned(),
+                    format!("Failed {}: must be positive", label),
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl ConfigSanitizer for ConsensusConfig {
@@ -373,6 +513,8 @@ impl ConfigSanitizer for ConsensusConfig {
         Self::sanitize_send_recv_block_limits(&sanitizer_name, &node_config.consensus)?;
         // Quorum store batches must be <= consensus blocks
         Self::sanitize_batch_block_limits(&sanitizer_name, &node_config.consensus)?;
+        // Limits for which 0 is meaningless must be positive
+        Self::sanitize_positive_limits(&sanitizer_name, &node_config.consensus)?;
 
         Ok(())
     }
@@ -400,4 +542,28 @@ mod test {
 
         serde_yaml::from_str::<ConsensusConfig>(&s).unwrap();
     }
+
+    #[test]
+    fn test_sanitize_zero_limits() {
+        for set_zero in [
+            |config: &mut ConsensusConfig| config.max_concurrent_payload_pulls = Some(0),
+            |config: &mut ConsensusConfig| config.dag_fetch_fanout = 0,
+            |config: &mut ConsensusConfig| config.dag_rb_rpc_timeout_ms = Some(0),
+            |config: &mut ConsensusConfig| config.dag_payload_pull_timeout_ms = 0,
+            |config: &mut ConsensusConfig| config.dag_payload_max_txns = 0,
+            |config: &mut ConsensusConfig| config.dag_payload_max_bytes = 0,
+            |config: &mut ConsensusConfig| config.dag_broadcast_checkpoint_interval = Some(0),
+            |config: &mut ConsensusConfig| config.dag_max_commit_lag_rounds = Some(0),
+            |config: &mut ConsensusConfig| config.dag_round_timeout_ms = Some(0),
+            |config: &mut ConsensusConfig| config.dag_payload_prefetch_batch_size = Some(0),
+            |config: &mut ConsensusConfig| config.dag_broadcast_failure_alert_threshold = Some(0),
+        ] {
+            let mut config = ConsensusConfig::default();
+            set_zero(&mut config);
+
+            let error = ConsensusConfig::sanitize_positive_limits("test", &config).unwrap_err();
+            assert!(matches!(error, Error::ConfigSanitizerFailed(_, _)));
+        }
+        ConsensusConfig::sanitize_positive_limits("test", &ConsensusConfig::default()).unwrap();
+    }
 }
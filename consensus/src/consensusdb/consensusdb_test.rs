@@ -3,7 +3,10 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::*;
-use crate::dag::{CertifiedNode, Extensions, Node, Vote};
+use crate::dag::{
+    AnchorCommitProof, BroadcastProgress, CertifiedNode, CommitLogEntry, DagSyncCursor, Extensions,
+    Node, NodeCertificate, Vote,
+};
 use aptos_consensus_types::{
     block::block_test_utils::certificate_for_genesis,
     common::{Author, Payload},
@@ -113,4 +116,33 @@ fn test_dag() {
 
     let vote = Vote::new(node.metadata().clone(), Signature::dummy_signature());
     test_dag_type::<DagVoteSchema, <DagVoteSchema as Schema>::Key>(node.id(), vote, &db);
+
+    let cursor = DagSyncCursor::new(node.id(), 1, vec![node.metadata().clone()]);
+    test_dag_type::<DagSyncCursorSchema, <DagSyncCursorSchema as Schema>::Key>((), cursor, &db);
+
+    let proof = AnchorCommitProof::new(
+        NodeCertificate::new(node.metadata().clone(), AggregateSignature::empty()),
+        vec![],
+    );
+    let entry = CommitLogEntry::Full(proof);
+    test_dag_type::<DagCommitLogSchema, <DagCommitLogSchema as Schema>::Key>(
+        (1, 1),
+        entry.clone(),
+        &db,
+    );
+    test_dag_type::<DagCommitLogSchema, <DagCommitLogSchema as Schema>::Key>(
+        (1, 2),
+        entry.compact(),
+        &db,
+    );
+
+    let progress = BroadcastProgress::new(
+        node.metadata().clone(),
+        [(*node.author(), Signature::dummy_signature())].into(),
+    );
+    test_dag_type::<DagBroadcastProgressSchema, <DagBroadcastProgressSchema as Schema>::Key>(
+        (),
+        progress,
+        &db,
+    );
 }
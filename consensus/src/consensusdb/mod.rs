@@ -16,13 +16,16 @@ use aptos_schemadb::{
 };
 pub use schema::{
     block::BlockSchema,
-    dag::{CertifiedNodeSchema, DagVoteSchema, NodeSchema},
+    dag::{
+        CertifiedNodeSchema, DagBroadcastProgressSchema, DagCommitLogSchema, DagSyncCursorSchema,
+        DagVoteSchema, NodeSchema,
+    },
     quorum_certificate::QCSchema,
 };
 use schema::{
     single_entry::{SingleEntryKey, SingleEntrySchema},
-    BLOCK_CF_NAME, CERTIFIED_NODE_CF_NAME, DAG_VOTE_CF_NAME, NODE_CF_NAME, QC_CF_NAME,
-    SINGLE_ENTRY_CF_NAME,
+    BLOCK_CF_NAME, CERTIFIED_NODE_CF_NAME, DAG_BROADCAST_PROGRESS_CF_NAME, DAG_COMMIT_LOG_CF_NAME,
+    DAG_SYNC_CURSOR_CF_NAME, DAG_VOTE_CF_NAME, NODE_CF_NAME, QC_CF_NAME, SINGLE_ENTRY_CF_NAME,
 };
 use std::{iter::Iterator, path::Path, time::Instant};
 
@@ -59,6 +62,9 @@ impl ConsensusDB {
             NODE_CF_NAME,
             CERTIFIED_NODE_CF_NAME,
             DAG_VOTE_CF_NAME,
+            DAG_SYNC_CURSOR_CF_NAME,
+            DAG_COMMIT_LOG_CF_NAME,
+            DAG_BROADCAST_PROGRESS_CF_NAME,
             "ordered_anchor_id", // deprecated CF
         ];
 
@@ -187,6 +193,22 @@ impl ConsensusDB {
         Ok(())
     }
 
+    /// Flushes the memtables of the DAG column families, making the writes so far durable without
+    /// relying on a replay of the write ahead log.
+    pub fn flush_dag(&self) -> Result<(), DbError> {
+        for cf_name in [
+            NODE_CF_NAME,
+            CERTIFIED_NODE_CF_NAME,
+            DAG_VOTE_CF_NAME,
+            DAG_SYNC_CURSOR_CF_NAME,
+            DAG_COMMIT_LOG_CF_NAME,
+            DAG_BROADCAST_PROGRESS_CF_NAME,
+        ] {
+            self.db.flush_cf(cf_name)?;
+        }
+        Ok(())
+    }
+
     pub fn put<S: Schema>(&self, key: &S::Key, value: &S::Value) -> Result<(), DbError> {
         let batch = SchemaBatch::new();
         batch.put::<S>(key, value)?;
@@ -194,6 +216,15 @@ impl ConsensusDB {
         Ok(())
     }
 
+    /// Writes all the entries in a single batch.
+    pub fn put_all<S: Schema>(&self, entries: &[(S::Key, &S::Value)]) -> Result<(), DbError> {
+        let batch = SchemaBatch::new();
+        entries
+            .iter()
+            .try_for_each(|(key, value)| batch.put::<S>(key, value))?;
+        self.commit(batch)
+    }
+
     pub fn delete<S: Schema>(&self, keys: Vec<S::Key>) -> Result<(), DbError> {
         let batch = SchemaBatch::new();
         keys.iter().try_for_each(|key| batch.delete::<S>(key))?;
@@ -7,10 +7,11 @@
 
 use crate::{
     consensusdb::schema::ensure_slice_len_eq,
-    dag::{CertifiedNode, Node, NodeId, Vote},
+    dag::{BroadcastProgress, CertifiedNode, CommitLogEntry, DagSyncCursor, Node, NodeId, Vote},
     define_schema,
 };
 use anyhow::Result;
+use aptos_consensus_types::common::Round;
 use aptos_crypto::HashValue;
 use aptos_schemadb::{
     schema::{KeyCodec, ValueCodec},
@@ -95,3 +96,100 @@ impl ValueCodec<CertifiedNodeSchema> for CertifiedNode {
         Ok(bcs::from_bytes(data)?)
     }
 }
+
+pub const DAG_SYNC_CURSOR_CF_NAME: ColumnFamilyName = "dag_sync_cursor";
+
+define_schema!(
+    DagSyncCursorSchema,
+    (),
+    DagSyncCursor,
+    DAG_SYNC_CURSOR_CF_NAME
+);
+
+impl KeyCodec<DagSyncCursorSchema> for () {
+    fn encode_key(&self) -> Result<Vec<u8>> {
+        Ok(vec![])
+    }
+
+    fn decode_key(data: &[u8]) -> Result<Self> {
+        ensure_slice_len_eq(data, size_of::<Self>())?;
+        Ok(())
+    }
+}
+
+impl ValueCodec<DagSyncCursorSchema> for DagSyncCursor {
+    fn encode_value(&self) -> Result<Vec<u8>> {
+        Ok(bcs::to_bytes(&self)?)
+    }
+
+    fn decode_value(data: &[u8]) -> Result<Self> {
+        Ok(bcs::from_bytes(data)?)
+    }
+}
+
+pub const DAG_BROADCAST_PROGRESS_CF_NAME: ColumnFamilyName = "dag_broadcast_progress";
+
+define_schema!(
+    DagBroadcastProgressSchema,
+    (),
+    BroadcastProgress,
+    DAG_BROADCAST_PROGRESS_CF_NAME
+);
+
+impl KeyCodec<DagBroadcastProgressSchema> for () {
+    fn encode_key(&self) -> Result<Vec<u8>> {
+        Ok(vec![])
+    }
+
+    fn decode_key(data: &[u8]) -> Result<Self> {
+        ensure_slice_len_eq(data, size_of::<Self>())?;
+        Ok(())
+    }
+}
+
+impl ValueCodec<DagBroadcastProgressSchema> for BroadcastProgress {
+    fn encode_value(&self) -> Result<Vec<u8>> {
+        Ok(bcs::to_bytes(&self)?)
+    }
+
+    fn decode_value(data: &[u8]) -> Result<Self> {
+        Ok(bcs::from_bytes(data)?)
+    }
+}
+
+pub const DAG_COMMIT_LOG_CF_NAME: ColumnFamilyName = "dag_commit_log";
+
+define_schema!(
+    DagCommitLogSchema,
+    (u64, Round),
+    CommitLogEntry,
+    DAG_COMMIT_LOG_CF_NAME
+);
+
+/// Keyed by (epoch, round) in big endian, so that iterating the log follows the commit order.
+impl KeyCodec<DagCommitLogSchema> for (u64, Round) {
+    fn encode_key(&self) -> Result<Vec<u8>> {
+        let mut encoded = self.0.to_be_bytes().to_vec();
+        encoded.extend_from_slice(&self.1.to_be_bytes());
+        Ok(encoded)
+    }
+
+    fn decode_key(data: &[u8]) -> Result<Self> {
+        ensure_slice_len_eq(data, size_of::<Self>())?;
+        let (epoch, round) = data.split_at(size_of::<u64>());
+        Ok((
+            u64::from_be_bytes(epoch.try_into()?),
+            Round::from_be_bytes(round.try_into()?),
+        ))
+    }
+}
+
+impl ValueCodec<DagCommitLogSchema> for CommitLogEntry {
+    fn encode_value(&self) -> Result<Vec<u8>> {
+        Ok(bcs::to_bytes(&self)?)
+    }
+
+    fn decode_value(data: &[u8]) -> Result<Self> {
+        Ok(bcs::from_bytes(data)?)
+    }
+}
@@ -2,25 +2,32 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    consensusdb::{CertifiedNodeSchema, ConsensusDB, DagVoteSchema, NodeSchema},
+    consensusdb::{
+        CertifiedNodeSchema, ConsensusDB, DagBroadcastProgressSchema, DagCommitLogSchema,
+        DagSyncCursorSchema, DagVoteSchema, NodeSchema,
+    },
     counters::update_counters_for_committed_blocks,
     dag::{
+        counters,
+        dag_state_sync::DagSyncCursor,
         storage::{CommitEvent, DAGStorage},
-        CertifiedNode, Node, NodeId, Vote,
+        AnchorCommitProof, BroadcastProgress, CertifiedNode, CommitLogEntry, Node, NodeId, Vote,
     },
     experimental::buffer_manager::OrderedBlocks,
 };
 use anyhow::{anyhow, bail};
 use aptos_bitvec::BitVec;
+use aptos_config::config::DagStorageBackend;
 use aptos_consensus_types::{
     block::Block,
     common::{Author, Payload, Round},
+    epoch_retrieval::EpochRetrievalRequest,
     executed_block::ExecutedBlock,
     quorum_cert::QuorumCert,
 };
 use aptos_crypto::HashValue;
 use aptos_executor_types::StateComputeResult;
-use aptos_infallible::RwLock;
+use aptos_infallible::{Mutex, RwLock};
 use aptos_logger::error;
 use aptos_storage_interface::{DbReader, Order};
 use aptos_types::{
@@ -33,7 +40,10 @@ use aptos_types::{
 };
 use async_trait::async_trait;
 use futures_channel::mpsc::UnboundedSender;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::Arc,
+};
 
 pub trait OrderedNotifier: Send + Sync {
     fn send_ordered_nodes(
@@ -48,6 +58,9 @@ pub trait ProofNotifier: Send + Sync {
     async fn send_epoch_change(&self, proof: EpochChangeProof);
 
     async fn send_commit_proof(&self, ledger_info: LedgerInfoWithSignatures);
+
+    /// Asks `peer` for the epoch change proofs, to catch up with an epoch seen in its messages.
+    async fn send_epoch_retrieval(&self, peer: Author, request: EpochRetrievalRequest);
 }
 
 pub(crate) fn compute_initial_block_and_ledger_info(
@@ -81,12 +94,44 @@ pub(crate) fn compute_initial_block_and_ledger_info(
     }
 }
 
+/// Payloads of the most recently committed blocks, so that their transactions are not pulled
+/// again once they fall out of the DAG window.
+pub(crate) struct RecentCommits {
+    max_payloads: usize,
+    payloads: VecDeque<Payload>,
+}
+
+impl RecentCommits {
+    pub(crate) fn new(max_payloads: usize) -> Self {
+        Self {
+            max_payloads,
+            payloads: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, payload: Payload) {
+        if self.max_payloads == 0 || payload.is_empty() {
+            return;
+        }
+        if self.payloads.len() == self.max_payloads {
+            self.payloads.pop_front();
+        }
+        self.payloads.push_back(payload);
+    }
+
+    pub(crate) fn payloads(&self) -> impl Iterator<Item = &Payload> {
+        self.payloads.iter()
+    }
+}
+
 pub(super) struct OrderedNotifierAdapter {
     executor_channel: UnboundedSender<OrderedBlocks>,
     storage: Arc<dyn DAGStorage>,
     parent_block_info: Arc<RwLock<BlockInfo>>,
     epoch_state: Arc<EpochState>,
     ledger_info_provider: Arc<RwLock<LedgerInfoProvider>>,
+    recent_commits: Arc<Mutex<RecentCommits>>,
+    shuffle_payloads: bool,
 }
 
 impl OrderedNotifierAdapter {
@@ -96,6 +141,7 @@ impl OrderedNotifierAdapter {
         epoch_state: Arc<EpochState>,
         parent_block_info: BlockInfo,
         ledger_info_provider: Arc<RwLock<LedgerInfoProvider>>,
+        recent_commits: Arc<Mutex<RecentCommits>>,
     ) -> Self {
         Self {
             executor_channel,
@@ -103,8 +149,16 @@ impl OrderedNotifierAdapter {
             parent_block_info: Arc::new(RwLock::new(parent_block_info)),
             epoch_state,
             ledger_info_provider,
+            recent_commits,
+            shuffle_payloads: false,
         }
     }
+
+    /// Executes the payload of every ordered node in the order of `Node::shuffled_payload`.
+    pub(super) fn with_payload_shuffle(mut self, shuffle_payloads: bool) -> Self {
+        self.shuffle_payloads = shuffle_payloads;
+        self
+    }
 }
 
 impl OrderedNotifier for OrderedNotifierAdapter {
@@ -121,7 +175,11 @@ impl OrderedNotifier for OrderedNotifierAdapter {
         let mut payload = Payload::empty(!anchor.payload().is_direct());
         let mut node_digests = vec![];
         for node in &ordered_nodes {
-            payload.extend(node.payload().clone());
+            if self.shuffle_payloads {
+                payload.extend(node.shuffled_payload());
+            } else {
+                payload.extend(node.payload().clone());
+            }
             node_digests.push(node.digest());
         }
         let parent_block_id = self.parent_block_info.read().id();
@@ -155,6 +213,7 @@ impl OrderedNotifier for OrderedNotifierAdapter {
         let block_info = block.block_info();
         let storage = self.storage.clone();
         let ledger_info_provider = self.ledger_info_provider.clone();
+        let recent_commits = self.recent_commits.clone();
         *self.parent_block_info.write() = block_info.clone();
         Ok(self.executor_channel.unbounded_send(OrderedBlocks {
             ordered_blocks: vec![block],
@@ -165,11 +224,15 @@ impl OrderedNotifier for OrderedNotifierAdapter {
             callback: Box::new(
                 move |committed_blocks: &[Arc<ExecutedBlock>],
                       commit_decision: LedgerInfoWithSignatures| {
+                    counters::update_finality_lag_on_commit(commit_decision.commit_info().round());
                     ledger_info_provider
                         .write()
                         .notify_commit_proof(commit_decision);
                     update_counters_for_committed_blocks(committed_blocks);
                     for executed_block in committed_blocks {
+                        if let Some(payload) = executed_block.block().payload() {
+                            recent_commits.lock().insert(payload.clone());
+                        }
                         if let Some(node_digests) = executed_block.block().block_data().dag_nodes()
                         {
                             if let Err(e) = storage.delete_certified_nodes(node_digests.clone()) {
@@ -302,6 +365,15 @@ impl DAGStorage for StorageAdapter {
             .put::<CertifiedNodeSchema>(&node.digest(), node)?)
     }
 
+    fn save_certified_nodes(&self, nodes: &[CertifiedNode]) -> anyhow::Result<()> {
+        let entries: Vec<_> = nodes.iter().map(|node| (node.digest(), node)).collect();
+        Ok(self.consensus_db.put_all::<CertifiedNodeSchema>(&entries)?)
+    }
+
+    fn get_certified_node(&self, digest: &HashValue) -> anyhow::Result<Option<CertifiedNode>> {
+        Ok(self.consensus_db.get::<CertifiedNodeSchema>(digest)?)
+    }
+
     fn get_certified_nodes(&self) -> anyhow::Result<Vec<(HashValue, CertifiedNode)>> {
         Ok(self.consensus_db.get_all::<CertifiedNodeSchema>()?)
     }
@@ -336,6 +408,230 @@ impl DAGStorage for StorageAdapter {
         // TODO: use callback from notifier to cache the latest ledger info
         self.aptos_db.get_latest_ledger_info()
     }
+
+    fn save_sync_cursor(&self, cursor: &DagSyncCursor) -> anyhow::Result<()> {
+        Ok(self.consensus_db.put::<DagSyncCursorSchema>(&(), cursor)?)
+    }
+
+    fn get_sync_cursor(&self) -> anyhow::Result<Option<DagSyncCursor>> {
+        Ok(self.consensus_db.get::<DagSyncCursorSchema>(&())?)
+    }
+
+    fn delete_sync_cursor(&self) -> anyhow::Result<()> {
+        Ok(self.consensus_db.delete::<DagSyncCursorSchema>(vec![()])?)
+    }
+
+    fn append_commit_log(&self, proof: &AnchorCommitProof) -> anyhow::Result<()> {
+        let metadata = proof.anchor().metadata();
+        Ok(self.consensus_db.put::<DagCommitLogSchema>(
+            &(metadata.epoch(), metadata.round()),
+            &CommitLogEntry::Full(proof.clone()),
+        )?)
+    }
+
+    fn get_commit_log(&self) -> anyhow::Result<Vec<CommitLogEntry>> {
+        Ok(self
+            .consensus_db
+            .get_all::<DagCommitLogSchema>()?
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .collect())
+    }
+
+    fn compact_commit_log(&self, num_retained: usize) -> anyhow::Result<usize> {
+        let entries = self.consensus_db.get_all::<DagCommitLogSchema>()?;
+        let num_expired = entries.len().saturating_sub(num_retained);
+        let mut num_compacted = 0;
+        for (key, entry) in entries.into_iter().take(num_expired) {
+            if entry.proof().is_some() {
+                self.consensus_db
+                    .put::<DagCommitLogSchema>(&key, &entry.compact())?;
+                num_compacted += 1;
+            }
+        }
+        Ok(num_compacted)
+    }
+
+    fn save_broadcast_progress(&self, progress: &BroadcastProgress) -> anyhow::Result<()> {
+        Ok(self
+            .consensus_db
+            .put::<DagBroadcastProgressSchema>(&(), progress)?)
+    }
+
+    fn get_broadcast_progress(&self) -> anyhow::Result<Option<BroadcastProgress>> {
+        Ok(self.consensus_db.get::<DagBroadcastProgressSchema>(&())?)
+    }
+
+    fn flush(&self) -> anyhow::Result<()> {
+        Ok(self.consensus_db.flush_dag()?)
+    }
+}
+
+/// Keeps the DAG in memory, it is lost on restart. The ledger is still read from the db.
+pub struct InMemoryStorage {
+    ledger: StorageAdapter,
+    pending_node: Mutex<Option<Node>>,
+    votes: Mutex<HashMap<NodeId, Vote>>,
+    certified_nodes: Mutex<HashMap<HashValue, CertifiedNode>>,
+    sync_cursor: Mutex<Option<DagSyncCursor>>,
+    commit_log: Mutex<BTreeMap<(u64, Round), CommitLogEntry>>,
+    broadcast_progress: Mutex<Option<BroadcastProgress>>,
+}
+
+impl InMemoryStorage {
+    pub fn new(
+        epoch: u64,
+        epoch_to_validators: HashMap<u64, Vec<Author>>,
+        consensus_db: Arc<ConsensusDB>,
+        aptos_db: Arc<dyn DbReader>,
+    ) -> Self {
+        Self {
+            ledger: StorageAdapter::new(epoch, epoch_to_validators, consensus_db, aptos_db),
+            pending_node: Mutex::new(None),
+            votes: Mutex::new(HashMap::new()),
+            certified_nodes: Mutex::new(HashMap::new()),
+            sync_cursor: Mutex::new(None),
+            commit_log: Mutex::new(BTreeMap::new()),
+            broadcast_progress: Mutex::new(None),
+        }
+    }
+}
+
+impl DAGStorage for InMemoryStorage {
+    fn save_pending_node(&self, node: &Node) -> anyhow::Result<()> {
+        *self.pending_node.lock() = Some(node.clone());
+        Ok(())
+    }
+
+    fn get_pending_node(&self) -> anyhow::Result<Option<Node>> {
+        Ok(self.pending_node.lock().clone())
+    }
+
+    fn delete_pending_node(&self) -> anyhow::Result<()> {
+        self.pending_node.lock().take();
+        Ok(())
+    }
+
+    fn save_vote(&self, node_id: &NodeId, vote: &Vote) -> anyhow::Result<()> {
+        self.votes.lock().insert(node_id.clone(), vote.clone());
+        Ok(())
+    }
+
+    fn get_votes(&self) -> anyhow::Result<Vec<(NodeId, Vote)>> {
+        Ok(self.votes.lock().clone().into_iter().collect())
+    }
+
+    fn delete_votes(&self, node_ids: Vec<NodeId>) -> anyhow::Result<()> {
+        let mut votes = self.votes.lock();
+        for node_id in node_ids {
+            votes.remove(&node_id);
+        }
+        Ok(())
+    }
+
+    fn save_certified_node(&self, node: &CertifiedNode) -> anyhow::Result<()> {
+        self.certified_nodes
+            .lock()
+            .insert(node.digest(), node.clone());
+        Ok(())
+    }
+
+    fn get_certified_node(&self, digest: &HashValue) -> anyhow::Result<Option<CertifiedNode>> {
+        Ok(self.certified_nodes.lock().get(digest).cloned())
+    }
+
+    fn get_certified_nodes(&self) -> anyhow::Result<Vec<(HashValue, CertifiedNode)>> {
+        Ok(self.certified_nodes.lock().clone().into_iter().collect())
+    }
+
+    fn delete_certified_nodes(&self, digests: Vec<HashValue>) -> anyhow::Result<()> {
+        let mut certified_nodes = self.certified_nodes.lock();
+        for digest in digests {
+            certified_nodes.remove(&digest);
+        }
+        Ok(())
+    }
+
+    fn get_latest_k_committed_events(&self, k: u64) -> anyhow::Result<Vec<CommitEvent>> {
+        self.ledger.get_latest_k_committed_events(k)
+    }
+
+    fn get_latest_ledger_info(&self) -> anyhow::Result<LedgerInfoWithSignatures> {
+        self.ledger.get_latest_ledger_info()
+    }
+
+    fn save_sync_cursor(&self, cursor: &DagSyncCursor) -> anyhow::Result<()> {
+        *self.sync_cursor.lock() = Some(cursor.clone());
+        Ok(())
+    }
+
+    fn get_sync_cursor(&self) -> anyhow::Result<Option<DagSyncCursor>> {
+        Ok(self.sync_cursor.lock().clone())
+    }
+
+    fn delete_sync_cursor(&self) -> anyhow::Result<()> {
+        self.sync_cursor.lock().take();
+        Ok(())
+    }
+
+    fn append_commit_log(&self, proof: &AnchorCommitProof) -> anyhow::Result<()> {
+        let metadata = proof.anchor().metadata();
+        self.commit_log.lock().insert(
+            (metadata.epoch(), metadata.round()),
+            CommitLogEntry::Full(proof.clone()),
+        );
+        Ok(())
+    }
+
+    fn get_commit_log(&self) -> anyhow::Result<Vec<CommitLogEntry>> {
+        Ok(self.commit_log.lock().values().cloned().collect())
+    }
+
+    fn compact_commit_log(&self, num_retained: usize) -> anyhow::Result<usize> {
+        let mut commit_log = self.commit_log.lock();
+        let num_expired = commit_log.len().saturating_sub(num_retained);
+        let mut num_compacted = 0;
+        for entry in commit_log.values_mut().take(num_expired) {
+            if entry.proof().is_some() {
+                *entry = entry.clone().compact();
+                num_compacted += 1;
+            }
+        }
+        Ok(num_compacted)
+    }
+
+    fn save_broadcast_progress(&self, progress: &BroadcastProgress) -> anyhow::Result<()> {
+        *self.broadcast_progress.lock() = Some(progress.clone());
+        Ok(())
+    }
+
+    fn get_broadcast_progress(&self) -> anyhow::Result<Option<BroadcastProgress>> {
+        Ok(self.broadcast_progress.lock().clone())
+    }
+}
+
+/// Builds the DAG storage of the configured backend.
+pub fn build_dag_storage(
+    backend: DagStorageBackend,
+    epoch: u64,
+    epoch_to_validators: HashMap<u64, Vec<Author>>,
+    consensus_db: Arc<ConsensusDB>,
+    aptos_db: Arc<dyn DbReader>,
+) -> Arc<dyn DAGStorage> {
+    match backend {
+        DagStorageBackend::InMemory => Arc::new(InMemoryStorage::new(
+            epoch,
+            epoch_to_validators,
+            consensus_db,
+            aptos_db,
+        )),
+        DagStorageBackend::OnDisk => Arc::new(StorageAdapter::new(
+            epoch,
+            epoch_to_validators,
+            consensus_db,
+            aptos_db,
+        )),
+    }
 }
 
 pub(crate) trait TLedgerInfoProvider: Send + Sync {
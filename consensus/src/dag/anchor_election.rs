@@ -1,13 +1,20 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::liveness::proposer_election::choose_index;
 use aptos_consensus_types::common::{Author, Round};
+use aptos_infallible::Mutex;
+use aptos_logger::warn;
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
 
 pub trait AnchorElection: Send + Sync {
     fn get_anchor(&self, round: Round) -> Author;
 
     fn update_reputation(
-        &mut self,
+        &self,
         round: Round,
         author: &Author,
         parents: Vec<Author>,
@@ -15,6 +22,43 @@ pub trait AnchorElection: Send + Sync {
     );
 }
 
+/// How the anchor of each round is elected.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AnchorElectionPolicy {
+    RoundRobin,
+    /// Weight the election by the reputation of the validators in the last `window_size`
+    /// committed anchors, at least `REPUTATION_EXCLUDED_ROUNDS` below the round. The window is
+    /// capped to the DAG window, as a restarted validator only recovers the history of the last
+    /// `reputation_history_len(dag_window)` committed anchors.
+    Reputation {
+        window_size: usize,
+    },
+}
+
+impl Default for AnchorElectionPolicy {
+    fn default() -> Self {
+        Self::RoundRobin
+    }
+}
+
+impl AnchorElectionPolicy {
+    pub fn build(&self, validators: Vec<Author>, dag_window: usize) -> Arc<dyn AnchorElection> {
+        match self {
+            Self::RoundRobin => Arc::new(RoundRobinAnchorElection::new(validators)),
+            Self::Reputation { window_size } => {
+                if *window_size > dag_window {
+                    warn!(
+                        "reputation window {} exceeds the dag window {}, capping it",
+                        window_size, dag_window
+                    );
+                }
+                let window_size = (*window_size).min(dag_window);
+                Arc::new(ReputationAnchorElection::new(validators, window_size))
+            },
+        }
+    }
+}
+
 pub struct RoundRobinAnchorElection {
     validators: Vec<Author>,
 }
@@ -31,7 +75,7 @@ impl AnchorElection for RoundRobinAnchorElection {
     }
 
     fn update_reputation(
-        &mut self,
+        &self,
         _round: Round,
         _author: &Author,
         _parents: Vec<Author>,
@@ -39,3 +83,107 @@ impl AnchorElection for RoundRobinAnchorElection {
     ) {
     }
 }
+
+/// Number of rounds below a round whose committed anchors are left out of its reputation. The
+/// anchors just below a round may still be ordered, indirectly, after its anchor is elected, so
+/// only the anchors committed well below it decide the election.
+pub(crate) const REPUTATION_EXCLUDED_ROUNDS: Round = 20;
+
+/// Number of the latest committed anchors a restarted validator replays into the anchor election,
+/// which covers the reputation window below the excluded rounds of the rounds still to be ordered.
+pub(crate) fn reputation_history_len(dag_window: usize) -> usize {
+    dag_window + REPUTATION_EXCLUDED_ROUNDS as usize
+}
+
+/// Weight of a validator that authored an ordered anchor, or one of its parents, within the window.
+const ACTIVE_WEIGHT: u128 = 1000;
+/// Weight of a validator with no ordered node nor failed anchor within the window.
+const INACTIVE_WEIGHT: u128 = 10;
+/// Weight of a validator that failed to get its anchor ordered within the window.
+const FAILED_WEIGHT: u128 = 1;
+
+/// Elects the anchors at random, weighted towards the validators whose certified nodes were ordered
+/// in the last `window_size` committed anchors, and away from the ones that failed to get their
+/// anchor ordered. The anchor of a round only depends on the anchors committed at least
+/// `REPUTATION_EXCLUDED_ROUNDS` below it, so it doesn't depend on when the anchors just below it
+/// are ordered, as long as the ordering lags less than `REPUTATION_EXCLUDED_ROUNDS` behind the
+/// rounds it elects anchors for. The history is in memory only, a restarted validator recovers it
+/// from the commit events in storage.
+pub struct ReputationAnchorElection {
+    validators: Vec<Author>,
+    window_size: usize,
+    /// (authors of the anchor and its parents, failed authors) by round of the committed anchors
+    history: Mutex<BTreeMap<Round, (HashSet<Author>, HashSet<Author>)>>,
+}
+
+impl ReputationAnchorElection {
+    pub fn new(validators: Vec<Author>, window_size: usize) -> Self {
+        Self {
+            validators,
+            window_size,
+            history: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn weights(&self, round: Round) -> Vec<u128> {
+        let history = self.history.lock();
+        let mut active_authors = HashSet::new();
+        let mut failed_authors = HashSet::new();
+        for (active, failed) in history
+            .range(..round.saturating_sub(REPUTATION_EXCLUDED_ROUNDS))
+            .rev()
+            .take(self.window_size)
+            .map(|(_, v)| v)
+        {
+            active_authors.extend(active);
+            failed_authors.extend(failed);
+        }
+        self.validators
+            .iter()
+            .map(|validator| {
+                if failed_authors.contains(validator) {
+                    FAILED_WEIGHT
+                } else if active_authors.contains(validator) {
+                    ACTIVE_WEIGHT
+                } else {
+                    INACTIVE_WEIGHT
+                }
+            })
+            .collect()
+    }
+}
+
+impl AnchorElection for ReputationAnchorElection {
+    fn get_anchor(&self, round: Round) -> Author {
+        let index = choose_index(self.weights(round), round.to_le_bytes().to_vec());
+        self.validators[index]
+    }
+
+    fn update_reputation(
+        &self,
+        round: Round,
+        author: &Author,
+        parents: Vec<Author>,
+        failed_authors: Vec<Author>,
+    ) {
+        let mut active_authors: HashSet<_> = parents.into_iter().collect();
+        active_authors.insert(*author);
+        let mut history = self.history.lock();
+        history.insert(
+            round,
+            (active_authors, failed_authors.into_iter().collect()),
+        );
+        // keep the window below the excluded rounds of the rounds still to be ordered, which are
+        // above the latest committed anchor
+        let lowest_retained_round = history
+            .range(..(round + 1).saturating_sub(REPUTATION_EXCLUDED_ROUNDS))
+            .rev()
+            .take(self.window_size)
+            .last()
+            .map(|(round, _)| *round);
+        if let Some(lowest_retained_round) = lowest_retained_round {
+            let retained = history.split_off(&lowest_retained_round);
+            *history = retained;
+        }
+    }
+}
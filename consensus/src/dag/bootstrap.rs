@@ -1,18 +1,20 @@
 // Copyright © Aptos Foundation
 
 use super::{
-    adapter::{OrderedNotifier, OrderedNotifierAdapter, TLedgerInfoProvider},
-    anchor_election::RoundRobinAnchorElection,
-    dag_driver::DagDriver,
+    adapter::{OrderedNotifier, OrderedNotifierAdapter, RecentCommits, TLedgerInfoProvider},
+    anchor_election::AnchorElectionPolicy,
+    dag_driver::{DagDriver, DagDriverConfig, DagPayloadConfig},
     dag_fetcher::{DagFetcher, DagFetcherService, FetchRequestHandler},
     dag_handler::NetworkHandler,
     dag_network::TDAGNetworkSender,
-    dag_state_sync::{DagStateSynchronizer, StateSyncTrigger, DAG_WINDOW},
-    dag_store::Dag,
+    dag_state_sync::{
+        DagStateSynchronizer, LiveNodeBuffer, LiveNodePolicy, StateSyncTrigger, DAG_WINDOW,
+    },
+    dag_store::{Dag, StartupVerification},
     order_rule::OrderRule,
     rb_handler::NodeBroadcastHandler,
     storage::DAGStorage,
-    types::DAGMessage,
+    types::{DAGMessage, TDAGMessage},
     ProofNotifier,
 };
 use crate::{
@@ -25,17 +27,20 @@ use crate::{
     payload_manager::PayloadManager,
     state_replication::{PayloadClient, StateComputer},
 };
+use anyhow::anyhow;
 use aptos_channels::{
     aptos_channel::{self, Receiver},
     message_queues::QueueStyle,
 };
 use aptos_consensus_types::common::{Author, Round};
-use aptos_infallible::RwLock;
-use aptos_logger::{debug, error};
+use aptos_infallible::{Mutex, RwLock};
+use aptos_logger::{debug, error, warn};
+use aptos_network::protocols::network::RpcError;
 use aptos_reliable_broadcast::{RBNetworkSender, ReliableBroadcast};
 use aptos_types::{
     epoch_state::EpochState, ledger_info::LedgerInfo, validator_signer::ValidatorSigner,
 };
+use futures::StreamExt;
 use futures_channel::{
     mpsc::{UnboundedReceiver, UnboundedSender},
     oneshot,
@@ -44,6 +49,69 @@ use std::{sync::Arc, time::Duration};
 use tokio::{select, task::JoinHandle};
 use tokio_retry::strategy::ExponentialBackoff;
 
+// Reliable broadcast backoff cap and rpc timeout for validator sets of up to
+// RB_VALIDATORS_PER_SCALING_STEP validators, scaled up by one step for every that many more.
+const RB_BASE_MAX_BACKOFF: Duration = Duration::from_secs(3);
+const RB_BASE_RPC_TIMEOUT: Duration = Duration::from_millis(500);
+const RB_VALIDATORS_PER_SCALING_STEP: usize = 100;
+
+/// Returns the reliable broadcast backoff cap and rpc timeout for the given number of validators.
+/// Both grow with the validator set, as collecting signatures from more validators takes longer.
+pub(super) fn rb_max_backoff_and_timeout(num_validators: usize) -> (Duration, Duration) {
+    let scale = 1 + (num_validators / RB_VALIDATORS_PER_SCALING_STEP) as u32;
+    (RB_BASE_MAX_BACKOFF * scale, RB_BASE_RPC_TIMEOUT * scale)
+}
+
+/// The backoff between the reliable broadcast retries to a peer. The first delay is
+/// `backoff_base_ms * backoff_factor` milliseconds, and every retry multiplies it by
+/// `backoff_base_ms`.
+#[derive(Clone, Debug)]
+pub struct ReliableBroadcastConfig {
+    pub backoff_base_ms: u64,
+    pub backoff_factor: u64,
+    /// Cap on the delay between retries, scaled with the validator set if not set, see
+    /// `rb_max_backoff_and_timeout`.
+    pub backoff_max_delay: Option<Duration>,
+    /// Timeout of each broadcast rpc, scaled with the validator set if not set, see
+    /// `rb_max_backoff_and_timeout`.
+    pub rpc_timeout: Option<Duration>,
+    /// Cap on the RPCs in flight across all broadcasts, sending the messages with the highest
+    /// priority first beyond it. Unbounded if not set, 0 is treated as unbounded.
+    pub max_concurrent_sends: Option<usize>,
+}
+
+impl Default for ReliableBroadcastConfig {
+    /// Starts at 100ms and doubles every retry.
+    fn default() -> Self {
+        Self {
+            backoff_base_ms: 2,
+            backoff_factor: 50,
+            backoff_max_delay: None,
+            rpc_timeout: None,
+            max_concurrent_sends: None,
+        }
+    }
+}
+
+impl ReliableBroadcastConfig {
+    pub fn backoff_policy(&self, num_validators: usize) -> ExponentialBackoff {
+        let max_delay = self
+            .backoff_max_delay
+            .unwrap_or_else(|| rb_max_backoff_and_timeout(num_validators).0);
+        ExponentialBackoff::from_millis(self.backoff_base_ms)
+            .factor(self.backoff_factor)
+            .max_delay(max_delay)
+    }
+
+    pub fn rpc_timeout(&self, num_validators: usize) -> Duration {
+        self.rpc_timeout
+            .unwrap_or_else(|| rb_max_backoff_and_timeout(num_validators).1)
+    }
+}
+
+/// Number of recently committed block payloads excluded from new nodes by default.
+const DEFAULT_RECENT_COMMITS_DEDUP_SIZE: usize = 100;
+
 pub struct DagBootstrapper {
     self_peer: Author,
     signer: Arc<ValidatorSigner>,
@@ -56,6 +124,15 @@ pub struct DagBootstrapper {
     payload_manager: Arc<PayloadManager>,
     payload_client: Arc<dyn PayloadClient>,
     state_computer: Arc<dyn StateComputer>,
+    live_node_policy: LiveNodePolicy,
+    recent_commits: Arc<Mutex<RecentCommits>>,
+    startup_verification: StartupVerification,
+    rb_config: ReliableBroadcastConfig,
+    anchor_election_policy: AnchorElectionPolicy,
+    dag_window: usize,
+    shuffle_payloads: bool,
+    fetch_fanout: u32,
+    driver_config: DagDriverConfig,
 }
 
 impl DagBootstrapper {
@@ -84,6 +161,122 @@ impl DagBootstrapper {
             payload_manager,
             payload_client,
             state_computer,
+            live_node_policy: LiveNodePolicy::default(),
+            recent_commits: Arc::new(Mutex::new(RecentCommits::new(
+                DEFAULT_RECENT_COMMITS_DEDUP_SIZE,
+            ))),
+            startup_verification: StartupVerification::default(),
+            rb_config: ReliableBroadcastConfig::default(),
+            anchor_election_policy: AnchorElectionPolicy::default(),
+            dag_window: DAG_WINDOW,
+            shuffle_payloads: false,
+            fetch_fanout: 1,
+            driver_config: DagDriverConfig::default(),
+        }
+    }
+
+    pub fn with_live_node_policy(mut self, live_node_policy: LiveNodePolicy) -> Self {
+        self.live_node_policy = live_node_policy;
+        self
+    }
+
+    /// Sets how many recently committed block payloads are excluded from new nodes, 0 disables it.
+    pub fn with_recent_commits_dedup_size(mut self, size: usize) -> Self {
+        self.recent_commits = Arc::new(Mutex::new(RecentCommits::new(size)));
+        self
+    }
+
+    /// Sets whether the certificates of the nodes recovered from storage are verified at startup.
+    pub fn with_startup_verification(mut self, startup_verification: StartupVerification) -> Self {
+        self.startup_verification = startup_verification;
+        self
+    }
+
+    pub fn with_rb_config(mut self, rb_config: ReliableBroadcastConfig) -> Self {
+        self.rb_config = rb_config;
+        self
+    }
+
+    pub fn with_anchor_election_policy(mut self, policy: AnchorElectionPolicy) -> Self {
+        self.anchor_election_policy = policy;
+        self
+    }
+
+    /// Sets the number of rounds below the highest committed anchor whose nodes are kept in the
+    /// DAG, trading memory for the depth of the causal history an anchor can order. It decides
+    /// which nodes are ordered, so it must come from the on-chain config of the epoch.
+    pub fn with_dag_window(mut self, dag_window: usize) -> Self {
+        self.dag_window = dag_window;
+        self
+    }
+
+    /// Sets whether the transactions of each ordered node are shuffled, seeded by the node digest,
+    /// to make their order harder to exploit for front-running. All the validators must agree on
+    /// it, or they execute different blocks, so it must come from the on-chain config of the epoch.
+    pub fn with_payload_shuffle(mut self, shuffle_payloads: bool) -> Self {
+        self.shuffle_payloads = shuffle_payloads;
+        self
+    }
+
+    /// Sets the number of signers of a node its missing parents are fetched from at once. The
+    /// first valid response is used and the other requests are cancelled.
+    pub fn with_fetch_fanout(mut self, fetch_fanout: u32) -> Self {
+        self.fetch_fanout = fetch_fanout;
+        self
+    }
+
+    /// Sets the limits of the payload pulled into each authored node.
+    pub fn with_payload_config(mut self, payload_config: DagPayloadConfig) -> Self {
+        self.driver_config.payload = payload_config;
+        self
+    }
+
+    /// Sets how many new signatures the broadcast of our node collects between checkpoints, so
+    /// that it resumes with them after a restart. Not checkpointed if not set.
+    pub fn with_broadcast_checkpoint_interval(mut self, interval: Option<usize>) -> Self {
+        self.driver_config.broadcast_checkpoint_interval = interval;
+        self
+    }
+
+    /// Sets how many rounds the DAG can be ahead of the highest committed anchor before no new
+    /// round is entered. Unbounded if not set.
+    pub fn with_max_commit_lag_rounds(mut self, max_commit_lag_rounds: Option<Round>) -> Self {
+        self.driver_config.max_commit_lag_rounds = max_commit_lag_rounds;
+        self
+    }
+
+    /// Sets how long the current round can go without advancing before the pending node is
+    /// re-broadcast. Disabled if not set.
+    pub fn with_round_timeout(mut self, round_timeout: Option<Duration>) -> Self {
+        self.driver_config.round_timeout = round_timeout;
+        self
+    }
+
+    /// Sets how many payloads of added nodes are buffered to prefetch their data in one batch.
+    /// Each payload is prefetched right away if not set.
+    pub fn with_payload_prefetch_batch_size(
+        mut self,
+        payload_prefetch_batch_size: Option<usize>,
+    ) -> Self {
+        self.driver_config.payload_prefetch_batch_size = payload_prefetch_batch_size;
+        self
+    }
+
+    /// Sets the number of consecutive broadcasts of our nodes replaced before collecting a
+    /// signature quorum that raises an alert. Disabled if not set.
+    pub fn with_broadcast_failure_alert_threshold(
+        mut self,
+        broadcast_failure_alert_threshold: Option<u64>,
+    ) -> Self {
+        self.driver_config.broadcast_failure_alert_threshold = broadcast_failure_alert_threshold;
+        self
+    }
+
+    /// The config of the driver of every DAG instance, within the DAG window of the epoch.
+    pub(super) fn driver_config(&self) -> DagDriverConfig {
+        DagDriverConfig {
+            dag_window: Some(self.dag_window),
+            ..self.driver_config.clone()
         }
     }
 
@@ -93,6 +286,10 @@ impl DagBootstrapper {
         notifier: Arc<dyn OrderedNotifier>,
         dag_window_size_config: usize,
     ) -> (Arc<RwLock<Dag>>, OrderRule) {
+        if let Err(e) = self.storage.prune_epoch(self.epoch_state.epoch) {
+            error!(error = ?e, "unable to prune the previous epochs from dag storage");
+        }
+
         let initial_round = if initial_ledger_info.round() <= dag_window_size_config as Round {
             1
         } else {
@@ -101,20 +298,24 @@ impl DagBootstrapper {
                 .saturating_sub(dag_window_size_config as Round)
         };
 
-        let dag = Arc::new(RwLock::new(Dag::new(
+        let dag = Arc::new(RwLock::new(Dag::new_with_startup_verification(
             self.epoch_state.clone(),
             self.storage.clone(),
             initial_round,
             dag_window_size_config,
+            self.startup_verification,
         )));
 
         let validators = self.epoch_state.verifier.get_ordered_account_addresses();
-        let anchor_election = Box::new(RoundRobinAnchorElection::new(validators));
+        let anchor_election = self
+            .anchor_election_policy
+            .build(validators, dag_window_size_config);
 
         let order_rule = OrderRule::new(
             self.epoch_state.clone(),
             initial_ledger_info,
             dag.clone(),
+            dag_window_size_config,
             anchor_election,
             notifier,
             self.storage.clone(),
@@ -132,16 +333,21 @@ impl DagBootstrapper {
     ) -> (NetworkHandler, DagFetcherService) {
         let validators = self.epoch_state.verifier.get_ordered_account_addresses();
 
-        // A backoff policy that starts at 100ms and doubles each iteration.
-        let rb_backoff_policy = ExponentialBackoff::from_millis(2).factor(50);
-        let rb = Arc::new(ReliableBroadcast::new(
+        let rb_timeout = self.rb_config.rpc_timeout(validators.len());
+        let rb_backoff_policy = self.rb_config.backoff_policy(validators.len());
+        let mut rb = ReliableBroadcast::new(
             validators.clone(),
             self.rb_network_sender.clone(),
             rb_backoff_policy,
             self.time_service.clone(),
-            // TODO: add to config
-            Duration::from_millis(500),
-        ));
+            rb_timeout,
+        );
+        match self.rb_config.max_concurrent_sends {
+            Some(0) => warn!("max_concurrent_sends is 0, leaving the broadcast sends unbounded"),
+            Some(max_concurrent_sends) => rb = rb.with_max_concurrent_sends(max_concurrent_sends),
+            None => {},
+        }
+        let rb = Arc::new(rb);
 
         let (dag_fetcher, fetch_requester, node_fetch_waiter, certified_node_fetch_waiter) =
             DagFetcherService::new(
@@ -150,6 +356,7 @@ impl DagBootstrapper {
                 dag.clone(),
                 self.time_service.clone(),
             );
+        let dag_fetcher = dag_fetcher.with_fanout(self.fetch_fanout);
         let fetch_requester = Arc::new(fetch_requester);
 
         let dag_driver = DagDriver::new(
@@ -161,9 +368,11 @@ impl DagBootstrapper {
             rb,
             self.time_service.clone(),
             self.storage.clone(),
-            order_rule,
+            Box::new(order_rule),
             fetch_requester.clone(),
             ledger_info_provider,
+            self.recent_commits.clone(),
+            self.driver_config(),
         );
         let rb_handler = NodeBroadcastHandler::new(
             dag.clone(),
@@ -187,6 +396,39 @@ impl DagBootstrapper {
         (dag_handler, dag_fetcher)
     }
 
+    /// Holds on to a certified node received from its author while state syncing. Other messages
+    /// are rejected, so that their senders don't wait for the sync to finish.
+    fn buffer_live_node(&self, rpc_request: IncomingDAGRequest, live_nodes: &mut LiveNodeBuffer) {
+        let IncomingDAGRequest {
+            req,
+            sender,
+            response_sender,
+            ..
+        } = rpc_request;
+        let rejection = match DAGMessage::try_from(req) {
+            Ok(DAGMessage::CertifiedNodeMsg(certified_node_msg))
+                if *certified_node_msg.author() == sender =>
+            {
+                match certified_node_msg.verify(&self.epoch_state.verifier) {
+                    Ok(_) => {
+                        live_nodes.push(certified_node_msg);
+                        return;
+                    },
+                    Err(e) => {
+                        warn!(error = ?e, "unable to verify live node received during state sync");
+                        e
+                    },
+                }
+            },
+            Ok(DAGMessage::CertifiedNodeMsg(_)) => {
+                anyhow!("certified node is not sent by its author")
+            },
+            Ok(message) => anyhow!("unable to process {} while state syncing", message.name()),
+            Err(e) => e,
+        };
+        let _ = response_sender.send(Err(RpcError::ApplicationError(rejection)));
+    }
+
     pub async fn start(
         self,
         mut dag_rpc_rx: Receiver<Author, IncomingDAGRequest>,
@@ -198,7 +440,9 @@ impl DagBootstrapper {
             self.time_service.clone(),
             self.state_computer.clone(),
             self.storage.clone(),
-        );
+        )
+        .with_dag_window(self.dag_window);
+        let mut live_nodes = LiveNodeBuffer::new(self.live_node_policy);
 
         loop {
             let ledger_info_from_storage = self
@@ -215,13 +459,17 @@ impl DagBootstrapper {
 
             let ledger_info_provider = Arc::new(RwLock::new(LedgerInfoProvider::new(ledger_info)));
 
-            let adapter = Arc::new(OrderedNotifierAdapter::new(
-                ordered_nodes_tx.clone(),
-                self.storage.clone(),
-                self.epoch_state.clone(),
-                parent_block_info,
-                ledger_info_provider.clone(),
-            ));
+            let adapter = Arc::new(
+                OrderedNotifierAdapter::new(
+                    ordered_nodes_tx.clone(),
+                    self.storage.clone(),
+                    self.epoch_state.clone(),
+                    parent_block_info,
+                    ledger_info_provider.clone(),
+                    self.recent_commits.clone(),
+                )
+                .with_payload_shuffle(self.shuffle_payloads),
+            );
 
             let (dag_store, order_rule) = self.bootstrap_dag_store(
                 ledger_info_provider
@@ -229,7 +477,7 @@ impl DagBootstrapper {
                     .ledger_info()
                     .clone(),
                 adapter.clone(),
-                DAG_WINDOW,
+                self.dag_window,
             );
 
             let state_sync_trigger = StateSyncTrigger::new(
@@ -237,9 +485,11 @@ impl DagBootstrapper {
                 ledger_info_provider.clone(),
                 dag_store.clone(),
                 self.proof_notifier.clone(),
-            );
+                self.time_service.clone(),
+            )
+            .with_dag_window(self.dag_window);
 
-            let (handler, fetch_service) = self.bootstrap_components(
+            let (mut handler, fetch_service) = self.bootstrap_components(
                 dag_store.clone(),
                 order_rule,
                 state_sync_trigger,
@@ -249,53 +499,79 @@ impl DagBootstrapper {
             let df_handle = tokio::spawn(fetch_service.start());
 
             // poll the network handler while waiting for rebootstrap notification or shutdown notification
-            select! {
-                biased;
-                Ok(ack_tx) = &mut shutdown_rx => {
-                    df_handle.abort();
-                    let _ = df_handle.await;
+            let run_result = {
+                let run = handler.run(&mut dag_rpc_rx, live_nodes.take());
+                tokio::pin!(run);
+                select! {
+                    biased;
+                    Ok(ack_tx) = &mut shutdown_rx => Err(ack_tx),
+                    sync_status = &mut run => Ok(sync_status),
+                }
+            };
+            // this DAG instance is torn down either way, stop its broadcast before acking or syncing
+            df_handle.abort();
+            let _ = df_handle.await;
+            handler.shutdown().await;
+
+            match run_result {
+                Err(ack_tx) => {
                     if let Err(e) = ack_tx.send(()) {
                         error!(error = ?e, "unable to ack to shutdown signal");
                     }
                     return;
                 },
-                sync_status = handler.run(&mut dag_rpc_rx) => {
-                    df_handle.abort();
-                    let _ = df_handle.await;
-
-                    match sync_status {
-                        StateSyncStatus::NeedsSync(certified_node_msg) => {
-                            let highest_committed_anchor_round = ledger_info_provider.get_highest_committed_anchor_round();
-                            debug!("state sync notification received for round {}, dag round {}, ordered round {:?} commit round {} ", certified_node_msg.round(), dag_store.read().highest_round(), dag_store.read().highest_ordered_anchor_round(), highest_committed_anchor_round);
-                            let dag_fetcher = DagFetcher::new(self.epoch_state.clone(), self.dag_network_sender.clone(), self.time_service.clone());
-
-                            let sync_future = sync_manager.sync_dag_to(&certified_node_msg, dag_fetcher, dag_store.clone(), highest_committed_anchor_round);
-
-                            select! {
-                                result = sync_future => {
-                                    match result {
-                                        Ok(_) => debug!("Sync finishes"),
-                                        Err(e) => error!(error = ?e, "unable to sync"),
-                                    }
-                                },
-                                Ok(ack_tx) = &mut shutdown_rx => {
-                                    let _ = ack_tx.send(());
-                                    return;
+                Ok(StateSyncStatus::NeedsSync(certified_node_msg)) => {
+                    let highest_committed_anchor_round =
+                        ledger_info_provider.get_highest_committed_anchor_round();
+                    debug!("state sync notification received for round {}, dag round {}, ordered round {:?} commit round {} ", certified_node_msg.round(), dag_store.read().highest_round(), dag_store.read().highest_ordered_anchor_round(), highest_committed_anchor_round);
+                    let dag_fetcher = DagFetcher::new(
+                        self.epoch_state.clone(),
+                        self.dag_network_sender.clone(),
+                        self.time_service.clone(),
+                    )
+                    .with_fanout(self.fetch_fanout);
+
+                    let sync_future = sync_manager.sync_dag_to(
+                        &certified_node_msg,
+                        dag_fetcher,
+                        dag_store.clone(),
+                        highest_committed_anchor_round,
+                    );
+                    tokio::pin!(sync_future);
+
+                    // keep draining the rpc channel while syncing, so live nodes can be replayed after the sync
+                    loop {
+                        select! {
+                            result = &mut sync_future => {
+                                match result {
+                                    Ok(_) => debug!("Sync finishes"),
+                                    Err(e) => error!(error = ?e, "unable to sync"),
                                 }
-                            }
-
-                            debug!("going to rebootstrap.");
-                        },
-                        StateSyncStatus::EpochEnds => {
-                            // Wait for epoch manager to signal shutdown
-                            if let Ok(ack_tx) = shutdown_rx.await {
+                                break;
+                            },
+                            Ok(ack_tx) = &mut shutdown_rx => {
                                 let _ = ack_tx.send(());
+                                return;
+                            },
+                            Some(rpc_request) = dag_rpc_rx.next() => {
+                                self.buffer_live_node(rpc_request, &mut live_nodes);
                             }
-                            return;
-                        },
-                        _ => unreachable!()
+                        }
                     }
-                }
+
+                    debug!(
+                        "going to rebootstrap with {} buffered live nodes.",
+                        live_nodes.len()
+                    );
+                },
+                Ok(StateSyncStatus::EpochEnds) => {
+                    // Wait for epoch manager to signal shutdown
+                    if let Ok(ack_tx) = shutdown_rx.await {
+                        let _ = ack_tx.send(());
+                    }
+                    return;
+                },
+                Ok(_) => unreachable!(),
             }
         }
     }
@@ -342,26 +618,34 @@ pub(super) fn bootstrap_dag_for_test(
     let ledger_info_provider = Arc::new(RwLock::new(LedgerInfoProvider::new(ledger_info)));
 
     let (ordered_nodes_tx, ordered_nodes_rx) = futures_channel::mpsc::unbounded();
-    let adapter = Arc::new(OrderedNotifierAdapter::new(
-        ordered_nodes_tx,
-        storage.clone(),
-        epoch_state.clone(),
-        parent_block_info,
-        ledger_info_provider.clone(),
-    ));
+    let adapter = Arc::new(
+        OrderedNotifierAdapter::new(
+            ordered_nodes_tx,
+            storage.clone(),
+            epoch_state.clone(),
+            parent_block_info,
+            ledger_info_provider.clone(),
+            bootstraper.recent_commits.clone(),
+        )
+        .with_payload_shuffle(bootstraper.shuffle_payloads),
+    );
     let (dag_rpc_tx, dag_rpc_rx) = aptos_channel::new(QueueStyle::FIFO, 64, None);
 
-    let (dag_store, order_rule) =
-        bootstraper.bootstrap_dag_store(latest_ledger_info, adapter.clone(), DAG_WINDOW);
+    let (dag_store, order_rule) = bootstraper.bootstrap_dag_store(
+        latest_ledger_info,
+        adapter.clone(),
+        bootstraper.dag_window,
+    );
 
     let state_sync_trigger = StateSyncTrigger::new(
         epoch_state,
         ledger_info_provider.clone(),
         dag_store.clone(),
         proof_notifier.clone(),
+        bootstraper.time_service.clone(),
     );
 
-    let (handler, fetch_service) = bootstraper.bootstrap_components(
+    let (mut handler, fetch_service) = bootstraper.bootstrap_components(
         dag_store.clone(),
         order_rule,
         state_sync_trigger,
@@ -370,7 +654,7 @@ pub(super) fn bootstrap_dag_for_test(
 
     let dh_handle = tokio::spawn(async move {
         let mut dag_rpc_rx = dag_rpc_rx;
-        handler.run(&mut dag_rpc_rx).await
+        handler.run(&mut dag_rpc_rx, vec![]).await
     });
     let df_handle = tokio::spawn(fetch_service.start());
 
@@ -0,0 +1,192 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use aptos_consensus_types::common::Round;
+use aptos_metrics_core::{
+    exponential_buckets, register_histogram, register_int_counter, register_int_counter_vec,
+    register_int_gauge, Histogram, IntCounter, IntCounterVec, IntGauge,
+};
+use once_cell::sync::Lazy;
+
+/// The round the DAG driver is currently authoring a node for.
+pub static CURRENT_ROUND: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "aptos_consensus_dag_current_round",
+        "The round the DAG driver is currently authoring a node for"
+    )
+    .unwrap()
+});
+
+/// Number of rounds between the current round and the highest committed anchor round.
+pub static FINALITY_LAG_ROUNDS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "aptos_consensus_dag_finality_lag_rounds",
+        "Number of rounds between the current round and the highest committed anchor round"
+    )
+    .unwrap()
+});
+
+/// Number of fetch requests, for nodes with missing parents, waiting for the fetch service.
+pub static PENDING_FETCHES: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "aptos_consensus_dag_pending_fetches",
+        "Number of fetch requests waiting for the fetch service"
+    )
+    .unwrap()
+});
+
+/// Number of payload pulls that timed out, the node is authored with an empty payload.
+pub static PAYLOAD_PULL_TIMEOUTS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "aptos_consensus_dag_payload_pull_timeouts",
+        "Number of payload pulls that timed out"
+    )
+    .unwrap()
+});
+
+/// Number of payload pulls that failed for a reason other than a timeout.
+pub static PAYLOAD_PULL_ERRORS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "aptos_consensus_dag_payload_pull_errors",
+        "Number of payload pulls that failed for a reason other than a timeout"
+    )
+    .unwrap()
+});
+
+/// Number of certified nodes authored by this validator that were received back.
+pub static OWN_NODES_RECEIVED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "aptos_consensus_dag_own_nodes_received",
+        "Number of certified nodes authored by this validator that were received back"
+    )
+    .unwrap()
+});
+
+/// Number of fetches of missing parents suppressed while nodes were added quickly.
+pub static FETCHES_SUPPRESSED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "aptos_consensus_dag_fetches_suppressed",
+        "Number of fetches of missing parents suppressed while nodes were added quickly"
+    )
+    .unwrap()
+});
+
+/// Number of acks whose ledger info commits a different block than the one carried by the certified node.
+pub static LEDGER_INFO_DISAGREEMENTS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "aptos_consensus_dag_ledger_info_disagreements",
+        "Number of acks whose ledger info commits a different block than the certified node message"
+    )
+    .unwrap()
+});
+
+/// Number of certified nodes dropped for arriving after their round was ordered.
+pub static LATE_NODES_DROPPED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "aptos_consensus_dag_late_nodes_dropped",
+        "Number of certified nodes dropped for arriving after their round was ordered"
+    )
+    .unwrap()
+});
+
+/// Number of times entering a new round was held back because commits lag behind the DAG.
+pub static ROUNDS_STALLED_BY_BACKPRESSURE: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "aptos_consensus_dag_rounds_stalled_by_backpressure",
+        "Number of times entering a new round was held back because commits lag behind the DAG"
+    )
+    .unwrap()
+});
+
+/// Number of conflicting nodes received for a round an author already has a node at, by author.
+pub static EQUIVOCATIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "aptos_consensus_dag_equivocations",
+        "Number of conflicting nodes received for a round an author already has a node at",
+        &["author"]
+    )
+    .unwrap()
+});
+
+/// Number of fetch requests collapsed into a queued or in-flight fetch of the same node.
+pub static DEDUPLICATED_FETCH_REQUESTS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "aptos_consensus_dag_deduplicated_fetch_requests",
+        "Number of fetch requests collapsed into a queued or in-flight fetch of the same node"
+    )
+    .unwrap()
+});
+
+/// Number of times a fetch request was retried because the fetch requests queue was full.
+pub static FETCH_REQUEST_RETRIES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "aptos_consensus_dag_fetch_request_retries",
+        "Number of times a fetch request was retried because the fetch requests queue was full"
+    )
+    .unwrap()
+});
+
+/// Number of pulled transactions dropped from an authored node for failing payload validation.
+pub static INVALID_PAYLOAD_TXNS_DROPPED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "aptos_consensus_dag_invalid_payload_txns_dropped",
+        "Number of pulled transactions dropped from an authored node for failing payload validation"
+    )
+    .unwrap()
+});
+
+/// Time in seconds from starting the broadcast of our own node until its certificate is formed.
+pub static BROADCAST_TO_CERTIFICATE_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "aptos_consensus_dag_broadcast_to_certificate_seconds",
+        "Time in seconds from starting the broadcast of a node until its certificate is formed",
+        exponential_buckets(/*start=*/ 0.01, /*factor=*/ 2.0, /*count=*/ 14).unwrap(),
+    )
+    .unwrap()
+});
+
+/// Number of in-flight broadcasts aborted because a newer node was broadcast.
+pub static ABORTED_BROADCASTS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "aptos_consensus_dag_aborted_broadcasts",
+        "Number of in-flight broadcasts aborted because a newer node was broadcast"
+    )
+    .unwrap()
+});
+
+/// Number of times the broadcasts of this validator's nodes consecutively failed to collect a
+/// signature quorum, as when it is partitioned from the majority and effectively excluded.
+pub static BROADCAST_QUORUM_LOST_ALERTS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "aptos_consensus_dag_broadcast_quorum_lost_alerts",
+        "Number of times the broadcasts of this validator's nodes consecutively failed to collect a signature quorum"
+    )
+    .unwrap()
+});
+
+/// Number of nodes pruned from the DAG below the window of the committed anchor.
+pub static PRUNED_NODES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "aptos_consensus_dag_pruned_nodes",
+        "Number of nodes pruned from the DAG below the window of the committed anchor"
+    )
+    .unwrap()
+});
+
+/// Number of rounds that timed out without advancing, re-broadcasting the pending node.
+pub static ROUND_TIMEOUTS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "aptos_consensus_dag_round_timeouts",
+        "Number of rounds that timed out without advancing"
+    )
+    .unwrap()
+});
+
+pub fn update_finality_lag(current_round: Round, highest_committed_anchor_round: Round) {
+    CURRENT_ROUND.set(current_round as i64);
+    FINALITY_LAG_ROUNDS.set(current_round.saturating_sub(highest_committed_anchor_round) as i64);
+}
+
+pub fn update_finality_lag_on_commit(highest_committed_anchor_round: Round) {
+    update_finality_lag(CURRENT_ROUND.get() as Round, highest_committed_anchor_round);
+}
@@ -2,44 +2,332 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::{
-    adapter::TLedgerInfoProvider,
-    dag_fetcher::FetchRequester,
-    order_rule::OrderRule,
+    adapter::{RecentCommits, TLedgerInfoProvider},
+    dag_fetcher::{request_for_certified_node_with_retry, FetchRequester, FetchRetryConfig},
+    order_rule::{OrderedAnchor, TOrderRule},
     storage::DAGStorage,
-    types::{CertifiedAck, CertifiedNodeMessage, DAGMessage, Extensions},
+    types::{AnchorCommitProof, CertifiedAck, CertifiedNodeMessage, DAGMessage, Extensions},
     RpcHandler,
 };
 use crate::{
     dag::{
-        dag_fetcher::TFetchRequester,
+        counters,
         dag_state_sync::DAG_WINDOW,
-        dag_store::Dag,
-        types::{CertificateAckState, CertifiedNode, Node, SignatureBuilder},
+        dag_store::{Dag, DagStoreError, NodeProvenance},
+        types::{
+            CertificateAckState, CertifiedNode, Node, NodeCertificate, NodeMetadata,
+            SignatureBuilder,
+        },
     },
     payload_manager::PayloadManager,
     state_replication::PayloadClient,
 };
 use anyhow::bail;
-use aptos_consensus_types::common::{Author, PayloadFilter};
-use aptos_infallible::RwLock;
-use aptos_logger::{debug, error};
+use aptos_consensus_types::common::{Author, Payload, PayloadFilter};
+use aptos_crypto::HashValue;
+use aptos_infallible::{Mutex, RwLock};
+use aptos_logger::{debug, error, warn};
 use aptos_reliable_broadcast::ReliableBroadcast;
 use aptos_time_service::{TimeService, TimeServiceTrait};
-use aptos_types::{block_info::Round, epoch_state::EpochState};
+use aptos_types::{
+    block_info::Round, epoch_state::EpochState, transaction::SignedTransaction,
+    validator_verifier::ValidatorVerifier,
+};
 use async_trait::async_trait;
 use futures::{
     executor::block_on,
-    future::{AbortHandle, Abortable},
-    FutureExt,
+    future::{self, AbortHandle, Abortable, Aborted},
+    Future, FutureExt,
+};
+use futures_channel::mpsc::UnboundedReceiver;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
-use std::{sync::Arc, time::Duration};
 use thiserror::Error as ThisError;
+use tokio::task::JoinHandle;
 use tokio_retry::strategy::ExponentialBackoff;
+use tracing::{debug_span, Instrument};
 
 #[derive(Debug, ThisError)]
 pub enum DagDriverError {
     #[error("missing parents")]
     MissingParents,
+    #[error("node from future epoch {node_epoch}, current epoch {current_epoch}")]
+    FutureEpoch { node_epoch: u64, current_epoch: u64 },
+    #[error("invalid certificate")]
+    InvalidCertificate,
+    #[error("author {author} equivocated at round {round}")]
+    Equivocation { author: Author, round: Round },
+    #[error("node round {round} is not one above its highest parent round {highest_parent_round}")]
+    InvalidRound {
+        round: Round,
+        highest_parent_round: Round,
+    },
+    #[error("parents of node at round {round} don't satisfy the voting power quorum")]
+    InsufficientParents { round: Round },
+    #[error("node at round {round} has no parents, only nodes of the genesis round may")]
+    ParentlessNode { round: Round },
+    #[error("driver is shut down")]
+    ShutDown,
+    #[error("round {round} is below the genesis round")]
+    BelowGenesisRound { round: Round },
+}
+
+/// Verifies the certificate of the nodes processed by the driver. The network handler verifies the
+/// incoming nodes already, so the driver only verifies them again with an injected verifier, e.g. to
+/// test how it handles badly-certified nodes.
+pub trait TCertifiedNodeVerifier: Send + Sync {
+    fn verify(&self, node: &CertifiedNode, verifier: &ValidatorVerifier) -> anyhow::Result<()>;
+}
+
+/// Validates the transactions of a pulled payload before they're included in an authored node, e.g.
+/// against per-account limits. Transactions that fail validation are dropped so they don't take up
+/// space in the node. Only a direct mempool payload carries its transactions, a quorum store payload
+/// is included as pulled.
+pub trait TPayloadValidator: Send + Sync {
+    fn is_valid(&self, txn: &SignedTransaction) -> bool;
+}
+
+/// What to do when entering a non-genesis round without strong links to the previous round. The
+/// validators reject nodes without parents above the genesis round, so authoring one is only
+/// meant for tests.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmptyStrongLinksPolicy {
+    /// Author a node without parents.
+    Proceed,
+    /// Author a node without parents and log a warning.
+    Warn,
+    /// Skip authoring a node for the round.
+    #[default]
+    Refuse,
+}
+
+/// What to do with certified nodes from a future epoch, which can't be added to the DAG of the
+/// current epoch. Either way the node is rejected, so that its author retries it later.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FutureEpochNodePolicy {
+    #[default]
+    Reject,
+    /// Also keep up to `max_nodes` nodes, to be handed over to the DAG of the next epoch.
+    Buffer { max_nodes: usize },
+}
+
+/// What to do when pulling the payload of a node fails. A pull that times out always proceeds with
+/// an empty payload.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PayloadPullErrorPolicy {
+    /// Author the node with an empty payload, so that a briefly unavailable payload service doesn't
+    /// stop the validator from making progress.
+    #[default]
+    EmptyPayload,
+    /// Panic, for tests to surface a failing payload client. A validator must never use it, as a
+    /// single failed pull would crash the node.
+    Panic,
+}
+
+/// What to do with a certified node authored by this validator, received back through its own
+/// broadcast. A node already in the DAG is acknowledged without further work either way.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OwnNodePolicy {
+    /// Add it to the DAG like a node of any other author.
+    #[default]
+    Add,
+    /// Add it to the DAG without prefetching its payload, which this validator pulled itself.
+    SkipPayloadPrefetch,
+}
+
+/// What to do with a certified node for a round up to the highest anchor ordered by the order rule,
+/// which arrives too late to be ordered. The node is acknowledged either way.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LateNodePolicy {
+    /// Add it to the DAG, so that the DAG is complete for auditing.
+    #[default]
+    AddForAudit,
+    Drop,
+}
+
+/// How the timestamp of an authored node relates to its parents. The timestamp is always above the
+/// timestamps of all the parents, and so above their median, clamping the local clock if it's behind.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParentTimestampPolicy {
+    #[default]
+    Clamp,
+    /// Wait for the local clock to pass the median timestamp of the parents before authoring the
+    /// node, so that a validator with a lagging clock doesn't skew the timestamps of the DAG.
+    WaitForMedian,
+}
+
+/// The first round of an epoch, whose nodes have no parents.
+pub const GENESIS_ROUND: Round = 1;
+
+/// Each round, a node is broadcast to every validator to collect signatures, and then again
+/// as a certified node.
+const BROADCASTS_PER_ROUND: u64 = 2;
+
+/// Number of most recent rounds the finality latency estimate averages over.
+const FINALITY_ESTIMATE_WINDOW: usize = 10;
+
+/// Longest a buffered payload prefetch waits for the buffer to fill up before it's flushed.
+const PAYLOAD_PREFETCH_MAX_DELAY: Duration = Duration::from_millis(50);
+
+/// Limits of the payload pulled into each authored node.
+#[derive(Clone, Debug)]
+pub struct DagPayloadConfig {
+    /// How long to wait for a payload when authoring a node, unless backing off when idle.
+    pub pull_timeout: Duration,
+    pub max_txns: u64,
+    pub max_bytes: u64,
+}
+
+impl Default for DagPayloadConfig {
+    fn default() -> Self {
+        Self {
+            pull_timeout: Duration::from_secs(1),
+            max_txns: 1000,
+            max_bytes: 10 * 1024 * 1024,
+        }
+    }
+}
+
+/// Slows down authoring while there are no transactions. After `empty_rounds_threshold` consecutive
+/// rounds with an empty payload, the time to wait for a payload doubles every round, up to
+/// `max_poll_time`. As the payload pull returns as soon as transactions appear, rounds resume at the
+/// normal pace right away.
+#[derive(Clone, Debug)]
+pub struct IdleBackoffConfig {
+    pub empty_rounds_threshold: u64,
+    pub max_poll_time: Duration,
+}
+
+/// Suppresses fetching the missing parents of a node while nodes are added quickly, as the parents
+/// are then likely to arrive momentarily. Parents are fetched once fewer than `min_adds` nodes were
+/// added in the last `window`, or once they have been missing for `max_absence`.
+#[derive(Clone, Debug)]
+pub struct FetchSuppressionConfig {
+    pub min_adds: usize,
+    pub window: Duration,
+    pub max_absence: Duration,
+}
+
+/// The recently added nodes and since when parents have been missing, for `FetchSuppressionConfig`.
+#[derive(Default)]
+struct FetchSuppression {
+    recent_adds: VecDeque<Duration>,
+    missing_since: HashMap<HashValue, Duration>,
+}
+
+impl FetchSuppression {
+    fn record_add(&mut self, digest: &HashValue, now: Duration, config: &FetchSuppressionConfig) {
+        self.missing_since.remove(digest);
+        self.recent_adds.push_back(now);
+        self.prune(now, config);
+    }
+
+    fn should_suppress(
+        &mut self,
+        missing_parents: &[NodeMetadata],
+        now: Duration,
+        config: &FetchSuppressionConfig,
+    ) -> bool {
+        self.prune(now, config);
+        let mut suppress = self.recent_adds.len() >= config.min_adds;
+        for parent in missing_parents {
+            let missing_since = *self.missing_since.entry(*parent.digest()).or_insert(now);
+            if now.saturating_sub(missing_since) >= config.max_absence {
+                suppress = false;
+            }
+        }
+        if !suppress {
+            // the fetch takes over from here
+            for parent in missing_parents {
+                self.missing_since.remove(parent.digest());
+            }
+        }
+        suppress
+    }
+
+    fn prune(&mut self, now: Duration, config: &FetchSuppressionConfig) {
+        while let Some(added_at) = self.recent_adds.front() {
+            if now.saturating_sub(*added_at) < config.window {
+                break;
+            }
+            self.recent_adds.pop_front();
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DagDriverConfig {
+    /// Only meant to be changed from the default in tests, see [`EmptyStrongLinksPolicy`].
+    pub empty_strong_links_policy: EmptyStrongLinksPolicy,
+    pub idle_backoff: Option<IdleBackoffConfig>,
+    pub future_epoch_node_policy: FutureEpochNodePolicy,
+    /// Seed for pulling payloads deterministically, for reproducible testing and shadow execution.
+    pub payload_seed: Option<u64>,
+    /// Only meant to be changed from the default in tests, see [`PayloadPullErrorPolicy::Panic`].
+    pub payload_pull_error_policy: PayloadPullErrorPolicy,
+    pub own_node_policy: OwnNodePolicy,
+    /// How many rounds below the strong links to look for nodes they don't reach, to reference as
+    /// weak links. Deeper lookback improves the connectivity of lagging validators at the cost of
+    /// larger nodes, 0 disables weak links.
+    pub weak_link_lookback_rounds: Round,
+    pub fetch_suppression: Option<FetchSuppressionConfig>,
+    /// Minimum fraction of the total voting power the strong links must hold to enter the next
+    /// round, for extra safety above the 2f+1 quorum. Only the quorum is required if not set.
+    pub min_strong_link_stake_fraction: Option<f64>,
+    pub late_node_policy: LateNodePolicy,
+    pub payload: DagPayloadConfig,
+    pub parent_timestamp_policy: ParentTimestampPolicy,
+    /// Maximum number of rounds the DAG can be ahead of the highest committed anchor. Beyond it,
+    /// no new round is entered until commits catch up, which is checked again whenever a node is
+    /// added. Unbounded if not set.
+    pub max_commit_lag_rounds: Option<Round>,
+    /// How requests to fetch missing parents are retried while the fetch requests queue is full.
+    pub fetch_retry: FetchRetryConfig,
+    /// Checkpoint the signatures collected by the broadcast of our node every this many new
+    /// signatures, so that after a restart the broadcast resumes with them. Not checkpointed if not
+    /// set.
+    pub broadcast_checkpoint_interval: Option<usize>,
+    /// Re-broadcast the pending node whenever the current round hasn't advanced for this long, in
+    /// case the strong links of the round never formed because its broadcast was lost. Disabled if
+    /// not set.
+    pub round_timeout: Option<Duration>,
+    /// Buffer the payloads of up to this many added nodes to prefetch their data in one batch, so
+    /// that the batches shared by nodes received in a burst are fetched once. The buffer is also
+    /// flushed on entering a new round, and once its oldest payload waited
+    /// `PAYLOAD_PREFETCH_MAX_DELAY`. Each payload is prefetched right away if not set.
+    pub payload_prefetch_batch_size: Option<usize>,
+    /// Number of rounds below the highest committed anchor whose nodes are kept, to exclude their
+    /// payloads from new nodes. `DAG_WINDOW` if not set.
+    pub dag_window: Option<usize>,
+    /// Alert once this many consecutive broadcasts of our nodes in the epoch were replaced before
+    /// collecting a signature quorum, as the validator is then likely partitioned from the
+    /// majority. Disabled if not set.
+    pub broadcast_failure_alert_threshold: Option<u64>,
+}
+
+impl DagDriverConfig {
+    pub fn dag_window(&self) -> usize {
+        self.dag_window.unwrap_or(DAG_WINDOW)
+    }
+}
+
+/// Point-in-time view of the driver state, for monitoring.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DagMetrics {
+    pub current_round: Round,
+    pub finality_lag_rounds: Round,
+    pub broadcast_in_flight: bool,
+    pub pending_fetches: usize,
+    /// Fraction of the maximum number of transactions per node used by the last authored node.
+    pub node_utilization: f64,
+    /// Number of in-flight broadcasts aborted because a newer node was broadcast.
+    pub aborted_broadcasts: u64,
 }
 
 pub(crate) struct DagDriver {
@@ -52,10 +340,38 @@ pub(crate) struct DagDriver {
     current_round: Round,
     time_service: TimeService,
     rb_abort_handle: Option<AbortHandle>,
+    rb_task: Option<JoinHandle<Result<(), Aborted>>>,
     storage: Arc<dyn DAGStorage>,
-    order_rule: OrderRule,
+    order_rule: Box<dyn TOrderRule>,
     fetch_requester: Arc<FetchRequester>,
     ledger_info_provider: Arc<dyn TLedgerInfoProvider>,
+    recent_commits: Arc<Mutex<RecentCommits>>,
+    config: DagDriverConfig,
+    consecutive_empty_rounds: u64,
+    last_node_size: u64,
+    last_node_txns: u64,
+    last_node_parents: Vec<NodeMetadata>,
+    broadcast_in_flight: Arc<AtomicBool>,
+    /// Whether the current broadcast collected a signature quorum for our node.
+    broadcast_quorum_collected: Arc<AtomicBool>,
+    consecutive_failed_broadcasts: u64,
+    future_epoch_nodes: Vec<CertifiedNode>,
+    highest_own_certified_round: Option<Round>,
+    fetch_suppression: FetchSuppression,
+    certified_node_verifier: Option<Arc<dyn TCertifiedNodeVerifier>>,
+    payload_validator: Option<Arc<dyn TPayloadValidator>>,
+    current_round_entered_at: Instant,
+    /// When the round timer last started, on entering the round or on its last timeout.
+    round_timer_started_at: Instant,
+    aborted_broadcasts: u64,
+    /// (duration, commit depth in rounds) of the most recently entered rounds
+    recent_rounds: VecDeque<(Duration, Round)>,
+    last_pruned_commit_round: Round,
+    /// (payload, timestamp) of the added nodes whose payload data is not prefetched yet
+    pending_prefetches: Vec<(Payload, u64)>,
+    /// When the oldest of the `pending_prefetches` was buffered.
+    pending_prefetches_since: Instant,
+    is_shut_down: bool,
 }
 
 impl DagDriver {
@@ -68,18 +384,18 @@ impl DagDriver {
         reliable_broadcast: Arc<ReliableBroadcast<DAGMessage, ExponentialBackoff>>,
         time_service: TimeService,
         storage: Arc<dyn DAGStorage>,
-        order_rule: OrderRule,
+        order_rule: Box<dyn TOrderRule>,
         fetch_requester: Arc<FetchRequester>,
         ledger_info_provider: Arc<dyn TLedgerInfoProvider>,
+        recent_commits: Arc<Mutex<RecentCommits>>,
+        config: DagDriverConfig,
     ) -> Self {
         let pending_node = storage
             .get_pending_node()
             .expect("should be able to read dag storage");
         let highest_round = dag.read().highest_round();
-        let highest_strong_links_round = dag
-            .read()
-            .get_strong_links_for_round(highest_round, &epoch_state.verifier)
-            .map_or_else(|| highest_round.saturating_sub(1), |_| highest_round);
+        let highest_strong_links_round =
+            Self::strong_links_frontier(&dag.read(), &epoch_state.verifier);
 
         debug!(
             "highest_round: {}, current_round: {}",
@@ -94,12 +410,35 @@ impl DagDriver {
             payload_client,
             reliable_broadcast,
             current_round: highest_strong_links_round,
-            time_service,
+            time_service: time_service.clone(),
             rb_abort_handle: None,
+            rb_task: None,
             storage,
             order_rule,
             fetch_requester,
             ledger_info_provider,
+            recent_commits,
+            config,
+            consecutive_empty_rounds: 0,
+            last_node_size: 0,
+            last_node_txns: 0,
+            last_node_parents: Vec::new(),
+            broadcast_in_flight: Arc::new(AtomicBool::new(false)),
+            broadcast_quorum_collected: Arc::new(AtomicBool::new(false)),
+            consecutive_failed_broadcasts: 0,
+            future_epoch_nodes: Vec::new(),
+            highest_own_certified_round: None,
+            fetch_suppression: FetchSuppression::default(),
+            certified_node_verifier: None,
+            payload_validator: None,
+            current_round_entered_at: time_service.now(),
+            round_timer_started_at: time_service.now(),
+            aborted_broadcasts: 0,
+            recent_rounds: VecDeque::with_capacity(FINALITY_ESTIMATE_WINDOW),
+            last_pruned_commit_round: 0,
+            pending_prefetches: Vec::new(),
+            pending_prefetches_since: time_service.now(),
+            is_shut_down: false,
         };
 
         // If we were broadcasting the node for the round already, resume it
@@ -110,89 +449,466 @@ impl DagDriver {
             driver.broadcast_node(node);
         } else {
             // kick start a new round
-            block_on(driver.enter_new_round(highest_strong_links_round + 1));
+            block_on(driver.enter_new_round(highest_strong_links_round + 1))
+                .expect("a new driver is not shut down");
         }
         driver
     }
 
-    pub async fn add_node(&mut self, node: CertifiedNode) -> anyhow::Result<()> {
-        let highest_strong_links_round = {
-            let mut dag_writer = self.dag.write();
+    /// Verifies the certificate of every processed node with the given verifier, see
+    /// `TCertifiedNodeVerifier`.
+    pub fn with_certified_node_verifier(
+        mut self,
+        certified_node_verifier: Arc<dyn TCertifiedNodeVerifier>,
+    ) -> Self {
+        self.certified_node_verifier = Some(certified_node_verifier);
+        self
+    }
 
-            if !dag_writer.all_exists(node.parents_metadata()) {
-                if let Err(err) = self.fetch_requester.request_for_certified_node(node) {
-                    error!("request to fetch failed: {}", err);
-                }
-                bail!(DagDriverError::MissingParents);
-            }
+    /// Validates the transactions of every pulled payload with the given validator, see
+    /// `TPayloadValidator`.
+    pub fn with_payload_validator(mut self, payload_validator: Arc<dyn TPayloadValidator>) -> Self {
+        self.payload_validator = Some(payload_validator);
+        self
+    }
 
-            self.payload_manager
-                .prefetch_payload_data(node.payload(), node.metadata().timestamp());
-            dag_writer.add_node(node)?;
+    /// The highest round this validator has enough strong links for to build upon. Unlike
+    /// `Dag::highest_round`, this excludes a top round that lacks a quorum.
+    pub fn highest_strong_links_round(&self) -> Round {
+        Self::strong_links_frontier(&self.dag.read(), &self.epoch_state.verifier)
+    }
+
+    /// Number of rounds between the current round and the highest committed anchor round.
+    pub fn finality_lag_rounds(&self) -> Round {
+        self.current_round.saturating_sub(
+            self.ledger_info_provider
+                .get_highest_committed_anchor_round(),
+        )
+    }
+
+    /// How long since the current round was entered. A growing value signals a stalled DAG.
+    pub fn time_in_current_round(&self) -> Duration {
+        self.time_service
+            .now()
+            .saturating_duration_since(self.current_round_entered_at)
+    }
+
+    /// Completes when the current round times out, never if round timeouts are disabled. The
+    /// returned future doesn't borrow the driver, call `check_round_timeout` once it completes.
+    pub fn round_timeout(&self) -> impl Future<Output = ()> + 'static {
+        let sleep = self.config.round_timeout.map(|round_timeout| {
+            self.time_service
+                .sleep_until(self.round_timer_started_at + round_timeout)
+        });
+        async move {
+            match sleep {
+                Some(sleep) => sleep.await,
+                None => future::pending().await,
+            }
+        }
+    }
 
-            let highest_round = dag_writer.highest_round();
-            dag_writer
-                .get_strong_links_for_round(highest_round, &self.epoch_state.verifier)
-                .map_or_else(|| highest_round.saturating_sub(1), |_| highest_round)
+    /// Re-broadcasts the pending node if the current round has timed out, restarting the round
+    /// timer. Returns whether it timed out.
+    pub fn check_round_timeout(&mut self) -> bool {
+        let round_timeout = match self.config.round_timeout {
+            Some(round_timeout) => round_timeout,
+            None => return false,
         };
+        let now = self.time_service.now();
+        if now.saturating_duration_since(self.round_timer_started_at) < round_timeout {
+            return false;
+        }
+        warn!("round {} timed out", self.current_round);
+        counters::ROUND_TIMEOUTS.inc();
+        self.round_timer_started_at = now;
+        match self.storage.get_pending_node() {
+            Ok(Some(node)) if node.round() == self.current_round => self.broadcast_node(node),
+            Ok(_) => debug!("no pending node for round {}", self.current_round),
+            Err(e) => error!("failed to read the pending node: {:?}", e),
+        }
+        true
+    }
 
-        if self.current_round <= highest_strong_links_round {
-            self.enter_new_round(highest_strong_links_round + 1).await;
+    /// Estimated time for a transaction in a newly authored node to commit: the average duration
+    /// of the recent rounds times their average commit depth, i.e. how many rounds the committed
+    /// anchor trails the entered round. Zero until a round has been entered after the first one.
+    pub fn estimated_finality_latency(&self) -> Duration {
+        if self.recent_rounds.is_empty() {
+            return Duration::ZERO;
         }
-        Ok(())
+        let num_rounds = self.recent_rounds.len();
+        let total_duration: Duration = self.recent_rounds.iter().map(|(d, _)| *d).sum();
+        let total_depth: Round = self.recent_rounds.iter().map(|(_, depth)| *depth).sum();
+        (total_duration / num_rounds as u32).mul_f64(total_depth as f64 / num_rounds as f64)
     }
 
-    pub async fn enter_new_round(&mut self, new_round: Round) {
-        debug!("entering new round {}", new_round);
-        let strong_links = self
-            .dag
+    /// Records the duration of the current round and the commit depth of the round entered next.
+    fn record_round_timing(&mut self, new_round: Round) {
+        let duration = self
+            .time_service
+            .now()
+            .saturating_duration_since(self.current_round_entered_at);
+        let commit_depth = new_round.saturating_sub(
+            self.ledger_info_provider
+                .get_highest_committed_anchor_round(),
+        );
+        if self.recent_rounds.len() == FINALITY_ESTIMATE_WINDOW {
+            self.recent_rounds.pop_front();
+        }
+        self.recent_rounds.push_back((duration, commit_depth));
+    }
+
+    /// Estimated outbound bytes per round, based on the size of the last authored node. The
+    /// signatures and ledger info attached to the certified node are not accounted for.
+    pub fn estimated_egress_per_round(&self) -> u64 {
+        self.last_node_size * self.epoch_state.verifier.len() as u64 * BROADCASTS_PER_ROUND
+    }
+
+    pub fn metrics_snapshot(&self) -> DagMetrics {
+        DagMetrics {
+            current_round: self.current_round,
+            finality_lag_rounds: self.finality_lag_rounds(),
+            broadcast_in_flight: self.broadcast_in_flight.load(Ordering::Relaxed),
+            pending_fetches: self.fetch_requester.pending_requests(),
+            node_utilization: self.last_node_txns as f64 / self.config.payload.max_txns as f64,
+            aborted_broadcasts: self.aborted_broadcasts,
+        }
+    }
+
+    /// The strong links the last authored node used as parents.
+    pub fn last_node_parents(&self) -> &[NodeMetadata] {
+        &self.last_node_parents
+    }
+
+    /// The nodes a node built on the given strong links can reference as weak links, within the
+    /// configured lookback.
+    pub fn weak_link_candidates(&self, strong_links: &[NodeCertificate]) -> Vec<NodeMetadata> {
+        self.dag
             .read()
-            .get_strong_links_for_round(new_round - 1, &self.epoch_state.verifier)
-            .unwrap_or_else(|| {
-                assert_eq!(new_round, 1, "Only expect empty strong links for round 1");
-                vec![]
+            .weak_link_candidates(strong_links, self.config.weak_link_lookback_rounds)
+    }
+
+    /// The highest round of a node authored by this validator that came back certified, i.e. whose
+    /// broadcast for signatures completed.
+    pub fn highest_own_certified_round(&self) -> Option<Round> {
+        self.highest_own_certified_round
+    }
+
+    /// Number of consecutive broadcasts of our nodes replaced before collecting a signature quorum.
+    pub fn consecutive_failed_broadcasts(&self) -> u64 {
+        self.consecutive_failed_broadcasts
+    }
+
+    /// Whether enough consecutive broadcasts failed to alert, see
+    /// `DagDriverConfig::broadcast_failure_alert_threshold`.
+    pub fn broadcast_quorum_lost(&self) -> bool {
+        self.config
+            .broadcast_failure_alert_threshold
+            .map_or(false, |threshold| {
+                self.consecutive_failed_broadcasts >= threshold
+            })
+    }
+
+    /// Subscribes to the commit proofs of the anchors ordered by this driver.
+    pub fn subscribe_commit_proofs(&mut self) -> UnboundedReceiver<AnchorCommitProof> {
+        self.order_rule.subscribe_commit_proofs()
+    }
+
+    /// Subscribes to the anchors ordered by this driver, with their causal history.
+    pub fn subscribe_ordered_anchors(&mut self) -> UnboundedReceiver<OrderedAnchor> {
+        self.order_rule.subscribe_ordered_anchors()
+    }
+
+    /// Takes out the nodes from future epochs kept under `FutureEpochNodePolicy::Buffer`, in the
+    /// order they were received. They are not verified, as the validators of their epoch are not
+    /// known yet.
+    pub fn take_future_epoch_nodes(&mut self) -> Vec<CertifiedNode> {
+        std::mem::take(&mut self.future_epoch_nodes)
+    }
+
+    fn buffer_future_epoch_node(&mut self, node: CertifiedNode) {
+        match self.config.future_epoch_node_policy {
+            FutureEpochNodePolicy::Buffer { max_nodes }
+                if self.future_epoch_nodes.len() < max_nodes =>
+            {
+                self.future_epoch_nodes.push(node);
+            },
+            FutureEpochNodePolicy::Buffer { max_nodes } => {
+                warn!(
+                    "future epoch node buffer is full ({} nodes), dropping node {}",
+                    max_nodes,
+                    node.id()
+                );
+            },
+            FutureEpochNodePolicy::Reject => {},
+        }
+    }
+
+    fn strong_links_frontier(dag: &Dag, verifier: &ValidatorVerifier) -> Round {
+        let highest_round = dag.highest_round();
+        dag.get_strong_links_for_round(highest_round, verifier)
+            .map_or_else(|| highest_round.saturating_sub(1), |_| highest_round)
+    }
+
+    /// Checks the parents of a node above the genesis round hold a quorum of the voting power, as
+    /// the strong links are only taken once a quorum of the previous round is certified.
+    pub(super) fn check_parents_quorum(
+        node: &CertifiedNode,
+        verifier: &ValidatorVerifier,
+    ) -> anyhow::Result<()> {
+        let parent_authors = node.parents_metadata().map(|parent| parent.author());
+        if verifier.check_voting_power(parent_authors, true).is_err() {
+            bail!(DagDriverError::InsufficientParents {
+                round: node.round()
             });
-        let payload_filter = {
-            let dag_reader = self.dag.read();
-            let highest_commit_round = self
-                .ledger_info_provider
-                .get_highest_committed_anchor_round();
-            if strong_links.is_empty() {
-                PayloadFilter::Empty
-            } else {
-                PayloadFilter::from(
-                    &dag_reader
-                        .reachable(
-                            strong_links.iter().map(|node| node.metadata()),
-                            Some(highest_commit_round.saturating_sub(DAG_WINDOW as u64)),
-                            |_| true,
-                        )
-                        .map(|node_status| node_status.as_node().payload())
-                        .collect(),
-                )
-            }
-        };
-        let payload = match self
-            .payload_client
-            .pull_payload(
-                Duration::from_secs(1),
-                1000,
-                10 * 1024 * 1024,
-                payload_filter,
-                Box::pin(async {}),
-                false,
-                0,
-                0.0,
+        }
+        Ok(())
+    }
+
+    pub async fn add_node(&mut self, node: CertifiedNode, sender: Author) -> anyhow::Result<()> {
+        if self.is_shut_down {
+            bail!(DagDriverError::ShutDown);
+        }
+        let now = self.time_service.now_unix_time();
+        // the fetch request may back off, so it's issued without holding the DAG lock
+        let missing_parents = self.dag.read().missing_parents_of(&node);
+        if !missing_parents.is_empty() {
+            let suppress_fetch = match &self.config.fetch_suppression {
+                Some(config) => {
+                    self.fetch_suppression
+                        .should_suppress(&missing_parents, now, config)
+                },
+                None => false,
+            };
+            if suppress_fetch {
+                counters::FETCHES_SUPPRESSED.inc();
+            } else if let Err(err) = request_for_certified_node_with_retry(
+                self.fetch_requester.as_ref(),
+                node,
+                &self.config.fetch_retry,
+                &self.time_service,
             )
             .await
+            {
+                error!("request to fetch failed: {}", err);
+            }
+            bail!(DagDriverError::MissingParents);
+        }
+        // a node without parents is only authored in the genesis round, which has no round below
+        // it to link to
+        if node.parents().is_empty() && node.round() != GENESIS_ROUND {
+            bail!(DagDriverError::ParentlessNode {
+                round: node.round()
+            });
+        }
+        if let Some(highest_parent_round) =
+            node.parents_metadata().map(|parent| parent.round()).max()
         {
-            Ok(payload) => payload,
-            Err(e) => {
-                // TODO: return empty payload instead
-                panic!("error pulling payload: {}", e);
+            if node.round() != highest_parent_round + 1 {
+                bail!(DagDriverError::InvalidRound {
+                    round: node.round(),
+                    highest_parent_round,
+                });
+            }
+        }
+        if node.round() > GENESIS_ROUND {
+            Self::check_parents_quorum(&node, &self.epoch_state.verifier)?;
+        }
+
+        let is_own_node = *node.author() == self.author;
+        let skip_prefetch =
+            is_own_node && self.config.own_node_policy == OwnNodePolicy::SkipPayloadPrefetch;
+        if !skip_prefetch {
+            self.prefetch_payload_data(node.payload(), node.metadata().timestamp());
+        }
+
+        let (highest_strong_links_round, highest_round) = {
+            let mut dag_writer = self.dag.write();
+
+            let round = node.round();
+            let digest = node.digest();
+            if let Err(e) = dag_writer.add_node_with_provenance(node, NodeProvenance::Rpc(sender)) {
+                if let Some(DagStoreError::Equivocation { author, round }) =
+                    e.downcast_ref::<DagStoreError>()
+                {
+                    counters::EQUIVOCATIONS
+                        .with_label_values(&[&author.to_string()])
+                        .inc();
+                    bail!(DagDriverError::Equivocation {
+                        author: *author,
+                        round: *round,
+                    });
+                }
+                return Err(e);
+            }
+            if let Some(config) = &self.config.fetch_suppression {
+                self.fetch_suppression.record_add(&digest, now, config);
+            }
+            if is_own_node {
+                self.highest_own_certified_round =
+                    std::cmp::max(self.highest_own_certified_round, Some(round));
+            }
+
+            let highest_strong_links_round =
+                Self::strong_links_frontier(&dag_writer, &self.epoch_state.verifier);
+            if !self.has_min_strong_link_stake(&dag_writer, highest_strong_links_round) {
+                return Ok(());
+            }
+            (highest_strong_links_round, dag_writer.highest_round())
+        };
+
+        if self.current_round <= highest_strong_links_round {
+            if self.exceeds_commit_lag(highest_round) {
+                counters::ROUNDS_STALLED_BY_BACKPRESSURE.inc();
+                return Ok(());
+            }
+            self.enter_new_round(highest_strong_links_round + 1).await?;
+        }
+        Ok(())
+    }
+
+    /// Whether the DAG is too far ahead of the highest committed anchor to enter a new round, per
+    /// `max_commit_lag_rounds`.
+    fn exceeds_commit_lag(&self, highest_round: Round) -> bool {
+        let max_commit_lag_rounds = match self.config.max_commit_lag_rounds {
+            Some(max_commit_lag_rounds) => max_commit_lag_rounds,
+            None => return false,
+        };
+        let highest_committed_anchor_round = self
+            .ledger_info_provider
+            .get_highest_committed_anchor_round();
+        let commit_lag = highest_round.saturating_sub(highest_committed_anchor_round);
+        if commit_lag > max_commit_lag_rounds {
+            warn!(
+                "not entering a new round, the DAG is {} rounds ahead of the highest committed anchor",
+                commit_lag
+            );
+            return true;
+        }
+        false
+    }
+
+    /// Whether the nodes of `round` hold enough stake to be the strong links of the next round,
+    /// per `min_strong_link_stake_fraction`.
+    fn has_min_strong_link_stake(&self, dag: &Dag, round: Round) -> bool {
+        let min_fraction = match self.config.min_strong_link_stake_fraction {
+            Some(min_fraction) => min_fraction,
+            None => return true,
+        };
+        let verifier = &self.epoch_state.verifier;
+        let stake = dag.round_voting_power(round, verifier);
+        stake as f64 >= min_fraction * verifier.total_voting_power() as f64
+    }
+
+    fn prefetch_payload_data(&mut self, payload: &Payload, timestamp: u64) {
+        let batch_size = match self.config.payload_prefetch_batch_size {
+            Some(batch_size) => batch_size,
+            None => {
+                self.payload_manager
+                    .prefetch_payload_data(payload, timestamp);
+                return;
             },
         };
-        // TODO: need to wait to pass median of parents timestamp
+        // only quorum store payloads have data to fetch
+        if matches!(payload, Payload::InQuorumStore(_)) {
+            if self.pending_prefetches.is_empty() {
+                self.pending_prefetches_since = self.time_service.now();
+            }
+            self.pending_prefetches.push((payload.clone(), timestamp));
+        }
+        if self.pending_prefetches.len() >= batch_size {
+            self.flush_prefetches();
+        }
+    }
+
+    /// Completes once the oldest buffered payload prefetch waited `PAYLOAD_PREFETCH_MAX_DELAY`,
+    /// never if none is buffered. The returned future doesn't borrow the driver, call
+    /// `flush_delayed_prefetches` once it completes.
+    pub fn prefetch_flush_timeout(&self) -> impl Future<Output = ()> + 'static {
+        let sleep = (!self.pending_prefetches.is_empty()).then(|| {
+            self.time_service
+                .sleep_until(self.pending_prefetches_since + PAYLOAD_PREFETCH_MAX_DELAY)
+        });
+        async move {
+            match sleep {
+                Some(sleep) => sleep.await,
+                None => future::pending().await,
+            }
+        }
+    }
+
+    /// Prefetches the buffered payloads once the oldest waited `PAYLOAD_PREFETCH_MAX_DELAY`, so
+    /// they're not held back while no new round is entered, e.g. under commit lag backpressure.
+    pub fn flush_delayed_prefetches(&mut self) {
+        let waited = self
+            .time_service
+            .now()
+            .saturating_duration_since(self.pending_prefetches_since);
+        if waited >= PAYLOAD_PREFETCH_MAX_DELAY {
+            self.flush_prefetches();
+        }
+    }
+
+    fn flush_prefetches(&mut self) {
+        if !self.pending_prefetches.is_empty() {
+            self.payload_manager
+                .prefetch_payload_data_batch(&self.pending_prefetches);
+            self.pending_prefetches.clear();
+        }
+    }
+
+    pub async fn enter_new_round(&mut self, new_round: Round) -> anyhow::Result<()> {
+        if self.is_shut_down {
+            bail!(DagDriverError::ShutDown);
+        }
+        if new_round < GENESIS_ROUND {
+            bail!(DagDriverError::BelowGenesisRound { round: new_round });
+        }
+        debug!("entering new round {}", new_round);
+        self.flush_prefetches();
+        let strong_links = if new_round == GENESIS_ROUND {
+            // there's no previous round to link to, whatever the empty strong links policy
+            vec![]
+        } else {
+            let maybe_strong_links = self
+                .dag
+                .read()
+                .get_strong_links_for_round(new_round - 1, &self.epoch_state.verifier);
+            match maybe_strong_links {
+                Some(strong_links) => strong_links,
+                None => match self.config.empty_strong_links_policy {
+                    EmptyStrongLinksPolicy::Proceed => vec![],
+                    EmptyStrongLinksPolicy::Warn => {
+                        warn!("entering round {} with empty strong links", new_round);
+                        vec![]
+                    },
+                    EmptyStrongLinksPolicy::Refuse => {
+                        error!(
+                            "refusing to enter round {} with empty strong links",
+                            new_round
+                        );
+                        return Ok(());
+                    },
+                },
+            }
+        };
+        let payload = self.pull_payload(new_round, &strong_links).await;
+        if payload.is_empty() {
+            self.consecutive_empty_rounds += 1;
+        } else {
+            self.consecutive_empty_rounds = 0;
+        }
+        if self.config.parent_timestamp_policy == ParentTimestampPolicy::WaitForMedian {
+            if let Some(median_timestamp) = Self::median_parent_timestamp(&strong_links) {
+                let now = self.time_service.now_unix_time().as_micros() as u64;
+                if now <= median_timestamp {
+                    self.time_service
+                        .sleep(Duration::from_micros(median_timestamp + 1 - now))
+                        .await;
+                }
+            }
+        }
         let highest_parent_timestamp = strong_links
             .iter()
             .map(|node| node.metadata().timestamp())
@@ -202,7 +918,18 @@ impl DagDriver {
             self.time_service.now_unix_time().as_micros() as u64,
             highest_parent_timestamp + 1,
         );
+        if self.current_round > 0 {
+            self.record_round_timing(new_round);
+        }
         self.current_round = new_round;
+        self.current_round_entered_at = self.time_service.now();
+        self.round_timer_started_at = self.current_round_entered_at;
+        counters::update_finality_lag(
+            self.current_round,
+            self.ledger_info_provider
+                .get_highest_committed_anchor_round(),
+        );
+        let weak_links = self.weak_link_candidates(&strong_links);
         let new_node = Node::new(
             self.epoch_state.epoch,
             self.current_round,
@@ -210,39 +937,282 @@ impl DagDriver {
             timestamp,
             payload,
             strong_links,
-            Extensions::empty(),
+            Extensions::with_weak_links(weak_links),
         );
         self.storage
             .save_pending_node(&new_node)
             .expect("node must be saved");
         self.broadcast_node(new_node);
+        Ok(())
+    }
+
+    /// The median timestamp of the parents, the upper one for an even number of parents.
+    fn median_parent_timestamp(parents: &[NodeCertificate]) -> Option<u64> {
+        let mut timestamps: Vec<_> = parents
+            .iter()
+            .map(|parent| parent.metadata().timestamp())
+            .collect();
+        timestamps.sort_unstable();
+        timestamps.get(timestamps.len() / 2).copied()
+    }
+
+    /// Pulls the payload of a node for `new_round` with the given strong links, excluding the
+    /// transactions in their causal history and the recently committed ones.
+    async fn pull_payload(&self, new_round: Round, strong_links: &[NodeCertificate]) -> Payload {
+        let highest_commit_round = self
+            .ledger_info_provider
+            .get_highest_committed_anchor_round();
+        let dag_window = self.config.dag_window() as Round;
+        let payload_filter = {
+            let dag_reader = self.dag.read();
+            let recent_commits = self.recent_commits.lock();
+            // transactions committed before the DAG window are excluded through the recent commits
+            let mut exclude_payloads: Vec<_> = recent_commits.payloads().collect();
+            if !strong_links.is_empty() {
+                exclude_payloads.extend(
+                    dag_reader
+                        .reachable(
+                            strong_links.iter().map(|node| node.metadata()),
+                            Some(highest_commit_round.saturating_sub(dag_window)),
+                            |_| true,
+                        )
+                        .map(|node_status| node_status.as_node().payload()),
+                );
+            }
+            PayloadFilter::from(&exclude_payloads)
+        };
+        match self
+            .payload_client
+            .pull_payload(
+                self.payload_poll_time(),
+                self.config.payload.max_txns,
+                self.config.payload.max_bytes,
+                payload_filter,
+                Box::pin(async {}),
+                false,
+                0,
+                0.0,
+                self.config.payload_seed,
+            )
+            .await
+        {
+            Ok(payload) => self.validate_payload(new_round, payload),
+            Err(e) if e.is_timeout() => {
+                warn!("timed out pulling payload for round {}: {}", new_round, e);
+                counters::PAYLOAD_PULL_TIMEOUTS.inc();
+                self.empty_payload()
+            },
+            Err(e) => {
+                counters::PAYLOAD_PULL_ERRORS.inc();
+                match self.config.payload_pull_error_policy {
+                    PayloadPullErrorPolicy::EmptyPayload => {
+                        error!("error pulling payload for round {}: {}", new_round, e);
+                        self.empty_payload()
+                    },
+                    PayloadPullErrorPolicy::Panic => panic!("error pulling payload: {}", e),
+                }
+            },
+        }
+    }
+
+    /// Drops the transactions of the payload that fail the configured validation.
+    fn validate_payload(&self, new_round: Round, payload: Payload) -> Payload {
+        match (&self.payload_validator, payload) {
+            (Some(payload_validator), Payload::DirectMempool(txns)) => {
+                let num_txns = txns.len();
+                let valid_txns: Vec<_> = txns
+                    .into_iter()
+                    .filter(|txn| payload_validator.is_valid(txn))
+                    .collect();
+                let num_dropped = num_txns - valid_txns.len();
+                if num_dropped > 0 {
+                    warn!(
+                        "dropped {} invalid transactions from the payload for round {}",
+                        num_dropped, new_round
+                    );
+                    counters::INVALID_PAYLOAD_TXNS_DROPPED.inc_by(num_dropped as u64);
+                }
+                Payload::DirectMempool(valid_txns)
+            },
+            (_, payload) => payload,
+        }
+    }
+
+    /// Pulls the payload a node for the next round would be authored with, given the current DAG
+    /// and mempool state, without authoring or broadcasting a node. For debugging and simulation.
+    pub async fn preview_payload(&self) -> Payload {
+        let strong_links = self
+            .dag
+            .read()
+            .get_strong_links_for_round(self.current_round, &self.epoch_state.verifier)
+            .unwrap_or_default();
+        self.pull_payload(self.current_round + 1, &strong_links)
+            .await
+    }
+
+    fn empty_payload(&self) -> Payload {
+        Payload::empty(matches!(
+            *self.payload_manager,
+            PayloadManager::InQuorumStore(..)
+        ))
+    }
+
+    fn payload_poll_time(&self) -> Duration {
+        match &self.config.idle_backoff {
+            Some(idle_backoff)
+                if self.consecutive_empty_rounds >= idle_backoff.empty_rounds_threshold =>
+            {
+                let exponent = (self.consecutive_empty_rounds - idle_backoff.empty_rounds_threshold)
+                    .min(30) as u32;
+                self.config
+                    .payload
+                    .pull_timeout
+                    .saturating_mul(2u32.pow(exponent + 1))
+                    .min(idle_backoff.max_poll_time)
+            },
+            _ => self.config.payload.pull_timeout,
+        }
+    }
+
+    /// Prunes the DAG below the window of the highest committed anchor, once per new commit. The
+    /// nodes below the window are no longer reachable for ordering or payload filtering.
+    fn prune_committed(&mut self) {
+        let committed_round = self
+            .ledger_info_provider
+            .get_highest_committed_anchor_round();
+        if committed_round <= self.last_pruned_commit_round {
+            return;
+        }
+        self.last_pruned_commit_round = committed_round;
+        let num_pruned = self
+            .dag
+            .write()
+            .prune(committed_round.saturating_sub(self.config.dag_window() as Round));
+        counters::PRUNED_NODES.inc_by(num_pruned as u64);
+    }
+
+    /// Acks a certified node along with our latest ledger info, so the author can detect a
+    /// disagreement with the ledger info it broadcast.
+    fn certified_ack(&self, epoch: u64) -> CertifiedAck {
+        let latest_ledger_info = self.ledger_info_provider.get_latest_ledger_info();
+        CertifiedAck::new(epoch).with_commit_info(latest_ledger_info.commit_info().clone())
+    }
+
+    /// Stops the broadcast and persists the pending node and the ordering state, returning once
+    /// they are durable. A driver restarted on the same storage resumes the pending node.
+    pub async fn graceful_shutdown(&mut self) -> anyhow::Result<()> {
+        if let Some(handle) = self.rb_abort_handle.take() {
+            handle.abort();
+        }
+        // wait for the task to stop, so it doesn't checkpoint the broadcast after the flush
+        if let Some(task) = self.rb_task.take() {
+            if let Err(e) = task.await {
+                warn!("broadcast task failed: {:?}", e);
+            }
+        }
+        self.broadcast_in_flight.store(false, Ordering::Relaxed);
+        // the pending node was saved when entering the round, the flush makes it durable
+        self.order_rule.flush()?;
+        self.storage.flush()
+    }
+
+    /// Stops the driver when its DAG instance is torn down, e.g. when its epoch ends: shuts down
+    /// gracefully, so that the node isn't broadcast across the epoch change, and makes the driver
+    /// inert. Nodes can't be added nor rounds entered afterwards.
+    pub async fn shutdown(&mut self) -> anyhow::Result<()> {
+        let result = self.graceful_shutdown().await;
+        self.pending_prefetches.clear();
+        self.is_shut_down = true;
+        result
     }
 
     pub fn broadcast_node(&mut self, node: Node) {
+        self.last_node_size = bcs::serialized_size(&node).unwrap_or_default() as u64;
+        self.last_node_txns = node.payload().len() as u64;
+        self.last_node_parents = node
+            .parents()
+            .iter()
+            .map(|parent| parent.metadata().clone())
+            .collect();
         let rb = self.reliable_broadcast.clone();
         let (abort_handle, abort_registration) = AbortHandle::new_pair();
-        let signature_builder =
+        let mut signature_builder =
             SignatureBuilder::new(node.metadata().clone(), self.epoch_state.clone());
-        let cert_ack_set = CertificateAckState::new(self.epoch_state.verifier.len());
+        if let Some(interval) = self.config.broadcast_checkpoint_interval {
+            // resume with the signatures collected before a restart, if it was broadcasting the node
+            match self.storage.get_broadcast_progress() {
+                Ok(Some(progress)) => signature_builder = signature_builder.with_progress(progress),
+                Ok(None) => {},
+                Err(e) => warn!("failed to read the broadcast progress: {:?}", e),
+            }
+            signature_builder = signature_builder.with_checkpoint(self.storage.clone(), interval);
+        }
         let latest_ledger_info = self.ledger_info_provider.get_latest_ledger_info();
+        let cert_ack_set = CertificateAckState::new(self.epoch_state.verifier.len())
+            .with_commit_info(latest_ledger_info.commit_info().clone());
         let round = node.round();
+        // the phases are child spans of the broadcast span, so they're correlated in traces
+        // whichever task polls them
+        let broadcast_span = debug_span!(
+            "dag_broadcast_node",
+            epoch = node.metadata().epoch(),
+            round,
+            digest = %node.digest()
+        );
+        let signatures_span = debug_span!(parent: &broadcast_span, "dag_collect_signatures");
+        let certified_node_span =
+            debug_span!(parent: &broadcast_span, "dag_broadcast_certified_node");
+        let broadcast_start = Instant::now();
+        let quorum_collected = Arc::new(AtomicBool::new(false));
+        let prev_quorum_collected = std::mem::replace(
+            &mut self.broadcast_quorum_collected,
+            quorum_collected.clone(),
+        );
         let core_task = self
             .reliable_broadcast
             .broadcast(node.clone(), signature_builder)
+            .instrument(signatures_span)
             .then(move |certificate| {
+                quorum_collected.store(true, Ordering::Relaxed);
+                counters::BROADCAST_TO_CERTIFICATE_SECONDS
+                    .observe(broadcast_start.elapsed().as_secs_f64());
                 let certified_node = CertifiedNode::new(node, certificate.signatures().to_owned());
                 let certified_node_msg =
                     CertifiedNodeMessage::new(certified_node, latest_ledger_info);
                 rb.broadcast(certified_node_msg, cert_ack_set)
+                    .instrument(certified_node_span)
             });
+        let broadcast_in_flight = Arc::new(AtomicBool::new(true));
+        self.broadcast_in_flight = broadcast_in_flight.clone();
         let task = async move {
             debug!("Start reliable broadcast for round {}", round);
             core_task.await;
+            broadcast_in_flight.store(false, Ordering::Relaxed);
             debug!("Finish reliable broadcast for round {}", round);
-        };
-        tokio::spawn(Abortable::new(task, abort_registration));
+        }
+        .instrument(broadcast_span);
+        self.rb_task = Some(tokio::spawn(Abortable::new(task, abort_registration)));
         if let Some(prev_handle) = self.rb_abort_handle.replace(abort_handle) {
+            self.aborted_broadcasts += 1;
+            counters::ABORTED_BROADCASTS.inc();
             prev_handle.abort();
+            self.record_broadcast_outcome(prev_quorum_collected.load(Ordering::Relaxed));
+        }
+    }
+
+    fn record_broadcast_outcome(&mut self, quorum_collected: bool) {
+        if quorum_collected {
+            self.consecutive_failed_broadcasts = 0;
+            return;
+        }
+        self.consecutive_failed_broadcasts += 1;
+        if self.config.broadcast_failure_alert_threshold == Some(self.consecutive_failed_broadcasts)
+        {
+            counters::BROADCAST_QUORUM_LOST_ALERTS.inc();
+            error!(
+                "the last {} broadcasts of our nodes in epoch {} failed to collect a signature quorum, this validator is likely partitioned from the majority",
+                self.consecutive_failed_broadcasts, self.epoch_state.epoch
+            );
         }
     }
 }
@@ -253,19 +1223,71 @@ impl RpcHandler for DagDriver {
     type Response = CertifiedAck;
 
     async fn process(&mut self, node: Self::Request) -> anyhow::Result<Self::Response> {
+        let author = *node.author();
+        self.process_from(node, author).await
+    }
+}
+
+impl DagDriver {
+    /// Processes a certified node delivered by `sender`, which is recorded as its provenance.
+    pub async fn process_from(
+        &mut self,
+        node: CertifiedNode,
+        sender: Author,
+    ) -> anyhow::Result<CertifiedAck> {
         let epoch = node.metadata().epoch();
+        if epoch > self.epoch_state.epoch {
+            self.buffer_future_epoch_node(node);
+            bail!(DagDriverError::FutureEpoch {
+                node_epoch: epoch,
+                current_epoch: self.epoch_state.epoch,
+            });
+        }
+
+        if let Some(certified_node_verifier) = &self.certified_node_verifier {
+            if let Err(e) = certified_node_verifier.verify(&node, &self.epoch_state.verifier) {
+                warn!(error = ?e, "invalid certificate for node {}", node.id());
+                bail!(DagDriverError::InvalidCertificate);
+            }
+        }
+
+        if *node.author() == self.author {
+            counters::OWN_NODES_RECEIVED.inc();
+        }
+
         {
             let dag_reader = self.dag.read();
             if dag_reader.exists(node.metadata()) {
-                return Ok(CertifiedAck::new(epoch));
+                return Ok(self.certified_ack(epoch));
+            }
+        }
+
+        if node.round() <= self.order_rule.highest_ordered_anchor_round() {
+            match self.config.late_node_policy {
+                LateNodePolicy::AddForAudit => {},
+                LateNodePolicy::Drop => {
+                    debug!("dropping node {} of an ordered round", node.id());
+                    counters::LATE_NODES_DROPPED.inc();
+                    return Ok(self.certified_ack(epoch));
+                },
             }
         }
 
         let node_metadata = node.metadata().clone();
-        self.add_node(node)
+        self.add_node(node, sender)
             .await
             .map(|_| self.order_rule.process_new_node(&node_metadata))?;
+        self.prune_committed();
 
-        Ok(CertifiedAck::new(epoch))
+        Ok(self.certified_ack(epoch))
+    }
+}
+
+impl Drop for DagDriver {
+    fn drop(&mut self) {
+        // the broadcast task is detached, stop it along with the driver
+        if let Some(handle) = self.rb_abort_handle.take() {
+            handle.abort();
+        }
     }
 }
@@ -1,22 +1,27 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 
-use super::{dag_network::RpcWithFallback, types::NodeMetadata, RpcHandler};
+use super::{
+    dag_network::{RpcResultWithResponder, RpcWithFallback},
+    RpcHandler,
+};
 use crate::dag::{
+    counters,
     dag_network::TDAGNetworkSender,
     dag_store::Dag,
     types::{CertifiedNode, FetchResponse, Node, RemoteFetchRequest},
 };
 use anyhow::{anyhow, ensure};
 use aptos_consensus_types::common::Author;
-use aptos_infallible::RwLock;
-use aptos_logger::{debug, error};
-use aptos_time_service::TimeService;
+use aptos_crypto::HashValue;
+use aptos_infallible::{Mutex, RwLock};
+use aptos_logger::{debug, error, warn};
+use aptos_time_service::{TimeService, TimeServiceTrait};
 use aptos_types::epoch_state::EpochState;
 use async_trait::async_trait;
 use futures::{stream::FuturesUnordered, Stream, StreamExt};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
@@ -24,10 +29,13 @@ use std::{
 };
 use thiserror::Error as ThisError;
 use tokio::sync::{
-    mpsc::{Receiver, Sender},
-    oneshot,
+    mpsc::{error::TrySendError, Receiver, Sender},
+    oneshot, Notify,
 };
 
+/// Maximum number of fetch requests waiting for the fetch service.
+const MAX_PENDING_FETCHES: usize = 16;
+
 pub struct FetchWaiter<T> {
     rx: Receiver<oneshot::Receiver<T>>,
     futures: Pin<Box<FuturesUnordered<oneshot::Receiver<T>>>>,
@@ -54,38 +62,186 @@ impl<T> Stream for FetchWaiter<T> {
     }
 }
 
+/// Why a fetch request could not be handed to the fetch service.
+#[derive(Debug, ThisError)]
+pub enum FetchRequesterError {
+    /// The fetch service is lagging behind, the request may go through later.
+    #[error("fetch requests queue is full")]
+    QueueFull,
+    /// The fetch service is gone, the request can never go through.
+    #[error("fetch service is closed")]
+    Closed,
+}
+
+impl<T> From<TrySendError<T>> for FetchRequesterError {
+    fn from(err: TrySendError<T>) -> Self {
+        match err {
+            TrySendError::Full(_) => FetchRequesterError::QueueFull,
+            TrySendError::Closed(_) => FetchRequesterError::Closed,
+        }
+    }
+}
+
 pub trait TFetchRequester: Send + Sync {
-    fn request_for_node(&self, node: Node) -> anyhow::Result<()>;
-    fn request_for_certified_node(&self, node: CertifiedNode) -> anyhow::Result<()>;
+    fn request_for_node(&self, node: Node) -> Result<(), FetchRequesterError>;
+    fn request_for_certified_node(&self, node: CertifiedNode) -> Result<(), FetchRequesterError>;
+}
+
+/// How a fetch request is retried while the fetch requests queue is full.
+#[derive(Clone, Debug)]
+pub struct FetchRetryConfig {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: usize,
+    /// Backoff before the first retry, doubling on every retry after it.
+    pub initial_backoff: Duration,
+}
+
+impl Default for FetchRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+/// Requests to fetch the missing parents of the certified node, retrying with an exponential
+/// backoff while the queue is full. Gives up right away if the fetch service is closed.
+pub async fn request_for_certified_node_with_retry(
+    fetch_requester: &dyn TFetchRequester,
+    node: CertifiedNode,
+    config: &FetchRetryConfig,
+    time_service: &TimeService,
+) -> Result<(), FetchRequesterError> {
+    let mut backoff = config.initial_backoff;
+    let mut attempt = 1;
+    loop {
+        match fetch_requester.request_for_certified_node(node.clone()) {
+            Err(FetchRequesterError::QueueFull) if attempt < config.max_attempts => {
+                debug!(
+                    "fetch requests queue is full, retrying the request for {} in {:?}",
+                    node.id(),
+                    backoff
+                );
+                counters::FETCH_REQUEST_RETRIES.inc();
+                time_service.sleep(backoff).await;
+                backoff = backoff.saturating_mul(2);
+                attempt += 1;
+            },
+            result => return result,
+        }
+    }
+}
+
+/// Fetch requests waiting for the fetch service. Once full, a new request evicts the oldest one,
+/// whose node is dropped: it is requested again if a later node still depends on it. A request for
+/// a node that is already queued or being fetched is collapsed into it, and completed along with it.
+pub(super) struct PendingFetches {
+    requests: Mutex<VecDeque<LocalFetchRequest>>,
+    /// The requests collapsed into each queued or in-flight request, by digest of the node.
+    duplicates: Mutex<HashMap<HashValue, Vec<LocalFetchRequest>>>,
+    max_size: usize,
+    notify: Notify,
+}
+
+impl PendingFetches {
+    pub(super) fn new(max_size: usize) -> Self {
+        Self {
+            requests: Mutex::new(VecDeque::with_capacity(max_size)),
+            duplicates: Mutex::new(HashMap::new()),
+            max_size,
+            notify: Notify::new(),
+        }
+    }
+
+    pub(super) fn push(&self, request: LocalFetchRequest) {
+        {
+            let mut requests = self.requests.lock();
+            let mut duplicates = self.duplicates.lock();
+            if let Some(collapsed) = duplicates.get_mut(&request.node().digest()) {
+                counters::DEDUPLICATED_FETCH_REQUESTS.inc();
+                collapsed.push(request);
+                return;
+            }
+            duplicates.insert(request.node().digest(), vec![]);
+            if requests.len() >= self.max_size {
+                if let Some(evicted) = requests.pop_front() {
+                    duplicates.remove(&evicted.node().digest());
+                    warn!(
+                        "pending fetches are full ({} requests), evicting the request for {}",
+                        self.max_size,
+                        evicted.node().id()
+                    );
+                }
+            }
+            requests.push_back(request);
+            counters::PENDING_FETCHES.set(requests.len() as i64);
+        }
+        self.notify.notify_one();
+    }
+
+    /// Waits for the oldest request.
+    pub(super) async fn pop(&self) -> LocalFetchRequest {
+        loop {
+            {
+                let mut requests = self.requests.lock();
+                if let Some(request) = requests.pop_front() {
+                    counters::PENDING_FETCHES.set(requests.len() as i64);
+                    return request;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.requests.lock().len()
+    }
+
+    /// Completes a popped request and the requests collapsed into it, notifying their waiters if
+    /// the fetch succeeded. Otherwise they are dropped, and their waiters observe it.
+    pub(super) fn complete(&self, request: LocalFetchRequest, fetched: bool) {
+        let collapsed = self
+            .duplicates
+            .lock()
+            .remove(&request.node().digest())
+            .unwrap_or_default();
+        if fetched {
+            request.notify();
+            for request in collapsed {
+                request.notify();
+            }
+        }
+    }
 }
 
 pub struct FetchRequester {
-    request_tx: Sender<LocalFetchRequest>,
+    pending_fetches: Arc<PendingFetches>,
     node_waiter_tx: Sender<oneshot::Receiver<Node>>,
     certified_node_waiter_tx: Sender<oneshot::Receiver<CertifiedNode>>,
 }
 
+impl FetchRequester {
+    /// Number of fetch requests waiting to be picked up by the fetch service.
+    pub fn pending_requests(&self) -> usize {
+        self.pending_fetches.len()
+    }
+}
+
 impl TFetchRequester for FetchRequester {
-    fn request_for_node(&self, node: Node) -> anyhow::Result<()> {
+    fn request_for_node(&self, node: Node) -> Result<(), FetchRequesterError> {
         let (res_tx, res_rx) = oneshot::channel();
-        let fetch_req = LocalFetchRequest::Node(node, res_tx);
-        self.request_tx
-            .try_send(fetch_req)
-            .map_err(|e| anyhow::anyhow!("unable to send node fetch request to channel: {}", e))?;
         self.node_waiter_tx.try_send(res_rx)?;
+        self.pending_fetches
+            .push(LocalFetchRequest::Node(node, res_tx));
         Ok(())
     }
 
-    fn request_for_certified_node(&self, node: CertifiedNode) -> anyhow::Result<()> {
+    fn request_for_certified_node(&self, node: CertifiedNode) -> Result<(), FetchRequesterError> {
         let (res_tx, res_rx) = oneshot::channel();
-        let fetch_req = LocalFetchRequest::CertifiedNode(node, res_tx);
-        self.request_tx.try_send(fetch_req).map_err(|e| {
-            anyhow::anyhow!(
-                "unable to send certified node fetch request to channel: {}",
-                e
-            )
-        })?;
         self.certified_node_waiter_tx.try_send(res_rx)?;
+        self.pending_fetches
+            .push(LocalFetchRequest::CertifiedNode(node, res_tx));
         Ok(())
     }
 }
@@ -128,7 +284,7 @@ impl LocalFetchRequest {
 pub struct DagFetcherService {
     inner: DagFetcher,
     dag: Arc<RwLock<Dag>>,
-    request_rx: Receiver<LocalFetchRequest>,
+    pending_fetches: Arc<PendingFetches>,
     ordered_authors: Vec<Author>,
 }
 
@@ -144,7 +300,7 @@ impl DagFetcherService {
         FetchWaiter<Node>,
         FetchWaiter<CertifiedNode>,
     ) {
-        let (request_tx, request_rx) = tokio::sync::mpsc::channel(16);
+        let pending_fetches = Arc::new(PendingFetches::new(MAX_PENDING_FETCHES));
         let (node_tx, node_rx) = tokio::sync::mpsc::channel(100);
         let (certified_node_tx, certified_node_rx) = tokio::sync::mpsc::channel(100);
         let ordered_authors = epoch_state.verifier.get_ordered_account_addresses();
@@ -152,11 +308,11 @@ impl DagFetcherService {
             Self {
                 inner: DagFetcher::new(epoch_state, network, time_service),
                 dag,
-                request_rx,
+                pending_fetches: pending_fetches.clone(),
                 ordered_authors,
             },
             FetchRequester {
-                request_tx,
+                pending_fetches,
                 node_waiter_tx: node_tx,
                 certified_node_waiter_tx: certified_node_tx,
             },
@@ -165,8 +321,15 @@ impl DagFetcherService {
         )
     }
 
+    /// Sets the number of responders each fetch is sent to at once.
+    pub fn with_fanout(mut self, fanout: u32) -> Self {
+        self.inner = self.inner.with_fanout(fanout);
+        self
+    }
+
     pub async fn start(mut self) {
-        while let Some(local_request) = self.request_rx.recv().await {
+        loop {
+            let local_request = self.pending_fetches.pop().await;
             match self
                 .fetch(
                     local_request.node(),
@@ -174,8 +337,11 @@ impl DagFetcherService {
                 )
                 .await
             {
-                Ok(_) => local_request.notify(),
-                Err(err) => error!("unable to complete fetch successfully: {}", err),
+                Ok(_) => self.pending_fetches.complete(local_request, true),
+                Err(err) => {
+                    error!("unable to complete fetch successfully: {}", err);
+                    self.pending_fetches.complete(local_request, false);
+                },
             }
         }
     }
@@ -194,11 +360,7 @@ impl DagFetcherService {
                 dag_reader.lowest_incomplete_round()
             );
 
-            let missing_parents: Vec<NodeMetadata> = dag_reader
-                .filter_missing(node.parents_metadata())
-                .cloned()
-                .collect();
-
+            let missing_parents = dag_reader.missing_parents_of(node);
             if missing_parents.is_empty() {
                 return Ok(());
             }
@@ -229,6 +391,7 @@ pub(crate) struct DagFetcher {
     network: Arc<dyn TDAGNetworkSender>,
     time_service: TimeService,
     epoch_state: Arc<EpochState>,
+    fanout: u32,
 }
 
 impl DagFetcher {
@@ -241,8 +404,15 @@ impl DagFetcher {
             network,
             time_service,
             epoch_state,
+            fanout: 1,
         }
     }
+
+    /// Sets the number of responders a fetch is sent to at once, see `RpcWithFallback::with_fanout`.
+    pub(crate) fn with_fanout(mut self, fanout: u32) -> Self {
+        self.fanout = fanout;
+        self
+    }
 }
 
 #[async_trait]
@@ -261,23 +431,27 @@ impl TDagFetcher for DagFetcher {
             Duration::from_secs(1),
             self.network.clone(),
             self.time_service.clone(),
-        );
+        )
+        .with_fanout(self.fanout);
 
         // TODO retry
-        while let Some(response) = rpc.next().await {
-            if let Ok(response) = response
+        while let Some(RpcResultWithResponder { responder, result }) = rpc.next().await {
+            if let Ok(response) = result
                 .and_then(FetchResponse::try_from)
                 .and_then(|response| response.verify(&remote_request, &self.epoch_state.verifier))
             {
                 let certified_nodes = response.certified_nodes();
                 // TODO: support chunk response or fallback to state sync
+                match dag
+                    .write()
+                    .add_fetched_nodes(certified_nodes.into_iter().rev(), responder)
                 {
-                    let mut dag_writer = dag.write();
-                    for node in certified_nodes.into_iter().rev() {
-                        if let Err(e) = dag_writer.add_node(node) {
+                    Ok(rejected) => {
+                        for e in rejected {
                             error!("Failed to add node {}", e);
                         }
-                    }
+                    },
+                    Err(e) => error!("Failed to save fetched nodes {}", e),
                 }
 
                 if dag.read().all_exists(remote_request.targets()) {
@@ -329,7 +503,7 @@ impl RpcHandler for FetchRequestHandler {
         );
 
         let certified_nodes: Vec<_> = dag_reader
-            .reachable(
+            .reachable_with_weak_links(
                 message.targets(),
                 Some(message.exists_bitmask().first_round()),
                 |_| true,
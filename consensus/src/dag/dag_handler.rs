@@ -4,7 +4,7 @@ use super::{
     dag_driver::DagDriver,
     dag_fetcher::{FetchRequestHandler, FetchWaiter},
     dag_state_sync::{StateSyncStatus, StateSyncTrigger},
-    types::TDAGMessage,
+    types::{CertifiedNodeMessage, TDAGMessage},
     CertifiedNode, Node,
 };
 use crate::{
@@ -14,7 +14,7 @@ use crate::{
 use anyhow::ensure;
 use aptos_channels::aptos_channel;
 use aptos_consensus_types::common::Author;
-use aptos_logger::{debug, warn};
+use aptos_logger::{debug, error, warn};
 use aptos_network::protocols::network::RpcError;
 use aptos_types::epoch_state::EpochState;
 use bytes::Bytes;
@@ -54,9 +54,35 @@ impl NetworkHandler {
     }
 
     pub async fn run(
-        mut self,
+        &mut self,
         dag_rpc_rx: &mut aptos_channel::Receiver<Author, IncomingDAGRequest>,
+        buffered_nodes: Vec<CertifiedNodeMessage>,
     ) -> StateSyncStatus {
+        // Replay the nodes received while the previous instance was state syncing.
+        for certified_node_msg in buffered_nodes {
+            match self.state_sync_trigger.check(certified_node_msg).await {
+                Ok(StateSyncStatus::Synced(Some(certified_node_msg))) => {
+                    if let Err(e) = self
+                        .dag_driver
+                        .process(certified_node_msg.certified_node())
+                        .await
+                    {
+                        warn!(error = ?e, "error processing buffered certified node");
+                    }
+                },
+                Ok(status @ (StateSyncStatus::NeedsSync(_) | StateSyncStatus::EpochEnds)) => {
+                    return status
+                },
+                Ok(status) => {
+                    warn!(
+                        "skipping buffered certified node with sync status {:?}",
+                        status
+                    )
+                },
+                Err(e) => warn!(error = ?e, "error checking buffered certified node"),
+            }
+        }
+
         // TODO(ibalajiarun): clean up Reliable Broadcast storage periodically.
         loop {
             select! {
@@ -82,6 +108,12 @@ impl NetworkHandler {
                         },
                     };
                 },
+                _ = self.dag_driver.round_timeout() => {
+                    self.dag_driver.check_round_timeout();
+                },
+                _ = self.dag_driver.prefetch_flush_timeout() => {
+                    self.dag_driver.flush_delayed_prefetches();
+                },
                 Some(res) = self.certified_node_fetch_waiter.next() => {
                     match res {
                         Ok(certified_node) => if let Err(e) = self.dag_driver.process(certified_node).await {
@@ -95,6 +127,14 @@ impl NetworkHandler {
         }
     }
 
+    /// Shuts the driver down, once the handler stopped running, before its DAG instance is torn
+    /// down.
+    pub async fn shutdown(&mut self) {
+        if let Err(e) = self.dag_driver.shutdown().await {
+            error!(error = ?e, "unable to shut down the dag driver");
+        }
+    }
+
     fn verify_incoming_rpc(
         &self,
         dag_message: &DAGMessage,
@@ -124,6 +164,27 @@ impl NetworkHandler {
         }
     }
 
+    async fn process_future_epoch_node(
+        &mut self,
+        certified_node_msg: CertifiedNodeMessage,
+        sender: Author,
+    ) -> anyhow::Result<DAGMessage> {
+        ensure!(
+            *certified_node_msg.author() == sender,
+            "Message author mismatch network sender"
+        );
+        let node_epoch = certified_node_msg.epoch();
+        let response = self
+            .dag_driver
+            .process_from(certified_node_msg.certified_node(), sender)
+            .await
+            .map(|r| r.into());
+        self.state_sync_trigger
+            .request_epoch_sync(sender, node_epoch)
+            .await;
+        response
+    }
+
     async fn process_rpc(
         &mut self,
         rpc_request: IncomingDAGRequest,
@@ -136,9 +197,17 @@ impl NetworkHandler {
             rpc_request.sender
         );
 
-        let response: anyhow::Result<DAGMessage> = {
-            let verification_result = self.verify_incoming_rpc(&dag_message, rpc_request.sender);
-            match verification_result {
+        let response: anyhow::Result<DAGMessage> = match dag_message {
+            DAGMessage::CertifiedNodeMsg(certified_node_msg)
+                if certified_node_msg.epoch() > self.epoch_state.epoch =>
+            {
+                // A node from a future epoch can't be verified against the validators of this
+                // epoch, so leave it to the driver to reject the node, and catch up with the
+                // sender's epoch.
+                self.process_future_epoch_node(certified_node_msg, rpc_request.sender)
+                    .await
+            },
+            dag_message => match self.verify_incoming_rpc(&dag_message, rpc_request.sender) {
                 Ok(_) => match dag_message {
                     DAGMessage::NodeMsg(node) => {
                         self.node_receiver.process(node).await.map(|r| r.into())
@@ -147,7 +216,10 @@ impl NetworkHandler {
                         match self.state_sync_trigger.check(certified_node_msg).await? {
                             StateSyncStatus::Synced(Some(certified_node_msg)) => self
                                 .dag_driver
-                                .process(certified_node_msg.certified_node())
+                                .process_from(
+                                    certified_node_msg.certified_node(),
+                                    rpc_request.sender,
+                                )
                                 .await
                                 .map(|r| r.into()),
                             status @ (StateSyncStatus::NeedsSync(_)
@@ -161,7 +233,7 @@ impl NetworkHandler {
                     _ => unreachable!("verification must catch this error"),
                 },
                 Err(err) => Err(err),
-            }
+            },
         };
 
         debug!(
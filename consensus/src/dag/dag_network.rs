@@ -17,6 +17,9 @@ use std::{
     time::Duration,
 };
 
+/// Handles the DAG RPCs of one type. `process` takes `&mut self`, so the calls on a handler never
+/// overlap: the `NetworkHandler` processes the incoming RPCs one at a time, and the DAG RPC channel
+/// buffers up to 10 pending requests per peer, dropping new ones beyond that.
 #[async_trait]
 pub trait RpcHandler {
     type Request;
@@ -45,6 +48,9 @@ pub trait TDAGNetworkSender: Send + Sync + RBNetworkSender<DAGMessage> {
     ) -> RpcWithFallback;
 }
 
+/// Maximum number of responders requested at once by the fallbacks, unless the fan-out is larger.
+const MAX_FALLBACK_REQUESTS: u32 = 4;
+
 struct Responders {
     peers: Vec<Author>,
     generator: ExponentialNumberGenerator,
@@ -72,15 +78,20 @@ impl Responders {
     }
 }
 
+/// The result of an RPC along with the peer it was sent to.
+pub struct RpcResultWithResponder {
+    pub responder: Author,
+    pub result: anyhow::Result<DAGMessage>,
+}
+
 pub struct RpcWithFallback {
     responders: Responders,
     message: DAGMessage,
     rpc_timeout: Duration,
 
     terminated: bool,
-    futures: Pin<
-        Box<FuturesUnordered<Pin<Box<dyn Future<Output = anyhow::Result<DAGMessage>> + Send>>>>,
-    >,
+    futures:
+        Pin<Box<FuturesUnordered<Pin<Box<dyn Future<Output = RpcResultWithResponder> + Send>>>>>,
     sender: Arc<dyn TDAGNetworkSender>,
     interval: Pin<Box<Interval>>,
 }
@@ -95,7 +106,7 @@ impl RpcWithFallback {
         time_service: TimeService,
     ) -> Self {
         Self {
-            responders: Responders::new(responders, 1, 4),
+            responders: Responders::new(responders, 1, MAX_FALLBACK_REQUESTS),
             message,
             rpc_timeout,
 
@@ -105,6 +116,17 @@ impl RpcWithFallback {
             interval: Box::pin(time_service.interval(retry_interval)),
         }
     }
+
+    /// Sends the rpc to `fanout` responders at once, so a single slow or faulty responder doesn't
+    /// stall it. Every retry interval, or as soon as all the pending rpcs failed, twice as many
+    /// responders that weren't requested yet are tried. A fan-out of 0 is treated as 1, as the rpc
+    /// would never be sent otherwise.
+    pub fn with_fanout(mut self, fanout: u32) -> Self {
+        let fanout = fanout.max(1);
+        self.responders.generator =
+            ExponentialNumberGenerator::new(fanout, 2, fanout.max(MAX_FALLBACK_REQUESTS));
+        self
+    }
 }
 
 async fn send_rpc(
@@ -112,12 +134,15 @@ async fn send_rpc(
     peer: Author,
     message: DAGMessage,
     timeout: Duration,
-) -> anyhow::Result<DAGMessage> {
-    sender.send_rpc(peer, message, timeout).await
+) -> RpcResultWithResponder {
+    RpcResultWithResponder {
+        responder: peer,
+        result: sender.send_rpc(peer, message, timeout).await,
+    }
 }
 
 impl Stream for RpcWithFallback {
-    type Item = anyhow::Result<DAGMessage>;
+    type Item = RpcResultWithResponder;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         if !self.futures.is_empty() {
@@ -5,25 +5,36 @@ use super::{
     dag_fetcher::TDagFetcher,
     dag_store::Dag,
     storage::DAGStorage,
-    types::{CertifiedNodeMessage, RemoteFetchRequest},
-    ProofNotifier,
+    types::{CertifiedNodeMessage, NodeMetadata, RemoteFetchRequest},
+    NodeId, ProofNotifier,
 };
 use crate::state_replication::StateComputer;
 use anyhow::ensure;
-use aptos_consensus_types::common::Round;
-use aptos_infallible::RwLock;
-use aptos_logger::error;
-use aptos_time_service::TimeService;
+use aptos_consensus_types::{
+    common::{Author, Round},
+    epoch_retrieval::EpochRetrievalRequest,
+};
+use aptos_infallible::{Mutex, RwLock};
+use aptos_logger::{debug, error, warn};
+use aptos_time_service::{TimeService, TimeServiceTrait};
 use aptos_types::{
     epoch_change::EpochChangeProof, epoch_state::EpochState, ledger_info::LedgerInfoWithSignatures,
 };
 use itertools::Itertools;
-use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 // TODO: move this to onchain config
 // TODO: temporarily setting DAG_WINDOW to 1 to maintain Shoal safety
 pub const DAG_WINDOW: usize = 1;
 pub const STATE_SYNC_WINDOW_MULTIPLIER: usize = 30;
+/// Number of rounds fetched at once when syncing the DAG.
+pub const STATE_SYNC_CHUNK_ROUNDS: Round = 10;
+/// Minimum interval between two epoch retrievals up to the same epoch.
+const EPOCH_RETRIEVAL_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Debug)]
 pub enum StateSyncStatus {
@@ -32,11 +43,114 @@ pub enum StateSyncStatus {
     EpochEnds,
 }
 
+/// What to do with live certified nodes that arrive while the DAG is being state synced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LiveNodePolicy {
+    /// Drop the nodes, they are fetched again as parents of later nodes if needed.
+    Drop,
+    /// Hold up to `max_nodes` nodes and replay them once the sync completes.
+    Buffer { max_nodes: usize },
+}
+
+impl Default for LiveNodePolicy {
+    fn default() -> Self {
+        Self::Buffer { max_nodes: 100 }
+    }
+}
+
+/// Certified nodes received while state syncing, to be replayed after the sync completes.
+pub(super) struct LiveNodeBuffer {
+    policy: LiveNodePolicy,
+    nodes: Vec<CertifiedNodeMessage>,
+}
+
+impl LiveNodeBuffer {
+    pub(super) fn new(policy: LiveNodePolicy) -> Self {
+        Self {
+            policy,
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Returns false if the node is dropped, either by policy or because the buffer is full.
+    pub(super) fn push(&mut self, node: CertifiedNodeMessage) -> bool {
+        match self.policy {
+            LiveNodePolicy::Buffer { max_nodes } if self.nodes.len() < max_nodes => {
+                self.nodes.push(node);
+                true
+            },
+            LiveNodePolicy::Buffer { max_nodes } => {
+                warn!(
+                    "live node buffer is full ({} nodes), dropping node {}",
+                    max_nodes,
+                    node.id()
+                );
+                false
+            },
+            LiveNodePolicy::Drop => false,
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Takes the buffered nodes out in the order they were received.
+    pub(super) fn take(&mut self) -> Vec<CertifiedNodeMessage> {
+        std::mem::take(&mut self.nodes)
+    }
+}
+
+/// Progress of a DAG state sync, persisted after every chunk so that a sync interrupted by a crash
+/// resumes from the last fetched chunk.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct DagSyncCursor {
+    target: NodeId,
+    /// Every round from this one up to the target is fetched and stored.
+    lowest_fetched_round: Round,
+    /// Nodes below `lowest_fetched_round` that are still to be fetched.
+    next_targets: Vec<NodeMetadata>,
+}
+
+impl DagSyncCursor {
+    pub fn new(
+        target: NodeId,
+        lowest_fetched_round: Round,
+        next_targets: Vec<NodeMetadata>,
+    ) -> Self {
+        Self {
+            target,
+            lowest_fetched_round,
+            next_targets,
+        }
+    }
+
+    pub fn target(&self) -> &NodeId {
+        &self.target
+    }
+
+    pub fn lowest_fetched_round(&self) -> Round {
+        self.lowest_fetched_round
+    }
+
+    pub fn next_targets(&self) -> &[NodeMetadata] {
+        &self.next_targets
+    }
+}
+
 pub(super) struct StateSyncTrigger {
     epoch_state: Arc<EpochState>,
     ledger_info_provider: Arc<dyn TLedgerInfoProvider>,
     dag_store: Arc<RwLock<Dag>>,
     proof_notifier: Arc<dyn ProofNotifier>,
+    dag_window: usize,
+    time_service: TimeService,
+    // The highest epoch retrieval requested, and when.
+    last_epoch_retrieval: Mutex<Option<(u64, Instant)>>,
 }
 
 impl StateSyncTrigger {
@@ -45,15 +159,24 @@ impl StateSyncTrigger {
         ledger_info_provider: Arc<dyn TLedgerInfoProvider>,
         dag_store: Arc<RwLock<Dag>>,
         proof_notifier: Arc<dyn ProofNotifier>,
+        time_service: TimeService,
     ) -> Self {
         Self {
             epoch_state,
             ledger_info_provider,
             dag_store,
             proof_notifier,
+            dag_window: DAG_WINDOW,
+            time_service,
+            last_epoch_retrieval: Mutex::new(None),
         }
     }
 
+    pub(super) fn with_dag_window(mut self, dag_window: usize) -> Self {
+        self.dag_window = dag_window;
+        self
+    }
+
     fn verify_ledger_info(&self, ledger_info: &LedgerInfoWithSignatures) -> anyhow::Result<()> {
         ensure!(ledger_info.commit_info().epoch() == self.epoch_state.epoch);
 
@@ -96,6 +219,35 @@ impl StateSyncTrigger {
         Ok(StateSyncStatus::NeedsSync(node))
     }
 
+    /// Requests the epoch change proofs up to `epoch` from `peer`, after it sent a node from that
+    /// future epoch. The epoch manager starts the new epoch once the proofs arrive. Requests up to
+    /// an epoch already requested within `EPOCH_RETRIEVAL_INTERVAL` are skipped, as every node of
+    /// the future epoch triggers one.
+    pub(super) async fn request_epoch_sync(&self, peer: Author, epoch: u64) {
+        {
+            let now = self.time_service.now();
+            let mut last_epoch_retrieval = self.last_epoch_retrieval.lock();
+            if let Some((last_epoch, requested_at)) = *last_epoch_retrieval {
+                if epoch <= last_epoch
+                    && now.saturating_duration_since(requested_at) < EPOCH_RETRIEVAL_INTERVAL
+                {
+                    debug!(
+                        "epoch retrieval up to {} already requested, skipping request to {}",
+                        last_epoch, peer
+                    );
+                    return;
+                }
+            }
+            *last_epoch_retrieval = Some((epoch, now));
+        }
+        self.proof_notifier
+            .send_epoch_retrieval(peer, EpochRetrievalRequest {
+                start_epoch: self.epoch_state.epoch,
+                end_epoch: epoch,
+            })
+            .await
+    }
+
     /// Fast forward in the decoupled-execution pipeline if the block exists there
     async fn notify_commit_proof(&self, ledger_info: &LedgerInfoWithSignatures) {
         // if the anchor exists between ledger info round and highest ordered round
@@ -131,7 +283,7 @@ impl StateSyncTrigger {
         let dag_reader = self.dag_store.read();
         // check whether if DAG order round is behind the given ledger info round
         // (meaning consensus is behind) or
-        // the highest committed anchor round is 2*DAG_WINDOW behind the given ledger info round
+        // the highest committed anchor round is 2*dag_window behind the given ledger info round
         // (meaning execution is behind the DAG window)
         dag_reader
             .highest_ordered_anchor_round()
@@ -139,7 +291,7 @@ impl StateSyncTrigger {
             || self
                 .ledger_info_provider
                 .get_highest_committed_anchor_round()
-                + ((STATE_SYNC_WINDOW_MULTIPLIER * DAG_WINDOW) as Round)
+                + ((STATE_SYNC_WINDOW_MULTIPLIER * self.dag_window) as Round)
                 < li.commit_info().round()
     }
 }
@@ -149,6 +301,8 @@ pub(super) struct DagStateSynchronizer {
     time_service: TimeService,
     state_computer: Arc<dyn StateComputer>,
     storage: Arc<dyn DAGStorage>,
+    chunk_rounds: Round,
+    dag_window: usize,
 }
 
 impl DagStateSynchronizer {
@@ -163,9 +317,22 @@ impl DagStateSynchronizer {
             time_service,
             state_computer,
             storage,
+            chunk_rounds: STATE_SYNC_CHUNK_ROUNDS,
+            dag_window: DAG_WINDOW,
         }
     }
 
+    pub fn with_chunk_rounds(mut self, chunk_rounds: Round) -> Self {
+        assert!(chunk_rounds > 0, "chunk_rounds must be positive");
+        self.chunk_rounds = chunk_rounds;
+        self
+    }
+
+    pub fn with_dag_window(mut self, dag_window: usize) -> Self {
+        self.dag_window = dag_window;
+        self
+    }
+
     /// Note: Assumes that the sync checks have been done
     pub async fn sync_dag_to(
         &self,
@@ -184,7 +351,7 @@ impl DagStateSynchronizer {
                     .unwrap_or_default()
                     < commit_li.commit_info().round()
                     || highest_committed_anchor_round
-                        + ((STATE_SYNC_WINDOW_MULTIPLIER * DAG_WINDOW) as Round)
+                        + ((STATE_SYNC_WINDOW_MULTIPLIER * self.dag_window) as Round)
                         < commit_li.commit_info().round()
             );
         }
@@ -192,43 +359,144 @@ impl DagStateSynchronizer {
         // TODO: there is a case where DAG fetches missing nodes in window and a crash happens and when we restart,
         // we end up with a gap between the DAG and we need to be smart enough to clean up the DAG before the gap.
 
-        // Create a new DAG store and Fetch blocks
         let target_round = node.round();
         let start_round = commit_li
             .commit_info()
             .round()
-            .saturating_sub(DAG_WINDOW as Round);
-        let sync_dag_store = Arc::new(RwLock::new(Dag::new_empty(
-            self.epoch_state.clone(),
-            self.storage.clone(),
-            start_round,
-        )));
-        let bitmask = { sync_dag_store.read().bitmask(target_round) };
-        let request = RemoteFetchRequest::new(
-            self.epoch_state.epoch,
-            node.parents_metadata().cloned().collect_vec(),
-            bitmask,
-        );
-
+            .saturating_sub(self.dag_window as Round);
+        // When the local DAG holds the causal predecessors of the target from the start of the
+        // window up to some round, only the rounds above it are missing. Otherwise, sync the full
+        // window. Nodes absent from the local DAG that the target doesn't reach don't matter.
+        let mut fetch_start_round = {
+            let dag_reader = current_dag_store.read();
+            if dag_reader.lowest_round() <= start_round {
+                dag_reader
+                    .lowest_missing_predecessor_round(node.parents_metadata(), start_round)
+                    .unwrap_or(target_round)
+            } else {
+                start_round
+            }
+        };
+        if fetch_start_round > start_round {
+            debug!(
+                "incremental dag sync to {}, fetching rounds {} to {}",
+                node.id(),
+                fetch_start_round,
+                target_round
+            );
+        } else {
+            debug!(
+                "full window dag sync to {}, fetching rounds {} to {}",
+                node.id(),
+                start_round,
+                target_round
+            );
+        }
         let responders = node
             .certificate()
             .signatures()
             .get_signers_addresses(&self.epoch_state.verifier.get_ordered_account_addresses());
 
-        match dag_fetcher
-            .fetch(request, responders, sync_dag_store.clone())
-            .await
-        {
-            Ok(_) => {},
-            Err(err) => {
+        // A sync to the same node resumes after the chunks fetched before the interruption.
+        let mut cursor = match self.storage.get_sync_cursor()? {
+            Some(cursor) if cursor.target == node.id() => {
+                debug!(
+                    "resuming dag sync to {} from round {}",
+                    cursor.target, cursor.lowest_fetched_round
+                );
+                cursor
+            },
+            _ => DagSyncCursor::new(
+                node.id(),
+                target_round,
+                node.parents_metadata()
+                    .filter(|parent| parent.round() >= fetch_start_round)
+                    .cloned()
+                    .collect(),
+            ),
+        };
+
+        // Fetch the DAG top down, a chunk of rounds at a time. A chunk can only be added to a DAG
+        // once the rounds below it are, so each chunk goes to a DAG of its own, which stores the
+        // nodes.
+        while !cursor.next_targets.is_empty() {
+            // The weak links of the missing nodes are only known once they are fetched, so the
+            // cut-off is lowered to any of them missing from the local DAG.
+            fetch_start_round = cursor
+                .next_targets
+                .iter()
+                .map(|target| target.round())
+                .fold(fetch_start_round, Round::min);
+            let chunk_end = cursor
+                .next_targets
+                .iter()
+                .map(|target| target.round())
+                .max()
+                .expect("targets are not empty");
+            let chunk_start = (chunk_end + 1)
+                .saturating_sub(self.chunk_rounds)
+                .max(fetch_start_round);
+            let (targets, mut next_targets): (Vec<_>, Vec<_>) = cursor
+                .next_targets
+                .iter()
+                .cloned()
+                .partition(|target| target.round() >= chunk_start);
+
+            let chunk_dag_store = Arc::new(RwLock::new(Dag::new_empty(
+                self.epoch_state.clone(),
+                self.storage.clone(),
+                chunk_start,
+            )));
+            let bitmask = { chunk_dag_store.read().bitmask(chunk_end) };
+            let request = RemoteFetchRequest::new(self.epoch_state.epoch, targets.clone(), bitmask);
+            if let Err(err) = dag_fetcher
+                .fetch(request, responders.clone(), chunk_dag_store.clone())
+                .await
+            {
                 error!("error fetching nodes {}", err);
                 return Err(err);
-            },
+            }
+
+            let chunk_dag = chunk_dag_store.read();
+            next_targets.extend(
+                chunk_dag
+                    .reachable_with_weak_links(targets.iter(), None, |_| true)
+                    .flat_map(|node_status| {
+                        let node = node_status.as_node();
+                        node.parents_metadata().chain(node.weak_links())
+                    })
+                    .filter(|parent| {
+                        parent.round() < chunk_start
+                            && parent.round() >= start_round
+                            && (parent.round() >= fetch_start_round
+                                || !current_dag_store.read().exists(parent))
+                    })
+                    .cloned(),
+            );
+            cursor.next_targets = next_targets
+                .into_iter()
+                .unique_by(|target| *target.digest())
+                .collect();
+            cursor.lowest_fetched_round = chunk_start;
+            self.storage.save_sync_cursor(&cursor)?;
+            debug!(
+                "dag sync to {} fetched rounds {} to {}, {} rounds remaining",
+                node.id(),
+                chunk_start,
+                chunk_end,
+                chunk_start - fetch_start_round
+            );
         }
 
         // State sync
         self.state_computer.sync_to(commit_li.clone()).await?;
+        self.storage.delete_sync_cursor()?;
 
-        Ok(Arc::into_inner(sync_dag_store).map(|r| r.into_inner()))
+        Ok(Some(Dag::new(
+            self.epoch_state.clone(),
+            self.storage.clone(),
+            start_round,
+            self.dag_window,
+        )))
     }
 }
@@ -4,17 +4,28 @@
 use super::types::{DagSnapshotBitmask, NodeMetadata};
 use crate::dag::{
     storage::DAGStorage,
-    types::{CertifiedNode, NodeCertificate},
+    types::{CertifiedNode, Node, NodeCertificate},
 };
 use anyhow::{anyhow, ensure};
 use aptos_consensus_types::common::{Author, Round};
 use aptos_crypto::HashValue;
-use aptos_logger::{debug, error};
-use aptos_types::{epoch_state::EpochState, validator_verifier::ValidatorVerifier};
+use aptos_logger::{debug, error, warn};
+use aptos_types::{
+    aggregate_signature::AggregateSignature, epoch_state::EpochState,
+    validator_verifier::ValidatorVerifier,
+};
+use rayon::prelude::*;
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
     sync::Arc,
 };
+use thiserror::Error as ThisError;
+
+#[derive(ThisError, Debug)]
+pub enum DagStoreError {
+    #[error("equivocation: {author} already has a node at round {round}")]
+    Equivocation { author: Author, round: Round },
+}
 
 #[derive(Clone)]
 pub enum NodeStatus {
@@ -34,6 +45,45 @@ impl NodeStatus {
         *self = NodeStatus::Ordered(self.as_node().clone());
     }
 }
+
+/// Verification of the certificates of the nodes recovered from storage at startup.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StartupVerification {
+    /// Trust the nodes in storage, they were verified before being stored.
+    #[default]
+    Disabled,
+    /// Verify the nodes on a pool of `num_threads` threads.
+    Enabled { num_threads: usize },
+}
+
+/// Two different certified nodes from the same author at the same round.
+#[derive(Clone, Debug)]
+pub struct EquivocationEvidence {
+    pub accepted: Arc<CertifiedNode>,
+    pub rejected: Arc<CertifiedNode>,
+}
+
+/// The peer that delivered a node, for debugging how nodes are disseminated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeProvenance {
+    /// Received over RPC from the peer, which is the author broadcasting its node.
+    Rpc(Author),
+    /// Received in the response to a fetch request.
+    Fetch(Author),
+}
+
+/// A node that passed the checks of `Dag::insert_node`.
+enum Insertion {
+    /// The node was already in memory, but missing from storage.
+    Restored,
+    /// The node was added to memory, along with its round if `new_round`.
+    Inserted {
+        round: Round,
+        index: usize,
+        new_round: bool,
+    },
+}
+
 /// Data structure that stores the DAG representation, it maintains round based index.
 #[derive(Clone)]
 pub struct Dag {
@@ -43,38 +93,69 @@ pub struct Dag {
     storage: Arc<dyn DAGStorage>,
     initial_round: Round,
     epoch_state: Arc<EpochState>,
+    equivocations: Vec<EquivocationEvidence>,
+    /// Which peer delivered each node, nodes recovered from storage have none.
+    provenance: HashMap<HashValue, NodeProvenance>,
 }
 
 impl Dag {
     pub fn new(
+        epoch_state: Arc<EpochState>,
+        storage: Arc<dyn DAGStorage>,
+        initial_round: Round,
+        dag_window_size_config: usize,
+    ) -> Self {
+        Self::new_with_startup_verification(
+            epoch_state,
+            storage,
+            initial_round,
+            dag_window_size_config,
+            StartupVerification::Disabled,
+        )
+    }
+
+    /// Recovers the DAG from storage, optionally verifying the certificates of the recovered nodes.
+    /// Nodes that fail verification are deleted from storage along with the expired ones.
+    pub fn new_with_startup_verification(
         epoch_state: Arc<EpochState>,
         storage: Arc<dyn DAGStorage>,
         initial_round: Round,
         _dag_window_size_config: usize,
+        startup_verification: StartupVerification,
     ) -> Self {
         let epoch = epoch_state.epoch;
         let author_to_index = epoch_state.verifier.address_to_validator_index().clone();
         let num_validators = author_to_index.len();
         let all_nodes = storage.get_certified_nodes().unwrap_or_default();
         let mut expired = vec![];
-        let mut nodes_by_round = BTreeMap::new();
+        let mut recovered = vec![];
         for (digest, certified_node) in all_nodes {
             if certified_node.metadata().epoch() == epoch && certified_node.round() >= initial_round
             {
-                let arc_node = Arc::new(certified_node);
-                let index = *author_to_index
-                    .get(arc_node.metadata().author())
-                    .expect("Author from certified node should exist");
-                let round = arc_node.metadata().round();
-                debug!("Recovered node {} from storage", arc_node.id());
-                nodes_by_round
-                    .entry(round)
-                    .or_insert_with(|| vec![None; num_validators])[index] =
-                    Some(NodeStatus::Unordered(arc_node));
+                recovered.push((digest, certified_node));
             } else {
                 expired.push(digest);
             }
         }
+        if let StartupVerification::Enabled { num_threads } = startup_verification {
+            let (valid, invalid) =
+                Self::verify_recovered_nodes(recovered, &epoch_state.verifier, num_threads);
+            recovered = valid;
+            expired.extend(invalid);
+        }
+        let mut nodes_by_round = BTreeMap::new();
+        for (_, certified_node) in recovered {
+            let arc_node = Arc::new(certified_node);
+            let index = *author_to_index
+                .get(arc_node.metadata().author())
+                .expect("Author from certified node should exist");
+            let round = arc_node.metadata().round();
+            debug!("Recovered node {} from storage", arc_node.id());
+            nodes_by_round
+                .entry(round)
+                .or_insert_with(|| vec![None; num_validators])[index] =
+                Some(NodeStatus::Unordered(arc_node));
+        }
         if let Err(e) = storage.delete_certified_nodes(expired) {
             error!("Error deleting expired nodes: {:?}", e);
         }
@@ -84,9 +165,41 @@ impl Dag {
             storage,
             initial_round,
             epoch_state,
+            equivocations: vec![],
+            provenance: HashMap::new(),
         }
     }
 
+    /// Splits the nodes into the ones with a valid certificate and the digests of the others.
+    fn verify_recovered_nodes(
+        nodes: Vec<(HashValue, CertifiedNode)>,
+        verifier: &ValidatorVerifier,
+        num_threads: usize,
+    ) -> (Vec<(HashValue, CertifiedNode)>, Vec<HashValue>) {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .expect("should be able to build the verification thread pool");
+        let verified: Vec<bool> = pool.install(|| {
+            nodes
+                .par_iter()
+                .map(|(_, node)| node.verify(verifier).is_ok())
+                .collect()
+        });
+
+        let mut valid = vec![];
+        let mut invalid = vec![];
+        for ((digest, node), is_valid) in nodes.into_iter().zip(verified) {
+            if is_valid {
+                valid.push((digest, node));
+            } else {
+                error!("Dropping node {} with an invalid certificate", node.id());
+                invalid.push(digest);
+            }
+        }
+        (valid, invalid)
+    }
+
     pub fn new_empty(
         epoch_state: Arc<EpochState>,
         storage: Arc<dyn DAGStorage>,
@@ -100,6 +213,8 @@ impl Dag {
             storage,
             initial_round,
             epoch_state,
+            equivocations: vec![],
+            provenance: HashMap::new(),
         }
     }
 
@@ -121,6 +236,17 @@ impl Dag {
 
     pub fn add_node(&mut self, node: CertifiedNode) -> anyhow::Result<()> {
         let node = Arc::new(node);
+        let insertion = self.insert_node(node.clone())?;
+        // a node is only kept in memory once it's in storage
+        if let Err(e) = self.storage.save_certified_node(&node) {
+            self.undo_insertion(insertion);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Checks the node and adds it to memory, without saving it to storage.
+    fn insert_node(&mut self, node: Arc<CertifiedNode>) -> anyhow::Result<Insertion> {
         let author = node.metadata().author();
         let index = *self
             .author_to_index
@@ -129,24 +255,171 @@ impl Dag {
         let round = node.metadata().round();
         ensure!(round >= self.lowest_round(), "round too low");
         ensure!(round <= self.highest_round() + 1, "round too high");
+        if let Some(parent) = Self::find_cyclic_parent(&node) {
+            return Err(anyhow!(
+                "node {} references parent {} at round {} which is not below its own round, forming a cycle",
+                node.id(),
+                parent.digest(),
+                parent.round(),
+            ));
+        }
+        if let Some(parent) = Self::find_cross_epoch_parent(&node) {
+            return Err(anyhow!(
+                "node {} references parent {} from epoch {}, not its own epoch",
+                node.id(),
+                parent.digest(),
+                parent.epoch(),
+            ));
+        }
         if round > self.lowest_round() {
             for parent in node.parents() {
                 ensure!(self.exists(parent.metadata()), "parent not exist");
             }
         }
+        // weak links are dependencies like the parents, so that a node orders the same nodes on
+        // every validator, weak links below the lowest round are already GC'ed
+        for weak_link in node.weak_links() {
+            ensure!(
+                weak_link.round() < self.lowest_round() || self.exists(weak_link),
+                "weak link not exist"
+            );
+        }
+        let new_round = !self.nodes_by_round.contains_key(&round);
         let round_ref = self
             .nodes_by_round
             .entry(round)
             .or_insert_with(|| vec![None; self.author_to_index.len()]);
-        ensure!(round_ref[index].is_none(), "duplicate node");
+        if let Some(accepted) = round_ref[index].as_ref().map(NodeStatus::as_node) {
+            if accepted.digest() == node.digest() {
+                // the node is already in memory, but its write to storage may have been lost
+                if self.storage.get_certified_node(&node.digest())?.is_none() {
+                    warn!(
+                        "node {} is in memory but not in storage, saving it",
+                        node.id()
+                    );
+                    return Ok(Insertion::Restored);
+                }
+                return Err(anyhow!("duplicate node {}", node.id()));
+            }
+            warn!(
+                "equivocation: node {} at round {} conflicts with accepted node {}",
+                node.digest(),
+                round,
+                accepted.digest(),
+            );
+            self.equivocations.push(EquivocationEvidence {
+                accepted: accepted.clone(),
+                rejected: node.clone(),
+            });
+            return Err(DagStoreError::Equivocation {
+                author: *author,
+                round,
+            }
+            .into());
+        }
 
-        // mutate after all checks pass
-        self.storage.save_certified_node(&node)?;
+        // mutate after all checks pass, the caller's save overwrites a node that is already in
+        // storage but not in memory
         debug!("Added node {}", node.id());
-        round_ref[index] = Some(NodeStatus::Unordered(node.clone()));
+        round_ref[index] = Some(NodeStatus::Unordered(node));
+        Ok(Insertion::Inserted {
+            round,
+            index,
+            new_round,
+        })
+    }
+
+    /// Removes a node added by `insert_node` from memory, after saving it to storage failed.
+    fn undo_insertion(&mut self, insertion: Insertion) {
+        if let Insertion::Inserted {
+            round,
+            index,
+            new_round,
+        } = insertion
+        {
+            if new_round {
+                self.nodes_by_round.remove(&round);
+            } else if let Some(round_ref) = self.nodes_by_round.get_mut(&round) {
+                round_ref[index] = None;
+            }
+        }
+    }
+
+    /// Adds the node like `add_node`, recording the peer that delivered it. The first delivery of a
+    /// node is the one recorded.
+    pub fn add_node_with_provenance(
+        &mut self,
+        node: CertifiedNode,
+        provenance: NodeProvenance,
+    ) -> anyhow::Result<()> {
+        let digest = node.digest();
+        self.add_node(node)?;
+        self.provenance.entry(digest).or_insert(provenance);
         Ok(())
     }
 
+    /// Adds the nodes fetched from `responder`, in order so that parents come before the nodes
+    /// referencing them, and saves them to storage in a single batch. None of the nodes are added if
+    /// the batch fails to save, otherwise returns the errors of the rejected nodes.
+    pub fn add_fetched_nodes(
+        &mut self,
+        nodes: impl IntoIterator<Item = CertifiedNode>,
+        responder: Author,
+    ) -> anyhow::Result<Vec<anyhow::Error>> {
+        let mut rejected = vec![];
+        let mut insertions = vec![];
+        for node in nodes {
+            let node = Arc::new(node);
+            match self.insert_node(node.clone()) {
+                Ok(insertion) => insertions.push((node, insertion)),
+                Err(e) => rejected.push(e),
+            }
+        }
+        let accepted: Vec<_> = insertions
+            .iter()
+            .map(|(node, _)| node.as_ref().clone())
+            .collect();
+        if let Err(e) = self.storage.save_certified_nodes(&accepted) {
+            // in reverse, so that a round created for a node is only removed along with its node
+            for (_, insertion) in insertions.into_iter().rev() {
+                self.undo_insertion(insertion);
+            }
+            return Err(e);
+        }
+        for (node, _) in insertions {
+            self.provenance
+                .entry(node.digest())
+                .or_insert(NodeProvenance::Fetch(responder));
+        }
+        Ok(rejected)
+    }
+
+    /// The peer that delivered the node with the given digest, if known.
+    pub fn provenance(&self, digest: &HashValue) -> Option<NodeProvenance> {
+        self.provenance.get(digest).copied()
+    }
+
+    /// Nodes only reference strictly lower rounds, through their parents and weak links, which
+    /// keeps the DAG acyclic. A parent at the same or a higher round could (transitively) reference
+    /// the node itself.
+    fn find_cyclic_parent(node: &CertifiedNode) -> Option<&NodeMetadata> {
+        node.parents_metadata()
+            .chain(node.weak_links())
+            .find(|parent| parent.round() >= node.round())
+    }
+
+    /// Nodes only reference parents and weak links of their own epoch.
+    fn find_cross_epoch_parent(node: &CertifiedNode) -> Option<&NodeMetadata> {
+        node.parents_metadata()
+            .chain(node.weak_links())
+            .find(|parent| parent.epoch() != node.epoch())
+    }
+
+    /// The conflicting nodes rejected so far, with the nodes accepted for the same author and round.
+    pub fn equivocations(&self) -> &[EquivocationEvidence] {
+        &self.equivocations
+    }
+
     pub fn exists(&self, metadata: &NodeMetadata) -> bool {
         self.get_node_ref_by_metadata(metadata).is_some()
     }
@@ -169,6 +442,18 @@ impl Dag {
         nodes.filter(|node_metadata| !self.exists(node_metadata))
     }
 
+    /// The given parents that are not in the DAG, in the order they were given.
+    pub fn missing_parents(&self, parents: &[NodeMetadata]) -> Vec<NodeMetadata> {
+        self.filter_missing(parents.iter()).cloned().collect()
+    }
+
+    /// The parents and weak links of the node that are not in the DAG.
+    pub fn missing_parents_of(&self, node: &Node) -> Vec<NodeMetadata> {
+        self.filter_missing(node.parents_metadata().chain(node.weak_links()))
+            .cloned()
+            .collect()
+    }
+
     fn get_node_ref_by_metadata(&self, metadata: &NodeMetadata) -> Option<&NodeStatus> {
         self.get_node_ref(metadata.round(), metadata.author())
     }
@@ -196,6 +481,54 @@ impl Dag {
             .map(|node_status| node_status.as_node().clone())
     }
 
+    /// Returns the aggregate signature of the node with the given digest, if it's in the dag.
+    pub fn certificate_for(&self, digest: &HashValue) -> Option<AggregateSignature> {
+        self.get_node_by_digest(digest)
+            .map(|node| node.signatures().clone())
+    }
+
+    /// The ancestors of the node with the given digest through the strong links, up to `depth`
+    /// links away, closest first. Ancestors missing from the dag are skipped, and so is the node if
+    /// it's not in the dag.
+    pub fn predecessors(&self, digest: &HashValue, depth: usize) -> Vec<CertifiedNode> {
+        let node = match self.get_node_by_digest(digest) {
+            Some(node) => node,
+            None => return vec![],
+        };
+        let mut predecessors = vec![];
+        let mut visited = HashSet::new();
+        let mut frontier: Vec<_> = node
+            .parents()
+            .iter()
+            .map(|parent| parent.metadata().clone())
+            .collect();
+        for _ in 0..depth {
+            let mut next_frontier = vec![];
+            for metadata in frontier {
+                if !visited.insert(*metadata.digest()) {
+                    continue;
+                }
+                if let Some(parent) = self.get_node(&metadata) {
+                    next_frontier.extend(parent.parents().iter().map(|p| p.metadata().clone()));
+                    predecessors.push(parent.as_ref().clone());
+                }
+            }
+            frontier = next_frontier;
+        }
+        predecessors
+    }
+
+    /// Returns the node with the given digest, if it's in the dag. Scans the whole dag, as the
+    /// nodes are indexed by round and author.
+    fn get_node_by_digest(&self, digest: &HashValue) -> Option<&Arc<CertifiedNode>> {
+        self.nodes_by_round
+            .values()
+            .flatten()
+            .flatten()
+            .map(|node_status| node_status.as_node())
+            .find(|node| node.digest() == *digest)
+    }
+
     pub fn get_node_by_round_author(
         &self,
         round: Round,
@@ -227,13 +560,38 @@ impl Dag {
             .unwrap_or(false)
     }
 
-    fn reachable_filter(start: Vec<HashValue>) -> impl FnMut(&Arc<CertifiedNode>) -> bool {
+    /// Certificates of the next round nodes that link to the given node.
+    pub fn get_vote_certificates(&self, metadata: &NodeMetadata) -> Vec<NodeCertificate> {
+        self.get_round_iter(metadata.round() + 1)
+            .map(|next_round_iter| {
+                next_round_iter
+                    .map(|node_status| node_status.as_node())
+                    .filter(|node| {
+                        node.parents()
+                            .iter()
+                            .any(|cert| cert.metadata() == metadata)
+                    })
+                    .map(|node| node.certificate())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn reachable_filter(
+        start: Vec<HashValue>,
+        follow_weak_links: bool,
+    ) -> impl FnMut(&Arc<CertifiedNode>) -> bool {
         let mut reachable: HashSet<HashValue> = HashSet::from_iter(start);
         move |node| {
             if reachable.contains(&node.digest()) {
                 for parent in node.parents() {
                     reachable.insert(*parent.metadata().digest());
                 }
+                if follow_weak_links {
+                    for weak_link in node.weak_links() {
+                        reachable.insert(*weak_link.digest());
+                    }
+                }
                 true
             } else {
                 false
@@ -241,13 +599,15 @@ impl Dag {
         }
     }
 
+    /// The unordered nodes in the causal history of `from`, following both the strong and the weak
+    /// links, so that the nodes only referenced through weak links get ordered as well.
     pub fn reachable_mut(
         &mut self,
         from: &Arc<CertifiedNode>,
         until: Option<Round>,
     ) -> impl Iterator<Item = &mut NodeStatus> {
         let until = until.unwrap_or(self.lowest_round());
-        let mut reachable_filter = Self::reachable_filter(vec![from.digest()]);
+        let mut reachable_filter = Self::reachable_filter(vec![from.digest()], true);
         self.nodes_by_round
             .range_mut(until..=from.round())
             .rev()
@@ -259,18 +619,40 @@ impl Dag {
             })
     }
 
+    /// The nodes in the causal history of `targets` through the strong links.
     pub fn reachable<'a>(
         &self,
         targets: impl Iterator<Item = &'a NodeMetadata> + Clone,
         until: Option<Round>,
         // TODO: replace filter with bool to filter unordered
         filter: impl Fn(&NodeStatus) -> bool,
+    ) -> impl Iterator<Item = &NodeStatus> {
+        self.reachable_impl(targets, until, filter, false)
+    }
+
+    /// The nodes in the causal history of `targets` through both the strong and the weak links,
+    /// i.e. all the nodes needed to add the targets to a DAG.
+    pub fn reachable_with_weak_links<'a>(
+        &self,
+        targets: impl Iterator<Item = &'a NodeMetadata> + Clone,
+        until: Option<Round>,
+        filter: impl Fn(&NodeStatus) -> bool,
+    ) -> impl Iterator<Item = &NodeStatus> {
+        self.reachable_impl(targets, until, filter, true)
+    }
+
+    fn reachable_impl<'a>(
+        &self,
+        targets: impl Iterator<Item = &'a NodeMetadata> + Clone,
+        until: Option<Round>,
+        filter: impl Fn(&NodeStatus) -> bool,
+        follow_weak_links: bool,
     ) -> impl Iterator<Item = &NodeStatus> {
         let until = until.unwrap_or(self.lowest_round());
         let initial_round = targets.clone().map(|t| t.round()).max().unwrap();
         let initial = targets.map(|t| *t.digest()).collect();
 
-        let mut reachable_filter = Self::reachable_filter(initial);
+        let mut reachable_filter = Self::reachable_filter(initial, follow_weak_links);
         self.nodes_by_round
             .range(until..=initial_round)
             .rev()
@@ -281,6 +663,43 @@ impl Dag {
             })
     }
 
+    /// The nodes of the `lookback_rounds` rounds below the strong links that are not reachable from
+    /// them, i.e. the candidates for weak links of a node built on these strong links.
+    pub fn weak_link_candidates(
+        &self,
+        strong_links: &[NodeCertificate],
+        lookback_rounds: Round,
+    ) -> Vec<NodeMetadata> {
+        let strong_links_round = match strong_links.first() {
+            Some(strong_link) => strong_link.metadata().round(),
+            None => return vec![],
+        };
+        if lookback_rounds == 0 {
+            return vec![];
+        }
+        let lowest_round = std::cmp::max(
+            strong_links_round.saturating_sub(lookback_rounds),
+            self.lowest_round(),
+        );
+        let reachable: HashSet<HashValue> = self
+            .reachable(
+                strong_links
+                    .iter()
+                    .map(|strong_link| strong_link.metadata()),
+                Some(lowest_round),
+                |_| true,
+            )
+            .map(|node_status| node_status.as_node().digest())
+            .collect();
+        self.nodes_by_round
+            .range(lowest_round..strong_links_round)
+            .flat_map(|(_, round_ref)| round_ref.iter().flatten())
+            .map(|node_status| node_status.as_node().metadata())
+            .filter(|metadata| !reachable.contains(metadata.digest()))
+            .cloned()
+            .collect()
+    }
+
     pub fn get_strong_links_for_round(
         &self,
         round: Round,
@@ -304,6 +723,18 @@ impl Dag {
         }
     }
 
+    /// Total voting power of the authors of the nodes in `round`.
+    pub fn round_voting_power(&self, round: Round, validator_verifier: &ValidatorVerifier) -> u128 {
+        self.get_round_iter(round).map_or(0, |nodes| {
+            nodes
+                .filter_map(|node_status| {
+                    validator_verifier.get_voting_power(node_status.as_node().metadata().author())
+                })
+                .map(u128::from)
+                .sum()
+        })
+    }
+
     pub fn lowest_incomplete_round(&self) -> Round {
         if self.nodes_by_round.is_empty() {
             return self.lowest_round();
@@ -318,6 +749,49 @@ impl Dag {
         self.highest_round() + 1
     }
 
+    /// Lowest round, down to `until`, of the causal predecessors of `targets` that are missing
+    /// from the DAG, as far as the DAG tells. Any node of its highest round is taken as one when
+    /// the targets are above it, and the predecessors of a missing node below are not known until
+    /// it is fetched. `None` if none of the known predecessors is missing.
+    pub fn lowest_missing_predecessor_round<'a>(
+        &self,
+        targets: impl Iterator<Item = &'a NodeMetadata>,
+        until: Round,
+    ) -> Option<Round> {
+        let mut reached: HashMap<Round, HashSet<Author>> = HashMap::new();
+        for target in targets {
+            reached
+                .entry(target.round())
+                .or_default()
+                .insert(*target.author());
+        }
+        let highest_target_round = *reached.keys().max()?;
+
+        let mut lowest_missing_round = None;
+        for round in (until..=highest_target_round).rev() {
+            let reached_authors = reached.remove(&round).unwrap_or_default();
+            let parents_unknown = round >= self.highest_round() && round < highest_target_round;
+            for author in self.author_to_index.keys() {
+                if !parents_unknown && !reached_authors.contains(author) {
+                    continue;
+                }
+                match self.get_node_ref(round, author) {
+                    Some(node_status) => {
+                        let node = node_status.as_node();
+                        for parent in node.parents_metadata().chain(node.weak_links()) {
+                            reached
+                                .entry(parent.round())
+                                .or_default()
+                                .insert(*parent.author());
+                        }
+                    },
+                    None => lowest_missing_round = Some(round),
+                }
+            }
+        }
+        lowest_missing_round
+    }
+
     pub fn bitmask(&self, target_round: Round) -> DagSnapshotBitmask {
         let lowest_round = self.lowest_incomplete_round();
 
@@ -330,13 +804,68 @@ impl Dag {
         DagSnapshotBitmask::new(lowest_round, bitmask)
     }
 
-    pub(super) fn prune(&mut self) {
+    /// Drops the nodes strictly below `below_round` from memory and storage, along with their
+    /// provenance and equivocation evidence. A node still referenced as a parent by a node at or
+    /// above `below_round` is kept, and gets pruned by a later call once its children are.
+    /// Returns the number of nodes pruned.
+    pub fn prune(&mut self, below_round: Round) -> usize {
+        let referenced: HashSet<HashValue> = self
+            .nodes_by_round
+            .range(below_round..)
+            .flat_map(|(_, round_nodes)| round_nodes.iter().flatten())
+            .flat_map(|node_status| node_status.as_node().parents())
+            .map(|parent| *parent.metadata().digest())
+            .collect();
+
+        let mut pruned = vec![];
+        let rounds: Vec<Round> = self
+            .nodes_by_round
+            .range(..below_round)
+            .map(|(round, _)| *round)
+            .collect();
+        for round in rounds {
+            let round_nodes = match self.nodes_by_round.get_mut(&round) {
+                Some(round_nodes) => round_nodes,
+                None => continue,
+            };
+            for maybe_node_status in round_nodes.iter_mut() {
+                let is_referenced = match maybe_node_status {
+                    Some(node_status) => referenced.contains(&node_status.as_node().digest()),
+                    None => continue,
+                };
+                if !is_referenced {
+                    if let Some(node_status) = maybe_node_status.take() {
+                        pruned.push(node_status.as_node().digest());
+                    }
+                }
+            }
+            if round_nodes.iter().all(Option::is_none) {
+                self.nodes_by_round.remove(&round);
+            }
+        }
+
+        for digest in &pruned {
+            self.provenance.remove(digest);
+        }
+        self.equivocations
+            .retain(|evidence| evidence.accepted.round() >= below_round);
+        let num_pruned = pruned.len();
+        if let Err(e) = self.storage.delete_certified_nodes(pruned) {
+            error!("Error deleting pruned nodes: {:?}", e);
+        }
+        debug!("Pruned {} nodes below round {}", num_pruned, below_round);
+        num_pruned
+    }
+
+    /// Deletes the nodes of other epochs or below the initial round from storage.
+    pub(super) fn prune_expired(&mut self) {
         let all_nodes = self.storage.get_certified_nodes().unwrap_or_default();
         let mut expired = vec![];
         for (digest, certified_node) in all_nodes {
             if certified_node.metadata().epoch() != self.epoch_state.epoch
                 || certified_node.metadata().round() < self.initial_round
             {
+                self.provenance.remove(&digest);
                 expired.push(digest);
                 self.nodes_by_round
                     .remove(&certified_node.metadata().round());
@@ -347,6 +876,61 @@ impl Dag {
         }
     }
 
+    /// Removes every node in rounds `start..=end` from the DAG and the storage.
+    /// This is an operator recovery tool for corrupted state, it refuses to prune any round above
+    /// the committed frontier. Returns the number of nodes pruned.
+    pub fn force_prune_range(&mut self, start: Round, end: Round) -> anyhow::Result<usize> {
+        ensure!(start <= end, "invalid round range [{}, {}]", start, end);
+        let committed_round = self.storage.get_latest_ledger_info()?.commit_info().round();
+        ensure!(
+            end <= committed_round,
+            "refusing to prune rounds [{}, {}] above the committed round {}",
+            start,
+            end,
+            committed_round
+        );
+        error!(
+            "Force pruning DAG rounds [{}, {}], committed round {}",
+            start, end, committed_round
+        );
+
+        let mut expired: HashSet<HashValue> = HashSet::new();
+        let rounds: Vec<Round> = self
+            .nodes_by_round
+            .range(start..=end)
+            .map(|(round, _)| *round)
+            .collect();
+        for round in rounds {
+            if let Some(round_nodes) = self.nodes_by_round.remove(&round) {
+                expired.extend(
+                    round_nodes
+                        .iter()
+                        .flatten()
+                        .map(|node_status| node_status.as_node().digest()),
+                );
+            }
+        }
+        for (digest, certified_node) in self.storage.get_certified_nodes()? {
+            if certified_node.epoch() == self.epoch_state.epoch
+                && (start..=end).contains(&certified_node.round())
+            {
+                expired.insert(digest);
+            }
+        }
+
+        let num_pruned = expired.len();
+        for digest in &expired {
+            self.provenance.remove(digest);
+        }
+        self.storage
+            .delete_certified_nodes(expired.into_iter().collect())?;
+        error!(
+            "Force pruned {} nodes in DAG rounds [{}, {}]",
+            num_pruned, start, end
+        );
+        Ok(num_pruned)
+    }
+
     pub(super) fn highest_ordered_anchor_round(&self) -> Option<Round> {
         for (round, round_nodes) in self.nodes_by_round.iter().rev() {
             for maybe_node_status in round_nodes {
@@ -6,6 +6,7 @@ mod adapter;
 mod anchor_election;
 mod bootstrap;
 mod commit_signer;
+mod counters;
 mod dag_driver;
 mod dag_fetcher;
 mod dag_handler;
@@ -19,9 +20,16 @@ mod storage;
 mod tests;
 mod types;
 
-pub use adapter::{ProofNotifier, StorageAdapter};
-pub use bootstrap::DagBootstrapper;
+pub use adapter::{build_dag_storage, InMemoryStorage, ProofNotifier, StorageAdapter};
+pub use anchor_election::AnchorElectionPolicy;
+pub use bootstrap::{DagBootstrapper, ReliableBroadcastConfig};
 pub use commit_signer::DagCommitSigner;
-pub use dag_network::{RpcHandler, RpcWithFallback, TDAGNetworkSender};
+pub use dag_driver::DagPayloadConfig;
+pub use dag_network::{RpcHandler, RpcResultWithResponder, RpcWithFallback, TDAGNetworkSender};
+pub use dag_state_sync::{DagSyncCursor, LiveNodePolicy};
+pub use dag_store::StartupVerification;
 pub use storage::DAGStorage;
-pub use types::{CertifiedNode, DAGMessage, DAGNetworkMessage, Extensions, Node, NodeId, Vote};
+pub use types::{
+    AnchorCommitProof, BroadcastProgress, CertifiedNode, CommitLogEntry, DAGMessage,
+    DAGNetworkMessage, Extensions, Node, NodeCertificate, NodeId, Vote,
+};
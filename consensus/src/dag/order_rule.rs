@@ -3,26 +3,103 @@
 
 use crate::dag::{
     adapter::OrderedNotifier,
-    anchor_election::AnchorElection,
-    dag_state_sync::DAG_WINDOW,
+    anchor_election::{reputation_history_len, AnchorElection},
     dag_store::{Dag, NodeStatus},
     storage::DAGStorage,
-    types::NodeMetadata,
+    types::{AnchorCommitProof, NodeId, NodeMetadata},
     CertifiedNode,
 };
-use aptos_consensus_types::common::Round;
+use aptos_consensus_types::common::{Author, Payload, Round, TransactionSummary};
 use aptos_infallible::RwLock;
 use aptos_logger::{debug, error};
 use aptos_types::{epoch_state::EpochState, ledger_info::LedgerInfo};
-use std::sync::Arc;
+use futures_channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Number of most recently ordered anchors kept for block space accounting.
+const CONTRIBUTION_HISTORY_LEN: usize = 1000;
+
+/// Block space taken by an author's nodes in the ordered output.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AuthorContribution {
+    pub num_txns: u64,
+    pub num_bytes: u64,
+    pub txns_fraction: f64,
+    pub bytes_fraction: f64,
+}
+
+/// Orders the nodes of the DAG as they are added, this allows plugging in alternate ordering
+/// algorithms into the driver.
+pub trait TOrderRule: Send + Sync {
+    /// Check if this node can trigger anchors to be ordered
+    fn process_new_node(&mut self, node_metadata: &NodeMetadata);
+
+    /// Check the whole dag to see if anything can be ordered.
+    fn process_all(&mut self);
+
+    /// Returns a stream of commit proofs, one per directly committed anchor. Ordering rules
+    /// without commit proofs return a stream that never yields.
+    fn subscribe_commit_proofs(&mut self) -> UnboundedReceiver<AnchorCommitProof> {
+        unbounded().1
+    }
+
+    /// Returns a stream of the ordered anchors with their causal history, in the order they are
+    /// committed. Ordering rules that don't order by anchors return a stream that never yields.
+    fn subscribe_ordered_anchors(&mut self) -> UnboundedReceiver<OrderedAnchor> {
+        unbounded().1
+    }
+
+    /// The round of the highest ordered anchor, nodes up to this round are no longer needed for
+    /// ordering. Ordering rules that don't track it return 0.
+    fn highest_ordered_anchor_round(&self) -> Round {
+        0
+    }
+
+    /// Orders whatever the DAG allows and persists the resulting state, called before shutting
+    /// down so a restart resumes from it.
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.process_all();
+        Ok(())
+    }
+}
+
+/// An anchor ordered by the ordering rule, as sent to the subscribers.
+#[derive(Clone, Debug)]
+pub struct OrderedAnchor {
+    pub anchor: Arc<CertifiedNode>,
+    /// The causal history of the anchor ordered with it, in commit order, ending with the anchor.
+    pub ordered_nodes: Vec<Arc<CertifiedNode>>,
+    /// The anchors of the rounds skipped since the previous ordered anchor.
+    pub failed_authors: Vec<(Round, Author)>,
+}
 
 pub struct OrderRule {
     epoch_state: Arc<EpochState>,
     lowest_unordered_anchor_round: Round,
     dag: Arc<RwLock<Dag>>,
-    anchor_election: Box<dyn AnchorElection>,
+    /// Number of rounds below an ordered anchor whose nodes it can order.
+    dag_window: usize,
+    anchor_election: Arc<dyn AnchorElection>,
     notifier: Arc<dyn OrderedNotifier>,
     storage: Arc<dyn DAGStorage>,
+    /// (author, number of txns, number of bytes) of the ordered nodes, one entry per ordered anchor
+    ordered_contributions: VecDeque<Vec<(Author, u64, u64)>>,
+    /// (anchor timestamp in microseconds, number of txns) of the ordered anchors
+    ordered_txns: VecDeque<(u64, u64)>,
+    /// (node, txns first included by the node) of the ordered nodes, one entry per ordered anchor
+    unique_contributions: VecDeque<Vec<(NodeId, Vec<TransactionSummary>)>>,
+    /// The txns of `unique_contributions`, to tell whether an ordered txn was included before
+    included_txns: HashSet<TransactionSummary>,
+    commit_proof_subscribers: Vec<UnboundedSender<AnchorCommitProof>>,
+    ordered_anchor_subscribers: Vec<UnboundedSender<OrderedAnchor>>,
+    /// Number of most recent commit log entries keeping the full commit proof, the log is not
+    /// compacted if not set.
+    commit_log_retention: Option<usize>,
+    commits_since_compaction: usize,
 }
 
 impl OrderRule {
@@ -30,7 +107,8 @@ impl OrderRule {
         epoch_state: Arc<EpochState>,
         latest_ledger_info: LedgerInfo,
         dag: Arc<RwLock<Dag>>,
-        mut anchor_election: Box<dyn AnchorElection>,
+        dag_window: usize,
+        anchor_election: Arc<dyn AnchorElection>,
         notifier: Arc<dyn OrderedNotifier>,
         storage: Arc<dyn DAGStorage>,
     ) -> Self {
@@ -40,7 +118,7 @@ impl OrderRule {
             latest_ledger_info.round()
         };
         let commit_events = storage
-            .get_latest_k_committed_events(DAG_WINDOW as u64)
+            .get_latest_k_committed_events(reputation_history_len(dag_window) as u64)
             .expect("Failed to read commit events from storage");
         // make sure it's sorted
         assert!(commit_events
@@ -57,27 +135,45 @@ impl OrderRule {
                         .reachable_mut(&anchor, None)
                         .for_each(|node_status| node_status.mark_as_ordered());
                 }
+                // the reputation history is by round, which restarts every epoch
+                anchor_election.update_reputation(
+                    event.round(),
+                    event.author(),
+                    event.parents(),
+                    event.failed_authors(),
+                );
             }
-            anchor_election.update_reputation(
-                event.round(),
-                event.author(),
-                event.parents(),
-                event.failed_authors(),
-            );
         }
         let mut order_rule = Self {
             epoch_state,
             lowest_unordered_anchor_round: committed_round + 1,
             dag,
+            dag_window,
             anchor_election,
             notifier,
             storage,
+            ordered_contributions: VecDeque::new(),
+            ordered_txns: VecDeque::new(),
+            unique_contributions: VecDeque::new(),
+            included_txns: HashSet::new(),
+            commit_proof_subscribers: Vec::new(),
+            ordered_anchor_subscribers: Vec::new(),
+            commit_log_retention: None,
+            commits_since_compaction: 0,
         };
         // re-check if anything can be ordered to recover pending anchors
         order_rule.process_all();
         order_rule
     }
 
+    /// Compacts the commit log beyond the `num_retained` most recent commits, down to the anchor
+    /// metadata. The log is compacted once every `num_retained` commits, so it holds at most twice
+    /// as many full entries.
+    pub fn with_commit_log_retention(mut self, num_retained: usize) -> Self {
+        self.commit_log_retention = Some(num_retained);
+        self
+    }
+
     /// Check if two rounds have the same parity
     fn check_parity(r1: Round, r2: Round) -> bool {
         (r1 ^ r2) & 1 == 0
@@ -90,8 +186,12 @@ impl OrderRule {
             if let Some(direct_anchor) =
                 self.find_first_anchor_with_enough_votes(start_round, round)
             {
-                let ordered_anchor = self.find_first_anchor_to_order(direct_anchor);
-                self.finalize_order(ordered_anchor);
+                let ordered_anchor = self.find_first_anchor_to_order(direct_anchor.clone());
+                self.finalize_order(ordered_anchor.clone());
+                // only the directly ordered anchor has a quorum of votes to prove its commit
+                if ordered_anchor.digest() == direct_anchor.digest() {
+                    self.record_commit_proof(&ordered_anchor);
+                }
                 // if there's any anchor being ordered, the loop continues to check if new anchor can be ordered as well.
                 start_round = self.lowest_unordered_anchor_round;
             } else {
@@ -159,7 +259,7 @@ impl OrderRule {
             self.lowest_unordered_anchor_round,
             anchor.round(),
         ));
-        let lowest_round_to_reach = anchor.round().saturating_sub(DAG_WINDOW as u64);
+        let lowest_round_to_reach = anchor.round().saturating_sub(self.dag_window as u64);
 
         // Ceil it to the closest unordered anchor round
         let lowest_anchor_round = std::cmp::max(
@@ -185,8 +285,9 @@ impl OrderRule {
             failed_authors.iter().map(|(_, author)| *author).collect(),
         );
 
-        let mut dag_writer = self.dag.write();
-        let mut ordered_nodes: Vec<_> = dag_writer
+        let mut ordered_nodes: Vec<_> = self
+            .dag
+            .write()
             .reachable_mut(&anchor, Some(lowest_round_to_reach))
             .map(|node_status| {
                 node_status.mark_as_ordered();
@@ -202,6 +303,20 @@ impl OrderRule {
         );
 
         self.lowest_unordered_anchor_round = anchor.round() + 1;
+        self.record_contributions(&ordered_nodes);
+        self.record_ordered_txns(anchor.timestamp(), &ordered_nodes);
+        self.record_unique_contributions(&ordered_nodes);
+        self.ordered_anchor_subscribers.retain(|tx| !tx.is_closed());
+        if !self.ordered_anchor_subscribers.is_empty() {
+            let ordered_anchor = OrderedAnchor {
+                anchor,
+                ordered_nodes: ordered_nodes.clone(),
+                failed_authors: failed_authors.clone(),
+            };
+            for tx in &self.ordered_anchor_subscribers {
+                let _ = tx.unbounded_send(ordered_anchor.clone());
+            }
+        }
         if let Err(e) = self
             .notifier
             .send_ordered_nodes(ordered_nodes, failed_authors)
@@ -210,8 +325,164 @@ impl OrderRule {
         }
     }
 
-    /// Check if this node can trigger anchors to be ordered
-    pub fn process_new_node(&mut self, node_metadata: &NodeMetadata) {
+    /// Appends the commit proof of the anchor to the commit log and sends it to the subscribers.
+    fn record_commit_proof(&mut self, anchor: &CertifiedNode) {
+        let votes = self.dag.read().get_vote_certificates(anchor.metadata());
+        let proof = AnchorCommitProof::new(anchor.certificate(), votes);
+        if let Err(e) = self.storage.append_commit_log(&proof) {
+            error!(
+                "Failed to append anchor {} to commit log {:?}",
+                anchor.id(),
+                e
+            );
+        }
+        self.maybe_compact_commit_log();
+        self.commit_proof_subscribers.retain(|tx| !tx.is_closed());
+        for tx in &self.commit_proof_subscribers {
+            let _ = tx.unbounded_send(proof.clone());
+        }
+    }
+
+    fn maybe_compact_commit_log(&mut self) {
+        let num_retained = match self.commit_log_retention {
+            Some(num_retained) => num_retained,
+            None => return,
+        };
+        self.commits_since_compaction += 1;
+        if self.commits_since_compaction < num_retained {
+            return;
+        }
+        self.commits_since_compaction = 0;
+        match self.storage.compact_commit_log(num_retained) {
+            Ok(num_compacted) => debug!("Compacted {} commit log entries", num_compacted),
+            Err(e) => error!("Failed to compact commit log {:?}", e),
+        }
+    }
+
+    fn record_contributions(&mut self, ordered_nodes: &[Arc<CertifiedNode>]) {
+        if self.ordered_contributions.len() == CONTRIBUTION_HISTORY_LEN {
+            self.ordered_contributions.pop_front();
+        }
+        self.ordered_contributions.push_back(
+            ordered_nodes
+                .iter()
+                .map(|node| {
+                    (
+                        *node.author(),
+                        node.payload().len() as u64,
+                        node.payload().size() as u64,
+                    )
+                })
+                .collect(),
+        );
+    }
+
+    fn record_ordered_txns(&mut self, timestamp_usecs: u64, ordered_nodes: &[Arc<CertifiedNode>]) {
+        if self.ordered_txns.len() == CONTRIBUTION_HISTORY_LEN {
+            self.ordered_txns.pop_front();
+        }
+        let num_txns = ordered_nodes
+            .iter()
+            .map(|node| node.payload().len() as u64)
+            .sum();
+        self.ordered_txns.push_back((timestamp_usecs, num_txns));
+    }
+
+    /// Credits every ordered txn to the first node in the order that includes it, duplicates in
+    /// later nodes are not credited. Only a direct mempool payload carries its txns, nodes with a
+    /// quorum store payload are credited with none.
+    fn record_unique_contributions(&mut self, ordered_nodes: &[Arc<CertifiedNode>]) {
+        if self.unique_contributions.len() == CONTRIBUTION_HISTORY_LEN {
+            if let Some(evicted) = self.unique_contributions.pop_front() {
+                for txn in evicted.iter().flat_map(|(_, txns)| txns) {
+                    self.included_txns.remove(txn);
+                }
+            }
+        }
+        let contributions = ordered_nodes
+            .iter()
+            .map(|node| {
+                let unique_txns = match node.payload() {
+                    Payload::DirectMempool(txns) => txns
+                        .iter()
+                        .map(|txn| TransactionSummary::new(txn.sender(), txn.sequence_number()))
+                        .filter(|summary| self.included_txns.insert(*summary))
+                        .collect(),
+                    Payload::InQuorumStore(_) => vec![],
+                };
+                (node.id(), unique_txns)
+            })
+            .collect();
+        self.unique_contributions.push_back(contributions);
+    }
+
+    /// Returns the txns of the node's payload that no node ordered before it included, if the node
+    /// was ordered within the last `CONTRIBUTION_HISTORY_LEN` anchors.
+    pub fn unique_committed_txns(&self, node_id: &NodeId) -> Option<&[TransactionSummary]> {
+        self.unique_contributions
+            .iter()
+            .flatten()
+            .find(|(id, _)| id == node_id)
+            .map(|(_, txns)| txns.as_slice())
+    }
+
+    /// Returns the committed transactions per second over the `window` ending at the latest
+    /// ordered anchor, using anchor timestamps as commit times.
+    pub fn commit_throughput(&self, window: Duration) -> f64 {
+        let latest_timestamp = match self.ordered_txns.back() {
+            Some((timestamp, _)) => *timestamp,
+            None => return 0.0,
+        };
+        if window.is_zero() {
+            return 0.0;
+        }
+        let window_start = latest_timestamp.saturating_sub(window.as_micros() as u64);
+        let num_txns: u64 = self
+            .ordered_txns
+            .iter()
+            .rev()
+            .take_while(|(timestamp, _)| *timestamp > window_start)
+            .map(|(_, num_txns)| num_txns)
+            .sum();
+        num_txns as f64 / window.as_secs_f64()
+    }
+
+    /// Returns the transactions and bytes each author contributed to the last `window` ordered
+    /// anchors, along with its fraction of the total.
+    pub fn author_contribution_stats(&self, window: usize) -> HashMap<Author, AuthorContribution> {
+        let mut stats: HashMap<Author, AuthorContribution> = HashMap::new();
+        let mut total_txns = 0;
+        let mut total_bytes = 0;
+        for (author, num_txns, num_bytes) in self
+            .ordered_contributions
+            .iter()
+            .rev()
+            .take(window)
+            .flatten()
+        {
+            let contribution = stats.entry(*author).or_default();
+            contribution.num_txns += *num_txns;
+            contribution.num_bytes += *num_bytes;
+            total_txns += *num_txns;
+            total_bytes += *num_bytes;
+        }
+        let fraction = |part: u64, total: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                part as f64 / total as f64
+            }
+        };
+        for contribution in stats.values_mut() {
+            contribution.txns_fraction = fraction(contribution.num_txns, total_txns);
+            contribution.bytes_fraction = fraction(contribution.num_bytes, total_bytes);
+        }
+        stats
+    }
+}
+
+impl TOrderRule for OrderRule {
+    fn process_new_node(&mut self, node_metadata: &NodeMetadata) {
         let round = node_metadata.round();
         // If the node comes from the proposal round in the current instance, it can't trigger any ordering
         if round <= self.lowest_unordered_anchor_round
@@ -224,10 +495,37 @@ impl OrderRule {
         self.check_ordering_between(start_round, round)
     }
 
-    /// Check the whole dag to see if anything can be ordered.
-    pub fn process_all(&mut self) {
+    fn process_all(&mut self) {
         let start_round = self.lowest_unordered_anchor_round;
         let round = self.dag.read().highest_round();
         self.check_ordering_between(start_round, round);
     }
+
+    fn subscribe_commit_proofs(&mut self) -> UnboundedReceiver<AnchorCommitProof> {
+        let (tx, rx) = unbounded();
+        self.commit_proof_subscribers.push(tx);
+        rx
+    }
+
+    fn subscribe_ordered_anchors(&mut self) -> UnboundedReceiver<OrderedAnchor> {
+        let (tx, rx) = unbounded();
+        self.ordered_anchor_subscribers.push(tx);
+        rx
+    }
+
+    fn highest_ordered_anchor_round(&self) -> Round {
+        self.lowest_unordered_anchor_round.saturating_sub(1)
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.process_all();
+        // apply the compaction deferred to the next commits
+        if let Some(num_retained) = self.commit_log_retention {
+            if self.commits_since_compaction > 0 {
+                self.storage.compact_commit_log(num_retained)?;
+                self.commits_since_compaction = 0;
+            }
+        }
+        self.storage.flush()
+    }
 }
@@ -1,7 +1,7 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 
-use super::{dag_fetcher::TFetchRequester, storage::DAGStorage, NodeId};
+use super::{dag_driver::GENESIS_ROUND, dag_fetcher::TFetchRequester, storage::DAGStorage, NodeId};
 use crate::dag::{
     dag_network::RpcHandler,
     dag_store::Dag,
@@ -24,6 +24,8 @@ pub enum NodeBroadcastHandleError {
     MissingParents,
     #[error("stale round number")]
     StaleRound(Round),
+    #[error("node above the genesis round has no parents")]
+    ParentlessNode(Round),
 }
 
 pub(crate) struct NodeBroadcastHandler {
@@ -81,6 +83,11 @@ impl NodeBroadcastHandler {
             current_round >= lowest_round,
             NodeBroadcastHandleError::StaleRound(current_round)
         );
+        // only the nodes of the genesis round have no round below them to link to
+        ensure!(
+            current_round == GENESIS_ROUND || !node.parents().is_empty(),
+            NodeBroadcastHandleError::ParentlessNode(current_round)
+        );
 
         // check which parents are missing in the DAG
         let missing_parents: Vec<NodeCertificate> = node
@@ -89,9 +96,13 @@ impl NodeBroadcastHandler {
             .filter(|parent| !dag_reader.exists(parent.metadata()))
             .cloned()
             .collect();
+        let missing_weak_links = node
+            .weak_links()
+            .iter()
+            .any(|weak_link| weak_link.round() >= lowest_round && !dag_reader.exists(weak_link));
         drop(dag_reader); // Drop the DAG store early as it is no longer required
 
-        if !missing_parents.is_empty() {
+        if !missing_parents.is_empty() || missing_weak_links {
             // For each missing parent, verify their signatures and voting power.
             // Otherwise, a malicious node can send bad nodes with fake parents
             // and cause this peer to issue unnecessary fetch requests.
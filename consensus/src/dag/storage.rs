@@ -1,7 +1,11 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 
-use super::{types::Vote, NodeId};
+use super::{
+    dag_state_sync::DagSyncCursor,
+    types::{AnchorCommitProof, BroadcastProgress, CommitLogEntry, Vote},
+    NodeId,
+};
 use crate::dag::{CertifiedNode, Node};
 use aptos_consensus_types::common::{Author, Round};
 use aptos_crypto::HashValue;
@@ -58,11 +62,94 @@ pub trait DAGStorage: Send + Sync {
 
     fn save_certified_node(&self, node: &CertifiedNode) -> anyhow::Result<()>;
 
+    fn get_certified_node(&self, digest: &HashValue) -> anyhow::Result<Option<CertifiedNode>>;
+
     fn get_certified_nodes(&self) -> anyhow::Result<Vec<(HashValue, CertifiedNode)>>;
 
     fn delete_certified_nodes(&self, digests: Vec<HashValue>) -> anyhow::Result<()>;
 
+    /// Saves the certified nodes, e.g. the nodes fetched while catching up. Saves them one at a
+    /// time unless overridden, storages that write in batches save them at once.
+    fn save_certified_nodes(&self, nodes: &[CertifiedNode]) -> anyhow::Result<()> {
+        nodes
+            .iter()
+            .try_for_each(|node| self.save_certified_node(node))
+    }
+
+    /// Deletes the certified nodes below `round`, of any epoch, returns the number of nodes
+    /// deleted.
+    fn delete_nodes_below(&self, round: Round) -> anyhow::Result<usize> {
+        let expired: Vec<_> = self
+            .get_certified_nodes()?
+            .into_iter()
+            .filter(|(_, node)| node.round() < round)
+            .map(|(digest, _)| digest)
+            .collect();
+        let num_expired = expired.len();
+        self.delete_certified_nodes(expired)?;
+        Ok(num_expired)
+    }
+
+    /// Deletes the pending node, votes, certified nodes and sync cursor of the epochs below
+    /// `epoch`, e.g. on entering `epoch`. Deleting what's already gone is a no-op, so it can be
+    /// called again.
+    fn prune_epoch(&self, epoch: u64) -> anyhow::Result<()> {
+        if let Some(node) = self.get_pending_node()? {
+            if node.epoch() < epoch {
+                self.delete_pending_node()?;
+            }
+        }
+        let expired_votes: Vec<_> = self
+            .get_votes()?
+            .into_iter()
+            .filter(|(node_id, _)| node_id.epoch() < epoch)
+            .map(|(node_id, _)| node_id)
+            .collect();
+        self.delete_votes(expired_votes)?;
+        let expired_nodes: Vec<_> = self
+            .get_certified_nodes()?
+            .into_iter()
+            .filter(|(_, node)| node.epoch() < epoch)
+            .map(|(digest, _)| digest)
+            .collect();
+        self.delete_certified_nodes(expired_nodes)?;
+        if let Some(cursor) = self.get_sync_cursor()? {
+            if cursor.target().epoch() < epoch {
+                self.delete_sync_cursor()?;
+            }
+        }
+        Ok(())
+    }
+
     fn get_latest_k_committed_events(&self, k: u64) -> anyhow::Result<Vec<CommitEvent>>;
 
     fn get_latest_ledger_info(&self) -> anyhow::Result<LedgerInfoWithSignatures>;
+
+    fn save_sync_cursor(&self, cursor: &DagSyncCursor) -> anyhow::Result<()>;
+
+    fn get_sync_cursor(&self) -> anyhow::Result<Option<DagSyncCursor>>;
+
+    fn delete_sync_cursor(&self) -> anyhow::Result<()>;
+
+    /// Appends the proof of a committed anchor to the commit log, kept apart from the DAG nodes
+    /// so that it survives pruning.
+    fn append_commit_log(&self, proof: &AnchorCommitProof) -> anyhow::Result<()>;
+
+    /// The commit log in commit order.
+    fn get_commit_log(&self) -> anyhow::Result<Vec<CommitLogEntry>>;
+
+    /// Compacts all but the `num_retained` most recent entries of the commit log, returns the
+    /// number of entries compacted.
+    fn compact_commit_log(&self, num_retained: usize) -> anyhow::Result<usize>;
+
+    /// Checkpoints the signatures collected by the broadcast of our pending node, replacing the
+    /// previous checkpoint.
+    fn save_broadcast_progress(&self, progress: &BroadcastProgress) -> anyhow::Result<()>;
+
+    fn get_broadcast_progress(&self) -> anyhow::Result<Option<BroadcastProgress>>;
+
+    /// Makes all the writes so far durable. Storages that write through return right away.
+    fn flush(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
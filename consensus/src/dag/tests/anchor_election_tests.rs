@@ -0,0 +1,150 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::dag::anchor_election::{
+    reputation_history_len, AnchorElection, AnchorElectionPolicy, ReputationAnchorElection,
+    RoundRobinAnchorElection, REPUTATION_EXCLUDED_ROUNDS,
+};
+use aptos_consensus_types::common::{Author, Round};
+use aptos_types::validator_verifier::random_validator_verifier;
+
+fn validators() -> Vec<Author> {
+    let (_, validator_verifier) = random_validator_verifier(4, None, false);
+    validator_verifier.get_ordered_account_addresses()
+}
+
+/// Orders the anchor of every even round in `rounds` with all the validators but the last one as
+/// parents, and the last one as failed.
+fn order_anchors(anchor_election: &dyn AnchorElection, rounds: impl Iterator<Item = Round>) {
+    let validators = validators();
+    let (failed, active) = validators.split_last().unwrap();
+    for round in rounds.filter(|round| round % 2 == 0) {
+        anchor_election.update_reputation(round, &active[0], active.to_vec(), vec![*failed]);
+    }
+}
+
+#[test]
+fn test_round_robin_anchor_election() {
+    let validators = validators();
+    let anchor_election = RoundRobinAnchorElection::new(validators.clone());
+    let anchors: Vec<_> = (0..10)
+        .map(|round| anchor_election.get_anchor(round))
+        .collect();
+    let expected: Vec<_> = [0, 0, 1, 1, 2, 2, 3, 3, 0, 0]
+        .into_iter()
+        .map(|i| validators[i])
+        .collect();
+    assert_eq!(anchors, expected);
+
+    // updating the reputation doesn't change the round robin
+    order_anchors(&anchor_election, 0..10);
+    let anchors: Vec<_> = (0..10)
+        .map(|round| anchor_election.get_anchor(round))
+        .collect();
+    assert_eq!(anchors, expected);
+
+    // round robin is the default policy
+    let default_election = AnchorElectionPolicy::default().build(validators, 10);
+    assert!((0..10).all(|round| default_election.get_anchor(round) == anchors[round as usize]));
+}
+
+#[test]
+fn test_reputation_anchor_election_deterministic() {
+    let first = ReputationAnchorElection::new(validators(), 10);
+    let second = ReputationAnchorElection::new(validators(), 10);
+    assert!((0..100).all(|round| first.get_anchor(round) == second.get_anchor(round)));
+
+    order_anchors(&first, 0..50);
+    order_anchors(&second, 0..50);
+    assert!((50..150).all(|round| first.get_anchor(round) == second.get_anchor(round)));
+
+    // ordering the anchor of a round doesn't change it
+    for round in (150..200).step_by(2) {
+        let anchor = first.get_anchor(round);
+        order_anchors(&first, round..round + 1);
+        assert_eq!(first.get_anchor(round), anchor);
+    }
+}
+
+#[test]
+fn test_reputation_anchor_election_failed_validator() {
+    let validators = validators();
+    let anchor_election = ReputationAnchorElection::new(validators.clone(), 10);
+    order_anchors(&anchor_election, 0..100);
+
+    let count = |author: &Author| {
+        (100..200)
+            .filter(|round| anchor_election.get_anchor(*round) == *author)
+            .count()
+    };
+    let (failed, active) = validators.split_last().unwrap();
+    let num_failed_anchors = count(failed);
+    assert!(num_failed_anchors < 10);
+    assert!(active
+        .iter()
+        .all(|author| count(author) > num_failed_anchors));
+}
+
+#[test]
+fn test_reputation_window_capped_to_dag_window() {
+    let dag_window = 10;
+    let capped =
+        AnchorElectionPolicy::Reputation { window_size: 50 }.build(validators(), dag_window);
+    let expected = ReputationAnchorElection::new(validators(), dag_window);
+
+    // the last validator fails first, then the first one, in the last dag window of anchors
+    let validators = validators();
+    order_anchors(capped.as_ref(), 0..100);
+    order_anchors(&expected, 0..100);
+    for round in (100..140).step_by(2) {
+        for anchor_election in [capped.as_ref(), &expected as &dyn AnchorElection] {
+            anchor_election.update_reputation(
+                round,
+                &validators[1],
+                validators[1..].to_vec(),
+                vec![validators[0]],
+            );
+        }
+    }
+    // the history beyond the dag window, which a restarted validator can't recover, is ignored
+    assert!((140..240).all(|round| capped.get_anchor(round) == expected.get_anchor(round)));
+}
+
+/// Commits the anchor of `round`, with a failed validator changing every 20 rounds.
+fn commit_anchor(anchor_election: &dyn AnchorElection, round: Round) {
+    let validators = validators();
+    let failed = validators[(round / 20) as usize % validators.len()];
+    let active: Vec<_> = validators
+        .into_iter()
+        .filter(|author| *author != failed)
+        .collect();
+    anchor_election.update_reputation(round, &active[0], active.clone(), vec![failed]);
+}
+
+#[test]
+fn test_reputation_anchor_election_ordering_interleaving() {
+    // one validator commits the anchor of each round right after electing it, the other one lags
+    // behind, committing the anchors up to the excluded rounds below the round it elects
+    let eager = ReputationAnchorElection::new(validators(), 5);
+    let lagging = ReputationAnchorElection::new(validators(), 5);
+    let mut lagging_committed_round = 0;
+    for round in (0..300).step_by(2) {
+        while lagging_committed_round + REPUTATION_EXCLUDED_ROUNDS < round {
+            commit_anchor(&lagging, lagging_committed_round);
+            lagging_committed_round += 2;
+        }
+        assert_eq!(eager.get_anchor(round), lagging.get_anchor(round));
+        commit_anchor(&eager, round);
+    }
+
+    // a restarted validator replays the latest committed anchors from storage
+    let dag_window = 5;
+    let restarted = ReputationAnchorElection::new(validators(), dag_window);
+    for round in (0..300)
+        .step_by(2)
+        .skip(150 - reputation_history_len(dag_window))
+    {
+        commit_anchor(&restarted, round);
+    }
+    assert!((300..400).all(|round| eager.get_anchor(round) == restarted.get_anchor(round)));
+}
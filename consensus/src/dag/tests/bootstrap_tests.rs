@@ -0,0 +1,126 @@
+// Copyright © Aptos Foundation
+
+use crate::{
+    dag::{
+        bootstrap::DagBootstrapper,
+        dag_driver::DagPayloadConfig,
+        tests::{
+            dag_driver_tests::MockNetworkSender, dag_state_sync_tests::MockProofNotifier,
+            dag_test::MockStorage,
+        },
+    },
+    payload_manager::PayloadManager,
+    test_utils::{EmptyStateComputer, MockPayloadManager},
+};
+use aptos_time_service::TimeService;
+use aptos_types::{epoch_state::EpochState, validator_verifier::random_validator_verifier};
+use std::{sync::Arc, time::Duration};
+
+fn new_bootstrapper() -> DagBootstrapper {
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let network_sender = Arc::new(MockNetworkSender {});
+    DagBootstrapper::new(
+        signers[0].author(),
+        Arc::new(signers[0].clone()),
+        epoch_state,
+        Arc::new(MockStorage::new()),
+        network_sender.clone(),
+        network_sender,
+        Arc::new(MockProofNotifier::default()),
+        TimeService::mock(),
+        Arc::new(PayloadManager::DirectMempool),
+        Arc::new(MockPayloadManager::new(None)),
+        Arc::new(EmptyStateComputer {}),
+    )
+}
+
+#[test]
+fn test_payload_config() {
+    let bootstrapper = new_bootstrapper().with_payload_config(DagPayloadConfig {
+        pull_timeout: Duration::from_millis(200),
+        max_txns: 10,
+        max_bytes: 1024,
+    });
+    let payload_config = bootstrapper.driver_config().payload;
+    assert_eq!(payload_config.pull_timeout, Duration::from_millis(200));
+    assert_eq!(payload_config.max_txns, 10);
+    assert_eq!(payload_config.max_bytes, 1024);
+
+    // without it, the driver keeps the default limits
+    let payload_config = new_bootstrapper().driver_config().payload;
+    assert_eq!(
+        payload_config.max_txns,
+        DagPayloadConfig::default().max_txns
+    );
+}
+
+#[test]
+fn test_broadcast_checkpoint_interval() {
+    assert_eq!(
+        new_bootstrapper()
+            .driver_config()
+            .broadcast_checkpoint_interval,
+        None
+    );
+    let bootstrapper = new_bootstrapper().with_broadcast_checkpoint_interval(Some(3));
+    assert_eq!(
+        bootstrapper.driver_config().broadcast_checkpoint_interval,
+        Some(3)
+    );
+}
+
+#[test]
+fn test_max_commit_lag_rounds() {
+    assert_eq!(
+        new_bootstrapper().driver_config().max_commit_lag_rounds,
+        None
+    );
+    let bootstrapper = new_bootstrapper().with_max_commit_lag_rounds(Some(20));
+    assert_eq!(bootstrapper.driver_config().max_commit_lag_rounds, Some(20));
+}
+
+#[test]
+fn test_round_timeout() {
+    assert_eq!(new_bootstrapper().driver_config().round_timeout, None);
+    let bootstrapper = new_bootstrapper().with_round_timeout(Some(Duration::from_secs(2)));
+    assert_eq!(
+        bootstrapper.driver_config().round_timeout,
+        Some(Duration::from_secs(2))
+    );
+}
+
+#[test]
+fn test_payload_prefetch_batch_size() {
+    assert_eq!(
+        new_bootstrapper()
+            .driver_config()
+            .payload_prefetch_batch_size,
+        None
+    );
+    let bootstrapper = new_bootstrapper().with_payload_prefetch_batch_size(Some(8));
+    assert_eq!(
+        bootstrapper.driver_config().payload_prefetch_batch_size,
+        Some(8)
+    );
+}
+
+#[test]
+fn test_broadcast_failure_alert_threshold() {
+    assert_eq!(
+        new_bootstrapper()
+            .driver_config()
+            .broadcast_failure_alert_threshold,
+        None
+    );
+    let bootstrapper = new_bootstrapper().with_broadcast_failure_alert_threshold(Some(5));
+    assert_eq!(
+        bootstrapper
+            .driver_config()
+            .broadcast_failure_alert_threshold,
+        Some(5)
+    );
+}
@@ -1,40 +1,99 @@
 // Copyright © Aptos Foundation
 
 use crate::{
+    consensusdb::ConsensusDB,
     dag::{
-        adapter::TLedgerInfoProvider,
+        adapter::{build_dag_storage, LedgerInfoProvider, RecentCommits, TLedgerInfoProvider},
         anchor_election::RoundRobinAnchorElection,
-        dag_driver::{DagDriver, DagDriverError},
-        dag_fetcher::DagFetcherService,
+        counters,
+        dag_driver::{
+            DagDriver, DagDriverConfig, DagDriverError, DagMetrics, DagPayloadConfig,
+            EmptyStrongLinksPolicy, FetchSuppressionConfig, FutureEpochNodePolicy,
+            IdleBackoffConfig, LateNodePolicy, OwnNodePolicy, ParentTimestampPolicy,
+            PayloadPullErrorPolicy, TCertifiedNodeVerifier, TPayloadValidator, GENESIS_ROUND,
+        },
+        dag_fetcher::{DagFetcher, DagFetcherService, FetchRequestHandler, TDagFetcher},
+        dag_handler::NetworkHandler,
         dag_network::{RpcWithFallback, TDAGNetworkSender},
-        dag_state_sync::DAG_WINDOW,
-        dag_store::Dag,
-        order_rule::OrderRule,
+        dag_state_sync::{StateSyncTrigger, DAG_WINDOW},
+        dag_store::{Dag, NodeProvenance},
+        order_rule::{OrderRule, TOrderRule},
+        rb_handler::NodeBroadcastHandler,
+        storage::DAGStorage,
         tests::{
-            dag_test::MockStorage, helpers::new_certified_node, order_rule_tests::TestNotifier,
+            dag_state_sync_tests::MockProofNotifier,
+            dag_test::MockStorage,
+            helpers::{
+                new_certified_node, new_certified_node_with_payload, new_signed_certified_node,
+            },
+            order_rule_tests::TestNotifier,
+        },
+        types::{
+            CertifiedAck, CertifiedNode, DAGMessage, DAGNetworkMessage, Extensions, FetchResponse,
+            Node, NodeMetadata, RemoteFetchRequest, Vote,
         },
-        types::{CertifiedAck, DAGMessage},
         RpcHandler,
     },
+    error::QuorumStoreError,
+    network::IncomingDAGRequest,
     payload_manager::PayloadManager,
-    test_utils::MockPayloadManager,
+    state_replication::PayloadClient,
+    test_utils::{CountingBatchReader, MockPayloadManager},
 };
-use aptos_consensus_types::common::{Author, Round};
-use aptos_infallible::RwLock;
+use anyhow::bail;
+use aptos_channels::{aptos_channel, message_queues::QueueStyle};
+use aptos_config::config::DagStorageBackend;
+use aptos_consensus_types::{
+    block::block_test_utils::random_payload,
+    common::{Author, Payload, PayloadFilter, ProofWithData, Round, TransactionSummary},
+    proof_of_store::{BatchId, BatchInfo, ProofOfStore},
+};
+use aptos_crypto::HashValue;
+use aptos_infallible::{Mutex, RwLock};
+use aptos_network::ProtocolId;
 use aptos_reliable_broadcast::{RBNetworkSender, ReliableBroadcast};
-use aptos_time_service::TimeService;
+use aptos_storage_interface::DbReader;
+use aptos_temppath::TempPath;
+use aptos_time_service::{MockTimeService, TimeService, TimeServiceTrait};
 use aptos_types::{
+    aggregate_signature::AggregateSignature,
+    block_info::BlockInfo,
     epoch_state::EpochState,
     ledger_info::{generate_ledger_info_with_sig, LedgerInfo, LedgerInfoWithSignatures},
-    validator_verifier::random_validator_verifier,
+    transaction::SignedTransaction,
+    validator_signer::ValidatorSigner,
+    validator_verifier::{random_validator_verifier, ValidatorConsensusInfo, ValidatorVerifier},
+    PeerId,
 };
 use async_trait::async_trait;
 use claims::{assert_ok, assert_ok_eq};
-use futures_channel::mpsc::unbounded;
-use std::{sync::Arc, time::Duration};
+use futures::{
+    channel::oneshot,
+    future::{pending, BoxFuture},
+};
+use futures_channel::mpsc::{channel, unbounded};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::time::error::Elapsed;
 use tokio_retry::strategy::ExponentialBackoff;
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id},
+    Subscriber,
+};
+use tracing_subscriber::{
+    layer::{Context, SubscriberExt},
+    registry::LookupSpan,
+    Layer, Registry,
+};
 
-struct MockNetworkSender {}
+pub(crate) struct MockNetworkSender {}
 
 #[async_trait]
 impl RBNetworkSender<DAGMessage> for MockNetworkSender {
@@ -72,6 +131,78 @@ impl TDAGNetworkSender for MockNetworkSender {
     }
 }
 
+/// Answers the broadcasts of the driver as if every validator voted for its node and acked its
+/// certified node.
+struct VotingNetworkSender {
+    signers: Vec<ValidatorSigner>,
+}
+
+#[async_trait]
+impl RBNetworkSender<DAGMessage> for VotingNetworkSender {
+    async fn send_rb_rpc(
+        &self,
+        receiver: Author,
+        message: DAGMessage,
+        _timeout: Duration,
+    ) -> anyhow::Result<DAGMessage> {
+        match message {
+            DAGMessage::NodeMsg(node) => {
+                let signer = self
+                    .signers
+                    .iter()
+                    .find(|signer| signer.author() == receiver)
+                    .expect("receiver must be a validator");
+                let signature = node.sign_vote(signer)?;
+                Ok(Vote::new(node.metadata().clone(), signature).into())
+            },
+            DAGMessage::CertifiedNodeMsg(certified_node_msg) => {
+                Ok(CertifiedAck::new(certified_node_msg.epoch()).into())
+            },
+            message => bail!("unexpected broadcast message {}", message.name()),
+        }
+    }
+}
+
+/// Never answers the broadcasts of the driver, counting the RPCs sent and the ones dropped before
+/// they were answered.
+#[derive(Default)]
+struct HangingNetworkSender {
+    sent_rpcs: Arc<AtomicUsize>,
+    dropped_rpcs: Arc<AtomicUsize>,
+}
+
+struct DropCounter(Arc<AtomicUsize>);
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[async_trait]
+impl RBNetworkSender<DAGMessage> for HangingNetworkSender {
+    async fn send_rb_rpc(
+        &self,
+        _receiver: Author,
+        _message: DAGMessage,
+        _timeout: Duration,
+    ) -> anyhow::Result<DAGMessage> {
+        self.sent_rpcs.fetch_add(1, Ordering::Relaxed);
+        let _dropped = DropCounter(self.dropped_rpcs.clone());
+        pending().await
+    }
+}
+
+/// Waits until the counter reaches `expected`, giving up after a while.
+async fn wait_for_count(counter: &AtomicUsize, expected: usize) -> Result<(), Elapsed> {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while counter.load(Ordering::Relaxed) < expected {
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+}
+
 struct MockLedgerInfoProvider {
     latest_ledger_info: LedgerInfoWithSignatures,
 }
@@ -86,75 +217,392 @@ impl TLedgerInfoProvider for MockLedgerInfoProvider {
     }
 }
 
+/// Returns the queued payloads in order and empty ones afterwards, recording the poll time, limits
+/// and filter of every pull.
+#[derive(Default)]
+struct RecordingPayloadClient {
+    payloads: Mutex<VecDeque<Payload>>,
+    poll_times: Mutex<Vec<Duration>>,
+    /// (max items, max bytes) of every pull
+    limits: Mutex<Vec<(u64, u64)>>,
+    filters: Mutex<Vec<PayloadFilter>>,
+}
+
+#[async_trait]
+impl PayloadClient for RecordingPayloadClient {
+    async fn pull_payload(
+        &self,
+        max_poll_time: Duration,
+        max_items: u64,
+        max_bytes: u64,
+        exclude: PayloadFilter,
+        _wait_callback: BoxFuture<'static, ()>,
+        _pending_ordering: bool,
+        _pending_uncommitted_blocks: usize,
+        _recent_max_fill_fraction: f32,
+        _seed: Option<u64>,
+    ) -> Result<Payload, QuorumStoreError> {
+        self.poll_times.lock().push(max_poll_time);
+        self.limits.lock().push((max_items, max_bytes));
+        self.filters.lock().push(exclude);
+        Ok(self
+            .payloads
+            .lock()
+            .pop_front()
+            .unwrap_or_else(|| Payload::empty(false)))
+    }
+}
+
+/// Fails every pull, either by timing out or with an error.
+struct FailingPayloadClient {
+    timeout: bool,
+}
+
+#[async_trait]
+impl PayloadClient for FailingPayloadClient {
+    async fn pull_payload(
+        &self,
+        _max_poll_time: Duration,
+        _max_items: u64,
+        _max_bytes: u64,
+        _exclude: PayloadFilter,
+        _wait_callback: BoxFuture<'static, ()>,
+        _pending_ordering: bool,
+        _pending_uncommitted_blocks: usize,
+        _recent_max_fill_fraction: f32,
+        _seed: Option<u64>,
+    ) -> Result<Payload, QuorumStoreError> {
+        if self.timeout {
+            let elapsed = tokio::time::timeout(Duration::ZERO, pending::<()>())
+                .await
+                .unwrap_err();
+            Err(anyhow::Error::new(elapsed).into())
+        } else {
+            Err(anyhow::anyhow!("payload service unavailable").into())
+        }
+    }
+}
+
+/// Serves the transactions of a fixed mempool that are not excluded by the filter of the pull.
+struct MempoolPayloadClient {
+    txns: Vec<SignedTransaction>,
+}
+
+#[async_trait]
+impl PayloadClient for MempoolPayloadClient {
+    async fn pull_payload(
+        &self,
+        _max_poll_time: Duration,
+        _max_items: u64,
+        _max_bytes: u64,
+        exclude: PayloadFilter,
+        _wait_callback: BoxFuture<'static, ()>,
+        _pending_ordering: bool,
+        _pending_uncommitted_blocks: usize,
+        _recent_max_fill_fraction: f32,
+        _seed: Option<u64>,
+    ) -> Result<Payload, QuorumStoreError> {
+        let excluded = match exclude {
+            PayloadFilter::DirectMempool(excluded) => excluded,
+            _ => vec![],
+        };
+        Ok(Payload::DirectMempool(
+            self.txns
+                .iter()
+                .filter(|txn| {
+                    !excluded.contains(&TransactionSummary::new(
+                        txn.sender(),
+                        txn.sequence_number(),
+                    ))
+                })
+                .cloned()
+                .collect(),
+        ))
+    }
+}
+
+fn create_driver(
+    config: DagDriverConfig,
+    time_service: TimeService,
+) -> (
+    Vec<ValidatorSigner>,
+    Arc<RwLock<Dag>>,
+    Arc<MockStorage>,
+    DagDriver,
+) {
+    DagDriverBuilder::new()
+        .with_config(config)
+        .with_time_service(time_service)
+        .build()
+}
+
+/// Builds a fully wired driver for the first of 4 random validators. Every dependency defaults to
+/// an in-memory or mock implementation unless overridden.
+#[derive(Default)]
+pub(crate) struct DagDriverBuilder {
+    config: DagDriverConfig,
+    time_service: Option<TimeService>,
+    payload_manager: Option<Arc<PayloadManager>>,
+    payload_client: Option<Arc<dyn PayloadClient>>,
+    storage: Option<Arc<MockStorage>>,
+    dag_storage: Option<Arc<dyn DAGStorage>>,
+    order_rule: Option<Box<dyn TOrderRule>>,
+    ledger_info_provider: Option<Arc<dyn TLedgerInfoProvider>>,
+    recent_commits: Option<Arc<Mutex<RecentCommits>>>,
+    certified_node_verifier: Option<Arc<dyn TCertifiedNodeVerifier>>,
+    payload_validator: Option<Arc<dyn TPayloadValidator>>,
+    voting_network: bool,
+    rb_network_sender: Option<Arc<dyn RBNetworkSender<DAGMessage>>>,
+}
+
+impl DagDriverBuilder {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn with_config(mut self, config: DagDriverConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub(crate) fn with_time_service(mut self, time_service: TimeService) -> Self {
+        self.time_service = Some(time_service);
+        self
+    }
+
+    pub(crate) fn with_payload_manager(mut self, payload_manager: Arc<PayloadManager>) -> Self {
+        self.payload_manager = Some(payload_manager);
+        self
+    }
+
+    pub(crate) fn with_payload_client(mut self, payload_client: Arc<dyn PayloadClient>) -> Self {
+        self.payload_client = Some(payload_client);
+        self
+    }
+
+    pub(crate) fn with_storage(mut self, storage: Arc<MockStorage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Backs the driver with `dag_storage` instead of the mock storage, which is then left unused.
+    pub(crate) fn with_dag_storage(mut self, dag_storage: Arc<dyn DAGStorage>) -> Self {
+        self.dag_storage = Some(dag_storage);
+        self
+    }
+
+    pub(crate) fn with_order_rule(mut self, order_rule: Box<dyn TOrderRule>) -> Self {
+        self.order_rule = Some(order_rule);
+        self
+    }
+
+    pub(crate) fn with_ledger_info_provider(
+        mut self,
+        ledger_info_provider: Arc<dyn TLedgerInfoProvider>,
+    ) -> Self {
+        self.ledger_info_provider = Some(ledger_info_provider);
+        self
+    }
+
+    pub(crate) fn with_recent_commits(mut self, recent_commits: Arc<Mutex<RecentCommits>>) -> Self {
+        self.recent_commits = Some(recent_commits);
+        self
+    }
+
+    pub(crate) fn with_certified_node_verifier(
+        mut self,
+        certified_node_verifier: Arc<dyn TCertifiedNodeVerifier>,
+    ) -> Self {
+        self.certified_node_verifier = Some(certified_node_verifier);
+        self
+    }
+
+    pub(crate) fn with_payload_validator(
+        mut self,
+        payload_validator: Arc<dyn TPayloadValidator>,
+    ) -> Self {
+        self.payload_validator = Some(payload_validator);
+        self
+    }
+
+    /// Lets the broadcasts of the driver complete, as if every validator voted and acked.
+    pub(crate) fn with_voting_network(mut self) -> Self {
+        self.voting_network = true;
+        self
+    }
+
+    pub(crate) fn with_rb_network_sender(
+        mut self,
+        rb_network_sender: Arc<dyn RBNetworkSender<DAGMessage>>,
+    ) -> Self {
+        self.rb_network_sender = Some(rb_network_sender);
+        self
+    }
+
+    pub(crate) fn build(
+        self,
+    ) -> (
+        Vec<ValidatorSigner>,
+        Arc<RwLock<Dag>>,
+        Arc<MockStorage>,
+        DagDriver,
+    ) {
+        let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+        let epoch_state = Arc::new(EpochState {
+            epoch: 1,
+            verifier: validator_verifier,
+        });
+
+        let mock_ledger_info = LedgerInfo::mock_genesis(None);
+        let mock_ledger_info = generate_ledger_info_with_sig(&signers, mock_ledger_info);
+        let storage = self.storage.unwrap_or_else(|| {
+            Arc::new(MockStorage::new_with_ledger_info(mock_ledger_info.clone()))
+        });
+        let dag_storage: Arc<dyn DAGStorage> = self.dag_storage.unwrap_or_else(|| storage.clone());
+        let dag = Arc::new(RwLock::new(Dag::new(
+            epoch_state.clone(),
+            dag_storage.clone(),
+            0,
+            self.config.dag_window(),
+        )));
+
+        let network_sender = Arc::new(MockNetworkSender {});
+        let rb_network_sender: Arc<dyn RBNetworkSender<DAGMessage>> =
+            if let Some(rb_network_sender) = self.rb_network_sender {
+                rb_network_sender
+            } else if self.voting_network {
+                Arc::new(VotingNetworkSender {
+                    signers: signers.clone(),
+                })
+            } else {
+                network_sender.clone()
+            };
+        let rb = Arc::new(ReliableBroadcast::new(
+            signers.iter().map(|s| s.author()).collect(),
+            rb_network_sender,
+            ExponentialBackoff::from_millis(10),
+            aptos_time_service::TimeService::mock(),
+            Duration::from_millis(500),
+        ));
+        let validators = signers.iter().map(|vs| vs.author()).collect();
+        let order_rule = self.order_rule.unwrap_or_else(|| {
+            let (tx, _) = unbounded();
+            Box::new(OrderRule::new(
+                epoch_state.clone(),
+                LedgerInfo::mock_genesis(None),
+                dag.clone(),
+                self.config.dag_window(),
+                Arc::new(RoundRobinAnchorElection::new(validators)),
+                Arc::new(TestNotifier { tx }),
+                dag_storage.clone(),
+            ))
+        });
+
+        let (_, fetch_requester, _, _) = DagFetcherService::new(
+            epoch_state.clone(),
+            network_sender,
+            dag.clone(),
+            aptos_time_service::TimeService::mock(),
+        );
+        let fetch_requester = Arc::new(fetch_requester);
+
+        let ledger_info_provider = self.ledger_info_provider.unwrap_or_else(|| {
+            Arc::new(MockLedgerInfoProvider {
+                latest_ledger_info: mock_ledger_info,
+            })
+        });
+
+        let mut driver = DagDriver::new(
+            signers[0].author(),
+            epoch_state,
+            dag.clone(),
+            self.payload_manager
+                .unwrap_or_else(|| Arc::new(PayloadManager::DirectMempool)),
+            self.payload_client
+                .unwrap_or_else(|| Arc::new(MockPayloadManager::new(None))),
+            rb,
+            self.time_service.unwrap_or_else(TimeService::mock),
+            dag_storage,
+            order_rule,
+            fetch_requester,
+            ledger_info_provider,
+            self.recent_commits
+                .unwrap_or_else(|| Arc::new(Mutex::new(RecentCommits::new(0)))),
+            self.config,
+        );
+        if let Some(certified_node_verifier) = self.certified_node_verifier {
+            driver = driver.with_certified_node_verifier(certified_node_verifier);
+        }
+        if let Some(payload_validator) = self.payload_validator {
+            driver = driver.with_payload_validator(payload_validator);
+        }
+        (signers, dag, storage, driver)
+    }
+}
+
 #[tokio::test]
-async fn test_certified_node_handler() {
-    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
-    let epoch_state = Arc::new(EpochState {
-        epoch: 1,
-        verifier: validator_verifier,
-    });
+async fn test_driver_builder_defaults() {
+    let payload_client = Arc::new(RecordingPayloadClient::default());
+    let payload = random_payload(3);
+    payload_client.payloads.lock().push_back(payload.clone());
 
-    let mock_ledger_info = LedgerInfo::mock_genesis(None);
-    let mock_ledger_info = generate_ledger_info_with_sig(&signers, mock_ledger_info);
-    let storage = Arc::new(MockStorage::new_with_ledger_info(mock_ledger_info.clone()));
-    let dag = Arc::new(RwLock::new(Dag::new(
-        epoch_state.clone(),
-        storage.clone(),
-        0,
-        DAG_WINDOW,
-    )));
+    let (signers, _, storage, driver) = DagDriverBuilder::new()
+        .with_payload_client(payload_client.clone())
+        .build();
 
-    let network_sender = Arc::new(MockNetworkSender {});
-    let rb = Arc::new(ReliableBroadcast::new(
-        signers.iter().map(|s| s.author()).collect(),
-        network_sender.clone(),
-        ExponentialBackoff::from_millis(10),
-        aptos_time_service::TimeService::mock(),
-        Duration::from_millis(500),
-    ));
-    let time_service = TimeService::mock();
-    let validators = signers.iter().map(|vs| vs.author()).collect();
-    let (tx, _) = unbounded();
-    let order_rule = OrderRule::new(
-        epoch_state.clone(),
-        LedgerInfo::mock_genesis(None),
-        dag.clone(),
-        Box::new(RoundRobinAnchorElection::new(validators)),
-        Arc::new(TestNotifier { tx }),
-        storage.clone(),
-    );
+    // the driver authors its first node right away, from the overridden payload client
+    assert_eq!(payload_client.filters.lock().len(), 1);
+    let node = storage.get_pending_node().unwrap().unwrap();
+    assert_eq!(node.payload(), &payload);
+    assert_eq!(node.author(), &signers[0].author());
+    assert_eq!(driver.metrics_snapshot().current_round, node.round());
+}
 
-    let (_, fetch_requester, _, _) = DagFetcherService::new(
-        epoch_state.clone(),
-        network_sender,
-        dag.clone(),
-        aptos_time_service::TimeService::mock(),
-    );
-    let fetch_requester = Arc::new(fetch_requester);
+#[tokio::test]
+async fn test_payload_config() {
+    // the defaults
+    let payload_client = Arc::new(RecordingPayloadClient::default());
+    DagDriverBuilder::new()
+        .with_payload_client(payload_client.clone())
+        .build();
+    let poll_times = payload_client.poll_times.lock().clone();
+    assert_eq!(poll_times, vec![Duration::from_secs(1)]);
+    let limits = payload_client.limits.lock().clone();
+    assert_eq!(limits, vec![(1000, 10 * 1024 * 1024)]);
 
-    let ledger_info_provider = Arc::new(MockLedgerInfoProvider {
-        latest_ledger_info: mock_ledger_info,
-    });
+    // overridden limits are passed to the payload client
+    let payload_client = Arc::new(RecordingPayloadClient::default());
+    DagDriverBuilder::new()
+        .with_config(DagDriverConfig {
+            payload: DagPayloadConfig {
+                pull_timeout: Duration::from_millis(300),
+                max_txns: 50,
+                max_bytes: 64 * 1024,
+            },
+            ..Default::default()
+        })
+        .with_payload_client(payload_client.clone())
+        .build();
+    let poll_times = payload_client.poll_times.lock().clone();
+    assert_eq!(poll_times, vec![Duration::from_millis(300)]);
+    assert_eq!(*payload_client.limits.lock(), vec![(50, 64 * 1024)]);
+}
 
-    let mut driver = DagDriver::new(
-        signers[0].author(),
-        epoch_state,
-        dag,
-        Arc::new(PayloadManager::DirectMempool),
-        Arc::new(MockPayloadManager::new(None)),
-        rb,
-        time_service,
-        storage,
-        order_rule,
-        fetch_requester,
-        ledger_info_provider,
-    );
+/// The ack of a driver whose latest ledger info is the mock genesis ledger info.
+fn genesis_ack(epoch: u64) -> CertifiedAck {
+    CertifiedAck::new(epoch).with_commit_info(LedgerInfo::mock_genesis(None).commit_info().clone())
+}
+
+#[tokio::test]
+async fn test_certified_node_handler() {
+    let (signers, _, _, mut driver) =
+        create_driver(DagDriverConfig::default(), TimeService::mock());
 
     let first_round_node = new_certified_node(1, signers[0].author(), vec![]);
     // expect an ack for a valid message
     assert_ok!(driver.process(first_round_node.clone()).await);
     // expect an ack if the same message is sent again
-    assert_ok_eq!(driver.process(first_round_node).await, CertifiedAck::new(1));
+    assert_ok_eq!(driver.process(first_round_node).await, genesis_ack(1));
 
     let parent_node = new_certified_node(1, signers[1].author(), vec![]);
     let invalid_node = new_certified_node(2, signers[0].author(), vec![parent_node.certificate()]);
@@ -163,3 +611,1776 @@ async fn test_certified_node_handler() {
         DagDriverError::MissingParents.to_string()
     );
 }
+
+#[tokio::test]
+async fn test_node_round_follows_parents() {
+    let (signers, dag, _, mut driver) =
+        create_driver(DagDriverConfig::default(), TimeService::mock());
+
+    let mut parents = vec![];
+    for round in 1..=2 {
+        let nodes: Vec<_> = signers[1..]
+            .iter()
+            .map(|signer| new_certified_node(round, signer.author(), parents.clone()))
+            .collect();
+        for node in &nodes {
+            dag.write().add_node(node.clone()).unwrap();
+        }
+        parents = nodes.iter().map(|node| node.certificate()).collect();
+    }
+
+    // a node one round above its parents is accepted
+    let valid_node = new_certified_node(3, signers[1].author(), parents.clone());
+    assert_ok!(driver.process(valid_node).await);
+
+    // a node in the same round as its parents is rejected
+    let stale_node = new_certified_node(2, signers[0].author(), parents.clone());
+    let expected = DagDriverError::InvalidRound {
+        round: 2,
+        highest_parent_round: 2,
+    };
+    assert_eq!(
+        driver.process(stale_node).await.unwrap_err().to_string(),
+        expected.to_string()
+    );
+
+    // a node skipping a round above its parents is rejected
+    let skipping_node = new_certified_node(4, signers[2].author(), parents);
+    let expected = DagDriverError::InvalidRound {
+        round: 4,
+        highest_parent_round: 2,
+    };
+    assert_eq!(
+        driver.process(skipping_node).await.unwrap_err().to_string(),
+        expected.to_string()
+    );
+}
+
+#[tokio::test]
+async fn test_node_parents_satisfy_quorum() {
+    let (signers, dag, _, mut driver) =
+        create_driver(DagDriverConfig::default(), TimeService::mock());
+
+    let round_1: Vec<_> = signers
+        .iter()
+        .map(|signer| new_certified_node(1, signer.author(), vec![]))
+        .collect();
+    for node in &round_1 {
+        dag.write().add_node(node.clone()).unwrap();
+    }
+    let parents: Vec<_> = round_1.iter().map(|node| node.certificate()).collect();
+
+    // 2 of the 4 equally weighted validators are not a quorum
+    let weak_node = new_certified_node(2, signers[0].author(), parents[..2].to_vec());
+    let expected = DagDriverError::InsufficientParents { round: 2 };
+    assert_eq!(
+        driver.process(weak_node).await.unwrap_err().to_string(),
+        expected.to_string()
+    );
+    assert!(dag
+        .read()
+        .get_node_by_round_author(2, &signers[0].author())
+        .is_none());
+
+    // 3 of them are
+    let quorum_node = new_certified_node(2, signers[0].author(), parents[..3].to_vec());
+    assert_ok!(driver.process(quorum_node).await);
+
+    // and no parents at all aren't
+    let (_, verifier) = random_validator_verifier(4, None, false);
+    let parentless_node = new_certified_node(2, signers[1].author(), vec![]);
+    let expected = DagDriverError::InsufficientParents { round: 2 };
+    assert_eq!(
+        DagDriver::check_parents_quorum(&parentless_node, &verifier)
+            .unwrap_err()
+            .to_string(),
+        expected.to_string()
+    );
+}
+
+#[tokio::test]
+async fn test_parentless_node_above_genesis_round() {
+    let (signers, dag, _, mut driver) =
+        create_driver(DagDriverConfig::default(), TimeService::mock());
+
+    let parentless_node = new_certified_node(5, signers[1].author(), vec![]);
+    let expected = DagDriverError::ParentlessNode { round: 5 };
+    assert_eq!(
+        driver
+            .process(parentless_node)
+            .await
+            .unwrap_err()
+            .to_string(),
+        expected.to_string()
+    );
+    assert!(dag
+        .read()
+        .get_node_by_round_author(5, &signers[1].author())
+        .is_none());
+
+    // the nodes of the genesis round have no parents
+    let genesis_node = new_certified_node(GENESIS_ROUND, signers[1].author(), vec![]);
+    assert_ok!(driver.process(genesis_node).await);
+}
+
+#[tokio::test]
+async fn test_empty_strong_links_policy() {
+    for policy in [
+        EmptyStrongLinksPolicy::Proceed,
+        EmptyStrongLinksPolicy::Warn,
+    ] {
+        let (_, _, storage, mut driver) = create_driver(
+            DagDriverConfig {
+                empty_strong_links_policy: policy,
+                ..Default::default()
+            },
+            TimeService::mock(),
+        );
+        // round 2 has no nodes, so round 3 has no strong links
+        driver.enter_new_round(3).await.unwrap();
+        let pending_node = storage.get_pending_node().unwrap().unwrap();
+        assert_eq!(pending_node.round(), 3);
+        assert!(pending_node.parents().is_empty());
+    }
+
+    // refusing is the default
+    let (_, _, storage, mut driver) =
+        create_driver(DagDriverConfig::default(), TimeService::mock());
+    driver.enter_new_round(3).await.unwrap();
+    // the round 1 node is still the latest authored node
+    assert_eq!(storage.get_pending_node().unwrap().unwrap().round(), 1);
+}
+
+#[tokio::test]
+async fn test_genesis_round() {
+    // the genesis round has no strong links, even if entering a round without them is refused
+    let payload_client = Arc::new(RecordingPayloadClient::default());
+    let (_, _, storage, mut driver) = DagDriverBuilder::new()
+        .with_config(DagDriverConfig {
+            empty_strong_links_policy: EmptyStrongLinksPolicy::Refuse,
+            ..Default::default()
+        })
+        .with_payload_client(payload_client.clone())
+        .build();
+    let pending_node = storage.get_pending_node().unwrap().unwrap();
+    assert_eq!(pending_node.round(), GENESIS_ROUND);
+    assert!(pending_node.parents().is_empty());
+    // nothing is excluded from the payload, as there's no earlier node
+    assert!(matches!(
+        payload_client.filters.lock().as_slice(),
+        [PayloadFilter::Empty]
+    ));
+
+    // re-entering the genesis round doesn't look for a round below it
+    assert_ok!(driver.enter_new_round(GENESIS_ROUND).await);
+    assert!(matches!(
+        payload_client.filters.lock().last(),
+        Some(PayloadFilter::Empty)
+    ));
+
+    // and there's no round 0
+    assert_eq!(
+        driver.enter_new_round(0).await.unwrap_err().to_string(),
+        DagDriverError::BelowGenesisRound { round: 0 }.to_string()
+    );
+    assert_eq!(driver.metrics_snapshot().current_round, GENESIS_ROUND);
+    assert_eq!(payload_client.filters.lock().len(), 2);
+}
+
+#[tokio::test]
+async fn test_highest_strong_links_round() {
+    let (signers, dag, _, mut driver) =
+        create_driver(DagDriverConfig::default(), TimeService::mock());
+
+    let first_round_nodes: Vec<_> = signers
+        .iter()
+        .map(|signer| new_certified_node(1, signer.author(), vec![]))
+        .collect();
+    for node in &first_round_nodes {
+        assert_ok!(driver.process(node.clone()).await);
+    }
+    assert_eq!(driver.highest_strong_links_round(), 1);
+
+    // a single node in round 2 is not enough to build upon
+    let parents = first_round_nodes
+        .iter()
+        .map(|node| node.certificate())
+        .collect();
+    assert_ok!(
+        driver
+            .process(new_certified_node(2, signers[1].author(), parents))
+            .await
+    );
+    assert_eq!(dag.read().highest_round(), 2);
+    assert_eq!(driver.highest_strong_links_round(), 1);
+}
+
+#[tokio::test]
+async fn test_driver_timestamps_with_mock_time() {
+    let mock_time = MockTimeService::new();
+    mock_time.advance_secs(1);
+    let (signers, _, storage, mut driver) = create_driver(
+        DagDriverConfig::default(),
+        TimeService::from_mock(mock_time.clone()),
+    );
+    // the round 1 node is authored on construction
+    let pending_node = storage.get_pending_node().unwrap().unwrap();
+    assert_eq!(pending_node.round(), 1);
+    assert_eq!(pending_node.timestamp(), 1_000_000);
+
+    let mut parents = vec![];
+    for round in 1..=2 {
+        mock_time.advance_ms(500);
+        mock_time.flush_pending_async().await;
+        let nodes: Vec<_> = signers
+            .iter()
+            .map(|signer| new_certified_node(round, signer.author(), parents.clone()))
+            .collect();
+        for node in &nodes {
+            assert_ok!(driver.process(node.clone()).await);
+        }
+        let pending_node = storage.get_pending_node().unwrap().unwrap();
+        assert_eq!(pending_node.round(), round + 1);
+        assert_eq!(pending_node.timestamp(), 1_000_000 + round * 500_000);
+        parents = nodes.iter().map(|node| node.certificate()).collect();
+    }
+}
+
+/// Round 1 nodes of the other validators at 2s, 3s and 10s, ahead of the local clock at 1s.
+fn nodes_ahead_of_local_clock(signers: &[ValidatorSigner]) -> Vec<CertifiedNode> {
+    signers[1..]
+        .iter()
+        .zip([2_000_000, 3_000_000, 10_000_000])
+        .map(|(signer, timestamp)| {
+            let node = Node::new(
+                1,
+                1,
+                signer.author(),
+                timestamp,
+                Payload::empty(false),
+                vec![],
+                Extensions::empty(),
+            );
+            CertifiedNode::new(node, AggregateSignature::empty())
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_parent_timestamp_clamp() {
+    let mock_time = MockTimeService::new();
+    mock_time.advance_secs(1);
+    let (signers, _, storage, mut driver) = DagDriverBuilder::new()
+        .with_time_service(TimeService::from_mock(mock_time.clone()))
+        .build();
+
+    // the timestamp is clamped above the parents, right away
+    for node in nodes_ahead_of_local_clock(&signers) {
+        assert_ok!(driver.process(node).await);
+    }
+    let pending_node = storage.get_pending_node().unwrap().unwrap();
+    assert_eq!(pending_node.round(), 2);
+    assert_eq!(pending_node.timestamp(), 10_000_001);
+    assert_eq!(mock_time.now_unix_time(), Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn test_parent_timestamp_wait_for_median() {
+    let mock_time = MockTimeService::new();
+    mock_time.advance_secs(1);
+    let (signers, _, storage, mut driver) = DagDriverBuilder::new()
+        .with_config(DagDriverConfig {
+            parent_timestamp_policy: ParentTimestampPolicy::WaitForMedian,
+            ..Default::default()
+        })
+        .with_time_service(TimeService::from_mock(mock_time.clone()))
+        .build();
+
+    let mut nodes = nodes_ahead_of_local_clock(&signers);
+    let last_node = nodes.pop().unwrap();
+    for node in nodes {
+        assert_ok!(driver.process(node).await);
+    }
+    let process_last_node = tokio::spawn(async move {
+        assert_ok!(driver.process(last_node).await);
+    });
+
+    // the node isn't authored until the local clock passes the median of the parents, at 3s
+    while mock_time.num_waiters() == 0 {
+        tokio::task::yield_now().await;
+    }
+    assert_eq!(storage.get_pending_node().unwrap().unwrap().round(), 1);
+    mock_time.advance_secs(1);
+    assert_eq!(storage.get_pending_node().unwrap().unwrap().round(), 1);
+    mock_time.advance_secs(2);
+    process_last_node.await.unwrap();
+
+    let pending_node = storage.get_pending_node().unwrap().unwrap();
+    assert_eq!(pending_node.round(), 2);
+    assert!(mock_time.now_unix_time() > Duration::from_secs(3));
+    assert_eq!(pending_node.timestamp(), 10_000_001);
+}
+
+#[tokio::test]
+async fn test_parent_timestamp_wait_released_by_flush() {
+    let mock_time = MockTimeService::new();
+    mock_time.advance_secs(1);
+    let (signers, _, storage, mut driver) = DagDriverBuilder::new()
+        .with_config(DagDriverConfig {
+            parent_timestamp_policy: ParentTimestampPolicy::WaitForMedian,
+            ..Default::default()
+        })
+        .with_time_service(TimeService::from_mock(mock_time.clone()))
+        .build();
+
+    let mut nodes = nodes_ahead_of_local_clock(&signers);
+    let last_node = nodes.pop().unwrap();
+    for node in nodes {
+        assert_ok!(driver.process(node).await);
+    }
+    let process_last_node = tokio::spawn(async move {
+        assert_ok!(driver.process(last_node).await);
+    });
+
+    // the driver sleeps until just past the median of the parents, flushing wakes it up there
+    while mock_time.num_waiters() == 0 {
+        tokio::task::yield_now().await;
+    }
+    assert_eq!(storage.get_pending_node().unwrap().unwrap().round(), 1);
+    assert_eq!(mock_time.flush_pending_async().await, 1);
+    process_last_node.await.unwrap();
+
+    let pending_node = storage.get_pending_node().unwrap().unwrap();
+    assert_eq!(pending_node.round(), 2);
+    assert_eq!(mock_time.now_unix_time(), Duration::from_micros(3_000_001));
+}
+
+#[tokio::test]
+async fn test_idle_backoff() {
+    let payload_client = Arc::new(RecordingPayloadClient::default());
+    let (_, _, _, mut driver) = DagDriverBuilder::new()
+        .with_config(DagDriverConfig {
+            empty_strong_links_policy: EmptyStrongLinksPolicy::Proceed,
+            idle_backoff: Some(IdleBackoffConfig {
+                empty_rounds_threshold: 2,
+                max_poll_time: Duration::from_secs(5),
+            }),
+            ..Default::default()
+        })
+        .with_payload_client(payload_client.clone())
+        .build();
+
+    // rounds 1 to 5 are idle, transactions appear in round 6
+    for round in 2..=5 {
+        driver.enter_new_round(round).await.unwrap();
+    }
+    payload_client.payloads.lock().push_back(random_payload(10));
+    driver.enter_new_round(6).await.unwrap();
+    driver.enter_new_round(7).await.unwrap();
+
+    let secs = Duration::from_secs;
+    assert_eq!(*payload_client.poll_times.lock(), vec![
+        secs(1),
+        secs(1),
+        secs(2),
+        secs(4),
+        secs(5),
+        secs(5),
+        secs(1),
+    ]);
+}
+
+#[tokio::test]
+async fn test_time_in_current_round() {
+    let mock_time = MockTimeService::new();
+    let (signers, _, _, mut driver) = DagDriverBuilder::new()
+        .with_time_service(TimeService::from_mock(mock_time.clone()))
+        .build();
+    assert_eq!(driver.time_in_current_round(), Duration::ZERO);
+
+    // the duration grows while the round doesn't change
+    mock_time.advance_secs(5);
+    assert_eq!(driver.time_in_current_round(), Duration::from_secs(5));
+    mock_time.advance_secs(3);
+    assert_eq!(driver.time_in_current_round(), Duration::from_secs(8));
+
+    // and starts over in the next round
+    for signer in &signers[1..] {
+        assert_ok!(
+            driver
+                .process(new_certified_node(1, signer.author(), vec![]))
+                .await
+        );
+    }
+    assert_eq!(driver.metrics_snapshot().current_round, 2);
+    assert_eq!(driver.time_in_current_round(), Duration::ZERO);
+}
+
+#[tokio::test]
+async fn test_aborted_broadcasts() {
+    let (signers, _, _, mut driver) = DagDriverBuilder::new().build();
+    let aborted = counters::ABORTED_BROADCASTS.get();
+    let new_node = |round| {
+        Node::new(
+            1,
+            round,
+            signers[0].author(),
+            0,
+            Payload::empty(false),
+            vec![],
+            Extensions::empty(),
+        )
+    };
+
+    // the mock network never completes a broadcast, each one aborts the previous one
+    driver.broadcast_node(new_node(2));
+    let aborted_by_driver = driver.metrics_snapshot().aborted_broadcasts;
+    driver.broadcast_node(new_node(3));
+    assert_eq!(
+        driver.metrics_snapshot().aborted_broadcasts,
+        aborted_by_driver + 1
+    );
+    assert!(counters::ABORTED_BROADCASTS.get() > aborted);
+}
+
+#[tokio::test]
+async fn test_round_timeout() {
+    let mock_time = MockTimeService::new();
+    let (_, _, storage, mut driver) = DagDriverBuilder::new()
+        .with_config(DagDriverConfig {
+            round_timeout: Some(Duration::from_secs(2)),
+            ..Default::default()
+        })
+        .with_time_service(TimeService::from_mock(mock_time.clone()))
+        .build();
+    let pending_node = storage.get_pending_node().unwrap().unwrap();
+    let aborted = driver.metrics_snapshot().aborted_broadcasts;
+
+    mock_time.advance_secs(1);
+    assert!(!driver.check_round_timeout());
+
+    // the round doesn't advance past the timeout, the pending node is broadcast again
+    let timeout = tokio::spawn(driver.round_timeout());
+    mock_time.advance_secs(1);
+    assert_ok!(timeout.await);
+    assert!(driver.check_round_timeout());
+    assert_eq!(driver.metrics_snapshot().aborted_broadcasts, aborted + 1);
+    assert_eq!(
+        driver.metrics_snapshot().current_round,
+        pending_node.round()
+    );
+    assert_eq!(storage.get_pending_node().unwrap(), Some(pending_node));
+
+    // the timer restarts with the timeout
+    assert!(!driver.check_round_timeout());
+    mock_time.advance_secs(2);
+    assert!(driver.check_round_timeout());
+}
+
+#[tokio::test]
+async fn test_graceful_shutdown() {
+    let (signers, _, storage, mut driver) = DagDriverBuilder::new().build();
+    for signer in &signers[1..] {
+        assert_ok!(
+            driver
+                .process(new_certified_node(1, signer.author(), vec![]))
+                .await
+        );
+    }
+    assert_eq!(driver.metrics_snapshot().current_round, 2);
+    let pending_node = storage.get_pending_node().unwrap().unwrap();
+    assert_eq!(pending_node.round(), 2);
+
+    assert_ok!(driver.graceful_shutdown().await);
+    assert!(!driver.metrics_snapshot().broadcast_in_flight);
+    drop(driver);
+
+    // the restarted driver resumes the pending node rather than authoring another one
+    let payload_client = Arc::new(RecordingPayloadClient::default());
+    let (_, dag, storage, driver) = DagDriverBuilder::new()
+        .with_storage(storage)
+        .with_payload_client(payload_client.clone())
+        .build();
+    assert_eq!(dag.read().highest_round(), 1);
+    assert_eq!(driver.metrics_snapshot().current_round, 2);
+    assert!(payload_client.filters.lock().is_empty());
+    assert_eq!(storage.get_pending_node().unwrap(), Some(pending_node));
+}
+
+#[tokio::test]
+async fn test_shutdown() {
+    let network_sender = Arc::new(HangingNetworkSender::default());
+    let (signers, _, _, mut driver) = DagDriverBuilder::new()
+        .with_rb_network_sender(network_sender.clone())
+        .build();
+    // the round 1 node is broadcast on construction, and never gets its votes
+    assert_ok!(wait_for_count(&network_sender.sent_rpcs, signers.len()).await);
+    assert!(driver.metrics_snapshot().broadcast_in_flight);
+    assert_eq!(network_sender.dropped_rpcs.load(Ordering::Relaxed), 0);
+
+    assert_ok!(driver.shutdown().await);
+    // the aborted broadcast drops its in-flight RPCs
+    assert_ok!(wait_for_count(&network_sender.dropped_rpcs, signers.len()).await);
+    assert!(!driver.metrics_snapshot().broadcast_in_flight);
+
+    let node = new_certified_node(1, signers[1].author(), vec![]);
+    assert_eq!(
+        driver.process(node).await.unwrap_err().to_string(),
+        DagDriverError::ShutDown.to_string()
+    );
+    assert_eq!(
+        driver.enter_new_round(2).await.unwrap_err().to_string(),
+        DagDriverError::ShutDown.to_string()
+    );
+}
+
+#[tokio::test]
+async fn test_commit_lag_backpressure() {
+    let stalled = counters::ROUNDS_STALLED_BY_BACKPRESSURE.get();
+    // the highest committed anchor round stays at genesis
+    let (signers, dag, _, mut driver) = DagDriverBuilder::new()
+        .with_config(DagDriverConfig {
+            max_commit_lag_rounds: Some(1),
+            ..Default::default()
+        })
+        .build();
+
+    let round_1_nodes: Vec<_> = signers
+        .iter()
+        .map(|signer| new_certified_node(1, signer.author(), vec![]))
+        .collect();
+    for node in &round_1_nodes[1..] {
+        assert_ok!(driver.process(node.clone()).await);
+    }
+    assert_eq!(driver.metrics_snapshot().current_round, 2);
+
+    // a DAG 2 rounds ahead of the commits exceeds the lag, no new round is entered
+    let parents: Vec<_> = round_1_nodes[1..]
+        .iter()
+        .map(|node| node.certificate())
+        .collect();
+    for signer in &signers[1..] {
+        assert_ok!(
+            driver
+                .process(new_certified_node(2, signer.author(), parents.clone()))
+                .await
+        );
+    }
+    assert_eq!(dag.read().highest_round(), 2);
+    assert_eq!(driver.highest_strong_links_round(), 2);
+    assert_eq!(driver.metrics_snapshot().current_round, 2);
+    assert!(counters::ROUNDS_STALLED_BY_BACKPRESSURE.get() > stalled);
+}
+
+#[tokio::test]
+async fn test_finality_lag_rounds() {
+    let ledger_info_with_round = |round| {
+        LedgerInfoWithSignatures::new(
+            LedgerInfo::new(
+                BlockInfo::new(1, round, HashValue::zero(), HashValue::zero(), 0, 0, None),
+                HashValue::zero(),
+            ),
+            AggregateSignature::empty(),
+        )
+    };
+    let ledger_info_provider = Arc::new(RwLock::new(LedgerInfoProvider::new(
+        ledger_info_with_round(0),
+    )));
+    let (_, _, _, mut driver) = DagDriverBuilder::new()
+        .with_config(DagDriverConfig {
+            empty_strong_links_policy: EmptyStrongLinksPolicy::Proceed,
+            ..Default::default()
+        })
+        .with_ledger_info_provider(ledger_info_provider.clone())
+        .build();
+    assert_eq!(driver.finality_lag_rounds(), 1);
+
+    // rounds advance without commits, nor nodes
+    for round in 2..=5 {
+        driver.enter_new_round(round).await.unwrap();
+        assert_eq!(driver.finality_lag_rounds(), round);
+    }
+
+    ledger_info_provider
+        .write()
+        .notify_commit_proof(ledger_info_with_round(3));
+    assert_eq!(driver.finality_lag_rounds(), 2);
+    driver.enter_new_round(6).await.unwrap();
+    assert_eq!(driver.finality_lag_rounds(), 3);
+}
+
+#[tokio::test]
+async fn test_prune_after_commit() {
+    let ledger_info_with_round = |round| {
+        LedgerInfoWithSignatures::new(
+            LedgerInfo::new(
+                BlockInfo::new(1, round, HashValue::zero(), HashValue::zero(), 0, 0, None),
+                HashValue::zero(),
+            ),
+            AggregateSignature::empty(),
+        )
+    };
+    let ledger_info_provider = Arc::new(RwLock::new(LedgerInfoProvider::new(
+        ledger_info_with_round(0),
+    )));
+    let (signers, dag, storage, mut driver) = DagDriverBuilder::new()
+        .with_ledger_info_provider(ledger_info_provider.clone())
+        .build();
+
+    let mut nodes_by_round = vec![];
+    let mut parents = vec![];
+    for round in 1..=5 {
+        let round_nodes: Vec<_> = signers[1..]
+            .iter()
+            .map(|signer| new_certified_node(round, signer.author(), parents.clone()))
+            .collect();
+        for node in &round_nodes {
+            assert_ok!(driver.process(node.clone()).await);
+        }
+        parents = round_nodes.iter().map(|node| node.certificate()).collect();
+        nodes_by_round.push(round_nodes);
+    }
+    assert_eq!(dag.read().lowest_round(), 1);
+
+    // the commit of round 4 prunes below round 3, keeping the round 2 parents of round 3
+    ledger_info_provider
+        .write()
+        .notify_commit_proof(ledger_info_with_round(4));
+    assert_ok!(
+        driver
+            .process(new_certified_node(6, signers[1].author(), parents))
+            .await
+    );
+    let dag_reader = dag.read();
+    assert_eq!(dag_reader.lowest_round(), 2);
+    for node in &nodes_by_round[0] {
+        assert!(!dag_reader.exists(node.metadata()));
+        assert_ok_eq!(storage.get_certified_node(&node.digest()), None);
+    }
+    for node in nodes_by_round[1..].iter().flatten() {
+        assert!(dag_reader.exists(node.metadata()));
+    }
+}
+
+#[tokio::test]
+async fn test_estimated_finality_latency() {
+    let ledger_info = LedgerInfoWithSignatures::new(
+        LedgerInfo::new(
+            BlockInfo::new(1, 0, HashValue::zero(), HashValue::zero(), 0, 0, None),
+            HashValue::zero(),
+        ),
+        AggregateSignature::empty(),
+    );
+    let mock_time = MockTimeService::new();
+    let (_, _, _, mut driver) = DagDriverBuilder::new()
+        .with_config(DagDriverConfig {
+            empty_strong_links_policy: EmptyStrongLinksPolicy::Proceed,
+            ..Default::default()
+        })
+        .with_time_service(TimeService::from_mock(mock_time.clone()))
+        .with_ledger_info_provider(Arc::new(RwLock::new(LedgerInfoProvider::new(ledger_info))))
+        .build();
+    // entering the first round records nothing
+    assert_eq!(driver.estimated_finality_latency(), Duration::ZERO);
+
+    // rounds of 1s and 3s with the committed anchor at genesis, 2 and 3 rounds behind
+    mock_time.advance_secs(1);
+    driver.enter_new_round(2).await.unwrap();
+    mock_time.advance_secs(3);
+    driver.enter_new_round(3).await.unwrap();
+    assert_eq!(driver.estimated_finality_latency(), Duration::from_secs(5));
+}
+
+/// Records the nodes it is notified of without ordering anything.
+struct RecordingOrderRule {
+    processed: Arc<Mutex<Vec<NodeMetadata>>>,
+}
+
+impl TOrderRule for RecordingOrderRule {
+    fn process_new_node(&mut self, node_metadata: &NodeMetadata) {
+        self.processed.lock().push(node_metadata.clone());
+    }
+
+    fn process_all(&mut self) {}
+}
+
+#[tokio::test]
+async fn test_custom_order_rule() {
+    let processed = Arc::new(Mutex::new(vec![]));
+    let (signers, _, _, mut driver) = DagDriverBuilder::new()
+        .with_order_rule(Box::new(RecordingOrderRule {
+            processed: processed.clone(),
+        }))
+        .build();
+
+    let nodes: Vec<_> = signers
+        .iter()
+        .map(|signer| new_certified_node(1, signer.author(), vec![]))
+        .collect();
+    for node in &nodes {
+        assert_ok!(driver.process(node.clone()).await);
+    }
+    // a duplicate node is not passed to the order rule again
+    assert_ok!(driver.process(nodes[0].clone()).await);
+
+    let expected: Vec<_> = nodes.iter().map(|node| node.metadata().clone()).collect();
+    assert_eq!(*processed.lock(), expected);
+}
+
+/// Fails the verification of the given nodes and passes every other node.
+struct FailingCertifiedNodeVerifier {
+    invalid_nodes: HashSet<HashValue>,
+}
+
+impl TCertifiedNodeVerifier for FailingCertifiedNodeVerifier {
+    fn verify(&self, node: &CertifiedNode, _verifier: &ValidatorVerifier) -> anyhow::Result<()> {
+        if self.invalid_nodes.contains(&node.digest()) {
+            bail!("invalid signatures");
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_invalid_certificate_rejected() {
+    let (signers, _) = random_validator_verifier(4, None, false);
+    let nodes: Vec<_> = signers
+        .iter()
+        .map(|signer| new_certified_node(1, signer.author(), vec![]))
+        .collect();
+    let (_, dag, _, mut driver) = DagDriverBuilder::new()
+        .with_certified_node_verifier(Arc::new(FailingCertifiedNodeVerifier {
+            invalid_nodes: HashSet::from([nodes[1].digest()]),
+        }))
+        .build();
+
+    assert_eq!(
+        driver
+            .process(nodes[1].clone())
+            .await
+            .unwrap_err()
+            .to_string(),
+        DagDriverError::InvalidCertificate.to_string()
+    );
+    assert!(!dag.read().exists(nodes[1].metadata()));
+
+    for node in [&nodes[0], &nodes[2], &nodes[3]] {
+        assert_ok!(driver.process(node.clone()).await);
+        assert!(dag.read().exists(node.metadata()));
+    }
+}
+
+/// Rejects the transactions with a sequence number beyond the limit of their account.
+struct SequenceNumberLimitValidator {
+    max_sequence_number: u64,
+}
+
+impl TPayloadValidator for SequenceNumberLimitValidator {
+    fn is_valid(&self, txn: &SignedTransaction) -> bool {
+        txn.sequence_number() <= self.max_sequence_number
+    }
+}
+
+#[tokio::test]
+async fn test_invalid_payload_txns_dropped() {
+    let txns = match random_payload(3) {
+        Payload::DirectMempool(txns) => txns,
+        _ => unreachable!(),
+    };
+    let payload_client = Arc::new(RecordingPayloadClient::default());
+    payload_client
+        .payloads
+        .lock()
+        .extend([Payload::empty(false), Payload::DirectMempool(txns.clone())]);
+    let (signers, _, storage, mut driver) = DagDriverBuilder::new()
+        .with_payload_client(payload_client)
+        .with_payload_validator(Arc::new(SequenceNumberLimitValidator {
+            max_sequence_number: 1,
+        }))
+        .build();
+
+    for signer in &signers[1..] {
+        assert_ok!(
+            driver
+                .process(new_certified_node(1, signer.author(), vec![]))
+                .await
+        );
+    }
+
+    let pending_node = storage.get_pending_node().unwrap().unwrap();
+    assert_eq!(pending_node.round(), 2);
+    // the over-limit transaction is dropped before the node is broadcast
+    assert_eq!(
+        pending_node.payload(),
+        &Payload::DirectMempool(txns[..2].to_vec())
+    );
+}
+
+#[tokio::test]
+async fn test_equivocation() {
+    let (signers, dag, _, mut driver) = DagDriverBuilder::new().build();
+    let author = signers[1].author();
+    let node = new_certified_node(1, author, vec![]);
+    // same epoch, round and author, different timestamp
+    let conflicting_node = CertifiedNode::new(
+        Node::new(
+            1,
+            1,
+            author,
+            1,
+            Payload::empty(false),
+            vec![],
+            Extensions::empty(),
+        ),
+        AggregateSignature::empty(),
+    );
+    assert_ne!(node.digest(), conflicting_node.digest());
+    let equivocations = counters::EQUIVOCATIONS
+        .with_label_values(&[&author.to_string()])
+        .get();
+
+    assert_ok!(driver.process(node.clone()).await);
+    assert_eq!(
+        driver
+            .process(conflicting_node.clone())
+            .await
+            .unwrap_err()
+            .to_string(),
+        DagDriverError::Equivocation { author, round: 1 }.to_string()
+    );
+    assert_eq!(
+        counters::EQUIVOCATIONS
+            .with_label_values(&[&author.to_string()])
+            .get(),
+        equivocations + 1
+    );
+    // the node received first is kept, the conflicting one is kept as evidence
+    let dag_reader = dag.read();
+    assert!(dag_reader.exists(node.metadata()));
+    assert!(!dag_reader.exists(conflicting_node.metadata()));
+    assert_eq!(
+        dag_reader.equivocations()[0].rejected.digest(),
+        conflicting_node.digest()
+    );
+}
+
+/// Reports a fixed highest ordered anchor round without ordering anything.
+struct OrderedRoundOrderRule {
+    highest_ordered_anchor_round: Round,
+}
+
+impl TOrderRule for OrderedRoundOrderRule {
+    fn process_new_node(&mut self, _node_metadata: &NodeMetadata) {}
+
+    fn process_all(&mut self) {}
+
+    fn highest_ordered_anchor_round(&self) -> Round {
+        self.highest_ordered_anchor_round
+    }
+}
+
+#[tokio::test]
+async fn test_late_node_policy() {
+    for (policy, expect_added) in [
+        (LateNodePolicy::AddForAudit, true),
+        (LateNodePolicy::Drop, false),
+    ] {
+        let dropped = counters::LATE_NODES_DROPPED.get();
+        let (signers, dag, _, mut driver) = DagDriverBuilder::new()
+            .with_config(DagDriverConfig {
+                late_node_policy: policy,
+                ..Default::default()
+            })
+            .with_order_rule(Box::new(OrderedRoundOrderRule {
+                highest_ordered_anchor_round: 1,
+            }))
+            .build();
+
+        // the node is acknowledged either way
+        let node = new_certified_node(1, signers[1].author(), vec![]);
+        assert_eq!(driver.process(node.clone()).await.unwrap(), genesis_ack(1));
+        assert_eq!(dag.read().exists(node.metadata()), expect_added);
+        if !expect_added {
+            assert!(counters::LATE_NODES_DROPPED.get() > dropped);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_estimated_egress_per_round() {
+    let (_, _, storage, driver) = create_driver(DagDriverConfig::default(), TimeService::mock());
+
+    let node = storage.get_pending_node().unwrap().unwrap();
+    let node_size = bcs::to_bytes(&node).unwrap().len() as u64;
+    // 4 validators, each receiving the node and then the certified node
+    assert_eq!(driver.estimated_egress_per_round(), node_size * 4 * 2);
+}
+
+#[tokio::test]
+async fn test_recent_commits_excluded_from_payload() {
+    let payload_client = Arc::new(RecordingPayloadClient::default());
+    let recent_commits = Arc::new(Mutex::new(RecentCommits::new(1)));
+    let (_, _, _, mut driver) = DagDriverBuilder::new()
+        .with_config(DagDriverConfig {
+            empty_strong_links_policy: EmptyStrongLinksPolicy::Proceed,
+            ..Default::default()
+        })
+        .with_payload_client(payload_client.clone())
+        .with_recent_commits(recent_commits.clone())
+        .build();
+    assert_eq!(*payload_client.filters.lock(), vec![PayloadFilter::Empty]);
+
+    let committed_payload = random_payload(2);
+    let summaries = |payload: &Payload| -> Vec<TransactionSummary> {
+        match payload {
+            Payload::DirectMempool(txns) => txns
+                .iter()
+                .map(|txn| TransactionSummary {
+                    sender: txn.sender(),
+                    sequence_number: txn.sequence_number(),
+                })
+                .collect(),
+            _ => unreachable!(),
+        }
+    };
+    recent_commits.lock().insert(committed_payload.clone());
+    driver.enter_new_round(2).await.unwrap();
+    assert_eq!(
+        payload_client.filters.lock().last().unwrap(),
+        &PayloadFilter::DirectMempool(summaries(&committed_payload))
+    );
+
+    // the oldest commit is evicted once the dedup set is full
+    let newer_payload = random_payload(1);
+    recent_commits.lock().insert(newer_payload.clone());
+    driver.enter_new_round(3).await.unwrap();
+    assert_eq!(
+        payload_client.filters.lock().last().unwrap(),
+        &PayloadFilter::DirectMempool(summaries(&newer_payload))
+    );
+}
+
+#[tokio::test]
+async fn test_custom_dag_window() {
+    let ledger_info = LedgerInfoWithSignatures::new(
+        LedgerInfo::new(
+            BlockInfo::new(1, 4, HashValue::zero(), HashValue::zero(), 0, 0, None),
+            HashValue::zero(),
+        ),
+        AggregateSignature::empty(),
+    );
+    let summaries = |payload: &Payload| -> Vec<TransactionSummary> {
+        match payload {
+            Payload::DirectMempool(txns) => txns
+                .iter()
+                .map(|txn| TransactionSummary {
+                    sender: txn.sender(),
+                    sequence_number: txn.sequence_number(),
+                })
+                .collect(),
+            _ => unreachable!(),
+        }
+    };
+    for dag_window in [None, Some(2)] {
+        let payload_client = Arc::new(RecordingPayloadClient::default());
+        let (signers, dag, _, mut driver) = DagDriverBuilder::new()
+            .with_config(DagDriverConfig {
+                dag_window,
+                ..Default::default()
+            })
+            .with_payload_client(payload_client.clone())
+            .with_ledger_info_provider(Arc::new(MockLedgerInfoProvider {
+                latest_ledger_info: ledger_info.clone(),
+            }))
+            .build();
+
+        let mut payloads = vec![];
+        let mut parents = vec![];
+        for round in 1..=5 {
+            let payload = random_payload(1);
+            // only the first node of each round has a payload
+            let mut nodes = vec![new_certified_node_with_payload(
+                round,
+                signers[1].author(),
+                parents.clone(),
+                payload.clone(),
+            )];
+            nodes.extend(
+                signers[2..]
+                    .iter()
+                    .map(|signer| new_certified_node(round, signer.author(), parents.clone())),
+            );
+            for node in &nodes {
+                dag.write().add_node(node.clone()).unwrap();
+            }
+            parents = nodes.iter().map(|node| node.certificate()).collect();
+            payloads.push(payload);
+        }
+        driver.enter_new_round(6).await.unwrap();
+
+        // the payloads of the rounds within the window below the committed round 4 are excluded
+        let lowest_round = 4 - dag_window.unwrap_or(DAG_WINDOW);
+        let expected: HashSet<_> = payloads[lowest_round - 1..]
+            .iter()
+            .flat_map(summaries)
+            .collect();
+        let excluded: HashSet<_> = match payload_client.filters.lock().last().unwrap() {
+            PayloadFilter::DirectMempool(excluded) => excluded.iter().cloned().collect(),
+            _ => unreachable!(),
+        };
+        assert_eq!(excluded, expected);
+    }
+}
+
+#[tokio::test]
+async fn test_broadcast_failure_alert() {
+    let (signers, _, _, mut driver) = create_driver(
+        DagDriverConfig {
+            broadcast_failure_alert_threshold: Some(3),
+            ..Default::default()
+        },
+        TimeService::mock(),
+    );
+    let alerts_before = counters::BROADCAST_QUORUM_LOST_ALERTS.get();
+
+    // the mock network never completes the broadcasts, each new round replaces a failed one
+    let mut parents = vec![];
+    for round in 1..=4 {
+        let nodes: Vec<_> = signers[1..]
+            .iter()
+            .map(|signer| new_certified_node(round, signer.author(), parents.clone()))
+            .collect();
+        for node in &nodes {
+            assert_ok!(driver.process(node.clone()).await);
+        }
+        parents = nodes.iter().map(|node| node.certificate()).collect();
+
+        assert_eq!(driver.consecutive_failed_broadcasts(), round);
+        assert_eq!(driver.broadcast_quorum_lost(), round >= 3);
+    }
+    // alerted once for the streak
+    assert_eq!(
+        counters::BROADCAST_QUORUM_LOST_ALERTS.get(),
+        alerts_before + 1
+    );
+}
+
+#[tokio::test]
+async fn test_metrics_snapshot() {
+    let (signers, _, storage, mut driver) =
+        create_driver(DagDriverConfig::default(), TimeService::mock());
+
+    let nodes: Vec<_> = signers
+        .iter()
+        .map(|signer| new_certified_node(1, signer.author(), vec![]))
+        .collect();
+    for node in &nodes {
+        assert_ok!(driver.process(node.clone()).await);
+    }
+
+    let pending_node = storage.get_pending_node().unwrap().unwrap();
+    assert_eq!(pending_node.round(), 2);
+    // the mock network never completes the broadcast
+    assert_eq!(driver.metrics_snapshot(), DagMetrics {
+        current_round: 2,
+        finality_lag_rounds: 2,
+        broadcast_in_flight: true,
+        pending_fetches: 0,
+        node_utilization: pending_node.payload().len() as f64 / 1000.0,
+        aborted_broadcasts: 1,
+    });
+}
+
+#[tokio::test]
+async fn test_future_epoch_node() {
+    let future_epoch_node = |author| {
+        CertifiedNode::new(
+            Node::new(
+                2,
+                1,
+                author,
+                0,
+                Payload::empty(false),
+                vec![],
+                Extensions::empty(),
+            ),
+            AggregateSignature::empty(),
+        )
+    };
+
+    for (policy, expected_buffered) in [
+        (FutureEpochNodePolicy::Reject, 0),
+        (FutureEpochNodePolicy::Buffer { max_nodes: 2 }, 2),
+    ] {
+        let (signers, dag, _, mut driver) = create_driver(
+            DagDriverConfig {
+                future_epoch_node_policy: policy,
+                ..Default::default()
+            },
+            TimeService::mock(),
+        );
+
+        for signer in &signers[1..] {
+            let node = future_epoch_node(signer.author());
+            assert_eq!(
+                driver.process(node.clone()).await.unwrap_err().to_string(),
+                DagDriverError::FutureEpoch {
+                    node_epoch: 2,
+                    current_epoch: 1,
+                }
+                .to_string()
+            );
+            assert!(!dag.read().exists(node.metadata()));
+        }
+
+        let buffered = driver.take_future_epoch_nodes();
+        assert_eq!(buffered.len(), expected_buffered);
+        assert!(buffered.iter().all(|node| node.epoch() == 2));
+        assert!(driver.take_future_epoch_nodes().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn test_payload_pull_timeout_and_error() {
+    let timeouts = counters::PAYLOAD_PULL_TIMEOUTS.get();
+    let errors = counters::PAYLOAD_PULL_ERRORS.get();
+
+    // a timeout proceeds with an empty payload, even if errors panic
+    let (_, _, storage, _) = DagDriverBuilder::new()
+        .with_config(DagDriverConfig {
+            payload_pull_error_policy: PayloadPullErrorPolicy::Panic,
+            ..Default::default()
+        })
+        .with_payload_client(Arc::new(FailingPayloadClient { timeout: true }))
+        .build();
+    let node = storage.get_pending_node().unwrap().unwrap();
+    assert!(node.payload().is_empty());
+    assert_eq!(counters::PAYLOAD_PULL_TIMEOUTS.get(), timeouts + 1);
+    assert_eq!(counters::PAYLOAD_PULL_ERRORS.get(), errors);
+
+    // by default an error proceeds with an empty payload as well
+    let (signers, _, storage, mut driver) = DagDriverBuilder::new()
+        .with_payload_client(Arc::new(FailingPayloadClient { timeout: false }))
+        .build();
+    let node = storage.get_pending_node().unwrap().unwrap();
+    assert_eq!(node.round(), 1);
+    assert!(node.payload().is_empty());
+    assert_eq!(counters::PAYLOAD_PULL_TIMEOUTS.get(), timeouts + 1);
+    assert_eq!(counters::PAYLOAD_PULL_ERRORS.get(), errors + 1);
+
+    // and the round still completes once a quorum of its nodes is certified
+    for signer in &signers[1..] {
+        assert_ok!(
+            driver
+                .process(new_certified_node(1, signer.author(), vec![]))
+                .await
+        );
+    }
+    assert_eq!(driver.metrics_snapshot().current_round, 2);
+    let node = storage.get_pending_node().unwrap().unwrap();
+    assert_eq!(node.round(), 2);
+    assert!(node.payload().is_empty());
+    assert_eq!(node.parents().len(), 3);
+    assert_eq!(counters::PAYLOAD_PULL_ERRORS.get(), errors + 2);
+}
+
+#[tokio::test]
+async fn test_last_node_parents() {
+    let (signers, dag, _, mut driver) =
+        create_driver(DagDriverConfig::default(), TimeService::mock());
+    assert!(driver.last_node_parents().is_empty());
+
+    for signer in &signers {
+        let node = new_certified_node(1, signer.author(), vec![]);
+        dag.write().add_node(node).unwrap();
+    }
+    driver.enter_new_round(2).await.unwrap();
+
+    // the validators are generated from fixed seeds, so this is the verifier of the driver
+    let (_, verifier) = random_validator_verifier(4, None, false);
+    let strong_links: Vec<_> = dag
+        .read()
+        .get_strong_links_for_round(1, &verifier)
+        .unwrap()
+        .iter()
+        .map(|link| link.metadata().clone())
+        .collect();
+    assert_eq!(driver.last_node_parents(), strong_links.as_slice());
+}
+
+#[tokio::test]
+async fn test_own_node_received_back() {
+    let processed = Arc::new(Mutex::new(vec![]));
+    let (signers, dag, _, mut driver) = DagDriverBuilder::new()
+        .with_config(DagDriverConfig {
+            own_node_policy: OwnNodePolicy::SkipPayloadPrefetch,
+            ..Default::default()
+        })
+        .with_order_rule(Box::new(RecordingOrderRule {
+            processed: processed.clone(),
+        }))
+        .build();
+    assert_eq!(driver.highest_own_certified_round(), None);
+    let own_nodes_received = counters::OWN_NODES_RECEIVED.get();
+
+    let own_node = new_certified_node(1, signers[0].author(), vec![]);
+    assert_ok_eq!(driver.process(own_node.clone()).await, genesis_ack(1));
+    assert!(dag.read().exists(own_node.metadata()));
+    assert_eq!(driver.highest_own_certified_round(), Some(1));
+
+    // receiving it again is only acknowledged
+    assert_ok_eq!(driver.process(own_node.clone()).await, genesis_ack(1));
+    assert_eq!(*processed.lock(), vec![own_node.metadata().clone()]);
+    assert!(counters::OWN_NODES_RECEIVED.get() >= own_nodes_received + 2);
+
+    // nodes of other validators are not counted as own
+    let other_node = new_certified_node(1, signers[1].author(), vec![]);
+    assert_ok!(driver.process(other_node).await);
+    assert_eq!(driver.highest_own_certified_round(), Some(1));
+}
+
+#[tokio::test]
+async fn test_preview_payload() {
+    let txns = match random_payload(4) {
+        Payload::DirectMempool(txns) => txns,
+        _ => unreachable!(),
+    };
+    let recent_commits = Arc::new(Mutex::new(RecentCommits::new(1)));
+    let (_, _, storage, mut driver) = DagDriverBuilder::new()
+        .with_config(DagDriverConfig {
+            empty_strong_links_policy: EmptyStrongLinksPolicy::Proceed,
+            ..Default::default()
+        })
+        .with_payload_client(Arc::new(MempoolPayloadClient { txns: txns.clone() }))
+        .with_recent_commits(recent_commits.clone())
+        .build();
+    recent_commits
+        .lock()
+        .insert(Payload::DirectMempool(txns[..2].to_vec()));
+
+    // the preview excludes the committed transactions, without authoring a node
+    let preview = driver.preview_payload().await;
+    assert_eq!(preview, Payload::DirectMempool(txns[2..].to_vec()));
+    assert_eq!(storage.get_pending_node().unwrap().unwrap().round(), 1);
+
+    driver.enter_new_round(2).await.unwrap();
+    let node = storage.get_pending_node().unwrap().unwrap();
+    assert_eq!(node.round(), 2);
+    assert_eq!(node.payload(), &preview);
+}
+
+#[tokio::test]
+async fn test_weak_link_lookback_rounds() {
+    // the validators are generated from fixed seeds, so this is the verifier of the driver
+    let (_, verifier) = random_validator_verifier(4, None, false);
+    for (lookback_rounds, expect_referenced) in [(3, false), (4, true)] {
+        let (signers, dag, _, driver) = DagDriverBuilder::new()
+            .with_config(DagDriverConfig {
+                weak_link_lookback_rounds: lookback_rounds,
+                ..Default::default()
+            })
+            .build();
+
+        // the first three validators advance to round 5 without the lagging one
+        let mut strong_links = vec![];
+        for round in 1..=5 {
+            for signer in &signers[..3] {
+                let node = new_certified_node(round, signer.author(), strong_links.clone());
+                dag.write().add_node(node).unwrap();
+            }
+            strong_links = dag
+                .read()
+                .get_strong_links_for_round(round, &verifier)
+                .unwrap();
+        }
+        // the node of the lagging validator arrives four rounds behind the strong links
+        let lagging_node = new_certified_node(1, signers[3].author(), vec![]);
+        dag.write().add_node(lagging_node.clone()).unwrap();
+
+        let candidates = driver.weak_link_candidates(&strong_links);
+        let expected = if expect_referenced {
+            vec![lagging_node.metadata().clone()]
+        } else {
+            vec![]
+        };
+        assert_eq!(candidates, expected);
+    }
+}
+
+#[tokio::test]
+async fn test_fetch_suppression() {
+    let mock_time = MockTimeService::new();
+    let (signers, _, _, mut driver) = DagDriverBuilder::new()
+        .with_config(DagDriverConfig {
+            fetch_suppression: Some(FetchSuppressionConfig {
+                min_adds: 3,
+                window: Duration::from_secs(1),
+                max_absence: Duration::from_millis(500),
+            }),
+            ..Default::default()
+        })
+        .with_time_service(TimeService::from_mock(mock_time.clone()))
+        .build();
+
+    let round_1_nodes: Vec<_> = signers
+        .iter()
+        .map(|signer| new_certified_node(1, signer.author(), vec![]))
+        .collect();
+    for node in &round_1_nodes[..3] {
+        assert_ok!(driver.process(node.clone()).await);
+    }
+    let parents = round_1_nodes
+        .iter()
+        .map(|node| node.certificate())
+        .collect();
+    let node = new_certified_node(2, signers[1].author(), parents);
+
+    // nodes were just added, so the missing parent is likely to arrive momentarily
+    let suppressed = counters::FETCHES_SUPPRESSED.get();
+    assert!(driver.process(node.clone()).await.is_err());
+    mock_time.advance_ms(200);
+    assert!(driver.process(node.clone()).await.is_err());
+    assert_eq!(counters::FETCHES_SUPPRESSED.get(), suppressed + 2);
+
+    // the parent is fetched after a sustained absence
+    mock_time.advance_ms(300);
+    assert!(driver.process(node.clone()).await.is_err());
+    assert_eq!(counters::FETCHES_SUPPRESSED.get(), suppressed + 2);
+
+    assert_ok!(driver.process(round_1_nodes[3].clone()).await);
+    assert_ok!(driver.process(node).await);
+}
+
+#[tokio::test]
+async fn test_min_strong_link_stake_fraction() {
+    let (signers, _, _, mut driver) = DagDriverBuilder::new()
+        .with_config(DagDriverConfig {
+            min_strong_link_stake_fraction: Some(0.9),
+            ..Default::default()
+        })
+        .build();
+    assert_eq!(driver.metrics_snapshot().current_round, 1);
+
+    // 3 out of 4 equally staked validators meet the 2f+1 quorum, but not the configured fraction
+    for signer in &signers[1..] {
+        assert_ok!(
+            driver
+                .process(new_certified_node(1, signer.author(), vec![]))
+                .await
+        );
+    }
+    assert_eq!(driver.highest_strong_links_round(), 1);
+    assert_eq!(driver.metrics_snapshot().current_round, 1);
+
+    assert_ok!(
+        driver
+            .process(new_certified_node(1, signers[0].author(), vec![]))
+            .await
+    );
+    assert_eq!(driver.metrics_snapshot().current_round, 2);
+}
+
+/// Responds to every fetch request with the same response.
+struct FetchResponseSender {
+    response: FetchResponse,
+}
+
+#[async_trait]
+impl RBNetworkSender<DAGMessage> for FetchResponseSender {
+    async fn send_rb_rpc(
+        &self,
+        _receiver: Author,
+        _message: DAGMessage,
+        _timeout: Duration,
+    ) -> anyhow::Result<DAGMessage> {
+        unimplemented!()
+    }
+}
+
+#[async_trait]
+impl TDAGNetworkSender for FetchResponseSender {
+    async fn send_rpc(
+        &self,
+        _receiver: Author,
+        _message: DAGMessage,
+        _timeout: Duration,
+    ) -> anyhow::Result<DAGMessage> {
+        Ok(self.response.clone().into())
+    }
+
+    async fn send_rpc_with_fallbacks(
+        self: Arc<Self>,
+        _responders: Vec<Author>,
+        _message: DAGMessage,
+        _retry_interval: Duration,
+        _rpc_timeout: Duration,
+    ) -> RpcWithFallback {
+        unimplemented!()
+    }
+}
+
+#[tokio::test]
+async fn test_node_provenance() {
+    let (signers, dag, _, mut driver) = DagDriverBuilder::new().build();
+    let (_, validator_verifier) = random_validator_verifier(4, None, false);
+
+    let rpc_node = new_certified_node(1, signers[1].author(), vec![]);
+    assert_ok!(driver.process(rpc_node.clone()).await);
+
+    let fetched_node = new_signed_certified_node(
+        1,
+        signers[2].author(),
+        vec![],
+        &signers[..3],
+        &validator_verifier,
+    );
+    let request = RemoteFetchRequest::new(
+        1,
+        vec![fetched_node.metadata().clone()],
+        dag.read().bitmask(2),
+    );
+    let fetcher = DagFetcher::new(
+        Arc::new(EpochState {
+            epoch: 1,
+            verifier: validator_verifier,
+        }),
+        Arc::new(FetchResponseSender {
+            response: FetchResponse::new(1, vec![fetched_node.clone()]),
+        }),
+        TimeService::real(),
+    );
+    assert_ok!(
+        fetcher
+            .fetch(request, vec![signers[3].author()], dag.clone())
+            .await
+    );
+
+    let dag_reader = dag.read();
+    assert_eq!(
+        dag_reader.provenance(&rpc_node.digest()),
+        Some(NodeProvenance::Rpc(signers[1].author()))
+    );
+    assert_eq!(
+        dag_reader.provenance(&fetched_node.digest()),
+        Some(NodeProvenance::Fetch(signers[3].author()))
+    );
+}
+
+/// A span created while the capturing layer was the default subscriber.
+#[derive(Clone, Debug)]
+struct CapturedSpan {
+    name: &'static str,
+    parent: Option<&'static str>,
+    fields: HashMap<&'static str, String>,
+}
+
+#[derive(Clone, Default)]
+struct SpanCaptureLayer {
+    spans: Arc<Mutex<Vec<CapturedSpan>>>,
+}
+
+impl SpanCaptureLayer {
+    fn find(&self, name: &str) -> Option<CapturedSpan> {
+        self.spans
+            .lock()
+            .iter()
+            .find(|span| span.name == name)
+            .cloned()
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanCaptureLayer {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        struct FieldVisitor(HashMap<&'static str, String>);
+
+        impl Visit for FieldVisitor {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                self.0.insert(field.name(), format!("{:?}", value));
+            }
+        }
+
+        let mut visitor = FieldVisitor(HashMap::new());
+        attrs.record(&mut visitor);
+        let parent = ctx
+            .span(id)
+            .and_then(|span| span.parent())
+            .map(|parent| parent.name());
+        self.spans.lock().push(CapturedSpan {
+            name: attrs.metadata().name(),
+            parent,
+            fields: visitor.0,
+        });
+    }
+}
+
+#[tokio::test]
+async fn test_broadcast_node_span() {
+    let layer = SpanCaptureLayer::default();
+    // the test runs on a single thread, so the spawned broadcast is traced by the default too
+    let _guard = tracing::subscriber::set_default(Registry::default().with(layer.clone()));
+
+    let (_, _, storage, driver) = DagDriverBuilder::new().with_voting_network().build();
+    let node = storage.get_pending_node().unwrap().unwrap();
+    assert_ok!(
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while driver.metrics_snapshot().broadcast_in_flight {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+    );
+
+    let broadcast_span = layer.find("dag_broadcast_node").unwrap();
+    let epoch = node.metadata().epoch();
+    assert_eq!(broadcast_span.fields["epoch"], epoch.to_string());
+    assert_eq!(broadcast_span.fields["round"], node.round().to_string());
+    assert_eq!(broadcast_span.fields["digest"], node.digest().to_string());
+    for phase in ["dag_collect_signatures", "dag_broadcast_certified_node"] {
+        let phase_span = layer.find(phase).unwrap();
+        assert_eq!(phase_span.parent, Some("dag_broadcast_node"));
+    }
+}
+
+/// The ledger is never read during a short run of the driver.
+struct NoLedgerReader;
+
+impl DbReader for NoLedgerReader {}
+
+#[tokio::test]
+async fn test_storage_backends() {
+    let mut outcomes = vec![];
+    for backend in [DagStorageBackend::InMemory, DagStorageBackend::OnDisk] {
+        let path = TempPath::new();
+        let (signers, _) = random_validator_verifier(4, None, false);
+        let validators = signers.iter().map(|signer| signer.author()).collect();
+        let dag_storage = build_dag_storage(
+            backend,
+            1,
+            HashMap::from([(1, validators)]),
+            Arc::new(ConsensusDB::new(&path)),
+            Arc::new(NoLedgerReader),
+        );
+        let (signers, _, _, mut driver) = DagDriverBuilder::new()
+            .with_payload_client(Arc::new(RecordingPayloadClient::default()))
+            .with_dag_storage(dag_storage.clone())
+            .build();
+
+        let round_1: Vec<_> = signers
+            .iter()
+            .map(|signer| new_certified_node(1, signer.author(), vec![]))
+            .collect();
+        let parents: Vec<_> = round_1.iter().map(|node| node.certificate()).collect();
+        let round_2: Vec<_> = signers[1..]
+            .iter()
+            .map(|signer| new_certified_node(2, signer.author(), parents.clone()))
+            .collect();
+        for node in round_1.into_iter().chain(round_2) {
+            assert_ok!(driver.process(node).await);
+        }
+
+        let pending_node = dag_storage.get_pending_node().unwrap().unwrap();
+        let mut stored: Vec<_> = dag_storage
+            .get_certified_nodes()
+            .unwrap()
+            .into_iter()
+            .map(|(digest, _)| digest)
+            .collect();
+        stored.sort();
+        outcomes.push((pending_node, stored, driver.metrics_snapshot()));
+    }
+
+    let (pending_node, stored, metrics) = &outcomes[0];
+    assert_eq!(pending_node.round(), 3);
+    assert_eq!(stored.len(), 7);
+    assert_eq!(metrics.current_round, 3);
+    assert_eq!(outcomes[0], outcomes[1]);
+}
+
+fn new_proof_of_store() -> ProofOfStore {
+    ProofOfStore::new(
+        BatchInfo::new(
+            PeerId::random(),
+            BatchId::new_for_test(0),
+            0,
+            100,
+            HashValue::random(),
+            1,
+            1,
+            0,
+        ),
+        AggregateSignature::empty(),
+    )
+}
+
+#[tokio::test]
+async fn test_payload_prefetch_batched() {
+    let shared = new_proof_of_store();
+    let unique = [new_proof_of_store(), new_proof_of_store()];
+    // two nodes of round 1, not enough to enter round 2, whose payloads share a batch
+    let nodes = |signers: &[ValidatorSigner]| -> Vec<_> {
+        signers[1..]
+            .iter()
+            .zip(&unique)
+            .map(|(signer, proof)| {
+                let payload = ProofWithData::new(vec![shared.clone(), proof.clone()]);
+                new_certified_node_with_payload(
+                    1,
+                    signer.author(),
+                    vec![],
+                    Payload::InQuorumStore(payload),
+                )
+            })
+            .collect()
+    };
+
+    // each payload is prefetched on its own, fetching the shared batch for both
+    let batch_reader = Arc::new(CountingBatchReader::default());
+    let (signers, _, _, mut driver) = DagDriverBuilder::new()
+        .with_payload_manager(Arc::new(PayloadManager::InQuorumStore(
+            batch_reader.clone(),
+            channel(1).0,
+        )))
+        .build();
+    for node in nodes(&signers) {
+        assert_ok!(driver.process(node).await);
+    }
+    assert_eq!(batch_reader.requested().len(), 4);
+
+    let mock_time = MockTimeService::new();
+    let batch_reader = Arc::new(CountingBatchReader::default());
+    let (signers, _, _, mut driver) = DagDriverBuilder::new()
+        .with_config(DagDriverConfig {
+            payload_prefetch_batch_size: Some(10),
+            ..Default::default()
+        })
+        .with_time_service(TimeService::from_mock(mock_time.clone()))
+        .with_payload_manager(Arc::new(PayloadManager::InQuorumStore(
+            batch_reader.clone(),
+            channel(1).0,
+        )))
+        .build();
+    for node in nodes(&signers) {
+        assert_ok!(driver.process(node).await);
+    }
+    let flush_timeout = driver.prefetch_flush_timeout();
+    driver.flush_delayed_prefetches();
+    assert!(batch_reader.requested().is_empty());
+
+    // no new round flushes the buffer, it's flushed once the oldest payload waited long enough
+    mock_time.advance(Duration::from_millis(50));
+    flush_timeout.await;
+    driver.flush_delayed_prefetches();
+    // the shared batch is fetched once for both payloads
+    let requested = batch_reader.requested();
+    assert_eq!(requested.len(), 3);
+    assert_eq!(
+        requested
+            .iter()
+            .filter(|digest| *digest == shared.digest())
+            .count(),
+        1
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_network_handler_processes_rpcs_one_at_a_time() {
+    let (signers, dag, storage, driver) = DagDriverBuilder::new().build();
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: ValidatorVerifier::new(
+            signers
+                .iter()
+                .map(|signer| ValidatorConsensusInfo::new(signer.author(), signer.public_key(), 1))
+                .collect(),
+        ),
+    });
+    let (_, fetch_requester, node_fetch_waiter, certified_node_fetch_waiter) =
+        DagFetcherService::new(
+            epoch_state.clone(),
+            Arc::new(MockNetworkSender {}),
+            dag.clone(),
+            TimeService::mock(),
+        );
+    let node_receiver = NodeBroadcastHandler::new(
+        dag.clone(),
+        Arc::new(signers[0].clone()),
+        epoch_state.clone(),
+        storage.clone(),
+        Arc::new(fetch_requester),
+    );
+    let state_sync_trigger = StateSyncTrigger::new(
+        epoch_state.clone(),
+        Arc::new(MockLedgerInfoProvider {
+            latest_ledger_info: storage.get_latest_ledger_info().unwrap(),
+        }),
+        dag.clone(),
+        Arc::new(MockProofNotifier::default()),
+        TimeService::mock(),
+    );
+    let mut handler = NetworkHandler::new(
+        epoch_state.clone(),
+        node_receiver,
+        driver,
+        FetchRequestHandler::new(dag.clone(), epoch_state),
+        node_fetch_waiter,
+        certified_node_fetch_waiter,
+        state_sync_trigger,
+    );
+
+    // every peer sends its nodes of 3 rounds at once, and each vote takes a while to persist
+    storage.set_vote_write_delay(Duration::from_millis(10));
+    let (dag_rpc_tx, mut dag_rpc_rx) = aptos_channel::new(QueueStyle::FIFO, 10, None);
+    let mut responses = vec![];
+    let mut parents = vec![];
+    for round in 1..=3 {
+        let nodes: Vec<_> = signers
+            .iter()
+            .map(|signer| {
+                Node::new(
+                    1,
+                    round,
+                    signer.author(),
+                    0,
+                    Payload::empty(false),
+                    parents.clone(),
+                    Extensions::empty(),
+                )
+            })
+            .collect();
+        for node in &nodes {
+            let (response_sender, response_rx) = oneshot::channel();
+            let request = IncomingDAGRequest {
+                req: DAGNetworkMessage {
+                    epoch: 1,
+                    data: bcs::to_bytes(&DAGMessage::NodeMsg(node.clone())).unwrap(),
+                },
+                sender: *node.author(),
+                protocol: ProtocolId::ConsensusRpcBcs,
+                response_sender,
+            };
+            dag_rpc_tx.push(*node.author(), request).unwrap();
+            responses.push(response_rx);
+        }
+        // the nodes of a round are certified before the nodes of the next round reference them
+        let mut dag_writer = dag.write();
+        parents = nodes
+            .into_iter()
+            .map(|node| {
+                let certified_node = CertifiedNode::new(node, AggregateSignature::empty());
+                let certificate = certified_node.certificate();
+                dag_writer.add_node(certified_node).unwrap();
+                certificate
+            })
+            .collect();
+    }
+
+    let handle = tokio::spawn(async move { handler.run(&mut dag_rpc_rx, vec![]).await });
+    for response in responses {
+        assert_ok!(response.await.unwrap());
+    }
+    handle.abort();
+
+    // however many RPCs are pending, the handler processes them one at a time
+    assert_eq!(storage.get_votes().unwrap().len(), 3 * signers.len());
+    assert_eq!(storage.max_concurrent_vote_writes(), 1);
+}
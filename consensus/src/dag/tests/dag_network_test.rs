@@ -2,18 +2,29 @@
 
 use crate::dag::{
     dag_network::{RpcWithFallback, TDAGNetworkSender},
-    types::{DAGMessage, TestAck, TestMessage},
+    tests::helpers::new_node,
+    types::{
+        CertificateAckState, CertifiedNode, CertifiedNodeMessage, DAGMessage, SignatureBuilder,
+        TestAck, TestMessage,
+    },
 };
 use anyhow::{anyhow, bail};
 use aptos_consensus_types::common::Author;
 use aptos_infallible::Mutex;
-use aptos_reliable_broadcast::RBNetworkSender;
+use aptos_reliable_broadcast::{RBNetworkSender, ReliableBroadcast};
 use aptos_time_service::{TimeService, TimeServiceTrait};
-use aptos_types::validator_verifier::random_validator_verifier;
+use aptos_types::{
+    aggregate_signature::AggregateSignature,
+    epoch_state::EpochState,
+    ledger_info::{generate_ledger_info_with_sig, LedgerInfo},
+    validator_verifier::random_validator_verifier,
+};
 use async_trait::async_trait;
 use claims::{assert_err, assert_ok};
 use futures::StreamExt;
 use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::Notify;
+use tokio_retry::strategy::FixedInterval;
 
 #[derive(Clone)]
 enum TestPeerState {
@@ -120,9 +131,93 @@ async fn test_send_rpc_with_fallback() {
         )
         .await;
 
-    assert_ok!(rpc.next().await.unwrap());
-    assert_err!(rpc.next().await.unwrap());
-    assert_ok!(rpc.next().await.unwrap());
-    assert_err!(rpc.next().await.unwrap());
-    assert_ok!(rpc.next().await.unwrap());
+    assert_ok!(rpc.next().await.unwrap().result);
+    assert_err!(rpc.next().await.unwrap().result);
+    assert_ok!(rpc.next().await.unwrap().result);
+    assert_err!(rpc.next().await.unwrap().result);
+    assert_ok!(rpc.next().await.unwrap().result);
+}
+
+/// Records the messages it is asked to send. The first RPC hangs until released, to hold the only
+/// send slot and simulate congestion, and every RPC fails.
+struct CongestedRBSender {
+    sent: Mutex<Vec<String>>,
+    release: Notify,
+}
+
+#[async_trait]
+impl RBNetworkSender<DAGMessage> for CongestedRBSender {
+    async fn send_rb_rpc(
+        &self,
+        _receiver: Author,
+        message: DAGMessage,
+        _timeout: Duration,
+    ) -> anyhow::Result<DAGMessage> {
+        let is_first = {
+            let mut sent = self.sent.lock();
+            sent.push(message.name().to_string());
+            sent.len() == 1
+        };
+        if is_first {
+            self.release.notified().await;
+        }
+        bail!("congested");
+    }
+}
+
+#[tokio::test]
+async fn test_reliable_broadcast_priority() {
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let validators = validator_verifier.get_ordered_account_addresses();
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let sender = Arc::new(CongestedRBSender {
+        sent: Mutex::new(vec![]),
+        release: Notify::new(),
+    });
+    // failed sends are retried after a backoff that never elapses with the mock time service
+    let rb = Arc::new(
+        ReliableBroadcast::new(
+            validators.clone(),
+            sender.clone(),
+            FixedInterval::from_millis(10),
+            TimeService::mock(),
+            Duration::from_secs(1),
+        )
+        .with_max_concurrent_sends(1),
+    );
+
+    // the first node message takes the send slot, the others queue up behind it
+    let node = new_node(1, 0, validators[0], vec![]);
+    let signature_builder = SignatureBuilder::new(node.metadata().clone(), epoch_state);
+    tokio::spawn(rb.broadcast(node, signature_builder));
+    while rb.num_queued_sends() < validators.len() - 1 {
+        tokio::task::yield_now().await;
+    }
+    let certified_node = CertifiedNode::new(
+        new_node(1, 0, validators[1], vec![]),
+        AggregateSignature::empty(),
+    );
+    let ledger_info = generate_ledger_info_with_sig(&signers, LedgerInfo::mock_genesis(None));
+    tokio::spawn(rb.broadcast(
+        CertifiedNodeMessage::new(certified_node, ledger_info),
+        CertificateAckState::new(validators.len()),
+    ));
+    while rb.num_queued_sends() < 2 * validators.len() - 1 {
+        tokio::task::yield_now().await;
+    }
+
+    sender.release.notify_one();
+    while sender.sent.lock().len() < 2 * validators.len() {
+        tokio::task::yield_now().await;
+    }
+    let sent = sender.sent.lock().clone();
+    let expected: Vec<_> = ["NodeMsg"]
+        .into_iter()
+        .chain(["CertifiedNodeMsg"; 4])
+        .chain(["NodeMsg"; 3])
+        .collect();
+    assert_eq!(sent, expected);
 }
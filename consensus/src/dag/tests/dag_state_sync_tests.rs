@@ -2,31 +2,38 @@
 
 use crate::{
     dag::{
-        adapter::OrderedNotifier,
+        adapter::{LedgerInfoProvider, OrderedNotifier},
         dag_fetcher::{FetchRequestHandler, TDagFetcher},
-        dag_state_sync::{DagStateSynchronizer, DAG_WINDOW},
+        dag_state_sync::{
+            DagStateSynchronizer, LiveNodeBuffer, LiveNodePolicy, StateSyncTrigger, DAG_WINDOW,
+        },
         dag_store::Dag,
         storage::DAGStorage,
         tests::{dag_test::MockStorage, helpers::generate_dag_nodes},
         types::{CertifiedNodeMessage, RemoteFetchRequest},
-        CertifiedNode, DAGMessage, RpcHandler, RpcWithFallback, TDAGNetworkSender,
+        CertifiedNode, DAGMessage, ProofNotifier, RpcHandler, RpcWithFallback, TDAGNetworkSender,
     },
     test_utils::EmptyStateComputer,
 };
-use aptos_consensus_types::common::{Author, Round};
+use anyhow::ensure;
+use aptos_consensus_types::{
+    common::{Author, Round},
+    epoch_retrieval::EpochRetrievalRequest,
+};
 use aptos_crypto::HashValue;
-use aptos_infallible::RwLock;
+use aptos_infallible::{Mutex, RwLock};
 use aptos_reliable_broadcast::RBNetworkSender;
 use aptos_time_service::TimeService;
 use aptos_types::{
     aggregate_signature::AggregateSignature,
     block_info::BlockInfo,
+    epoch_change::EpochChangeProof,
     epoch_state::EpochState,
     ledger_info::{LedgerInfo, LedgerInfoWithSignatures},
     validator_verifier::random_validator_verifier,
 };
 use async_trait::async_trait;
-use claims::assert_none;
+use claims::{assert_none, assert_ok};
 use std::{sync::Arc, time::Duration};
 
 struct MockDAGNetworkSender {}
@@ -95,6 +102,34 @@ impl TDagFetcher for MockDagFetcher {
     }
 }
 
+/// Fetches through `MockDagFetcher` and records the requested round ranges, failing every fetch
+/// after `max_fetches` as if the node crashed.
+struct InterruptedDagFetcher {
+    inner: MockDagFetcher,
+    fetched_rounds: Arc<Mutex<Vec<(Round, Round)>>>,
+    max_fetches: usize,
+}
+
+#[async_trait]
+impl TDagFetcher for InterruptedDagFetcher {
+    async fn fetch(
+        &self,
+        remote_request: RemoteFetchRequest,
+        responders: Vec<Author>,
+        new_dag: Arc<RwLock<Dag>>,
+    ) -> anyhow::Result<()> {
+        {
+            let mut fetched_rounds = self.fetched_rounds.lock();
+            ensure!(fetched_rounds.len() < self.max_fetches, "interrupted");
+            fetched_rounds.push((
+                remote_request.exists_bitmask().first_round(),
+                remote_request.targets().map(|t| t.round()).max().unwrap(),
+            ));
+        }
+        self.inner.fetch(remote_request, responders, new_dag).await
+    }
+}
+
 struct MockNotifier {}
 
 #[async_trait]
@@ -190,3 +225,484 @@ async fn test_dag_state_sync() {
     assert_eq!(new_dag.highest_round(), (NUM_ROUNDS - 1) as Round);
     assert_none!(new_dag.highest_ordered_anchor_round(),);
 }
+
+#[tokio::test]
+async fn test_live_nodes_buffered_during_sync() {
+    const NUM_ROUNDS: usize = 30;
+    const LI_ROUNDS: usize = NUM_ROUNDS * 2 / 3;
+
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let validators = validator_verifier.get_ordered_account_addresses();
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let storage = Arc::new(MockStorage::new());
+
+    let virtual_dag = (0..NUM_ROUNDS)
+        .map(|_| {
+            signers
+                .iter()
+                .map(|_| Some(vec![true; signers.len() * 2 / 3 + 1]))
+                .collect()
+        })
+        .collect::<Vec<_>>();
+    let nodes = generate_dag_nodes(&virtual_dag, &validators);
+
+    let mut fast_dag = Dag::new(epoch_state.clone(), Arc::new(MockStorage::new()), 1, 0);
+    for round_nodes in &nodes {
+        for node in round_nodes.iter().flatten() {
+            fast_dag.add_node(node.clone()).unwrap();
+        }
+    }
+    let fast_dag = Arc::new(RwLock::new(fast_dag));
+    let slow_dag = Arc::new(RwLock::new(Dag::new(
+        epoch_state.clone(),
+        Arc::new(MockStorage::new()),
+        1,
+        0,
+    )));
+
+    let li_node = nodes[LI_ROUNDS - 1].first().unwrap().clone().unwrap();
+    let sync_to_li = LedgerInfoWithSignatures::new(
+        LedgerInfo::new(
+            BlockInfo::new(
+                epoch_state.epoch,
+                li_node.round(),
+                HashValue::zero(),
+                HashValue::zero(),
+                0,
+                0,
+                None,
+            ),
+            li_node.digest(),
+        ),
+        AggregateSignature::empty(),
+    );
+    let sync_to_node = nodes[NUM_ROUNDS - 1].first().unwrap().clone().unwrap();
+    let sync_node_li = CertifiedNodeMessage::new(sync_to_node, sync_to_li.clone());
+
+    let state_sync = setup(epoch_state.clone(), storage.clone());
+    let dag_fetcher = MockDagFetcher {
+        target_dag: fast_dag.clone(),
+        epoch_state: epoch_state.clone(),
+    };
+    let sync_future = state_sync.sync_dag_to(&sync_node_li, dag_fetcher, slow_dag.clone(), 0);
+
+    // The nodes of the sync target round arrive live while the sync is in progress, they are held up to the cap.
+    let live_nodes: Vec<_> = nodes[NUM_ROUNDS - 1].iter().flatten().cloned().collect();
+    let mut buffer = LiveNodeBuffer::new(LiveNodePolicy::Buffer { max_nodes: 3 });
+    for node in &live_nodes {
+        buffer.push(CertifiedNodeMessage::new(node.clone(), sync_to_li.clone()));
+    }
+    assert_eq!(buffer.len(), 3);
+
+    let mut drop_buffer = LiveNodeBuffer::new(LiveNodePolicy::Drop);
+    assert!(!drop_buffer.push(CertifiedNodeMessage::new(
+        live_nodes[0].clone(),
+        sync_to_li.clone()
+    )));
+    assert!(drop_buffer.is_empty());
+
+    let mut new_dag = sync_future.await.unwrap().unwrap();
+    assert_eq!(new_dag.highest_round(), (NUM_ROUNDS - 1) as Round);
+
+    // Replaying the buffered nodes after the sync applies them to the new DAG.
+    for certified_node_msg in buffer.take() {
+        assert_ok!(new_dag.add_node(certified_node_msg.certified_node()));
+    }
+    assert!(buffer.is_empty());
+    assert_eq!(new_dag.highest_round(), NUM_ROUNDS as Round);
+    for node in live_nodes.iter().take(3) {
+        assert!(new_dag.exists(node.metadata()));
+    }
+    assert!(!new_dag.exists(live_nodes[3].metadata()));
+}
+
+#[tokio::test]
+async fn test_dag_state_sync_resumes_from_cursor() {
+    const NUM_ROUNDS: usize = 30;
+    const LI_ROUNDS: usize = NUM_ROUNDS * 2 / 3;
+
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let validators = validator_verifier.get_ordered_account_addresses();
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let storage = Arc::new(MockStorage::new());
+
+    let virtual_dag = (0..NUM_ROUNDS)
+        .map(|_| {
+            signers
+                .iter()
+                .map(|_| Some(vec![true; signers.len() * 2 / 3 + 1]))
+                .collect()
+        })
+        .collect::<Vec<_>>();
+    let nodes = generate_dag_nodes(&virtual_dag, &validators);
+
+    let mut fast_dag = Dag::new(epoch_state.clone(), Arc::new(MockStorage::new()), 1, 0);
+    for round_nodes in &nodes {
+        for node in round_nodes.iter().flatten() {
+            fast_dag.add_node(node.clone()).unwrap();
+        }
+    }
+    let fast_dag = Arc::new(RwLock::new(fast_dag));
+    let slow_dag = Arc::new(RwLock::new(Dag::new(
+        epoch_state.clone(),
+        Arc::new(MockStorage::new()),
+        1,
+        0,
+    )));
+
+    let li_node = nodes[LI_ROUNDS - 1].first().unwrap().clone().unwrap();
+    let sync_to_li = LedgerInfoWithSignatures::new(
+        LedgerInfo::new(
+            BlockInfo::new(
+                epoch_state.epoch,
+                li_node.round(),
+                HashValue::zero(),
+                HashValue::zero(),
+                0,
+                0,
+                None,
+            ),
+            li_node.digest(),
+        ),
+        AggregateSignature::empty(),
+    );
+    let sync_to_node = nodes[NUM_ROUNDS - 1].first().unwrap().clone().unwrap();
+    let sync_node_li = CertifiedNodeMessage::new(sync_to_node.clone(), sync_to_li);
+
+    let new_fetcher = |max_fetches| InterruptedDagFetcher {
+        inner: MockDagFetcher {
+            target_dag: fast_dag.clone(),
+            epoch_state: epoch_state.clone(),
+        },
+        fetched_rounds: Arc::new(Mutex::new(vec![])),
+        max_fetches,
+    };
+
+    // Rounds 19 to 29 are synced in chunks of 3 rounds, the sync is interrupted after two chunks.
+    let dag_fetcher = new_fetcher(2);
+    let fetched_before_crash = dag_fetcher.fetched_rounds.clone();
+    let state_sync = setup(epoch_state.clone(), storage.clone()).with_chunk_rounds(3);
+    assert!(state_sync
+        .sync_dag_to(&sync_node_li, dag_fetcher, slow_dag.clone(), 0)
+        .await
+        .is_err());
+    assert_eq!(*fetched_before_crash.lock(), vec![(27, 29), (24, 26)]);
+    let cursor = storage.get_sync_cursor().unwrap().unwrap();
+    assert_eq!(*cursor.target(), sync_to_node.id());
+    assert_eq!(cursor.lowest_fetched_round(), 24);
+    assert!(cursor.next_targets().iter().all(|t| t.round() == 23));
+
+    // After a restart, the sync continues below the chunks fetched before the crash.
+    let dag_fetcher = new_fetcher(usize::MAX);
+    let fetched_after_crash = dag_fetcher.fetched_rounds.clone();
+    let state_sync = setup(epoch_state.clone(), storage.clone()).with_chunk_rounds(3);
+    let new_dag = state_sync
+        .sync_dag_to(&sync_node_li, dag_fetcher, slow_dag.clone(), 0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(*fetched_after_crash.lock(), vec![(21, 23), (19, 20)]);
+    assert_none!(storage.get_sync_cursor().unwrap());
+
+    let start_round = LI_ROUNDS - DAG_WINDOW;
+    assert_eq!(new_dag.lowest_round(), start_round as Round);
+    assert_eq!(new_dag.highest_round(), (NUM_ROUNDS - 1) as Round);
+    let fast_dag_reader = fast_dag.read();
+    let reachable: Vec<_> = fast_dag_reader
+        .reachable(
+            sync_to_node.parents_metadata(),
+            Some(start_round as Round),
+            |_| true,
+        )
+        .collect();
+    assert_eq!(reachable.len(), 3 * (NUM_ROUNDS - start_round));
+    for node_status in reachable {
+        assert!(new_dag.exists(node_status.as_node().metadata()));
+    }
+}
+
+#[tokio::test]
+async fn test_dag_state_sync_fetches_only_missing_rounds() {
+    const NUM_ROUNDS: usize = 30;
+    const LI_ROUNDS: usize = NUM_ROUNDS * 2 / 3;
+    const LOCAL_ROUNDS: usize = NUM_ROUNDS - 4;
+
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let validators = validator_verifier.get_ordered_account_addresses();
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let storage = Arc::new(MockStorage::new());
+
+    let virtual_dag = (0..NUM_ROUNDS)
+        .map(|_| {
+            signers
+                .iter()
+                .map(|_| Some(vec![true; signers.len() * 2 / 3 + 1]))
+                .collect()
+        })
+        .collect::<Vec<_>>();
+    let nodes = generate_dag_nodes(&virtual_dag, &validators);
+
+    let mut fast_dag = Dag::new(epoch_state.clone(), Arc::new(MockStorage::new()), 1, 0);
+    for round_nodes in &nodes {
+        for node in round_nodes.iter().flatten() {
+            fast_dag.add_node(node.clone()).unwrap();
+        }
+    }
+    let fast_dag = Arc::new(RwLock::new(fast_dag));
+
+    // The local DAG shares the storage of the synchronizer and is 3 rounds behind the fast DAG.
+    let mut slow_dag = Dag::new(epoch_state.clone(), storage.clone(), 1, 0);
+    for round_nodes in nodes.iter().take(LOCAL_ROUNDS) {
+        for node in round_nodes.iter().flatten() {
+            slow_dag.add_node(node.clone()).unwrap();
+        }
+    }
+    assert_eq!(slow_dag.highest_round(), LOCAL_ROUNDS as Round);
+    let slow_dag = Arc::new(RwLock::new(slow_dag));
+
+    let li_node = nodes[LI_ROUNDS - 1].first().unwrap().clone().unwrap();
+    let sync_to_li = LedgerInfoWithSignatures::new(
+        LedgerInfo::new(
+            BlockInfo::new(
+                epoch_state.epoch,
+                li_node.round(),
+                HashValue::zero(),
+                HashValue::zero(),
+                0,
+                0,
+                None,
+            ),
+            li_node.digest(),
+        ),
+        AggregateSignature::empty(),
+    );
+    let sync_to_node = nodes[NUM_ROUNDS - 1].first().unwrap().clone().unwrap();
+    let sync_node_li = CertifiedNodeMessage::new(sync_to_node.clone(), sync_to_li);
+
+    let dag_fetcher = InterruptedDagFetcher {
+        inner: MockDagFetcher {
+            target_dag: fast_dag.clone(),
+            epoch_state: epoch_state.clone(),
+        },
+        fetched_rounds: Arc::new(Mutex::new(vec![])),
+        max_fetches: usize::MAX,
+    };
+    let fetched_rounds = dag_fetcher.fetched_rounds.clone();
+    let state_sync = setup(epoch_state.clone(), storage.clone());
+    let new_dag = state_sync
+        .sync_dag_to(&sync_node_li, dag_fetcher, slow_dag.clone(), 0)
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Only the rounds above the local DAG are requested, not the full window.
+    let (lowest_round, highest_round) = (LOCAL_ROUNDS + 1, NUM_ROUNDS - 1);
+    assert_eq!(
+        *fetched_rounds.lock(),
+        vec![(lowest_round as Round, highest_round as Round)]
+    );
+    assert_eq!(highest_round - lowest_round + 1, 3);
+
+    let start_round = LI_ROUNDS - DAG_WINDOW;
+    assert_eq!(new_dag.lowest_round(), start_round as Round);
+    assert_eq!(new_dag.highest_round(), (NUM_ROUNDS - 1) as Round);
+    let fast_dag_reader = fast_dag.read();
+    for node_status in fast_dag_reader.reachable(
+        sync_to_node.parents_metadata(),
+        Some(start_round as Round),
+        |_| true,
+    ) {
+        assert!(new_dag.exists(node_status.as_node().metadata()));
+    }
+}
+
+#[tokio::test]
+async fn test_dag_state_sync_ignores_unreachable_missing_nodes() {
+    const NUM_ROUNDS: usize = 30;
+    const LI_ROUNDS: usize = NUM_ROUNDS * 2 / 3;
+    const LOCAL_ROUNDS: usize = NUM_ROUNDS - 4;
+
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let validators = validator_verifier.get_ordered_account_addresses();
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let storage = Arc::new(MockStorage::new());
+
+    // The node of one author per round is not a parent of any node, so the target doesn't reach
+    // it.
+    let unreferenced_author = |round_idx: usize| round_idx % signers.len();
+    let virtual_dag = (0..NUM_ROUNDS)
+        .map(|round_idx| {
+            signers
+                .iter()
+                .map(|_| {
+                    Some(
+                        (0..signers.len())
+                            .map(|idx| round_idx == 0 || idx != unreferenced_author(round_idx - 1))
+                            .collect(),
+                    )
+                })
+                .collect()
+        })
+        .collect::<Vec<_>>();
+    let nodes = generate_dag_nodes(&virtual_dag, &validators);
+
+    let mut fast_dag = Dag::new(epoch_state.clone(), Arc::new(MockStorage::new()), 1, 0);
+    for round_nodes in &nodes {
+        for node in round_nodes.iter().flatten() {
+            fast_dag.add_node(node.clone()).unwrap();
+        }
+    }
+    let fast_dag = Arc::new(RwLock::new(fast_dag));
+
+    // The local DAG is 3 rounds behind the fast DAG, and misses the unreferenced node of every
+    // round, so none of its rounds is complete.
+    let mut slow_dag = Dag::new(epoch_state.clone(), storage.clone(), 1, 0);
+    for (round_idx, round_nodes) in nodes.iter().enumerate().take(LOCAL_ROUNDS) {
+        for (idx, node) in round_nodes.iter().enumerate() {
+            if idx != unreferenced_author(round_idx) {
+                slow_dag.add_node(node.clone().unwrap()).unwrap();
+            }
+        }
+    }
+    assert_eq!(slow_dag.highest_round(), LOCAL_ROUNDS as Round);
+    assert_eq!(slow_dag.lowest_incomplete_round(), 1);
+    let slow_dag = Arc::new(RwLock::new(slow_dag));
+
+    let li_node = nodes[LI_ROUNDS - 1].first().unwrap().clone().unwrap();
+    let sync_to_li = LedgerInfoWithSignatures::new(
+        LedgerInfo::new(
+            BlockInfo::new(
+                epoch_state.epoch,
+                li_node.round(),
+                HashValue::zero(),
+                HashValue::zero(),
+                0,
+                0,
+                None,
+            ),
+            li_node.digest(),
+        ),
+        AggregateSignature::empty(),
+    );
+    let sync_to_node = nodes[NUM_ROUNDS - 1].first().unwrap().clone().unwrap();
+    let sync_node_li = CertifiedNodeMessage::new(sync_to_node.clone(), sync_to_li);
+
+    let dag_fetcher = InterruptedDagFetcher {
+        inner: MockDagFetcher {
+            target_dag: fast_dag.clone(),
+            epoch_state: epoch_state.clone(),
+        },
+        fetched_rounds: Arc::new(Mutex::new(vec![])),
+        max_fetches: usize::MAX,
+    };
+    let fetched_rounds = dag_fetcher.fetched_rounds.clone();
+    let state_sync = setup(epoch_state.clone(), storage.clone());
+    let new_dag = state_sync
+        .sync_dag_to(&sync_node_li, dag_fetcher, slow_dag.clone(), 0)
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The rounds above the local DAG are requested, along with its highest round, as the nodes
+    // above it that could reference its missing node are unknown. The missing nodes below it are
+    // not reachable from the target, so they are not requested.
+    assert_eq!(
+        *fetched_rounds.lock(),
+        vec![(LOCAL_ROUNDS as Round, (NUM_ROUNDS - 1) as Round)]
+    );
+
+    let start_round = LI_ROUNDS - DAG_WINDOW;
+    assert_eq!(new_dag.lowest_round(), start_round as Round);
+    assert_eq!(new_dag.highest_round(), (NUM_ROUNDS - 1) as Round);
+    let fast_dag_reader = fast_dag.read();
+    for node_status in fast_dag_reader.reachable(
+        sync_to_node.parents_metadata(),
+        Some(start_round as Round),
+        |_| true,
+    ) {
+        assert!(new_dag.exists(node_status.as_node().metadata()));
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct MockProofNotifier {
+    epoch_retrievals: Mutex<Vec<(Author, EpochRetrievalRequest)>>,
+}
+
+#[async_trait]
+impl ProofNotifier for MockProofNotifier {
+    async fn send_epoch_change(&self, _proof: EpochChangeProof) {
+        unimplemented!()
+    }
+
+    async fn send_commit_proof(&self, _ledger_info: LedgerInfoWithSignatures) {
+        unimplemented!()
+    }
+
+    async fn send_epoch_retrieval(&self, peer: Author, request: EpochRetrievalRequest) {
+        self.epoch_retrievals.lock().push((peer, request));
+    }
+}
+
+#[tokio::test]
+async fn test_epoch_retrieval_rate_limited() {
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let dag = Arc::new(RwLock::new(Dag::new(
+        epoch_state.clone(),
+        Arc::new(MockStorage::new()),
+        1,
+        0,
+    )));
+    let ledger_info_provider = Arc::new(RwLock::new(LedgerInfoProvider::new(
+        LedgerInfoWithSignatures::new(LedgerInfo::mock_genesis(None), AggregateSignature::empty()),
+    )));
+    let proof_notifier = Arc::new(MockProofNotifier::default());
+    let time_service = TimeService::mock();
+    let trigger = StateSyncTrigger::new(
+        epoch_state,
+        ledger_info_provider,
+        dag,
+        proof_notifier.clone(),
+        time_service.clone(),
+    );
+    let requested_epochs = || -> Vec<_> {
+        proof_notifier
+            .epoch_retrievals
+            .lock()
+            .iter()
+            .map(|(_, request)| request.end_epoch)
+            .collect()
+    };
+
+    // every node of the future epoch asks for it, but only the first one is sent
+    for signer in &signers {
+        trigger.request_epoch_sync(signer.author(), 2).await;
+    }
+    assert_eq!(requested_epochs(), vec![2]);
+
+    // a later epoch is requested right away, and covers the earlier ones
+    trigger.request_epoch_sync(signers[0].author(), 3).await;
+    trigger.request_epoch_sync(signers[1].author(), 2).await;
+    assert_eq!(requested_epochs(), vec![2, 3]);
+
+    // the request is retried once the interval passes, in case the peer didn't respond
+    time_service.into_mock().advance(Duration::from_secs(1));
+    trigger.request_epoch_sync(signers[1].author(), 3).await;
+    assert_eq!(requested_epochs(), vec![2, 3, 3]);
+}
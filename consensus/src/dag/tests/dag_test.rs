@@ -2,26 +2,49 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::dag::{
-    dag_state_sync::DAG_WINDOW,
-    dag_store::Dag,
+    dag_state_sync::{DagSyncCursor, DAG_WINDOW},
+    dag_store::{Dag, DagStoreError, NodeProvenance, StartupVerification},
     storage::{CommitEvent, DAGStorage},
-    tests::helpers::new_certified_node,
-    types::{CertifiedNode, DagSnapshotBitmask, Node},
+    tests::helpers::{new_certified_node, new_signed_certified_node},
+    types::{
+        AnchorCommitProof, BroadcastProgress, CertifiedNode, CommitLogEntry, DagSnapshotBitmask,
+        Extensions, Node,
+    },
     NodeId, Vote,
 };
+use aptos_consensus_types::common::Payload;
 use aptos_crypto::HashValue;
 use aptos_infallible::Mutex;
 use aptos_types::{
-    epoch_state::EpochState, ledger_info::LedgerInfoWithSignatures,
-    validator_signer::ValidatorSigner, validator_verifier::random_validator_verifier,
+    aggregate_signature::{AggregateSignature, PartialSignatures},
+    block_info::BlockInfo,
+    epoch_state::EpochState,
+    ledger_info::{LedgerInfo, LedgerInfoWithSignatures},
+    validator_signer::ValidatorSigner,
+    validator_verifier::random_validator_verifier,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
-use std::{collections::HashMap, sync::Arc};
 
 pub struct MockStorage {
     node_data: Mutex<Option<Node>>,
     vote_data: Mutex<HashMap<NodeId, Vote>>,
     certified_node_data: Mutex<HashMap<HashValue, CertifiedNode>>,
     latest_ledger_info: Option<LedgerInfoWithSignatures>,
+    sync_cursor: Mutex<Option<DagSyncCursor>>,
+    commit_log: Mutex<Vec<CommitLogEntry>>,
+    broadcast_progress: Mutex<Option<BroadcastProgress>>,
+    certified_node_batches: Mutex<Vec<usize>>,
+    fail_certified_node_writes: AtomicBool,
+    vote_write_delay: Mutex<Option<Duration>>,
+    vote_writes_in_flight: AtomicUsize,
+    max_concurrent_vote_writes: AtomicUsize,
 }
 
 impl MockStorage {
@@ -31,6 +54,14 @@ impl MockStorage {
             vote_data: Mutex::new(HashMap::new()),
             certified_node_data: Mutex::new(HashMap::new()),
             latest_ledger_info: None,
+            sync_cursor: Mutex::new(None),
+            commit_log: Mutex::new(vec![]),
+            broadcast_progress: Mutex::new(None),
+            certified_node_batches: Mutex::new(vec![]),
+            fail_certified_node_writes: AtomicBool::new(false),
+            vote_write_delay: Mutex::new(None),
+            vote_writes_in_flight: AtomicUsize::new(0),
+            max_concurrent_vote_writes: AtomicUsize::new(0),
         }
     }
 
@@ -40,8 +71,36 @@ impl MockStorage {
             vote_data: Mutex::new(HashMap::new()),
             certified_node_data: Mutex::new(HashMap::new()),
             latest_ledger_info: Some(ledger_info),
+            sync_cursor: Mutex::new(None),
+            commit_log: Mutex::new(vec![]),
+            broadcast_progress: Mutex::new(None),
+            certified_node_batches: Mutex::new(vec![]),
+            fail_certified_node_writes: AtomicBool::new(false),
+            vote_write_delay: Mutex::new(None),
+            vote_writes_in_flight: AtomicUsize::new(0),
+            max_concurrent_vote_writes: AtomicUsize::new(0),
         }
     }
+
+    /// The sizes of the batches of certified nodes saved so far.
+    pub fn certified_node_batches(&self) -> Vec<usize> {
+        self.certified_node_batches.lock().clone()
+    }
+
+    pub fn fail_certified_node_writes(&self, fail: bool) {
+        self.fail_certified_node_writes
+            .store(fail, Ordering::SeqCst);
+    }
+
+    /// Makes every vote write take `delay`, so that overlapping writes can be observed.
+    pub fn set_vote_write_delay(&self, delay: Duration) {
+        self.vote_write_delay.lock().replace(delay);
+    }
+
+    /// The most vote writes that were in progress at once so far.
+    pub fn max_concurrent_vote_writes(&self) -> usize {
+        self.max_concurrent_vote_writes.load(Ordering::SeqCst)
+    }
 }
 
 impl DAGStorage for MockStorage {
@@ -60,7 +119,15 @@ impl DAGStorage for MockStorage {
     }
 
     fn save_vote(&self, node_id: &NodeId, vote: &Vote) -> anyhow::Result<()> {
+        let in_flight = self.vote_writes_in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_concurrent_vote_writes
+            .fetch_max(in_flight, Ordering::SeqCst);
+        let delay = *self.vote_write_delay.lock();
+        if let Some(delay) = delay {
+            std::thread::sleep(delay);
+        }
         self.vote_data.lock().insert(node_id.clone(), vote.clone());
+        self.vote_writes_in_flight.fetch_sub(1, Ordering::SeqCst);
         Ok(())
     }
 
@@ -76,12 +143,31 @@ impl DAGStorage for MockStorage {
     }
 
     fn save_certified_node(&self, node: &CertifiedNode) -> anyhow::Result<()> {
+        if self.fail_certified_node_writes.load(Ordering::SeqCst) {
+            anyhow::bail!("failed to save certified node");
+        }
         self.certified_node_data
             .lock()
             .insert(node.digest(), node.clone());
         Ok(())
     }
 
+    fn save_certified_nodes(&self, nodes: &[CertifiedNode]) -> anyhow::Result<()> {
+        if self.fail_certified_node_writes.load(Ordering::SeqCst) {
+            anyhow::bail!("failed to save certified nodes");
+        }
+        self.certified_node_batches.lock().push(nodes.len());
+        let mut certified_node_data = self.certified_node_data.lock();
+        for node in nodes {
+            certified_node_data.insert(node.digest(), node.clone());
+        }
+        Ok(())
+    }
+
+    fn get_certified_node(&self, digest: &HashValue) -> anyhow::Result<Option<CertifiedNode>> {
+        Ok(self.certified_node_data.lock().get(digest).cloned())
+    }
+
     fn get_certified_nodes(&self) -> anyhow::Result<Vec<(HashValue, CertifiedNode)>> {
         Ok(self
             .certified_node_data
@@ -107,6 +193,53 @@ impl DAGStorage for MockStorage {
             .clone()
             .ok_or_else(|| anyhow::anyhow!("ledger info not set"))
     }
+
+    fn save_sync_cursor(&self, cursor: &DagSyncCursor) -> anyhow::Result<()> {
+        self.sync_cursor.lock().replace(cursor.clone());
+        Ok(())
+    }
+
+    fn get_sync_cursor(&self) -> anyhow::Result<Option<DagSyncCursor>> {
+        Ok(self.sync_cursor.lock().clone())
+    }
+
+    fn delete_sync_cursor(&self) -> anyhow::Result<()> {
+        self.sync_cursor.lock().take();
+        Ok(())
+    }
+
+    fn append_commit_log(&self, proof: &AnchorCommitProof) -> anyhow::Result<()> {
+        self.commit_log
+            .lock()
+            .push(CommitLogEntry::Full(proof.clone()));
+        Ok(())
+    }
+
+    fn get_commit_log(&self) -> anyhow::Result<Vec<CommitLogEntry>> {
+        Ok(self.commit_log.lock().clone())
+    }
+
+    fn compact_commit_log(&self, num_retained: usize) -> anyhow::Result<usize> {
+        let mut commit_log = self.commit_log.lock();
+        let num_expired = commit_log.len().saturating_sub(num_retained);
+        let mut num_compacted = 0;
+        for entry in commit_log.iter_mut().take(num_expired) {
+            if entry.proof().is_some() {
+                *entry = entry.clone().compact();
+                num_compacted += 1;
+            }
+        }
+        Ok(num_compacted)
+    }
+
+    fn save_broadcast_progress(&self, progress: &BroadcastProgress) -> anyhow::Result<()> {
+        self.broadcast_progress.lock().replace(progress.clone());
+        Ok(())
+    }
+
+    fn get_broadcast_progress(&self) -> anyhow::Result<Option<BroadcastProgress>> {
+        Ok(self.broadcast_progress.lock().clone())
+    }
 }
 
 fn setup() -> (Vec<ValidatorSigner>, Arc<EpochState>, Dag, Arc<MockStorage>) {
@@ -155,6 +288,203 @@ fn test_dag_insertion_succeed() {
         .is_none());
 }
 
+#[test]
+fn test_dag_predecessors() {
+    let (signers, _, mut dag, _) = setup();
+
+    // every node of rounds 1 to 3 links to the nodes 0, 1, 2 of the previous round
+    let mut parents = vec![];
+    let mut nodes_by_round = vec![];
+    for round in 1..=3 {
+        let nodes: Vec<_> = signers
+            .iter()
+            .map(|signer| new_certified_node(round, signer.author(), parents.clone()))
+            .collect();
+        for node in &nodes {
+            assert!(dag.add_node(node.clone()).is_ok());
+        }
+        parents = nodes[0..3].iter().map(|node| node.certificate()).collect();
+        nodes_by_round.push(nodes);
+    }
+    let node = new_certified_node(4, signers[0].author(), parents);
+    assert!(dag.add_node(node.clone()).is_ok());
+
+    // the nodes 0, 1, 2 of rounds 3 and 2
+    let expected: HashSet<_> = nodes_by_round[1..]
+        .iter()
+        .flat_map(|nodes| nodes[0..3].iter().map(|node| node.digest()))
+        .collect();
+    let predecessors = dag.predecessors(&node.digest(), 2);
+    assert_eq!(predecessors.len(), expected.len());
+    let digests: HashSet<_> = predecessors.iter().map(|node| node.digest()).collect();
+    assert_eq!(digests, expected);
+    // closest first
+    assert!(predecessors[0..3].iter().all(|node| node.round() == 3));
+
+    assert!(dag.predecessors(&node.digest(), 0).is_empty());
+    assert!(dag.predecessors(&HashValue::random(), 2).is_empty());
+}
+
+#[test]
+fn test_storage_batch_save_and_delete() {
+    let (signers, _, _, storage) = setup();
+    let mut nodes = vec![];
+    let mut parents = vec![];
+    for round in 1..=3 {
+        let round_nodes: Vec<_> = signers
+            .iter()
+            .map(|signer| new_certified_node(round, signer.author(), parents.clone()))
+            .collect();
+        parents = round_nodes.iter().map(|node| node.certificate()).collect();
+        nodes.extend(round_nodes);
+    }
+
+    storage.save_certified_nodes(&nodes).unwrap();
+    assert_eq!(storage.get_certified_nodes().unwrap().len(), nodes.len());
+    for node in &nodes {
+        assert_eq!(
+            storage.get_certified_node(&node.digest()).unwrap(),
+            Some(node.clone())
+        );
+    }
+
+    // only the nodes of rounds 1 and 2 are deleted
+    assert_eq!(storage.delete_nodes_below(3).unwrap(), 2 * signers.len());
+    for node in &nodes {
+        let stored = storage.get_certified_node(&node.digest()).unwrap();
+        assert_eq!(stored.is_some(), node.round() >= 3);
+    }
+    // nothing is left to delete below round 3
+    assert_eq!(storage.delete_nodes_below(3).unwrap(), 0);
+    assert_eq!(storage.get_certified_nodes().unwrap().len(), signers.len());
+}
+
+#[test]
+fn test_storage_prune_epoch() {
+    let (signers, _, _, storage) = setup();
+    let new_node = |epoch, round, signer: &ValidatorSigner| {
+        Node::new(
+            epoch,
+            round,
+            signer.author(),
+            0,
+            Payload::empty(false),
+            vec![],
+            Extensions::empty(),
+        )
+    };
+    let mut nodes = vec![];
+    for epoch in 1..=2 {
+        for signer in &signers {
+            let node = new_node(epoch, 1, signer);
+            let vote = Vote::new(node.metadata().clone(), node.sign_vote(signer).unwrap());
+            storage.save_vote(&node.id(), &vote).unwrap();
+            let certified_node = CertifiedNode::new(node, AggregateSignature::empty());
+            storage.save_certified_node(&certified_node).unwrap();
+            nodes.push(certified_node);
+        }
+    }
+    let pending_node = new_node(2, 2, &signers[0]);
+    storage.save_pending_node(&pending_node).unwrap();
+    let cursor = DagSyncCursor::new(NodeId::new(1, 1, signers[0].author()), 1, vec![]);
+    storage.save_sync_cursor(&cursor).unwrap();
+
+    // pruning again once the data of epoch 1 is gone changes nothing
+    for _ in 0..2 {
+        storage.prune_epoch(2).unwrap();
+
+        for node in &nodes {
+            let stored = storage.get_certified_node(&node.digest()).unwrap();
+            assert_eq!(stored.is_some(), node.epoch() == 2);
+        }
+        let votes = storage.get_votes().unwrap();
+        assert_eq!(votes.len(), signers.len());
+        assert!(votes.iter().all(|(node_id, _)| node_id.epoch() == 2));
+        assert_eq!(
+            storage.get_pending_node().unwrap(),
+            Some(pending_node.clone())
+        );
+        assert!(storage.get_sync_cursor().unwrap().is_none());
+    }
+}
+
+#[test]
+fn test_dag_reconciles_storage_and_memory() {
+    let (signers, _, mut dag, storage) = setup();
+
+    // in storage but not in memory
+    let node = new_certified_node(1, signers[0].author(), vec![]);
+    storage.save_certified_node(&node).unwrap();
+    assert!(dag.add_node(node.clone()).is_ok());
+    assert!(dag.exists(node.metadata()));
+    assert_eq!(storage.get_certified_nodes().unwrap().len(), 1);
+
+    // in memory but not in storage
+    storage.delete_certified_nodes(vec![node.digest()]).unwrap();
+    assert!(dag.add_node(node.clone()).is_ok());
+    assert_eq!(
+        storage.get_certified_node(&node.digest()).unwrap(),
+        Some(node.clone())
+    );
+
+    // consistent again, so adding it is a duplicate
+    assert!(dag.add_node(node).is_err());
+}
+
+#[test]
+fn test_dag_add_fetched_nodes() {
+    let (signers, _, mut dag, storage) = setup();
+    let responder = signers[3].author();
+
+    let first_round_nodes: Vec<_> = signers
+        .iter()
+        .map(|signer| new_certified_node(1, signer.author(), vec![]))
+        .collect();
+    let parents: Vec<_> = first_round_nodes
+        .iter()
+        .map(|node| node.certificate())
+        .collect();
+    let second_round_nodes: Vec<_> = signers[0..3]
+        .iter()
+        .map(|signer| new_certified_node(2, signer.author(), parents.clone()))
+        .collect();
+    // round too high
+    let rejected_node = new_certified_node(4, signers[0].author(), vec![]);
+
+    // a failed save leaves the DAG unchanged
+    storage.fail_certified_node_writes(true);
+    assert!(dag
+        .add_fetched_nodes(first_round_nodes.clone(), responder)
+        .is_err());
+    assert_eq!(dag.highest_round(), 1);
+    assert!(first_round_nodes
+        .iter()
+        .all(|node| !dag.exists(node.metadata())));
+
+    // the accepted nodes are saved in a single batch, parents first
+    storage.fail_certified_node_writes(false);
+    let nodes = first_round_nodes
+        .iter()
+        .chain(&second_round_nodes)
+        .chain([&rejected_node])
+        .cloned();
+    let rejected = dag.add_fetched_nodes(nodes, responder).unwrap();
+    assert_eq!(rejected.len(), 1);
+    assert_eq!(storage.certified_node_batches(), vec![7]);
+    for node in first_round_nodes.iter().chain(&second_round_nodes) {
+        assert!(dag.exists(node.metadata()));
+        assert!(storage
+            .get_certified_node(&node.digest())
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            dag.provenance(&node.digest()),
+            Some(NodeProvenance::Fetch(responder))
+        );
+    }
+    assert!(!dag.exists(rejected_node.metadata()));
+}
+
 #[test]
 fn test_dag_insertion_failure() {
     let (signers, epoch_state, mut dag, _) = setup();
@@ -188,6 +518,298 @@ fn test_dag_insertion_failure() {
     assert!(dag.add_node(node).is_err());
 }
 
+#[test]
+fn test_dag_rejects_cyclic_parents() {
+    let (signers, epoch_state, mut dag, _) = setup();
+
+    for signer in &signers[0..3] {
+        let node = new_certified_node(1, signer.author(), vec![]);
+        assert!(dag.add_node(node).is_ok());
+    }
+    let parents = dag
+        .get_strong_links_for_round(1, &epoch_state.verifier)
+        .unwrap();
+    let node = new_certified_node(2, signers[0].author(), parents.clone());
+    let same_round_parent = node.certificate();
+    assert!(dag.add_node(node).is_ok());
+
+    // a parent in the same round could reference this node back
+    let mut cyclic_parents = parents.clone();
+    cyclic_parents.push(same_round_parent);
+    let node = new_certified_node(2, signers[1].author(), cyclic_parents);
+    let err = dag.add_node(node).unwrap_err();
+    assert!(err.to_string().contains("forming a cycle"));
+
+    // a parent from a future round
+    let future_parent = new_certified_node(3, signers[2].author(), vec![]).certificate();
+    let node = new_certified_node(2, signers[2].author(), vec![future_parent]);
+    let err = dag.add_node(node).unwrap_err();
+    assert!(err.to_string().contains("forming a cycle"));
+}
+
+#[test]
+fn test_dag_rejects_cross_epoch_parents() {
+    let (signers, epoch_state, mut dag, _) = setup();
+
+    for signer in &signers[0..3] {
+        let node = new_certified_node(1, signer.author(), vec![]);
+        assert!(dag.add_node(node).is_ok());
+    }
+    let mut parents = dag
+        .get_strong_links_for_round(1, &epoch_state.verifier)
+        .unwrap();
+    let previous_epoch_parent = CertifiedNode::new(
+        Node::new(
+            0,
+            1,
+            signers[3].author(),
+            0,
+            Payload::empty(false),
+            vec![],
+            Extensions::empty(),
+        ),
+        AggregateSignature::empty(),
+    );
+    parents.push(previous_epoch_parent.certificate());
+    let node = new_certified_node(2, signers[0].author(), parents.clone());
+    let err = dag.add_node(node.clone()).unwrap_err();
+    assert!(err.to_string().contains("not its own epoch"));
+    assert!(!dag.exists(node.metadata()));
+
+    parents.pop();
+    assert!(dag
+        .add_node(new_certified_node(2, signers[0].author(), parents))
+        .is_ok());
+}
+
+#[test]
+fn test_dag_rejects_equivocation() {
+    let (signers, epoch_state, mut dag, storage) = setup();
+
+    for signer in &signers[0..3] {
+        let node = new_certified_node(1, signer.author(), vec![]);
+        assert!(dag.add_node(node).is_ok());
+    }
+    let parents = dag
+        .get_strong_links_for_round(1, &epoch_state.verifier)
+        .unwrap();
+    let node = new_certified_node(2, signers[0].author(), parents.clone());
+    assert!(dag.add_node(node.clone()).is_ok());
+
+    // the same node again is a duplicate, not an equivocation
+    assert!(dag.add_node(node.clone()).is_err());
+    assert!(dag.equivocations().is_empty());
+
+    let conflicting_node = new_certified_node(2, signers[0].author(), parents[0..2].to_vec());
+    assert_ne!(conflicting_node.digest(), node.digest());
+    let err = dag.add_node(conflicting_node.clone()).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<DagStoreError>(),
+        Some(DagStoreError::Equivocation { author, round: 2 }) if *author == signers[0].author()
+    ));
+
+    assert_eq!(dag.equivocations().len(), 1);
+    assert_eq!(*dag.equivocations()[0].accepted, node);
+    assert_eq!(*dag.equivocations()[0].rejected, conflicting_node);
+    let stored_node = dag.get_node_ref(2, &signers[0].author()).unwrap().as_node();
+    assert_eq!(stored_node.digest(), node.digest());
+    assert!(!storage
+        .get_certified_nodes()
+        .unwrap()
+        .iter()
+        .any(|(digest, _)| *digest == conflicting_node.digest()));
+}
+
+#[test]
+fn test_dag_startup_verification() {
+    let (signers, epoch_state, mut dag, storage) = setup();
+
+    let mut nodes = vec![];
+    let mut parents = vec![];
+    for round in 1..=10 {
+        let round_nodes: Vec<_> = signers
+            .iter()
+            .map(|signer| {
+                new_signed_certified_node(
+                    round,
+                    signer.author(),
+                    parents.clone(),
+                    &signers,
+                    &epoch_state.verifier,
+                )
+            })
+            .collect();
+        for node in &round_nodes {
+            assert!(dag.add_node(node.clone()).is_ok());
+        }
+        parents = round_nodes.iter().map(|node| node.certificate()).collect();
+        nodes.extend(round_nodes);
+    }
+    // the certificate of another node
+    let tampered_node = CertifiedNode::new(
+        Node::new(
+            1,
+            11,
+            signers[0].author(),
+            0,
+            Payload::empty(false),
+            parents,
+            Extensions::empty(),
+        ),
+        nodes[0].signatures().clone(),
+    );
+
+    storage.save_certified_node(&tampered_node).unwrap();
+    let unverified_dag = Dag::new(epoch_state.clone(), storage.clone(), 1, DAG_WINDOW);
+    assert!(unverified_dag.exists(tampered_node.metadata()));
+
+    for num_threads in [1, 4] {
+        let verified_dag = Dag::new_with_startup_verification(
+            epoch_state.clone(),
+            storage.clone(),
+            1,
+            DAG_WINDOW,
+            StartupVerification::Enabled { num_threads },
+        );
+        for node in &nodes {
+            assert!(verified_dag.exists(node.metadata()));
+        }
+        assert!(!verified_dag.exists(tampered_node.metadata()));
+        assert!(!storage
+            .get_certified_nodes()
+            .unwrap()
+            .iter()
+            .any(|(digest, _)| *digest == tampered_node.digest()));
+
+        storage.save_certified_node(&tampered_node).unwrap();
+    }
+}
+
+#[test]
+fn test_dag_missing_parents() {
+    let (signers, _, mut dag, _) = setup();
+
+    let round_1_nodes: Vec<_> = signers
+        .iter()
+        .map(|signer| new_certified_node(1, signer.author(), vec![]))
+        .collect();
+    let parents_metadata: Vec<_> = round_1_nodes
+        .iter()
+        .map(|node| node.metadata().clone())
+        .collect();
+    let parents = round_1_nodes
+        .iter()
+        .map(|node| node.certificate())
+        .collect();
+    let node = new_certified_node(2, signers[0].author(), parents);
+
+    // all missing
+    assert_eq!(dag.missing_parents(&parents_metadata), parents_metadata);
+    assert_eq!(dag.missing_parents_of(&node), parents_metadata);
+
+    // some missing
+    for node in &round_1_nodes[0..2] {
+        assert!(dag.add_node(node.clone()).is_ok());
+    }
+    let expected = parents_metadata[2..].to_vec();
+    assert_eq!(dag.missing_parents(&parents_metadata), expected);
+    assert_eq!(dag.missing_parents_of(&node), expected);
+
+    // all present
+    for node in &round_1_nodes[2..] {
+        assert!(dag.add_node(node.clone()).is_ok());
+    }
+    assert!(dag.missing_parents(&parents_metadata).is_empty());
+    assert!(dag.missing_parents_of(&node).is_empty());
+}
+
+#[test]
+fn test_dag_reachable_weak_links() {
+    let (signers, _, mut dag, _) = setup();
+
+    let mut parents = vec![];
+    for round in 1..=2 {
+        let nodes: Vec<_> = signers[0..3]
+            .iter()
+            .map(|signer| new_certified_node(round, signer.author(), parents.clone()))
+            .collect();
+        for node in &nodes {
+            assert!(dag.add_node(node.clone()).is_ok());
+        }
+        parents = nodes.iter().map(|node| node.certificate()).collect();
+    }
+    // the node of the lagging validator arrives after the round 2 nodes, which don't reference it
+    let lagging_node = new_certified_node(1, signers[3].author(), vec![]);
+    assert!(dag.add_node(lagging_node.clone()).is_ok());
+    let weak_links = dag.weak_link_candidates(&parents, 1);
+    assert_eq!(weak_links, vec![lagging_node.metadata().clone()]);
+    let node = Node::new(
+        1,
+        3,
+        signers[0].author(),
+        0,
+        Payload::empty(false),
+        parents,
+        Extensions::with_weak_links(weak_links),
+    );
+    let node = Arc::new(CertifiedNode::new(node, AggregateSignature::empty()));
+    assert!(dag.add_node(node.as_ref().clone()).is_ok());
+
+    // the weak link is not a strong dependency
+    assert!(!dag
+        .reachable(Some(node.metadata()).into_iter(), None, |_| true)
+        .any(|node_status| node_status.as_node().metadata() == lagging_node.metadata()));
+    // but is in the causal history to order
+    let ordered: Vec<_> = dag
+        .reachable_mut(&node, None)
+        .map(|node_status| node_status.as_node().metadata().clone())
+        .collect();
+    assert_eq!(ordered.len(), 8);
+    assert!(ordered.contains(lagging_node.metadata()));
+}
+
+#[test]
+fn test_dag_weak_links_are_dependencies() {
+    let (signers, _, mut dag, _) = setup();
+
+    let mut parents = vec![];
+    for round in 1..=2 {
+        let nodes: Vec<_> = signers[0..3]
+            .iter()
+            .map(|signer| new_certified_node(round, signer.author(), parents.clone()))
+            .collect();
+        for node in &nodes {
+            assert!(dag.add_node(node.clone()).is_ok());
+        }
+        parents = nodes.iter().map(|node| node.certificate()).collect();
+    }
+    // the weak link references a node this validator doesn't have
+    let lagging_node = new_certified_node(1, signers[3].author(), vec![]);
+    let node = Node::new(
+        1,
+        3,
+        signers[0].author(),
+        0,
+        Payload::empty(false),
+        parents,
+        Extensions::with_weak_links(vec![lagging_node.metadata().clone()]),
+    );
+    assert_eq!(
+        dag.missing_parents_of(&node),
+        vec![lagging_node.metadata().clone()]
+    );
+    let node = CertifiedNode::new(node, AggregateSignature::empty());
+    assert!(dag.add_node(node.clone()).is_err());
+
+    assert!(dag.add_node(lagging_node.clone()).is_ok());
+    assert!(dag.missing_parents_of(&node).is_empty());
+    assert!(dag.add_node(node.clone()).is_ok());
+    // the weak-linked node is part of what a fetch of the node returns
+    assert!(dag
+        .reachable_with_weak_links(Some(node.metadata()).into_iter(), None, |_| true)
+        .any(|node_status| node_status.as_node().metadata() == lagging_node.metadata()));
+}
+
 #[test]
 fn test_dag_recover_from_storage() {
     let (signers, epoch_state, mut dag, storage) = setup();
@@ -250,3 +872,127 @@ fn test_dag_bitmask() {
     assert_eq!(dag.bitmask(15), DagSnapshotBitmask::new(5, vec![]));
     assert_eq!(dag.bitmask(6), DagSnapshotBitmask::new(5, vec![]));
 }
+
+#[test]
+fn test_dag_certificate_for() {
+    let (signers, epoch_state, mut dag, _) = setup();
+
+    let node = Node::new(
+        1,
+        1,
+        signers[0].author(),
+        0,
+        Payload::empty(false),
+        vec![],
+        Extensions::empty(),
+    );
+    let mut partial_sigs = PartialSignatures::empty();
+    for signer in &signers[0..3] {
+        partial_sigs.add_signature(signer.author(), signer.sign(node.metadata()).unwrap());
+    }
+    let signatures = epoch_state
+        .verifier
+        .aggregate_signatures(&partial_sigs)
+        .unwrap();
+    let certified_node = CertifiedNode::new(node, signatures);
+    let digest = certified_node.digest();
+    assert!(dag.certificate_for(&digest).is_none());
+    assert!(dag.add_node(certified_node.clone()).is_ok());
+
+    let certificate = dag.certificate_for(&digest).unwrap();
+    assert_eq!(&certificate, certified_node.signatures());
+    assert!(epoch_state
+        .verifier
+        .verify_multi_signatures(certified_node.metadata(), &certificate)
+        .is_ok());
+    assert!(dag.certificate_for(&HashValue::random()).is_none());
+}
+
+#[test]
+fn test_dag_force_prune_range() {
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let ledger_info = LedgerInfoWithSignatures::new(
+        LedgerInfo::new(
+            BlockInfo::new(1, 6, HashValue::zero(), HashValue::zero(), 0, 0, None),
+            HashValue::zero(),
+        ),
+        AggregateSignature::empty(),
+    );
+    let storage = Arc::new(MockStorage::new_with_ledger_info(ledger_info));
+    let mut dag = Dag::new(epoch_state.clone(), storage.clone(), 1, DAG_WINDOW);
+
+    let mut metadatas = vec![];
+    for round in 1..10 {
+        let parents = dag
+            .get_strong_links_for_round(round, &epoch_state.verifier)
+            .unwrap_or_default();
+        for signer in &signers[0..3] {
+            let node = new_certified_node(round, signer.author(), parents.clone());
+            metadatas.push(node.metadata().clone());
+            assert!(dag.add_node(node).is_ok());
+        }
+    }
+
+    // Rounds above the committed round 6 are refused.
+    assert!(dag.force_prune_range(5, 7).is_err());
+    assert!(dag.force_prune_range(4, 2).is_err());
+    assert_eq!(storage.certified_node_data.lock().len(), metadatas.len());
+
+    assert_eq!(dag.force_prune_range(3, 5).unwrap(), 9);
+    for metadata in &metadatas {
+        let pruned = (3..=5).contains(&metadata.round());
+        assert_eq!(dag.exists(metadata), !pruned);
+    }
+    assert_eq!(
+        storage.certified_node_data.lock().len(),
+        metadatas.len() - 9
+    );
+    assert!(storage
+        .certified_node_data
+        .lock()
+        .values()
+        .all(|node| !(3..=5).contains(&node.round())));
+}
+
+#[test]
+fn test_dag_prune() {
+    let (signers, epoch_state, mut dag, storage) = setup();
+
+    let mut metadatas = vec![];
+    let mut parents = vec![];
+    for round in 1..=4 {
+        let mut round_nodes = vec![];
+        for signer in &signers {
+            let node = new_certified_node(round, signer.author(), parents.clone());
+            metadatas.push(node.metadata().clone());
+            round_nodes.push(node.certificate());
+            assert!(dag.add_node(node).is_ok());
+        }
+        // the nodes of the next round don't reference the last author of this round
+        round_nodes.pop();
+        parents = round_nodes;
+    }
+    assert_eq!(dag.lowest_round(), 1);
+
+    // rounds 1 and 2 are pruned except for the round 2 parents of round 3
+    assert_eq!(dag.prune(3), 5);
+    for metadata in &metadatas {
+        let retained = metadata.round() >= 3
+            || (metadata.round() == 2 && metadata.author() != &signers[3].author());
+        assert_eq!(dag.exists(metadata), retained);
+    }
+    assert_eq!(dag.lowest_round(), 2);
+    assert_eq!(storage.certified_node_data.lock().len(), 11);
+    assert!(dag
+        .get_strong_links_for_round(4, &epoch_state.verifier)
+        .is_some());
+
+    // once round 3 is pruned too, nothing references round 2
+    assert_eq!(dag.prune(4), 4);
+    assert_eq!(dag.lowest_round(), 3);
+    assert_eq!(storage.certified_node_data.lock().len(), 7);
+}
@@ -2,17 +2,34 @@
 
 use super::dag_test::MockStorage;
 use crate::dag::{
-    dag_fetcher::FetchRequestHandler,
+    counters,
+    dag_fetcher::{
+        request_for_certified_node_with_retry, DagFetcher, DagFetcherService, FetchRequestHandler,
+        FetchRequesterError, FetchRetryConfig, LocalFetchRequest, PendingFetches, TDagFetcher,
+        TFetchRequester,
+    },
+    dag_network::{RpcWithFallback, TDAGNetworkSender},
     dag_state_sync::DAG_WINDOW,
     dag_store::Dag,
-    tests::helpers::new_certified_node,
-    types::{DagSnapshotBitmask, FetchResponse, RemoteFetchRequest},
+    tests::helpers::{new_certified_node, new_node, new_signed_certified_node},
+    types::{
+        CertifiedNode, DAGMessage, DagSnapshotBitmask, FetchResponse, Node, RemoteFetchRequest,
+    },
     RpcHandler,
 };
-use aptos_infallible::RwLock;
-use aptos_types::{epoch_state::EpochState, validator_verifier::random_validator_verifier};
-use claims::assert_ok_eq;
-use std::sync::Arc;
+use aptos_consensus_types::common::Author;
+use aptos_infallible::{Mutex, RwLock};
+use aptos_reliable_broadcast::RBNetworkSender;
+use aptos_time_service::TimeService;
+use aptos_types::{
+    account_address::AccountAddress, epoch_state::EpochState,
+    validator_verifier::random_validator_verifier,
+};
+use async_trait::async_trait;
+use claims::{assert_matches, assert_ok, assert_ok_eq};
+use futures::{future::pending, StreamExt};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::oneshot;
 
 #[tokio::test]
 async fn test_dag_fetcher_receiver() {
@@ -61,4 +78,296 @@ async fn test_dag_fetcher_receiver() {
     );
 }
 
+#[tokio::test]
+async fn test_pending_fetches_evict_oldest() {
+    let (signers, _) = random_validator_verifier(4, None, false);
+    let pending_fetches = PendingFetches::new(2);
+
+    let mut nodes = vec![];
+    let mut receivers = vec![];
+    for (round, signer) in (1..=3).zip(&signers) {
+        let node = new_node(round, round * 10, signer.author(), vec![]);
+        let (tx, rx) = oneshot::channel();
+        pending_fetches.push(LocalFetchRequest::Node(node.clone(), tx));
+        nodes.push(node);
+        receivers.push(rx);
+    }
+
+    // The oldest request is evicted and its waiter observes the dropped responder.
+    assert_eq!(pending_fetches.len(), 2);
+    assert!(receivers.remove(0).await.is_err());
+
+    assert_eq!(pending_fetches.pop().await.node(), &nodes[1]);
+    assert_eq!(pending_fetches.pop().await.node(), &nodes[2]);
+    assert_eq!(pending_fetches.len(), 0);
+}
+
 // TODO: add more tests after commit rule tests
+
+/// Fails the first `num_failures` requests with the given error and accepts every request after.
+struct FlakyFetchRequester {
+    num_failures: usize,
+    failure: fn() -> FetchRequesterError,
+    num_requests: Mutex<usize>,
+}
+
+impl FlakyFetchRequester {
+    fn new(num_failures: usize, failure: fn() -> FetchRequesterError) -> Self {
+        Self {
+            num_failures,
+            failure,
+            num_requests: Mutex::new(0),
+        }
+    }
+
+    fn request(&self) -> Result<(), FetchRequesterError> {
+        let mut num_requests = self.num_requests.lock();
+        *num_requests += 1;
+        if *num_requests <= self.num_failures {
+            Err((self.failure)())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl TFetchRequester for FlakyFetchRequester {
+    fn request_for_node(&self, _node: Node) -> Result<(), FetchRequesterError> {
+        self.request()
+    }
+
+    fn request_for_certified_node(&self, _node: CertifiedNode) -> Result<(), FetchRequesterError> {
+        self.request()
+    }
+}
+
+#[tokio::test]
+async fn test_fetch_request_retry() {
+    let config = FetchRetryConfig {
+        max_attempts: 3,
+        initial_backoff: Duration::from_millis(1),
+    };
+    let time_service = TimeService::real();
+    let node = new_certified_node(1, AccountAddress::ONE, vec![]);
+    let retries = counters::FETCH_REQUEST_RETRIES.get();
+
+    // a full queue is retried until the request goes through
+    let requester = FlakyFetchRequester::new(2, || FetchRequesterError::QueueFull);
+    assert_ok!(
+        request_for_certified_node_with_retry(&requester, node.clone(), &config, &time_service)
+            .await
+    );
+    assert_eq!(*requester.num_requests.lock(), 3);
+    assert!(counters::FETCH_REQUEST_RETRIES.get() >= retries + 2);
+
+    // up to the maximum number of attempts
+    let requester = FlakyFetchRequester::new(3, || FetchRequesterError::QueueFull);
+    assert_matches!(
+        request_for_certified_node_with_retry(&requester, node.clone(), &config, &time_service)
+            .await,
+        Err(FetchRequesterError::QueueFull)
+    );
+    assert_eq!(*requester.num_requests.lock(), 3);
+
+    // a closed fetch service is not retried
+    let requester = FlakyFetchRequester::new(1, || FetchRequesterError::Closed);
+    assert_matches!(
+        request_for_certified_node_with_retry(&requester, node, &config, &time_service).await,
+        Err(FetchRequesterError::Closed)
+    );
+    assert_eq!(*requester.num_requests.lock(), 1);
+}
+
+/// Never answers the rpcs sent to `silent_peer`, answers the other peers with `response`.
+struct MockPeers {
+    silent_peer: Author,
+    response: FetchResponse,
+    requested: Mutex<Vec<Author>>,
+}
+
+#[async_trait]
+impl RBNetworkSender<DAGMessage> for MockPeers {
+    async fn send_rb_rpc(
+        &self,
+        _receiver: Author,
+        _message: DAGMessage,
+        _timeout: Duration,
+    ) -> anyhow::Result<DAGMessage> {
+        unimplemented!()
+    }
+}
+
+#[async_trait]
+impl TDAGNetworkSender for MockPeers {
+    async fn send_rpc(
+        &self,
+        receiver: Author,
+        _message: DAGMessage,
+        _timeout: Duration,
+    ) -> anyhow::Result<DAGMessage> {
+        self.requested.lock().push(receiver);
+        if receiver == self.silent_peer {
+            pending::<()>().await;
+        }
+        Ok(self.response.clone().into())
+    }
+
+    async fn send_rpc_with_fallbacks(
+        self: Arc<Self>,
+        _responders: Vec<Author>,
+        _message: DAGMessage,
+        _retry_interval: Duration,
+        _rpc_timeout: Duration,
+    ) -> RpcWithFallback {
+        unimplemented!()
+    }
+}
+
+#[tokio::test]
+async fn test_dag_fetcher_fanout() {
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let storage = Arc::new(MockStorage::new());
+    let dag = Arc::new(RwLock::new(Dag::new(
+        epoch_state.clone(),
+        storage,
+        0,
+        DAG_WINDOW,
+    )));
+    let missing_node = new_signed_certified_node(
+        1,
+        signers[0].author(),
+        vec![],
+        &signers,
+        &epoch_state.verifier,
+    );
+    let request = RemoteFetchRequest::new(
+        1,
+        vec![missing_node.metadata().clone()],
+        dag.read().bitmask(2),
+    );
+
+    let network = Arc::new(MockPeers {
+        silent_peer: signers[1].author(),
+        response: FetchResponse::new(1, vec![missing_node.clone()]),
+        requested: Mutex::new(vec![]),
+    });
+    let fetcher = DagFetcher::new(epoch_state, network.clone(), TimeService::mock()).with_fanout(2);
+
+    // the silent peer doesn't hold up the fetch, as the other peer is requested at the same time
+    let responders = vec![signers[1].author(), signers[2].author()];
+    let result = fetcher
+        .fetch(request, responders.clone(), dag.clone())
+        .await;
+    assert_ok!(result);
+    assert!(dag.read().exists(missing_node.metadata()));
+    let mut requested = network.requested.lock().clone();
+    requested.sort();
+    let mut expected = responders;
+    expected.sort();
+    assert_eq!(requested, expected);
+}
+
+#[tokio::test]
+async fn test_dag_fetcher_zero_fanout() {
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let dag = Arc::new(RwLock::new(Dag::new(
+        epoch_state.clone(),
+        Arc::new(MockStorage::new()),
+        0,
+        DAG_WINDOW,
+    )));
+    let missing_node = new_signed_certified_node(
+        1,
+        signers[0].author(),
+        vec![],
+        &signers,
+        &epoch_state.verifier,
+    );
+    let request = RemoteFetchRequest::new(
+        1,
+        vec![missing_node.metadata().clone()],
+        dag.read().bitmask(2),
+    );
+    let network = Arc::new(MockPeers {
+        silent_peer: signers[1].author(),
+        response: FetchResponse::new(1, vec![missing_node.clone()]),
+        requested: Mutex::new(vec![]),
+    });
+
+    // a fan-out of 0 is treated as 1 rather than never sending the rpc
+    let fetcher = DagFetcher::new(epoch_state, network.clone(), TimeService::mock()).with_fanout(0);
+    let result = tokio::time::timeout(
+        Duration::from_secs(5),
+        fetcher.fetch(request, vec![signers[2].author()], dag.clone()),
+    )
+    .await;
+    assert_ok!(result.unwrap());
+    assert!(dag.read().exists(missing_node.metadata()));
+    assert_eq!(*network.requested.lock(), vec![signers[2].author()]);
+}
+
+#[tokio::test]
+async fn test_fetch_requests_deduplicated() {
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let storage = Arc::new(MockStorage::new());
+    let dag = Arc::new(RwLock::new(Dag::new(
+        epoch_state.clone(),
+        storage,
+        0,
+        DAG_WINDOW,
+    )));
+    let parent = new_signed_certified_node(
+        1,
+        signers[0].author(),
+        vec![],
+        &signers,
+        &epoch_state.verifier,
+    );
+    let node = new_signed_certified_node(
+        2,
+        signers[1].author(),
+        vec![parent.certificate()],
+        &signers,
+        &epoch_state.verifier,
+    );
+
+    let network = Arc::new(MockPeers {
+        silent_peer: AccountAddress::ONE,
+        response: FetchResponse::new(1, vec![parent.clone()]),
+        requested: Mutex::new(vec![]),
+    });
+    let (fetch_service, fetch_requester, _, mut certified_node_waiter) = DagFetcherService::new(
+        epoch_state,
+        network.clone(),
+        dag.clone(),
+        TimeService::mock(),
+    );
+    let deduplicated = counters::DEDUPLICATED_FETCH_REQUESTS.get();
+
+    // the identical requests are collapsed into the first one
+    for _ in 0..3 {
+        assert_ok!(fetch_requester.request_for_certified_node(node.clone()));
+    }
+    assert_eq!(fetch_requester.pending_requests(), 1);
+    assert!(counters::DEDUPLICATED_FETCH_REQUESTS.get() >= deduplicated + 2);
+
+    // a single fetch completes all the waiters
+    tokio::spawn(fetch_service.start());
+    for _ in 0..3 {
+        assert_ok_eq!(certified_node_waiter.next().await.unwrap(), node.clone());
+    }
+    assert!(dag.read().exists(parent.metadata()));
+    assert_eq!(network.requested.lock().len(), 1);
+}
@@ -2,12 +2,37 @@
 
 use crate::dag::types::{CertifiedNode, Extensions, Node, NodeCertificate};
 use aptos_consensus_types::common::{Author, Payload, Round};
-use aptos_types::aggregate_signature::AggregateSignature;
+use aptos_types::{
+    aggregate_signature::{AggregateSignature, PartialSignatures},
+    validator_signer::ValidatorSigner,
+    validator_verifier::ValidatorVerifier,
+};
 
 pub(crate) fn new_certified_node(
     round: Round,
     author: Author,
     parents: Vec<NodeCertificate>,
+) -> CertifiedNode {
+    new_certified_node_with_payload(round, author, parents, Payload::empty(false))
+}
+
+pub(crate) fn new_certified_node_with_payload(
+    round: Round,
+    author: Author,
+    parents: Vec<NodeCertificate>,
+    payload: Payload,
+) -> CertifiedNode {
+    let node = Node::new(1, round, author, 0, payload, parents, Extensions::empty());
+    CertifiedNode::new(node, AggregateSignature::empty())
+}
+
+/// Certified node signed by `signers`, so that its certificate verifies.
+pub(crate) fn new_signed_certified_node(
+    round: Round,
+    author: Author,
+    parents: Vec<NodeCertificate>,
+    signers: &[ValidatorSigner],
+    verifier: &ValidatorVerifier,
 ) -> CertifiedNode {
     let node = Node::new(
         1,
@@ -18,7 +43,12 @@ pub(crate) fn new_certified_node(
         parents,
         Extensions::empty(),
     );
-    CertifiedNode::new(node, AggregateSignature::empty())
+    let mut partial_sigs = PartialSignatures::empty();
+    for signer in signers {
+        partial_sigs.add_signature(signer.author(), signer.sign(node.metadata()).unwrap());
+    }
+    let signatures = verifier.aggregate_signatures(&partial_sigs).unwrap();
+    CertifiedNode::new(node, signatures)
 }
 
 pub(crate) fn new_node(
@@ -2,7 +2,10 @@
 
 use super::dag_test;
 use crate::{
-    dag::{bootstrap::bootstrap_dag_for_test, dag_state_sync::StateSyncStatus},
+    dag::{
+        bootstrap::{bootstrap_dag_for_test, rb_max_backoff_and_timeout, ReliableBroadcastConfig},
+        dag_state_sync::StateSyncStatus,
+    },
     experimental::buffer_manager::OrderedBlocks,
     network::{IncomingDAGRequest, NetworkSender},
     network_interface::{ConsensusMsg, ConsensusNetworkClient, DIRECT_SEND, RPC},
@@ -13,6 +16,7 @@ use crate::{
 use aptos_channels::{aptos_channel, message_queues::QueueStyle};
 use aptos_config::network_id::{NetworkId, PeerNetworkId};
 use aptos_consensus_types::common::Author;
+use aptos_infallible::Mutex;
 use aptos_logger::debug;
 use aptos_network::{
     application::interface::NetworkClient,
@@ -34,19 +38,31 @@ use aptos_types::{
 use claims::assert_gt;
 use futures::{
     stream::{select, Select},
-    StreamExt,
+    Future, StreamExt,
 };
 use futures_channel::mpsc::UnboundedReceiver;
 use maplit::hashmap;
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use tokio::task::JoinHandle;
 
+type DagNetworkEvents =
+    Box<Select<NetworkEvents<ConsensusMsg>, aptos_channels::Receiver<Event<ConsensusMsg>>>>;
+
+/// A validator of the simulation. A crash drops the in-memory state of the validator, while its
+/// `DAGStorage` and network survive to recover it.
 struct DagBootstrapUnit {
     nh_task_handle: JoinHandle<StateSyncStatus>,
     df_task_handle: JoinHandle<()>,
-    dag_rpc_tx: aptos_channel::Sender<Author, IncomingDAGRequest>,
-    network_events:
-        Box<Select<NetworkEvents<ConsensusMsg>, aptos_channels::Receiver<Event<ConsensusMsg>>>>,
+    /// Not set while the validator is crashed, its incoming RPCs are dropped.
+    dag_rpc_tx: Arc<Mutex<Option<aptos_channel::Sender<Author, IncomingDAGRequest>>>>,
+    network_events: Option<DagNetworkEvents>,
+    self_peer: Author,
+    signer: ValidatorSigner,
+    epoch_state: Arc<EpochState>,
+    storage: Arc<MockStorage>,
+    dag_storage: Arc<dag_test::MockStorage>,
+    network: Arc<NetworkSender>,
+    time_service: TimeService,
 }
 
 impl DagBootstrapUnit {
@@ -57,70 +73,134 @@ impl DagBootstrapUnit {
         storage: Arc<MockStorage>,
         network: NetworkSender,
         time_service: TimeService,
-        network_events: Box<
-            Select<NetworkEvents<ConsensusMsg>, aptos_channels::Receiver<Event<ConsensusMsg>>>,
-        >,
+        network_events: DagNetworkEvents,
         all_signers: Vec<ValidatorSigner>,
     ) -> (Self, UnboundedReceiver<OrderedBlocks>) {
-        let epoch_state = EpochState {
+        let epoch_state = Arc::new(EpochState {
             epoch,
             verifier: storage.get_validator_set().into(),
-        };
+        });
         let ledger_info = generate_ledger_info_with_sig(&all_signers, storage.get_ledger_info());
-        let dag_storage = dag_test::MockStorage::new_with_ledger_info(ledger_info);
-
+        let dag_storage = Arc::new(dag_test::MockStorage::new_with_ledger_info(ledger_info));
         let network = Arc::new(network);
 
-        let payload_client = Arc::new(MockPayloadManager::new(None));
-        let payload_manager = Arc::new(PayloadManager::DirectMempool);
-
-        let state_computer = Arc::new(EmptyStateComputer {});
+        let (nh_task_handle, df_task_handle, dag_rpc_tx, ordered_nodes_rx) = Self::bootstrap(
+            self_peer,
+            signer.clone(),
+            epoch_state.clone(),
+            storage.clone(),
+            dag_storage.clone(),
+            network.clone(),
+            time_service.clone(),
+        );
 
-        let (nh_abort_handle, df_abort_handle, dag_rpc_tx, ordered_nodes_rx) =
-            bootstrap_dag_for_test(
+        (
+            Self {
+                nh_task_handle,
+                df_task_handle,
+                dag_rpc_tx: Arc::new(Mutex::new(Some(dag_rpc_tx))),
+                network_events: Some(network_events),
                 self_peer,
                 signer,
-                Arc::new(epoch_state),
-                storage.get_ledger_info(),
-                Arc::new(dag_storage),
-                network.clone(),
-                network.clone(),
-                network.clone(),
+                epoch_state,
+                storage,
+                dag_storage,
+                network,
                 time_service,
-                payload_manager,
-                payload_client,
-                state_computer,
-            );
-
-        (
-            Self {
-                nh_task_handle: nh_abort_handle,
-                df_task_handle: df_abort_handle,
-                dag_rpc_tx,
-                network_events,
             },
             ordered_nodes_rx,
         )
     }
 
-    async fn start(mut self) {
-        loop {
-            match self.network_events.next().await.unwrap() {
-                Event::RpcRequest(sender, msg, protocol, response_sender) => match msg {
-                    ConsensusMsg::DAGMessage(msg) => {
-                        debug!("handling RPC...");
-                        self.dag_rpc_tx.push(sender, IncomingDAGRequest {
-                            req: msg,
-                            sender,
-                            protocol,
-                            response_sender,
-                        })
+    fn bootstrap(
+        self_peer: Author,
+        signer: ValidatorSigner,
+        epoch_state: Arc<EpochState>,
+        storage: Arc<MockStorage>,
+        dag_storage: Arc<dag_test::MockStorage>,
+        network: Arc<NetworkSender>,
+        time_service: TimeService,
+    ) -> (
+        JoinHandle<StateSyncStatus>,
+        JoinHandle<()>,
+        aptos_channel::Sender<Author, IncomingDAGRequest>,
+        UnboundedReceiver<OrderedBlocks>,
+    ) {
+        let payload_client = Arc::new(MockPayloadManager::new(None));
+        let payload_manager = Arc::new(PayloadManager::DirectMempool);
+
+        let state_computer = Arc::new(EmptyStateComputer {});
+
+        bootstrap_dag_for_test(
+            self_peer,
+            signer,
+            epoch_state,
+            storage.get_ledger_info(),
+            dag_storage,
+            network.clone(),
+            network.clone(),
+            network,
+            time_service,
+            payload_manager,
+            payload_client,
+            state_computer,
+        )
+    }
+
+    /// Drops the in-memory state of the validator, as if its process crashed.
+    fn crash(&mut self) {
+        self.dag_rpc_tx.lock().take();
+        self.nh_task_handle.abort();
+        self.df_task_handle.abort();
+    }
+
+    /// Restarts the crashed validator from its storage, returns the receiver of its ordered blocks.
+    fn recover(&mut self) -> UnboundedReceiver<OrderedBlocks> {
+        let (nh_task_handle, df_task_handle, dag_rpc_tx, ordered_nodes_rx) = Self::bootstrap(
+            self.self_peer,
+            self.signer.clone(),
+            self.epoch_state.clone(),
+            self.storage.clone(),
+            self.dag_storage.clone(),
+            self.network.clone(),
+            self.time_service.clone(),
+        );
+        self.nh_task_handle = nh_task_handle;
+        self.df_task_handle = df_task_handle;
+        *self.dag_rpc_tx.lock() = Some(dag_rpc_tx);
+        ordered_nodes_rx
+    }
+
+    /// Returns the task delivering the network events to the validator, across crashes.
+    fn start(&mut self) -> impl Future<Output = ()> {
+        let mut network_events = self
+            .network_events
+            .take()
+            .expect("validator should only be started once");
+        let dag_rpc_tx = self.dag_rpc_tx.clone();
+        async move {
+            loop {
+                match network_events.next().await.unwrap() {
+                    Event::RpcRequest(sender, msg, protocol, response_sender) => match msg {
+                        ConsensusMsg::DAGMessage(msg) => {
+                            debug!("handling RPC...");
+                            match dag_rpc_tx.lock().as_ref() {
+                                Some(dag_rpc_tx) => dag_rpc_tx.push(sender, IncomingDAGRequest {
+                                    req: msg,
+                                    sender,
+                                    protocol,
+                                    response_sender,
+                                }),
+                                // a crashed validator doesn't respond
+                                None => Ok(()),
+                            }
+                        },
+                        _ => unreachable!("expected only DAG-related messages"),
                     },
-                    _ => unreachable!("expected only DAG-related messages"),
-                },
-                _ => panic!("Unexpected Network Event"),
+                    _ => panic!("Unexpected Network Event"),
+                }
+                .unwrap()
             }
-            .unwrap()
         }
     }
 }
@@ -130,10 +210,7 @@ fn create_network(
     id: usize,
     author: Author,
     validators: ValidatorVerifier,
-) -> (
-    NetworkSender,
-    Box<Select<NetworkEvents<ConsensusMsg>, aptos_channels::Receiver<Event<ConsensusMsg>>>>,
-) {
+) -> (NetworkSender, DagNetworkEvents) {
     let (network_reqs_tx, network_reqs_rx) = aptos_channel::new(QueueStyle::FIFO, 8, None);
     let (connection_reqs_tx, _) = aptos_channel::new(QueueStyle::FIFO, 8, None);
     let (consensus_tx, consensus_rx) = aptos_channel::new(QueueStyle::FIFO, 8, None);
@@ -213,8 +290,9 @@ async fn test_dag_e2e() {
     let mut playground = NetworkPlayground::new(runtime.handle().clone());
     let (signers, validators) = random_validator_verifier(num_nodes, None, false);
 
-    let (nodes, mut ordered_node_receivers) = bootstrap_nodes(&mut playground, signers, validators);
-    for node in nodes {
+    let (mut nodes, mut ordered_node_receivers) =
+        bootstrap_nodes(&mut playground, signers, validators);
+    for node in &mut nodes {
         runtime.spawn(node.start());
     }
 
@@ -236,3 +314,91 @@ async fn test_dag_e2e() {
     }
     runtime.shutdown_background();
 }
+
+#[tokio::test]
+async fn test_dag_crash_recovery() {
+    let num_nodes = 4;
+    let runtime = consensus_runtime();
+    let mut playground = NetworkPlayground::new(runtime.handle().clone());
+    let (signers, validators) = random_validator_verifier(num_nodes, None, false);
+
+    let (mut nodes, mut ordered_node_receivers) =
+        bootstrap_nodes(&mut playground, signers, validators);
+    for node in &mut nodes {
+        runtime.spawn(node.start());
+    }
+
+    runtime.spawn(playground.start());
+
+    // blocks ordered by the validators that never crash
+    let mut ordered_blocks = vec![];
+    for _ in 0..3 {
+        for receiver in &mut ordered_node_receivers {
+            receiver.next().await.unwrap();
+        }
+    }
+    let crashed = num_nodes - 1;
+    nodes[crashed].crash();
+    // the remaining validators hold a quorum and keep ordering
+    for _ in 0..6 {
+        let mut all_ordered = vec![];
+        for receiver in &mut ordered_node_receivers[..crashed] {
+            all_ordered.push(receiver.next().await.unwrap().ordered_blocks);
+        }
+        let first = all_ordered.first().unwrap();
+        for a in all_ordered.iter() {
+            assert_eq!(a, first);
+        }
+        ordered_blocks.push(first.clone());
+    }
+
+    // the recovered validator resumes from its storage and catches up with the blocks ordered while
+    // it was down
+    let mut recovered_receiver = nodes[crashed].recover();
+    let mut recovered_blocks = vec![];
+    while recovered_blocks.len() < 3 + ordered_blocks.len() {
+        recovered_blocks.push(recovered_receiver.next().await.unwrap().ordered_blocks);
+    }
+    assert_eq!(recovered_blocks[3..], ordered_blocks[..]);
+    runtime.shutdown_background();
+}
+
+#[test]
+fn test_rb_timeout_scales_with_validator_count() {
+    let (small_max_backoff, small_timeout) = rb_max_backoff_and_timeout(4);
+    let (large_max_backoff, large_timeout) = rb_max_backoff_and_timeout(400);
+    let (larger_max_backoff, larger_timeout) = rb_max_backoff_and_timeout(1000);
+
+    assert_gt!(large_timeout, small_timeout);
+    assert_gt!(larger_timeout, large_timeout);
+    assert_gt!(large_max_backoff, small_max_backoff);
+    assert_gt!(larger_max_backoff, large_max_backoff);
+}
+
+#[test]
+fn test_rb_config_backoff_policy() {
+    let millis =
+        |delays: &[u64]| -> Vec<_> { delays.iter().copied().map(Duration::from_millis).collect() };
+    // the default starts at 100ms and doubles, up to the cap of the validator set
+    let delays: Vec<_> = ReliableBroadcastConfig::default()
+        .backoff_policy(4)
+        .take(7)
+        .collect();
+    assert_eq!(delays, millis(&[100, 200, 400, 800, 1600, 3000, 3000]));
+
+    assert_eq!(
+        ReliableBroadcastConfig::default().rpc_timeout(400),
+        rb_max_backoff_and_timeout(400).1
+    );
+
+    let config = ReliableBroadcastConfig {
+        backoff_base_ms: 3,
+        backoff_factor: 10,
+        backoff_max_delay: Some(Duration::from_millis(500)),
+        rpc_timeout: Some(Duration::from_secs(2)),
+        max_concurrent_sends: None,
+    };
+    let delays: Vec<_> = config.backoff_policy(4).take(4).collect();
+    assert_eq!(delays, millis(&[30, 90, 270, 500]));
+    assert_eq!(config.rpc_timeout(400), Duration::from_secs(2));
+}
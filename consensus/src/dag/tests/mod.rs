@@ -1,6 +1,8 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 
+mod anchor_election_tests;
+mod bootstrap_tests;
 mod dag_driver_tests;
 mod dag_network_test;
 mod dag_state_sync_tests;
@@ -7,20 +7,34 @@ use crate::{
         anchor_election::RoundRobinAnchorElection,
         dag_state_sync::DAG_WINDOW,
         dag_store::Dag,
-        order_rule::OrderRule,
-        tests::{dag_test::MockStorage, helpers::generate_dag_nodes},
-        types::NodeMetadata,
+        order_rule::{OrderRule, TOrderRule},
+        storage::DAGStorage,
+        tests::{
+            dag_test::MockStorage,
+            helpers::{
+                generate_dag_nodes, new_certified_node_with_payload, new_signed_certified_node,
+            },
+        },
+        types::{AnchorCommitProof, Extensions, Node, NodeCertificate, NodeMetadata},
         CertifiedNode,
     },
     test_utils::placeholder_ledger_info,
 };
-use aptos_consensus_types::common::{Author, Round};
+use aptos_consensus_types::{
+    block::block_test_utils::random_payload,
+    common::{Author, Payload, Round, TransactionSummary},
+};
 use aptos_infallible::{Mutex, RwLock};
-use aptos_types::{epoch_state::EpochState, validator_verifier::random_validator_verifier};
+use aptos_types::{
+    aggregate_signature::AggregateSignature,
+    epoch_state::EpochState,
+    validator_signer::ValidatorSigner,
+    validator_verifier::{random_validator_verifier, ValidatorVerifier},
+};
 use async_trait::async_trait;
 use futures_channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
 use proptest::prelude::*;
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 /// Generate a virtual dag that first layer represents round
 /// second layer represents nodes, Some => node exist, None => not exist
@@ -95,9 +109,26 @@ impl OrderedNotifier for TestNotifier {
 fn create_order_rule(
     epoch_state: Arc<EpochState>,
     dag: Arc<RwLock<Dag>>,
+) -> (OrderRule, UnboundedReceiver<Vec<Arc<CertifiedNode>>>) {
+    create_order_rule_with_storage(epoch_state, dag, Arc::new(MockStorage::new()))
+}
+
+fn create_order_rule_with_storage(
+    epoch_state: Arc<EpochState>,
+    dag: Arc<RwLock<Dag>>,
+    storage: Arc<MockStorage>,
+) -> (OrderRule, UnboundedReceiver<Vec<Arc<CertifiedNode>>>) {
+    create_order_rule_with_window(epoch_state, dag, storage, DAG_WINDOW)
+}
+
+fn create_order_rule_with_window(
+    epoch_state: Arc<EpochState>,
+    dag: Arc<RwLock<Dag>>,
+    storage: Arc<MockStorage>,
+    dag_window: usize,
 ) -> (OrderRule, UnboundedReceiver<Vec<Arc<CertifiedNode>>>) {
     let ledger_info = placeholder_ledger_info();
-    let anchor_election = Box::new(RoundRobinAnchorElection::new(
+    let anchor_election = Arc::new(RoundRobinAnchorElection::new(
         epoch_state.verifier.get_ordered_account_addresses(),
     ));
     let (tx, rx) = unbounded();
@@ -106,9 +137,10 @@ fn create_order_rule(
             epoch_state,
             ledger_info,
             dag,
+            dag_window,
             anchor_election,
             Arc::new(TestNotifier { tx }),
-            Arc::new(MockStorage::new()),
+            storage,
         ),
         rx,
     )
@@ -263,3 +295,540 @@ fn test_order_rule_basic() {
         batch += 1;
     }
 }
+
+#[test]
+fn test_order_rule_window_decides_ordered_nodes() {
+    // round 2 node 0 is the only one linking (1, 3), and anchor (3, 1) links it
+    let dag = vec![
+        vec![Some(vec![]), Some(vec![]), Some(vec![]), Some(vec![])],
+        vec![
+            Some(vec![false, true, true, true]),
+            Some(vec![true, true, true, false]),
+            Some(vec![true, true, true, false]),
+            None,
+        ],
+        vec![
+            Some(vec![true, true, true]),
+            Some(vec![true, true, true]),
+            Some(vec![true, true, true]),
+            None,
+        ],
+        vec![
+            Some(vec![true, true, true]),
+            Some(vec![true, true, true]),
+            Some(vec![true, true, true]),
+            None,
+        ],
+    ];
+    let (_, validator_verifier) = random_validator_verifier(4, None, false);
+    let validators = validator_verifier.get_ordered_account_addresses();
+    let author_indexes = validator_verifier.address_to_validator_index().clone();
+    let nodes = generate_dag_nodes(&dag, &validators);
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let ordered_nodes = |dag_window: usize| {
+        let mut dag = Dag::new(
+            epoch_state.clone(),
+            Arc::new(MockStorage::new()),
+            0,
+            dag_window,
+        );
+        for node in nodes.iter().flatten().flatten() {
+            dag.add_node(node.clone()).unwrap();
+        }
+        let (mut order_rule, mut receiver) = create_order_rule_with_window(
+            epoch_state.clone(),
+            Arc::new(RwLock::new(dag)),
+            Arc::new(MockStorage::new()),
+            dag_window,
+        );
+        for node in nodes.iter().flatten().flatten() {
+            order_rule.process_new_node(node.metadata());
+        }
+        let mut ordered = vec![];
+        while let Ok(Some(ordered_nodes)) = receiver.try_next() {
+            ordered.extend(ordered_nodes.iter().map(|node| {
+                let metadata = node.metadata();
+                (
+                    metadata.round(),
+                    *author_indexes.get(metadata.author()).unwrap(),
+                )
+            }));
+        }
+        ordered
+    };
+
+    // validators with different windows order different nodes for the same anchors, so the
+    // window must be the same on all of them
+    let narrow = ordered_nodes(1);
+    let wide = ordered_nodes(2);
+    assert!(!narrow.contains(&(1, 3)));
+    assert!(wide.contains(&(1, 3)));
+    assert_ne!(narrow, wide);
+}
+
+#[test]
+fn test_author_contribution_stats() {
+    let (_, validator_verifier) = random_validator_verifier(4, None, false);
+    let validators = validator_verifier.get_ordered_account_addresses();
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let certificates = |nodes: &[CertifiedNode]| -> Vec<NodeCertificate> {
+        nodes
+            .iter()
+            .map(|node| NodeCertificate::new(node.metadata().clone(), AggregateSignature::empty()))
+            .collect()
+    };
+    // author i proposes i + 1 txns in round 1, everything after is empty
+    let round_1: Vec<_> = validators
+        .iter()
+        .enumerate()
+        .map(|(idx, author)| {
+            new_certified_node_with_payload(1, *author, vec![], random_payload(idx + 1))
+        })
+        .collect();
+    let round_2: Vec<_> = validators
+        .iter()
+        .map(|author| {
+            new_certified_node_with_payload(
+                2,
+                *author,
+                certificates(&round_1),
+                Payload::empty(false),
+            )
+        })
+        .collect();
+    // two votes for anchor (2, 1)
+    let round_3: Vec<_> = validators[..2]
+        .iter()
+        .map(|author| {
+            new_certified_node_with_payload(
+                3,
+                *author,
+                certificates(&round_2),
+                Payload::empty(false),
+            )
+        })
+        .collect();
+    let total_bytes: usize = round_1.iter().map(|node| node.payload().size()).sum();
+    let bytes = |idx: usize| round_1[idx].payload().size() as f64;
+
+    let mut dag = Dag::new(
+        epoch_state.clone(),
+        Arc::new(MockStorage::new()),
+        0,
+        DAG_WINDOW,
+    );
+    let all_nodes: Vec<_> = round_1.iter().chain(&round_2).chain(&round_3).collect();
+    for node in &all_nodes {
+        dag.add_node((*node).clone()).unwrap();
+    }
+    let (mut order_rule, mut receiver) = create_order_rule(epoch_state, Arc::new(RwLock::new(dag)));
+    for node in &all_nodes {
+        order_rule.process_new_node(node.metadata());
+    }
+    // anchor (1, 0) alone, then anchor (2, 1) with the rest of round 1
+    let mut num_batches = 0;
+    while let Ok(Some(_)) = receiver.try_next() {
+        num_batches += 1;
+    }
+    assert_eq!(num_batches, 2);
+
+    let assert_close = |a: f64, b: f64| assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+
+    let stats = order_rule.author_contribution_stats(2);
+    assert_eq!(stats.len(), 4);
+    for (idx, author) in validators.iter().enumerate() {
+        let contribution = stats.get(author).unwrap();
+        assert_eq!(contribution.num_txns, idx as u64 + 1);
+        assert_close(contribution.txns_fraction, (idx + 1) as f64 / 10.0);
+        assert_close(contribution.bytes_fraction, bytes(idx) / total_bytes as f64);
+    }
+
+    // the latest anchor excludes author 0's round 1 node
+    let stats = order_rule.author_contribution_stats(1);
+    assert!(!stats.contains_key(&validators[0]));
+    let window_bytes = total_bytes as f64 - bytes(0);
+    for (idx, author) in validators.iter().enumerate().skip(1) {
+        let contribution = stats.get(author).unwrap();
+        assert_eq!(contribution.num_txns, idx as u64 + 1);
+        assert_close(contribution.txns_fraction, (idx + 1) as f64 / 9.0);
+        assert_close(contribution.bytes_fraction, bytes(idx) / window_bytes);
+    }
+
+    assert!(order_rule.author_contribution_stats(0).is_empty());
+}
+
+#[test]
+fn test_unique_committed_txns() {
+    let (_, validator_verifier) = random_validator_verifier(4, None, false);
+    let validators = validator_verifier.get_ordered_account_addresses();
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let certificates = |nodes: &[CertifiedNode]| -> Vec<NodeCertificate> {
+        nodes
+            .iter()
+            .map(|node| NodeCertificate::new(node.metadata().clone(), AggregateSignature::empty()))
+            .collect()
+    };
+    let txns = match random_payload(4) {
+        Payload::DirectMempool(txns) => txns,
+        _ => unreachable!(),
+    };
+    let summaries = |idxs: &[usize]| -> Vec<TransactionSummary> {
+        idxs.iter()
+            .map(|idx| TransactionSummary::new(txns[*idx].sender(), txns[*idx].sequence_number()))
+            .collect()
+    };
+    // round 1 payloads overlap: author 1 repeats a txn of author 0 and one of author 2
+    let payloads = [vec![0, 1], vec![1, 2], vec![2], vec![3]];
+    let round_1: Vec<_> = validators
+        .iter()
+        .zip(&payloads)
+        .map(|(author, idxs)| {
+            let payload = idxs.iter().map(|idx| txns[*idx].clone()).collect();
+            new_certified_node_with_payload(1, *author, vec![], Payload::DirectMempool(payload))
+        })
+        .collect();
+    let round_2: Vec<_> = validators
+        .iter()
+        .map(|author| {
+            new_certified_node_with_payload(
+                2,
+                *author,
+                certificates(&round_1),
+                Payload::empty(false),
+            )
+        })
+        .collect();
+    // two votes for anchor (2, 1)
+    let round_3: Vec<_> = validators[..2]
+        .iter()
+        .map(|author| {
+            new_certified_node_with_payload(
+                3,
+                *author,
+                certificates(&round_2),
+                Payload::empty(false),
+            )
+        })
+        .collect();
+
+    let mut dag = Dag::new(
+        epoch_state.clone(),
+        Arc::new(MockStorage::new()),
+        0,
+        DAG_WINDOW,
+    );
+    let all_nodes: Vec<_> = round_1.iter().chain(&round_2).chain(&round_3).collect();
+    for node in &all_nodes {
+        dag.add_node((*node).clone()).unwrap();
+    }
+    let (mut order_rule, _receiver) = create_order_rule(epoch_state, Arc::new(RwLock::new(dag)));
+    for node in &all_nodes {
+        order_rule.process_new_node(node.metadata());
+    }
+
+    // anchor (1, 0) is ordered alone, then anchor (2, 1) orders the rest of round 1 with
+    // authors in descending order, so author 2 includes txn 2 before author 1
+    let unique_txns = |node: &CertifiedNode| {
+        order_rule
+            .unique_committed_txns(&node.id())
+            .map(|txns| txns.to_vec())
+    };
+    assert_eq!(unique_txns(&round_1[0]), Some(summaries(&[0, 1])));
+    assert_eq!(unique_txns(&round_1[1]), Some(summaries(&[])));
+    assert_eq!(unique_txns(&round_1[2]), Some(summaries(&[2])));
+    assert_eq!(unique_txns(&round_1[3]), Some(summaries(&[3])));
+    assert_eq!(unique_txns(&round_2[1]), Some(summaries(&[])));
+    // not ordered yet
+    assert_eq!(unique_txns(&round_2[0]), None);
+    assert_eq!(unique_txns(&round_3[0]), None);
+}
+
+#[test]
+fn test_commit_throughput() {
+    let (_, validator_verifier) = random_validator_verifier(4, None, false);
+    let validators = validator_verifier.get_ordered_account_addresses();
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let secs = |secs: u64| secs * 1_000_000;
+    let new_node = |round, author, timestamp, parents: &[CertifiedNode], payload| {
+        let parents = parents
+            .iter()
+            .map(|node| NodeCertificate::new(node.metadata().clone(), AggregateSignature::empty()))
+            .collect();
+        let node = Node::new(
+            1,
+            round,
+            author,
+            timestamp,
+            payload,
+            parents,
+            Extensions::empty(),
+        );
+        CertifiedNode::new(node, AggregateSignature::empty())
+    };
+    // author i proposes i + 1 txns in round 1 at 1s, rounds 2 and 3 are empty at 3s and 4s
+    let round_1: Vec<_> = validators
+        .iter()
+        .enumerate()
+        .map(|(idx, author)| new_node(1, *author, secs(1), &[], random_payload(idx + 1)))
+        .collect();
+    let round_2: Vec<_> = validators
+        .iter()
+        .map(|author| new_node(2, *author, secs(3), &round_1, Payload::empty(false)))
+        .collect();
+    // two votes for anchor (2, 1)
+    let round_3: Vec<_> = validators[..2]
+        .iter()
+        .map(|author| new_node(3, *author, secs(4), &round_2, Payload::empty(false)))
+        .collect();
+
+    let mut dag = Dag::new(
+        epoch_state.clone(),
+        Arc::new(MockStorage::new()),
+        0,
+        DAG_WINDOW,
+    );
+    let all_nodes: Vec<_> = round_1.iter().chain(&round_2).chain(&round_3).collect();
+    for node in &all_nodes {
+        dag.add_node((*node).clone()).unwrap();
+    }
+    let (mut order_rule, _receiver) = create_order_rule(epoch_state, Arc::new(RwLock::new(dag)));
+    assert_eq!(order_rule.commit_throughput(Duration::from_secs(1)), 0.0);
+    for node in &all_nodes {
+        order_rule.process_new_node(node.metadata());
+    }
+
+    // anchor (1, 0) commits 1 txn at 1s, then anchor (2, 1) commits the other 9 at 3s
+    assert_eq!(order_rule.commit_throughput(Duration::from_secs(1)), 9.0);
+    assert_eq!(order_rule.commit_throughput(Duration::from_secs(2)), 4.5);
+    assert_eq!(order_rule.commit_throughput(Duration::from_secs(4)), 2.5);
+    assert_eq!(order_rule.commit_throughput(Duration::ZERO), 0.0);
+}
+
+#[test]
+fn test_commit_proof_subscription() {
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let verifier = &epoch_state.verifier;
+
+    let round_1: Vec<_> = signers
+        .iter()
+        .map(|signer| {
+            new_signed_certified_node(1, signer.author(), vec![], &signers[..3], verifier)
+        })
+        .collect();
+    let parents: Vec<_> = round_1.iter().map(|node| node.certificate()).collect();
+    let round_2: Vec<_> = signers
+        .iter()
+        .map(|signer| {
+            new_signed_certified_node(2, signer.author(), parents.clone(), &signers[..3], verifier)
+        })
+        .collect();
+
+    let mut dag = Dag::new(
+        epoch_state.clone(),
+        Arc::new(MockStorage::new()),
+        0,
+        DAG_WINDOW,
+    );
+    for node in round_1.iter().chain(&round_2) {
+        dag.add_node(node.clone()).unwrap();
+    }
+    let (mut order_rule, _receiver) =
+        create_order_rule(epoch_state.clone(), Arc::new(RwLock::new(dag)));
+    let mut proofs = order_rule.subscribe_commit_proofs();
+    order_rule.process_new_node(round_2[0].metadata());
+
+    let proof = proofs.try_next().unwrap().unwrap();
+    assert_eq!(proof.anchor().metadata().round(), 1);
+    assert_eq!(proof.votes().len(), 4);
+    assert!(proof.verify(verifier).is_ok());
+    assert!(proofs.try_next().is_err());
+
+    // a proof without a quorum of votes doesn't verify
+    let weak_proof = AnchorCommitProof::new(proof.anchor().clone(), proof.votes()[..1].to_vec());
+    assert!(weak_proof.verify(verifier).is_err());
+    // neither does a forged anchor certificate
+    let forged_proof = AnchorCommitProof::new(
+        NodeCertificate::new(
+            proof.anchor().metadata().clone(),
+            AggregateSignature::empty(),
+        ),
+        proof.votes().to_vec(),
+    );
+    assert!(forged_proof.verify(verifier).is_err());
+}
+
+/// Fully connected rounds `1..=num_rounds` of signed certified nodes, one node per signer.
+fn new_signed_rounds(
+    signers: &[ValidatorSigner],
+    verifier: &ValidatorVerifier,
+    num_rounds: Round,
+) -> Vec<Vec<CertifiedNode>> {
+    let mut rounds: Vec<Vec<CertifiedNode>> = vec![];
+    for round in 1..=num_rounds {
+        let parents: Vec<_> = rounds
+            .last()
+            .map(|nodes| nodes.iter().map(|node| node.certificate()).collect())
+            .unwrap_or_default();
+        rounds.push(
+            signers
+                .iter()
+                .map(|signer| {
+                    new_signed_certified_node(
+                        round,
+                        signer.author(),
+                        parents.clone(),
+                        &signers[..3],
+                        verifier,
+                    )
+                })
+                .collect(),
+        );
+    }
+    rounds
+}
+
+#[test]
+fn test_commit_log() {
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let verifier = &epoch_state.verifier;
+    let rounds = new_signed_rounds(&signers, verifier, 5);
+
+    let storage = Arc::new(MockStorage::new());
+    let dag = Arc::new(RwLock::new(Dag::new(
+        epoch_state.clone(),
+        storage.clone(),
+        0,
+        DAG_WINDOW,
+    )));
+    let (mut order_rule, _receiver) =
+        create_order_rule_with_storage(epoch_state.clone(), dag.clone(), storage.clone());
+    for node in rounds.iter().flatten() {
+        dag.write().add_node(node.clone()).unwrap();
+        order_rule.process_new_node(node.metadata());
+    }
+
+    // anchors of rounds 1 and 3 are committed, the one of round 5 has no votes yet
+    let commit_log = storage.get_commit_log().unwrap();
+    let committed: Vec<_> = commit_log
+        .iter()
+        .map(|entry| entry.anchor().round())
+        .collect();
+    assert_eq!(committed, vec![1, 3]);
+    for entry in &commit_log {
+        let proof = entry.proof().unwrap();
+        let anchor = proof.anchor().metadata();
+        let node = rounds[anchor.round() as usize - 1]
+            .iter()
+            .find(|node| node.author() == anchor.author())
+            .unwrap();
+        assert_eq!(*anchor.digest(), node.digest());
+        assert!(proof.verify(verifier).is_ok());
+    }
+}
+
+#[test]
+fn test_subscribe_ordered_anchors() {
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let rounds = new_signed_rounds(&signers, &epoch_state.verifier, 7);
+
+    let dag = Arc::new(RwLock::new(Dag::new(
+        epoch_state.clone(),
+        Arc::new(MockStorage::new()),
+        0,
+        DAG_WINDOW,
+    )));
+    let (mut order_rule, mut receiver) = create_order_rule(epoch_state, dag.clone());
+    let mut ordered_anchors = order_rule.subscribe_ordered_anchors();
+    for node in rounds.iter().flatten() {
+        dag.write().add_node(node.clone()).unwrap();
+        order_rule.process_new_node(node.metadata());
+    }
+
+    let mut emitted = vec![];
+    while let Ok(Some(ordered_anchor)) = ordered_anchors.try_next() {
+        emitted.push(ordered_anchor);
+    }
+    let mut committed = vec![];
+    while let Ok(Some(ordered_nodes)) = receiver.try_next() {
+        committed.push(ordered_nodes);
+    }
+    // anchors of rounds 1, 3 and 5 are ordered, the one of round 7 has no votes yet
+    let anchor_rounds: Vec<_> = emitted
+        .iter()
+        .map(|ordered| ordered.anchor.round())
+        .collect();
+    assert_eq!(anchor_rounds, vec![1, 3, 5]);
+    assert_eq!(emitted.len(), committed.len());
+    for (ordered_anchor, ordered_nodes) in emitted.iter().zip(&committed) {
+        assert_eq!(ordered_anchor.ordered_nodes, *ordered_nodes);
+        assert_eq!(
+            ordered_anchor.ordered_nodes.last(),
+            Some(&ordered_anchor.anchor)
+        );
+        assert!(ordered_anchor.failed_authors.is_empty());
+    }
+}
+
+#[test]
+fn test_commit_log_compaction() {
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let rounds = new_signed_rounds(&signers, &epoch_state.verifier, 21);
+
+    let storage = Arc::new(MockStorage::new());
+    let dag = Arc::new(RwLock::new(Dag::new(
+        epoch_state.clone(),
+        storage.clone(),
+        0,
+        DAG_WINDOW,
+    )));
+    let (order_rule, _receiver) =
+        create_order_rule_with_storage(epoch_state.clone(), dag.clone(), storage.clone());
+    let mut order_rule = order_rule.with_commit_log_retention(2);
+    for node in rounds.iter().flatten() {
+        dag.write().add_node(node.clone()).unwrap();
+        order_rule.process_new_node(node.metadata());
+    }
+
+    // the anchors of the odd rounds up to 19 are committed, only the 2 most recent keep their proof
+    let commit_log = storage.get_commit_log().unwrap();
+    let committed: Vec<_> = commit_log
+        .iter()
+        .map(|entry| entry.anchor().round())
+        .collect();
+    assert_eq!(committed, (1..=19).step_by(2).collect::<Vec<_>>());
+    let (compacted, recent) = commit_log.split_at(8);
+    assert!(compacted.iter().all(|entry| entry.proof().is_none()));
+    for entry in recent {
+        let proof = entry.proof().unwrap();
+        assert_eq!(proof.anchor().metadata(), entry.anchor());
+        assert!(proof.verify(&epoch_state.verifier).is_ok());
+    }
+}
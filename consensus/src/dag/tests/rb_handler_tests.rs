@@ -2,7 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::dag::{
-    dag_fetcher::TFetchRequester,
+    dag_driver::GENESIS_ROUND,
+    dag_fetcher::{FetchRequesterError, TFetchRequester},
     dag_state_sync::DAG_WINDOW,
     dag_store::Dag,
     rb_handler::{NodeBroadcastHandleError, NodeBroadcastHandler},
@@ -23,11 +24,14 @@ use std::{collections::BTreeMap, sync::Arc};
 struct MockFetchRequester {}
 
 impl TFetchRequester for MockFetchRequester {
-    fn request_for_node(&self, _node: crate::dag::Node) -> anyhow::Result<()> {
+    fn request_for_node(&self, _node: crate::dag::Node) -> Result<(), FetchRequesterError> {
         Ok(())
     }
 
-    fn request_for_certified_node(&self, _node: crate::dag::CertifiedNode) -> anyhow::Result<()> {
+    fn request_for_certified_node(
+        &self,
+        _node: crate::dag::CertifiedNode,
+    ) -> Result<(), FetchRequesterError> {
         Ok(())
     }
 }
@@ -159,6 +163,46 @@ async fn test_node_broadcast_receiver_failure() {
     );
 }
 
+#[tokio::test]
+async fn test_node_broadcast_receiver_parentless_node() {
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let signers: Vec<_> = signers.into_iter().map(Arc::new).collect();
+
+    let storage = Arc::new(MockStorage::new());
+    let dag = Arc::new(RwLock::new(Dag::new(
+        epoch_state.clone(),
+        storage.clone(),
+        0,
+        DAG_WINDOW,
+    )));
+    let mut rb_receiver = NodeBroadcastHandler::new(
+        dag,
+        signers[3].clone(),
+        epoch_state,
+        storage.clone(),
+        Arc::new(MockFetchRequester {}),
+    );
+
+    // a node above the genesis round must link to the round below it
+    let parentless_node = new_node(5, 10, signers[0].author(), vec![]);
+    assert_eq!(
+        rb_receiver
+            .process(parentless_node)
+            .await
+            .unwrap_err()
+            .to_string(),
+        NodeBroadcastHandleError::ParentlessNode(5).to_string(),
+    );
+    assert!(storage.get_votes().unwrap().is_empty());
+
+    let genesis_node = new_node(GENESIS_ROUND, 10, signers[0].author(), vec![]);
+    assert_ok!(rb_receiver.process(genesis_node).await);
+}
+
 #[tokio::test]
 async fn test_node_broadcast_receiver_storage() {
     let (signers, validator_verifier) = random_validator_verifier(4, None, false);
@@ -2,19 +2,24 @@
 
 use super::helpers::new_node;
 use crate::dag::{
-    tests::helpers::new_certified_node,
+    storage::DAGStorage,
+    tests::{dag_test::MockStorage, helpers::new_certified_node},
     types::{
-        CertifiedNode, DAGNetworkMessage, DagSnapshotBitmask, Extensions, Node, NodeCertificate,
-        NodeMetadata, RemoteFetchRequest, TDAGMessage,
+        CertificateAckState, CertifiedAck, CertifiedNode, DAGNetworkMessage, DagSnapshotBitmask,
+        Extensions, Node, NodeCertificate, NodeMetadata, RemoteFetchRequest, SignatureBuilder,
+        TDAGMessage,
     },
+    Vote,
 };
-use aptos_consensus_types::common::Payload;
+use aptos_consensus_types::{block::block_test_utils::random_payload, common::Payload};
 use aptos_crypto::HashValue;
+use aptos_reliable_broadcast::BroadcastStatus;
 use aptos_types::{
-    aggregate_signature::AggregateSignature, validator_verifier::random_validator_verifier,
+    aggregate_signature::AggregateSignature, block_info::BlockInfo, epoch_state::EpochState,
+    validator_signer::ValidatorSigner, validator_verifier::random_validator_verifier,
 };
-use claims::assert_ok;
-use std::vec;
+use claims::{assert_ok, assert_ok_eq};
+use std::{sync::Arc, vec};
 
 #[test]
 fn test_node_verify() {
@@ -57,6 +62,96 @@ fn test_node_verify() {
     );
 }
 
+#[test]
+fn test_node_verify_weak_links() {
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let cert =
+        |node: &Node| NodeCertificate::new(node.metadata().clone(), AggregateSignature::empty());
+    let new_node_with_weak_links = |round, parents, weak_links| {
+        Node::new(
+            0,
+            round,
+            signers[0].author(),
+            0,
+            Payload::empty(false),
+            parents,
+            Extensions::with_weak_links(weak_links),
+        )
+    };
+
+    let first_round_nodes: Vec<_> = signers
+        .iter()
+        .map(|signer| new_node(1, 10, signer.author(), vec![]))
+        .collect();
+    let first_round_parents: Vec<_> = first_round_nodes[..3].iter().map(cert).collect();
+    let second_round_nodes: Vec<_> = signers[..3]
+        .iter()
+        .map(|signer| new_node(2, 20, signer.author(), first_round_parents.clone()))
+        .collect();
+    let parents: Vec<_> = second_round_nodes.iter().map(cert).collect();
+
+    // weak links to the nodes below the strong links
+    let node = new_node_with_weak_links(
+        3,
+        parents.clone(),
+        vec![first_round_nodes[3].metadata().clone()],
+    );
+    assert_ok!(node.verify(&validator_verifier));
+
+    // weak links can't be in the round of the strong links
+    let node = new_node_with_weak_links(3, parents, vec![second_round_nodes[0].metadata().clone()]);
+    assert_eq!(
+        node.verify(&validator_verifier).unwrap_err().to_string(),
+        "invalid weak link round"
+    );
+
+    // nor for a round 1 node
+    let node = new_node_with_weak_links(1, vec![], vec![first_round_nodes[3].metadata().clone()]);
+    assert_eq!(
+        node.verify(&validator_verifier).unwrap_err().to_string(),
+        "invalid weak links for round 1"
+    );
+}
+
+#[test]
+fn test_extensions_serialization() {
+    let (signers, _) = random_validator_verifier(2, None, false);
+    let weak_link = new_node(1, 10, signers[1].author(), vec![]);
+    let weak_links = vec![weak_link.metadata().clone()];
+
+    // nodes without weak links serialize as before
+    assert_eq!(Extensions::with_weak_links(vec![]), Extensions::empty());
+    assert_eq!(bcs::to_bytes(&Extensions::empty()).unwrap(), vec![0]);
+
+    let extensions = Extensions::with_weak_links(weak_links.clone());
+    let bytes = bcs::to_bytes(&extensions).unwrap();
+    let deserialized: Extensions = bcs::from_bytes(&bytes).unwrap();
+    assert_eq!(deserialized, extensions);
+    assert_eq!(deserialized.weak_links(), weak_links.as_slice());
+
+    let new_node_with_extensions = |extensions| {
+        Node::new(
+            0,
+            3,
+            signers[0].author(),
+            30,
+            Payload::empty(false),
+            vec![],
+            extensions,
+        )
+    };
+    let node = new_node_with_extensions(extensions);
+    let bytes = bcs::to_bytes(&node).unwrap();
+    let deserialized: Node = bcs::from_bytes(&bytes).unwrap();
+    assert_eq!(deserialized, node);
+    assert_eq!(deserialized.weak_links(), weak_links.as_slice());
+    // the weak links are covered by the digest
+    assert_ne!(
+        node.digest(),
+        new_node_with_extensions(Extensions::empty()).digest()
+    );
+}
+
 #[test]
 fn test_certified_node_verify() {
     let (signers, validator_verifier) = random_validator_verifier(4, None, false);
@@ -168,3 +263,141 @@ fn test_dag_network_message() {
         "DAGNetworkMessage { epoch: 2, data: \"1414141414141414141414141414141414141414\" }"
     );
 }
+
+#[test]
+fn test_certificate_ack_state_ignores_duplicates() {
+    let (signers, _) = random_validator_verifier(2, None, false);
+    let (first, second) = (signers[0].author(), signers[1].author());
+    let mut ack_state = CertificateAckState::new(2);
+
+    assert_ok_eq!(ack_state.add(first, CertifiedAck::new(1)), None);
+    assert_ok_eq!(ack_state.add(first, CertifiedAck::new(1)), None);
+    assert_eq!(ack_state.num_received(), 1);
+
+    assert_ok_eq!(ack_state.add(second, CertifiedAck::new(1)), Some(()));
+    assert_eq!(ack_state.num_received(), 2);
+    // a late duplicate does not complete the broadcast a second time
+    assert_ok_eq!(ack_state.add(second, CertifiedAck::new(1)), None);
+}
+
+#[test]
+fn test_certificate_ack_state_ledger_info_disagreement() {
+    let (signers, _) = random_validator_verifier(4, None, false);
+    let commit_info = BlockInfo::random_with_epoch(1, 10);
+    let mut ack_state = CertificateAckState::new(4).with_commit_info(commit_info.clone());
+
+    let ack = |commit_info: BlockInfo| CertifiedAck::new(1).with_commit_info(commit_info);
+    assert_ok_eq!(
+        ack_state.add(signers[0].author(), ack(commit_info.clone())),
+        None
+    );
+    // a different block committed in the same round is a disagreement
+    let forked = BlockInfo::random_with_epoch(1, 10);
+    assert_ok_eq!(ack_state.add(signers[1].author(), ack(forked)), None);
+    // a peer that is behind can't be compared
+    assert_ok_eq!(
+        ack_state.add(signers[2].author(), ack(BlockInfo::random_with_epoch(1, 9))),
+        None
+    );
+    assert_ok_eq!(
+        ack_state.add(signers[3].author(), CertifiedAck::new(1)),
+        Some(())
+    );
+
+    assert_eq!(
+        ack_state.ledger_info_agreed().collect::<Vec<_>>(),
+        vec![&signers[0].author()]
+    );
+    assert_eq!(
+        ack_state.ledger_info_disagreed().collect::<Vec<_>>(),
+        vec![&signers[1].author()]
+    );
+}
+
+#[test]
+fn test_signature_builder_resumes_from_checkpoint() {
+    let (signers, validator_verifier) = random_validator_verifier(4, None, false);
+    let validators = validator_verifier.get_ordered_account_addresses();
+    let epoch_state = Arc::new(EpochState {
+        epoch: 1,
+        verifier: validator_verifier,
+    });
+    let storage = Arc::new(MockStorage::new());
+    let node = new_node(1, 0, signers[0].author(), vec![]);
+    let vote = |node: &Node, signer: &ValidatorSigner| {
+        Vote::new(node.metadata().clone(), node.sign_vote(signer).unwrap())
+    };
+
+    let mut signature_builder = SignatureBuilder::new(node.metadata().clone(), epoch_state.clone())
+        .with_checkpoint(storage.clone(), 2);
+    assert_ok_eq!(
+        signature_builder.add(signers[0].author(), vote(&node, &signers[0])),
+        None
+    );
+    // a duplicate signature doesn't count towards the next checkpoint
+    assert_ok_eq!(
+        signature_builder.add(signers[0].author(), vote(&node, &signers[0])),
+        None
+    );
+    assert_eq!(storage.get_broadcast_progress().unwrap(), None);
+    assert_ok_eq!(
+        signature_builder.add(signers[1].author(), vote(&node, &signers[1])),
+        None
+    );
+    let progress = storage.get_broadcast_progress().unwrap().unwrap();
+    assert_eq!(progress.metadata(), node.metadata());
+    assert_eq!(progress.signatures().len(), 2);
+
+    // after a restart, a single new signature completes the quorum
+    let mut signature_builder = SignatureBuilder::new(node.metadata().clone(), epoch_state.clone())
+        .with_progress(progress.clone());
+    let certificate = signature_builder
+        .add(signers[2].author(), vote(&node, &signers[2]))
+        .unwrap()
+        .unwrap();
+    assert_eq!(certificate.signers(&validators).len(), 3);
+
+    // the progress of another node is ignored
+    let other_node = new_node(2, 0, signers[0].author(), vec![]);
+    let mut signature_builder =
+        SignatureBuilder::new(other_node.metadata().clone(), epoch_state).with_progress(progress);
+    assert_ok_eq!(
+        signature_builder.add(signers[2].author(), vote(&other_node, &signers[2])),
+        None
+    );
+}
+
+#[test]
+fn test_shuffled_payload() {
+    let (signers, _) = random_validator_verifier(1, None, false);
+    let payload = random_payload(20);
+    let new_node = |timestamp| {
+        Node::new(
+            1,
+            1,
+            signers[0].author(),
+            timestamp,
+            payload.clone(),
+            vec![],
+            Extensions::empty(),
+        )
+    };
+    let node = new_node(0);
+
+    // the same digest gives the same order, on any validator
+    let shuffled = node.shuffled_payload();
+    assert_eq!(node.clone().shuffled_payload(), shuffled);
+    let txns = |payload: Payload| match payload {
+        Payload::DirectMempool(mut txns) => {
+            txns.sort_by_key(|txn| txn.sequence_number());
+            txns
+        },
+        Payload::InQuorumStore(_) => unreachable!(),
+    };
+    assert_eq!(txns(shuffled.clone()), txns(payload.clone()));
+
+    // a different digest gives a different order
+    let other_node = new_node(1);
+    assert_ne!(other_node.digest(), node.digest());
+    assert_ne!(other_node.shuffled_payload(), shuffled);
+}
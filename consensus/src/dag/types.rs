@@ -1,7 +1,11 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{network::TConsensusMsg, network_interface::ConsensusMsg};
+use crate::{
+    dag::{counters, storage::DAGStorage},
+    network::TConsensusMsg,
+    network_interface::ConsensusMsg,
+};
 use anyhow::{bail, ensure};
 use aptos_consensus_types::common::{Author, Payload, Round};
 use aptos_crypto::{
@@ -12,18 +16,21 @@ use aptos_crypto::{
 };
 use aptos_crypto_derive::{BCSCryptoHash, CryptoHasher};
 use aptos_enum_conversion_derive::EnumConversion;
+use aptos_logger::warn;
 use aptos_reliable_broadcast::{BroadcastStatus, RBMessage};
 use aptos_types::{
     aggregate_signature::{AggregateSignature, PartialSignatures},
+    block_info::BlockInfo,
     epoch_state::EpochState,
     ledger_info::LedgerInfoWithSignatures,
     validator_signer::ValidatorSigner,
     validator_verifier::ValidatorVerifier,
 };
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
 use serde::{Deserialize, Serialize};
 use std::{
     cmp::min,
-    collections::HashSet,
+    collections::{BTreeMap, HashSet},
     fmt::{Display, Formatter},
     ops::Deref,
     sync::Arc,
@@ -48,6 +55,9 @@ impl TDAGMessage for CertifiedAck {
 #[derive(Clone, Serialize, Deserialize, CryptoHasher, Debug, PartialEq)]
 pub enum Extensions {
     Empty,
+    /// Links to nodes of earlier rounds that the strong links don't reach, so that they still get
+    /// ordered. They don't count as votes for the anchors.
+    WeakLinks(Vec<NodeMetadata>),
     // Reserved for future extensions such as randomness shares
 }
 
@@ -55,6 +65,21 @@ impl Extensions {
     pub fn empty() -> Self {
         Self::Empty
     }
+
+    pub fn with_weak_links(weak_links: Vec<NodeMetadata>) -> Self {
+        if weak_links.is_empty() {
+            Self::Empty
+        } else {
+            Self::WeakLinks(weak_links)
+        }
+    }
+
+    pub fn weak_links(&self) -> &[NodeMetadata] {
+        match self {
+            Self::Empty => &[],
+            Self::WeakLinks(weak_links) => weak_links,
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -260,6 +285,14 @@ impl Node {
         self.parents().iter().map(|cert| &cert.metadata)
     }
 
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub fn weak_links(&self) -> &[NodeMetadata] {
+        self.extensions.weak_links()
+    }
+
     pub fn author(&self) -> &Author {
         self.metadata.author()
     }
@@ -283,6 +316,22 @@ impl Node {
     pub fn payload(&self) -> &Payload {
         &self.payload
     }
+
+    /// The payload shuffled by an RNG seeded with the digest of the node, so that every validator
+    /// derives the same order while its author can't pick it without changing the node.
+    pub fn shuffled_payload(&self) -> Payload {
+        let mut rng = StdRng::from_seed(*self.digest());
+        match self.payload.clone() {
+            Payload::DirectMempool(mut txns) => {
+                txns.shuffle(&mut rng);
+                Payload::DirectMempool(txns)
+            },
+            Payload::InQuorumStore(mut proof_with_data) => {
+                proof_with_data.proofs.shuffle(&mut rng);
+                Payload::InQuorumStore(proof_with_data)
+            },
+        }
+    }
 }
 
 impl TDAGMessage for Node {
@@ -296,6 +345,10 @@ impl TDAGMessage for Node {
 
         if current_round == 1 {
             ensure!(self.parents().is_empty(), "invalid parents for round 1");
+            ensure!(
+                self.weak_links().is_empty(),
+                "invalid weak links for round 1"
+            );
             return Ok(());
         }
 
@@ -307,6 +360,13 @@ impl TDAGMessage for Node {
                 .all(|parent| parent.metadata().round() == prev_round),
             "invalid parent round"
         );
+        // weak links reference the nodes below the strong links
+        ensure!(
+            self.weak_links()
+                .iter()
+                .all(|weak_link| weak_link.round() < prev_round),
+            "invalid weak link round"
+        );
 
         ensure!(
             verifier
@@ -397,6 +457,81 @@ impl NodeCertificate {
     }
 }
 
+/// Compact proof that an anchor is committed, suitable for light client verification: the anchor
+/// certificate and the certificates of the next round nodes voting for it.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct AnchorCommitProof {
+    anchor: NodeCertificate,
+    votes: Vec<NodeCertificate>,
+}
+
+impl AnchorCommitProof {
+    pub fn new(anchor: NodeCertificate, votes: Vec<NodeCertificate>) -> Self {
+        Self { anchor, votes }
+    }
+
+    pub fn anchor(&self) -> &NodeCertificate {
+        &self.anchor
+    }
+
+    pub fn votes(&self) -> &[NodeCertificate] {
+        &self.votes
+    }
+
+    pub fn verify(&self, verifier: &ValidatorVerifier) -> anyhow::Result<()> {
+        self.anchor.verify(verifier)?;
+        let anchor_metadata = self.anchor.metadata();
+        let mut voters = HashSet::new();
+        for vote in &self.votes {
+            let vote_metadata = vote.metadata();
+            ensure!(
+                vote_metadata.epoch() == anchor_metadata.epoch()
+                    && vote_metadata.round() == anchor_metadata.round() + 1,
+                "vote {} is not from the round after anchor {}",
+                vote_metadata.digest(),
+                anchor_metadata.digest()
+            );
+            ensure!(
+                voters.insert(*vote_metadata.author()),
+                "duplicate vote from {}",
+                vote_metadata.author()
+            );
+            vote.verify(verifier)?;
+        }
+        verifier.check_voting_power(voters.iter(), false)?;
+        Ok(())
+    }
+}
+
+/// An entry of the commit log. Older entries are compacted down to the metadata of the anchor, the
+/// most recent ones keep the full commit proof.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub enum CommitLogEntry {
+    Full(AnchorCommitProof),
+    Compacted(NodeMetadata),
+}
+
+impl CommitLogEntry {
+    pub fn anchor(&self) -> &NodeMetadata {
+        match self {
+            CommitLogEntry::Full(proof) => proof.anchor().metadata(),
+            CommitLogEntry::Compacted(anchor) => anchor,
+        }
+    }
+
+    /// The commit proof, unless the entry was compacted.
+    pub fn proof(&self) -> Option<&AnchorCommitProof> {
+        match self {
+            CommitLogEntry::Full(proof) => Some(proof),
+            CommitLogEntry::Compacted(_) => None,
+        }
+    }
+
+    pub fn compact(self) -> Self {
+        CommitLogEntry::Compacted(self.anchor().clone())
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct CertifiedNode {
     node: Node,
@@ -489,10 +624,38 @@ impl Vote {
     }
 }
 
+/// The signatures collected so far by the broadcast of a node, checkpointed so that a broadcast
+/// interrupted by a restart resumes with them.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BroadcastProgress {
+    metadata: NodeMetadata,
+    signatures: BTreeMap<Author, Signature>,
+}
+
+impl BroadcastProgress {
+    pub fn new(metadata: NodeMetadata, signatures: BTreeMap<Author, Signature>) -> Self {
+        Self {
+            metadata,
+            signatures,
+        }
+    }
+
+    pub fn metadata(&self) -> &NodeMetadata {
+        &self.metadata
+    }
+
+    pub fn signatures(&self) -> &BTreeMap<Author, Signature> {
+        &self.signatures
+    }
+}
+
 pub struct SignatureBuilder {
     metadata: NodeMetadata,
     partial_signatures: PartialSignatures,
     epoch_state: Arc<EpochState>,
+    /// Where to checkpoint the progress, and every how many new signatures.
+    checkpoint: Option<(Arc<dyn DAGStorage>, usize)>,
+    signatures_since_checkpoint: usize,
 }
 
 impl SignatureBuilder {
@@ -501,6 +664,44 @@ impl SignatureBuilder {
             metadata,
             partial_signatures: PartialSignatures::empty(),
             epoch_state,
+            checkpoint: None,
+            signatures_since_checkpoint: 0,
+        }
+    }
+
+    /// Starts from the signatures collected by an interrupted broadcast of the same node.
+    pub fn with_progress(mut self, progress: BroadcastProgress) -> Self {
+        if progress.metadata == self.metadata {
+            self.partial_signatures = PartialSignatures::new(progress.signatures);
+        }
+        self
+    }
+
+    /// Saves the signatures collected so far to the storage every `interval` new signatures.
+    pub fn with_checkpoint(mut self, storage: Arc<dyn DAGStorage>, interval: usize) -> Self {
+        self.checkpoint = Some((storage, interval));
+        self
+    }
+
+    fn maybe_checkpoint(&mut self) {
+        let (storage, interval) = match &self.checkpoint {
+            Some(checkpoint) => checkpoint,
+            None => return,
+        };
+        self.signatures_since_checkpoint += 1;
+        if self.signatures_since_checkpoint < *interval {
+            return;
+        }
+        self.signatures_since_checkpoint = 0;
+        let progress = BroadcastProgress::new(
+            self.metadata.clone(),
+            self.partial_signatures.signatures().clone(),
+        );
+        if let Err(e) = storage.save_broadcast_progress(&progress) {
+            warn!(
+                "failed to save the broadcast progress of {}: {:?}",
+                self.metadata.node_id, e
+            );
         }
     }
 }
@@ -512,7 +713,10 @@ impl BroadcastStatus<DAGMessage> for SignatureBuilder {
 
     fn add(&mut self, peer: Author, ack: Self::Ack) -> anyhow::Result<Option<Self::Aggregated>> {
         ensure!(self.metadata == ack.metadata, "Digest mismatch");
-        self.partial_signatures.add_signature(peer, ack.signature);
+        if !self.partial_signatures.signatures().contains_key(&peer) {
+            self.partial_signatures.add_signature(peer, ack.signature);
+            self.maybe_checkpoint();
+        }
         Ok(self
             .epoch_state
             .verifier
@@ -532,6 +736,10 @@ impl BroadcastStatus<DAGMessage> for SignatureBuilder {
 pub struct CertificateAckState {
     num_validators: usize,
     received: HashSet<Author>,
+    /// Commit info of the ledger info carried by the certified node message.
+    commit_info: Option<BlockInfo>,
+    ledger_info_agreed: HashSet<Author>,
+    ledger_info_disagreed: HashSet<Author>,
 }
 
 impl CertificateAckState {
@@ -539,6 +747,46 @@ impl CertificateAckState {
         Self {
             num_validators,
             received: HashSet::new(),
+            commit_info: None,
+            ledger_info_agreed: HashSet::new(),
+            ledger_info_disagreed: HashSet::new(),
+        }
+    }
+
+    pub fn with_commit_info(mut self, commit_info: BlockInfo) -> Self {
+        self.commit_info = Some(commit_info);
+        self
+    }
+
+    /// Number of distinct validators that acked the certified node.
+    pub fn num_received(&self) -> usize {
+        self.received.len()
+    }
+
+    /// Validators whose latest ledger info commits the same block as the one carried by the
+    /// certified node message.
+    pub fn ledger_info_agreed(&self) -> impl Iterator<Item = &Author> {
+        self.ledger_info_agreed.iter()
+    }
+
+    /// Validators whose latest ledger info commits a different block in the same epoch and round
+    /// as the one carried by the certified node message, which indicates a potential fork.
+    pub fn ledger_info_disagreed(&self) -> impl Iterator<Item = &Author> {
+        self.ledger_info_disagreed.iter()
+    }
+
+    fn compare_commit_info(&mut self, peer: Author, peer_commit_info: &BlockInfo) {
+        let commit_info = match &self.commit_info {
+            Some(commit_info) => commit_info,
+            None => return,
+        };
+        if commit_info == peer_commit_info {
+            self.ledger_info_agreed.insert(peer);
+        } else if commit_info.epoch() == peer_commit_info.epoch()
+            && commit_info.round() == peer_commit_info.round()
+        {
+            counters::LEDGER_INFO_DISAGREEMENTS.inc();
+            self.ledger_info_disagreed.insert(peer);
         }
     }
 }
@@ -546,11 +794,25 @@ impl CertificateAckState {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct CertifiedAck {
     epoch: u64,
+    /// Commit info of the latest ledger info of the validator sending the ack.
+    commit_info: Option<BlockInfo>,
 }
 
 impl CertifiedAck {
     pub fn new(epoch: u64) -> Self {
-        Self { epoch }
+        Self {
+            epoch,
+            commit_info: None,
+        }
+    }
+
+    pub fn with_commit_info(mut self, commit_info: BlockInfo) -> Self {
+        self.commit_info = Some(commit_info);
+        self
+    }
+
+    pub fn commit_info(&self) -> Option<&BlockInfo> {
+        self.commit_info.as_ref()
     }
 }
 
@@ -559,8 +821,14 @@ impl BroadcastStatus<DAGMessage> for CertificateAckState {
     type Aggregated = ();
     type Message = CertifiedNodeMessage;
 
-    fn add(&mut self, peer: Author, _ack: Self::Ack) -> anyhow::Result<Option<Self::Aggregated>> {
-        self.received.insert(peer);
+    fn add(&mut self, peer: Author, ack: Self::Ack) -> anyhow::Result<Option<Self::Aggregated>> {
+        // A duplicate ack is ignored, so it neither counts twice nor completes the broadcast again.
+        if !self.received.insert(peer) {
+            return Ok(None);
+        }
+        if let Some(peer_commit_info) = ack.commit_info() {
+            self.compare_commit_info(peer, peer_commit_info);
+        }
         if self.received.len() == self.num_validators {
             Ok(Some(()))
         } else {
@@ -707,7 +975,15 @@ impl DAGMessage {
     }
 }
 
-impl RBMessage for DAGMessage {}
+impl RBMessage for DAGMessage {
+    /// Certified nodes advance finality, so they are sent ahead of new nodes under congestion.
+    fn priority(&self) -> u8 {
+        match self {
+            DAGMessage::CertifiedNodeMsg(_) => 1,
+            _ => 0,
+        }
+    }
+}
 
 impl TConsensusMsg for DAGMessage {
     fn epoch(&self) -> u64 {
@@ -8,7 +8,10 @@ use crate::{
         BlockStore,
     },
     counters,
-    dag::{DagBootstrapper, DagCommitSigner, StorageAdapter},
+    dag::{
+        build_dag_storage, AnchorElectionPolicy, DagBootstrapper, DagCommitSigner,
+        DagPayloadConfig, LiveNodePolicy, ReliableBroadcastConfig, StartupVerification,
+    },
     error::{error_kind, DbError},
     experimental::{
         buffer_manager::{OrderedBlocks, ResetRequest},
@@ -38,7 +41,7 @@ use crate::{
         IncomingDAGRequest, IncomingRpcRequest, NetworkReceivers, NetworkSender,
     },
     network_interface::{ConsensusMsg, ConsensusNetworkClient},
-    payload_client::QuorumStoreClient,
+    payload_client::{BoundedPayloadClient, QuorumStoreClient},
     payload_manager::PayloadManager,
     persistent_liveness_storage::{LedgerRecoveryData, PersistentLivenessStorage, RecoveryData},
     quorum_store::{
@@ -990,7 +993,11 @@ impl<P: OnChainConfigProvider> EpochManager<P> {
 
         self.init_commit_state_computer(epoch_state, payload_manager.clone(), execution_config);
         self.start_quorum_store(quorum_store_builder);
-        (network_sender, Arc::new(payload_client), payload_manager)
+        let payload_client = BoundedPayloadClient::maybe_wrap(
+            Arc::new(payload_client),
+            self.config.max_concurrent_payload_pulls,
+        );
+        (network_sender, payload_client, payload_manager)
     }
 
     async fn start_new_epoch_with_joltean(
@@ -1053,12 +1060,13 @@ impl<P: OnChainConfigProvider> EpochManager<P> {
             epoch_state.verifier.get_ordered_account_addresses(),
             onchain_dag_consensus_config.dag_ordering_causal_history_window as u64,
         );
-        let dag_storage = Arc::new(StorageAdapter::new(
+        let dag_storage = build_dag_storage(
+            self.config.dag_storage_backend,
             epoch,
             epoch_to_validators,
             self.storage.consensus_db(),
             self.storage.aptos_db(),
-        ));
+        );
 
         let signer = new_signer_from_storage(self.author, &self.config.safety_rules.backend);
         let network_sender_arc = Arc::new(network_sender);
@@ -1075,7 +1083,45 @@ impl<P: OnChainConfigProvider> EpochManager<P> {
             payload_manager,
             payload_client,
             state_computer,
-        );
+        )
+        .with_rb_config(ReliableBroadcastConfig {
+            backoff_base_ms: self.config.dag_rb_backoff_base_ms,
+            backoff_factor: self.config.dag_rb_backoff_factor,
+            backoff_max_delay: self
+                .config
+                .dag_rb_backoff_max_delay_ms
+                .map(Duration::from_millis),
+            rpc_timeout: self.config.dag_rb_rpc_timeout_ms.map(Duration::from_millis),
+            max_concurrent_sends: self.config.dag_rb_max_concurrent_sends,
+        })
+        .with_dag_window(onchain_dag_consensus_config.dag_ordering_causal_history_window)
+        .with_payload_shuffle(onchain_dag_consensus_config.shuffle_payloads)
+        .with_startup_verification(match self.config.dag_startup_verification_threads {
+            Some(num_threads) => StartupVerification::Enabled { num_threads },
+            None => StartupVerification::Disabled,
+        })
+        .with_live_node_policy(match self.config.dag_live_node_buffer_size {
+            0 => LiveNodePolicy::Drop,
+            max_nodes => LiveNodePolicy::Buffer { max_nodes },
+        })
+        .with_recent_commits_dedup_size(self.config.dag_recent_commits_dedup_size)
+        .with_anchor_election_policy(
+            match onchain_dag_consensus_config.anchor_election_reputation_window {
+                Some(window_size) => AnchorElectionPolicy::Reputation { window_size },
+                None => AnchorElectionPolicy::RoundRobin,
+            },
+        )
+        .with_fetch_fanout(self.config.dag_fetch_fanout)
+        .with_payload_config(DagPayloadConfig {
+            pull_timeout: Duration::from_millis(self.config.dag_payload_pull_timeout_ms),
+            max_txns: self.config.dag_payload_max_txns,
+            max_bytes: self.config.dag_payload_max_bytes,
+        })
+        .with_broadcast_checkpoint_interval(self.config.dag_broadcast_checkpoint_interval)
+        .with_max_commit_lag_rounds(self.config.dag_max_commit_lag_rounds)
+        .with_round_timeout(self.config.dag_round_timeout_ms.map(Duration::from_millis))
+        .with_payload_prefetch_batch_size(self.config.dag_payload_prefetch_batch_size)
+        .with_broadcast_failure_alert_threshold(self.config.dag_broadcast_failure_alert_threshold);
 
         let (dag_rpc_tx, dag_rpc_rx) = aptos_channel::new(QueueStyle::FIFO, 10, None);
         self.dag_rpc_tx = Some(dag_rpc_tx);
@@ -4,6 +4,7 @@
 
 use crate::experimental;
 use thiserror::Error;
+use tokio::time::error::Elapsed;
 
 #[derive(Debug, Error)]
 #[error(transparent)]
@@ -45,6 +46,13 @@ pub struct QuorumStoreError {
     inner: anyhow::Error,
 }
 
+impl QuorumStoreError {
+    /// Whether quorum store didn't respond in time, as opposed to failing.
+    pub fn is_timeout(&self) -> bool {
+        self.inner.downcast_ref::<Elapsed>().is_some()
+    }
+}
+
 #[derive(Debug, Error)]
 #[error(transparent)]
 pub struct VerifyError {
@@ -320,6 +320,7 @@ impl ProposalGenerator {
                     pending_ordering,
                     pending_blocks.len(),
                     max_fill_fraction,
+                    None,
                 )
                 .await
                 .context("Fail to retrieve payload")?;
@@ -18,6 +18,7 @@ use aptos_config::network_id::NetworkId;
 use aptos_consensus_types::{
     block_retrieval::{BlockRetrievalRequest, BlockRetrievalResponse, MAX_BLOCKS_PER_REQUEST},
     common::Author,
+    epoch_retrieval::EpochRetrievalRequest,
     experimental::{commit_decision::CommitDecision, commit_vote::CommitVote},
     proof_of_store::{ProofOfStore, ProofOfStoreMsg, SignedBatchInfo, SignedBatchInfoMsg},
     proposal_msg::ProposalMsg,
@@ -501,6 +502,11 @@ impl ProofNotifier for NetworkSender {
     async fn send_commit_proof(&self, ledger_info: LedgerInfoWithSignatures) {
         self.send_commit_proof(ledger_info).await
     }
+
+    async fn send_epoch_retrieval(&self, peer: Author, request: EpochRetrievalRequest) {
+        let msg = ConsensusMsg::EpochRetrievalRequest(Box::new(request));
+        self.send(msg, vec![peer]).await
+    }
 }
 
 pub struct NetworkTask {
@@ -16,8 +16,15 @@ use futures::{
     channel::{mpsc, oneshot},
     future::BoxFuture,
 };
-use std::time::{Duration, Instant};
-use tokio::time::{sleep, timeout};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::Semaphore,
+    time::{sleep, timeout},
+};
 
 const NO_TXN_DELAY: u64 = 30; // TODO: consider moving to a config
 
@@ -71,9 +78,9 @@ impl QuorumStoreClient {
             "pull_payload",
             timeout(Duration::from_millis(self.pull_timeout_ms), callback_rcv).await
         ) {
-            Err(_) => {
-                Err(anyhow::anyhow!("[consensus] did not receive GetBlockResponse on time").into())
-            },
+            Err(elapsed) => Err(anyhow::Error::new(elapsed)
+                .context("[consensus] did not receive GetBlockResponse on time")
+                .into()),
             Ok(resp) => match resp.map_err(anyhow::Error::from)?? {
                 GetPayloadResponse::GetPayloadResponse(payload) => Ok(payload),
             },
@@ -93,6 +100,7 @@ impl PayloadClient for QuorumStoreClient {
         pending_ordering: bool,
         pending_uncommitted_blocks: usize,
         recent_max_fill_fraction: f32,
+        seed: Option<u64>,
     ) -> Result<Payload, QuorumStoreError> {
         let return_non_full = recent_max_fill_fraction
             < self.wait_for_full_blocks_above_recent_fill_threshold
@@ -128,6 +136,10 @@ impl PayloadClient for QuorumStoreClient {
             }
             break payload;
         };
+        let payload = match seed {
+            Some(seed) => order_with_seed(payload, seed),
+            None => payload,
+        };
         info!(
             elapsed_time = start_time.elapsed(),
             max_poll_time = max_poll_time,
@@ -143,3 +155,241 @@ impl PayloadClient for QuorumStoreClient {
         Ok(payload)
     }
 }
+
+/// Sorts the payload into a canonical order and then shuffles it with an RNG seeded by `seed`, so
+/// the result only depends on the pulled contents and the seed.
+fn order_with_seed(payload: Payload, seed: u64) -> Payload {
+    let mut rng = StdRng::seed_from_u64(seed);
+    match payload {
+        Payload::DirectMempool(mut txns) => {
+            txns.sort_by_key(|txn| (txn.sender(), txn.sequence_number()));
+            txns.shuffle(&mut rng);
+            Payload::DirectMempool(txns)
+        },
+        Payload::InQuorumStore(mut proof_with_data) => {
+            proof_with_data.proofs.sort_by_key(|proof| *proof.digest());
+            proof_with_data.proofs.shuffle(&mut rng);
+            Payload::InQuorumStore(proof_with_data)
+        },
+    }
+}
+
+/// Wrapper around a `PayloadClient` that is shared by multiple drivers, limiting the number of
+/// `pull_payload` calls that can be in flight against the inner client at the same time.
+pub struct BoundedPayloadClient {
+    inner: Arc<dyn PayloadClient>,
+    pull_semaphore: Arc<Semaphore>,
+}
+
+impl BoundedPayloadClient {
+    pub fn new(inner: Arc<dyn PayloadClient>, max_concurrent_pulls: usize) -> Self {
+        assert!(
+            max_concurrent_pulls > 0,
+            "max_concurrent_pulls must be positive"
+        );
+        Self {
+            inner,
+            pull_semaphore: Arc::new(Semaphore::new(max_concurrent_pulls)),
+        }
+    }
+
+    /// Wraps `inner` if a limit is configured, otherwise returns it unchanged. A limit of 0 is
+    /// treated as unlimited.
+    pub fn maybe_wrap(
+        inner: Arc<dyn PayloadClient>,
+        max_concurrent_pulls: Option<usize>,
+    ) -> Arc<dyn PayloadClient> {
+        match max_concurrent_pulls {
+            Some(0) => {
+                warn!("max_concurrent_payload_pulls is 0, leaving the payload pulls unbounded");
+                inner
+            },
+            Some(limit) => Arc::new(Self::new(inner, limit)),
+            None => inner,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl PayloadClient for BoundedPayloadClient {
+    async fn pull_payload(
+        &self,
+        max_poll_time: Duration,
+        max_items: u64,
+        max_bytes: u64,
+        exclude_payloads: PayloadFilter,
+        wait_callback: BoxFuture<'static, ()>,
+        pending_ordering: bool,
+        pending_uncommitted_blocks: usize,
+        recent_max_fill_fraction: f32,
+        seed: Option<u64>,
+    ) -> Result<Payload, QuorumStoreError> {
+        let _permit = self
+            .pull_semaphore
+            .acquire()
+            .await
+            .map_err(anyhow::Error::from)?;
+        self.inner
+            .pull_payload(
+                max_poll_time,
+                max_items,
+                max_bytes,
+                exclude_payloads,
+                wait_callback,
+                pending_ordering,
+                pending_uncommitted_blocks,
+                recent_max_fill_fraction,
+                seed,
+            )
+            .await
+    }
+
+    fn trace_payloads(&self) {
+        self.inner.trace_payloads()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::QuorumStoreError,
+        payload_client::{BoundedPayloadClient, QuorumStoreClient},
+        state_replication::PayloadClient,
+    };
+    use aptos_consensus_types::{
+        block::block_test_utils::random_payload,
+        common::{Payload, PayloadFilter},
+        request_response::{GetPayloadCommand, GetPayloadResponse},
+    };
+    use futures::{
+        channel::mpsc,
+        future::{join, BoxFuture},
+        StreamExt,
+    };
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    #[derive(Default)]
+    struct SlowPayloadClient {
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl PayloadClient for SlowPayloadClient {
+        async fn pull_payload(
+            &self,
+            _max_poll_time: Duration,
+            _max_items: u64,
+            _max_bytes: u64,
+            _exclude: PayloadFilter,
+            _wait_callback: BoxFuture<'static, ()>,
+            _pending_ordering: bool,
+            _pending_uncommitted_blocks: usize,
+            _recent_max_fill_fraction: f32,
+            _seed: Option<u64>,
+        ) -> Result<Payload, QuorumStoreError> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(Payload::empty(false))
+        }
+    }
+
+    async fn pull(client: &dyn PayloadClient) -> Result<Payload, QuorumStoreError> {
+        client
+            .pull_payload(
+                Duration::from_secs(1),
+                100,
+                1000,
+                PayloadFilter::Empty,
+                Box::pin(async {}),
+                false,
+                0,
+                0.0,
+                None,
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_bounded_payload_client_serializes_pulls() {
+        let inner = Arc::new(SlowPayloadClient::default());
+        let client = BoundedPayloadClient::new(inner.clone(), 1);
+
+        let (first, second) = join(pull(&client), pull(&client)).await;
+        assert!(first.is_ok());
+        assert!(second.is_ok());
+        assert_eq!(inner.max_in_flight.load(Ordering::SeqCst), 1);
+
+        let inner = Arc::new(SlowPayloadClient::default());
+        let client = BoundedPayloadClient::new(inner.clone(), 2);
+
+        let (first, second) = join(pull(&client), pull(&client)).await;
+        assert!(first.is_ok());
+        assert!(second.is_ok());
+        assert_eq!(inner.max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_zero_max_concurrent_pulls_is_unbounded() {
+        let inner = Arc::new(SlowPayloadClient::default());
+        let client = BoundedPayloadClient::maybe_wrap(inner.clone(), Some(0));
+
+        let (first, second) = join(pull(client.as_ref()), pull(client.as_ref())).await;
+        assert!(first.is_ok());
+        assert!(second.is_ok());
+        assert_eq!(inner.max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_seeded_pull_is_deterministic() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let client = QuorumStoreClient::new(tx, 1000, 1.1, 100);
+        let txns = match random_payload(10) {
+            Payload::DirectMempool(txns) => txns,
+            Payload::InQuorumStore(_) => unreachable!(),
+        };
+        // The mempool returns the same transactions, but in a different order on every pull.
+        tokio::spawn(async move {
+            let mut txns = txns;
+            while let Some(GetPayloadCommand::GetPayloadRequest(_, _, _, _, callback)) =
+                rx.next().await
+            {
+                txns.reverse();
+                let payload = Payload::DirectMempool(txns.clone());
+                callback
+                    .send(Ok(GetPayloadResponse::GetPayloadResponse(payload)))
+                    .unwrap();
+            }
+        });
+
+        let pull = |seed| {
+            client.pull_payload(
+                Duration::from_secs(1),
+                100,
+                1000,
+                PayloadFilter::Empty,
+                Box::pin(async {}),
+                false,
+                0,
+                0.0,
+                seed,
+            )
+        };
+        let first = pull(Some(42)).await.unwrap();
+        let second = pull(Some(42)).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.len(), 10);
+
+        let unseeded_first = pull(None).await.unwrap();
+        let unseeded_second = pull(None).await.unwrap();
+        assert_ne!(unseeded_first, unseeded_second);
+    }
+}
@@ -3,15 +3,11 @@
 
 use crate::{
     counters,
-    network::NetworkSender,
-    quorum_store::{
-        batch_store::{BatchReader, BatchStore},
-        quorum_store_coordinator::CoordinatorCommand,
-    },
+    quorum_store::{batch_store::BatchReader, quorum_store_coordinator::CoordinatorCommand},
 };
 use aptos_consensus_types::{
     block::Block,
-    common::{DataStatus, Payload},
+    common::{DataStatus, Payload, ProofWithData},
     proof_of_store::ProofOfStore,
 };
 use aptos_crypto::HashValue;
@@ -19,21 +15,24 @@ use aptos_executor_types::{ExecutorError::DataNotFound, *};
 use aptos_logger::prelude::*;
 use aptos_types::transaction::SignedTransaction;
 use futures::{channel::mpsc::Sender, SinkExt};
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 use tokio::sync::oneshot;
 
 /// Responsible to extract the transactions out of the payload and notify QuorumStore about commits.
 /// If QuorumStore is enabled, has to ask BatchReader for the transaction behind the proofs of availability in the payload.
 pub enum PayloadManager {
     DirectMempool,
-    InQuorumStore(Arc<BatchStore<NetworkSender>>, Sender<CoordinatorCommand>),
+    InQuorumStore(Arc<dyn BatchReader>, Sender<CoordinatorCommand>),
 }
 
 impl PayloadManager {
     fn request_transactions(
         proofs: Vec<ProofOfStore>,
         block_timestamp: u64,
-        batch_store: &BatchStore<NetworkSender>,
+        batch_reader: &dyn BatchReader,
     ) -> Vec<(
         HashValue,
         oneshot::Receiver<ExecutorResult<Vec<SignedTransaction>>>,
@@ -47,7 +46,7 @@ impl PayloadManager {
                 block_timestamp
             );
             if block_timestamp <= pos.expiration() {
-                receivers.push((*pos.digest(), batch_store.get_batch(pos)));
+                receivers.push((*pos.digest(), batch_reader.get_batch(pos)));
             } else {
                 debug!("QSE: skipped expired pos {}", pos.digest());
             }
@@ -55,15 +54,89 @@ impl PayloadManager {
         receivers
     }
 
+    /// Like `request_transactions` for the proofs of several payloads, but the batch of a proof
+    /// shared by several payloads is requested only once, and its transactions are forwarded to
+    /// each of them.
+    fn request_transactions_batch(
+        payloads: &[(&ProofWithData, u64)],
+        batch_reader: &dyn BatchReader,
+    ) -> Vec<
+        Vec<(
+            HashValue,
+            oneshot::Receiver<ExecutorResult<Vec<SignedTransaction>>>,
+        )>,
+    > {
+        // the unexpired proofs of every payload, and how many payloads request each batch
+        let mut requested_digests = Vec::with_capacity(payloads.len());
+        let mut requests: HashMap<HashValue, (ProofOfStore, usize)> = HashMap::new();
+        for (proof_with_data, block_timestamp) in payloads {
+            let mut digests = Vec::new();
+            for pos in &proof_with_data.proofs {
+                if *block_timestamp <= pos.expiration() {
+                    digests.push(*pos.digest());
+                    requests
+                        .entry(*pos.digest())
+                        .or_insert_with(|| (pos.clone(), 0))
+                        .1 += 1;
+                } else {
+                    debug!("QSE: skipped expired pos {}", pos.digest());
+                }
+            }
+            requested_digests.push(digests);
+        }
+
+        let mut receivers_by_digest: HashMap<HashValue, Vec<_>> = HashMap::new();
+        for (digest, (pos, num_requesters)) in requests {
+            trace!("QSE: requesting pos {:?}, digest {}", pos, digest);
+            let batch_rx = batch_reader.get_batch(pos);
+            if num_requesters == 1 {
+                receivers_by_digest.insert(digest, vec![batch_rx]);
+                continue;
+            }
+            let (senders, receivers): (Vec<_>, Vec<_>) =
+                (0..num_requesters).map(|_| oneshot::channel()).unzip();
+            receivers_by_digest.insert(digest, receivers);
+            tokio::spawn(async move {
+                // if the batch request is dropped, so are the senders, as if each payload had
+                // requested the batch itself
+                if let Ok(result) = batch_rx.await {
+                    if let Err(e) = &result {
+                        warn!("QSE: failed to get batch {}: {:?}", digest, e);
+                    }
+                    for tx in senders {
+                        let result = match &result {
+                            Ok(txns) => Ok(txns.clone()),
+                            Err(_) => Err(DataNotFound(digest)),
+                        };
+                        let _ = tx.send(result);
+                    }
+                }
+            });
+        }
+
+        requested_digests
+            .into_iter()
+            .map(|digests| {
+                digests
+                    .into_iter()
+                    .map(|digest| {
+                        let rx = receivers_by_digest
+                            .get_mut(&digest)
+                            .and_then(|receivers| receivers.pop())
+                            .expect("a receiver per requester");
+                        (digest, rx)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
     ///Pass commit information to BatchReader and QuorumStore wrapper for their internal cleanups.
     pub async fn notify_commit(&self, block_timestamp: u64, payloads: Vec<Payload>) {
         match self {
             PayloadManager::DirectMempool => {},
-            PayloadManager::InQuorumStore(batch_store, coordinator_tx) => {
-                // TODO: move this to somewhere in quorum store, so this can be a batch reader
-                batch_store
-                    .update_certified_timestamp(block_timestamp)
-                    .await;
+            PayloadManager::InQuorumStore(batch_reader, coordinator_tx) => {
+                batch_reader.update_certified_timestamp(block_timestamp);
 
                 let batches: Vec<_> = payloads
                     .into_iter()
@@ -98,13 +171,13 @@ impl PayloadManager {
     pub fn prefetch_payload_data(&self, payload: &Payload, timestamp: u64) {
         match self {
             PayloadManager::DirectMempool => {},
-            PayloadManager::InQuorumStore(batch_store, _) => match payload {
+            PayloadManager::InQuorumStore(batch_reader, _) => match payload {
                 Payload::InQuorumStore(proof_with_status) => {
                     if proof_with_status.status.lock().is_none() {
                         let receivers = PayloadManager::request_transactions(
                             proof_with_status.proofs.clone(),
                             timestamp,
-                            batch_store,
+                            batch_reader.as_ref(),
                         );
                         proof_with_status
                             .status
@@ -119,6 +192,39 @@ impl PayloadManager {
         }
     }
 
+    /// Like `prefetch_payload_data` for several payloads at once, e.g. of nodes received in a burst,
+    /// so that the batches shared by the payloads are fetched only once.
+    pub fn prefetch_payload_data_batch(&self, payloads: &[(Payload, u64)]) {
+        match self {
+            PayloadManager::DirectMempool => {},
+            PayloadManager::InQuorumStore(batch_reader, _) => {
+                // clones of a payload share its status, so each is prefetched once
+                let mut seen = HashSet::new();
+                let pending: Vec<_> = payloads
+                    .iter()
+                    .filter_map(|(payload, timestamp)| match payload {
+                        Payload::InQuorumStore(proof_with_status) => {
+                            let is_new = seen.insert(Arc::as_ptr(&proof_with_status.status));
+                            (is_new && proof_with_status.status.lock().is_none())
+                                .then_some((proof_with_status, *timestamp))
+                        },
+                        Payload::DirectMempool(_) => {
+                            unreachable!()
+                        },
+                    })
+                    .collect();
+                let receivers =
+                    PayloadManager::request_transactions_batch(&pending, batch_reader.as_ref());
+                for ((proof_with_status, _), receivers) in pending.into_iter().zip(receivers) {
+                    proof_with_status
+                        .status
+                        .lock()
+                        .replace(DataStatus::Requested(receivers));
+                }
+            },
+        }
+    }
+
     /// Extract transaction from a given block
     /// Assumes it is never called for the same block concurrently. Otherwise status can be None.
     pub async fn get_transactions(&self, block: &Block) -> ExecutorResult<Vec<SignedTransaction>> {
@@ -130,11 +236,18 @@ impl PayloadManager {
         match (self, payload) {
             (PayloadManager::DirectMempool, Payload::DirectMempool(txns)) => Ok(txns.clone()),
             (
-                PayloadManager::InQuorumStore(batch_store, _),
+                PayloadManager::InQuorumStore(batch_reader, _),
                 Payload::InQuorumStore(proof_with_data),
             ) => {
-                let status = proof_with_data.status.lock().take();
-                match status.expect("Should have been updated before.") {
+                // the payload may not be prefetched yet, e.g. while buffered for a batched prefetch
+                let status = proof_with_data.status.lock().take().unwrap_or_else(|| {
+                    DataStatus::Requested(PayloadManager::request_transactions(
+                        proof_with_data.proofs.clone(),
+                        block.timestamp_usecs(),
+                        batch_reader.as_ref(),
+                    ))
+                });
+                match status {
                     DataStatus::Cached(data) => {
                         counters::QUORUM_BATCH_READY_COUNT.inc();
                         proof_with_data
@@ -161,7 +274,7 @@ impl PayloadManager {
                                     let new_receivers = PayloadManager::request_transactions(
                                         proof_with_data.proofs.clone(),
                                         block.timestamp_usecs(),
-                                        batch_store,
+                                        batch_reader.as_ref(),
                                     );
                                     // Could not get all data so requested again
                                     proof_with_data
@@ -177,7 +290,7 @@ impl PayloadManager {
                                     let new_receivers = PayloadManager::request_transactions(
                                         proof_with_data.proofs.clone(),
                                         block.timestamp_usecs(),
-                                        batch_store,
+                                        batch_reader.as_ref(),
                                     );
                                     // Could not get all data so requested again
                                     proof_with_data
@@ -208,3 +321,67 @@ impl PayloadManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{payload_manager::PayloadManager, test_utils::CountingBatchReader};
+    use aptos_consensus_types::{
+        common::ProofWithData,
+        proof_of_store::{BatchId, BatchInfo, ProofOfStore},
+    };
+    use aptos_crypto::HashValue;
+    use aptos_types::{aggregate_signature::AggregateSignature, PeerId};
+
+    fn create_proof(expiration: u64) -> ProofOfStore {
+        ProofOfStore::new(
+            BatchInfo::new(
+                PeerId::random(),
+                BatchId::new_for_test(0),
+                0,
+                expiration,
+                HashValue::random(),
+                1,
+                1,
+                0,
+            ),
+            AggregateSignature::empty(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_request_transactions_batch_coalesces_shared_batches() {
+        let shared = create_proof(100);
+        let expired = create_proof(10);
+        let payloads = vec![
+            ProofWithData::new(vec![shared.clone(), create_proof(100)]),
+            ProofWithData::new(vec![shared.clone(), expired.clone()]),
+            ProofWithData::new(vec![create_proof(100), shared.clone()]),
+        ];
+        let requests: Vec<_> = payloads.iter().map(|payload| (payload, 50)).collect();
+
+        let batch_reader = CountingBatchReader::default();
+        let receivers = PayloadManager::request_transactions_batch(&requests, &batch_reader);
+
+        // the shared batch is requested once for the 3 payloads, the expired one not at all
+        let requested = batch_reader.requested();
+        assert_eq!(requested.len(), 3);
+        assert_eq!(
+            requested
+                .iter()
+                .filter(|digest| *digest == shared.digest())
+                .count(),
+            1
+        );
+        assert!(!requested.contains(expired.digest()));
+
+        // every payload still gets the transactions of each of its unexpired batches
+        let num_receivers: Vec<_> = receivers.iter().map(|receivers| receivers.len()).collect();
+        assert_eq!(num_receivers, vec![2, 1, 2]);
+        for (payload, receivers) in payloads.iter().zip(receivers) {
+            for (digest, rx) in receivers {
+                assert!(payload.proofs.iter().any(|pos| *pos.digest() == digest));
+                assert_eq!(rx.await.unwrap().unwrap(), vec![]);
+            }
+        }
+    }
+}
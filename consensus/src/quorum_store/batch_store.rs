@@ -338,26 +338,6 @@ impl<T: QuorumStoreSender + Clone + Send + Sync + 'static> BatchStore<T> {
         }
     }
 
-    pub async fn update_certified_timestamp(&self, certified_time: u64) {
-        trace!("QS: batch reader updating time {:?}", certified_time);
-        let prev_time = self
-            .last_certified_time
-            .fetch_max(certified_time, Ordering::SeqCst);
-        // Note: prev_time may be equal to certified_time due to state-sync
-        // at the epoch boundary.
-        assert!(
-            prev_time <= certified_time,
-            "Decreasing executed block timestamp reported to BatchReader {} {}",
-            prev_time,
-            certified_time,
-        );
-
-        let expired_keys = self.clear_expired_payload(certified_time);
-        if let Err(e) = self.db.delete_batches(expired_keys) {
-            debug!("Error deleting batches: {:?}", e)
-        }
-    }
-
     fn last_certified_time(&self) -> u64 {
         self.last_certified_time.load(Ordering::Relaxed)
     }
@@ -399,6 +379,9 @@ pub trait BatchReader: Send + Sync {
         &self,
         proof: ProofOfStore,
     ) -> oneshot::Receiver<ExecutorResult<Vec<SignedTransaction>>>;
+
+    /// Clears the batches expired by `certified_time`, the timestamp of the last committed block.
+    fn update_certified_timestamp(&self, certified_time: u64);
 }
 
 impl<T: QuorumStoreSender + Clone + Send + Sync + 'static> BatchReader for BatchStore<T> {
@@ -426,4 +409,24 @@ impl<T: QuorumStoreSender + Clone + Send + Sync + 'static> BatchReader for Batch
         }
         rx
     }
+
+    fn update_certified_timestamp(&self, certified_time: u64) {
+        trace!("QS: batch reader updating time {:?}", certified_time);
+        let prev_time = self
+            .last_certified_time
+            .fetch_max(certified_time, Ordering::SeqCst);
+        // Note: prev_time may be equal to certified_time due to state-sync
+        // at the epoch boundary.
+        assert!(
+            prev_time <= certified_time,
+            "Decreasing executed block timestamp reported to BatchReader {} {}",
+            prev_time,
+            certified_time,
+        );
+
+        let expired_keys = self.clear_expired_payload(certified_time);
+        if let Err(e) = self.db.delete_batches(expired_keys) {
+            debug!("Error deleting batches: {:?}", e)
+        }
+    }
 }
@@ -34,6 +34,10 @@ impl BatchReader for MockBatchReader {
     fn get_batch(&self, _proof: ProofOfStore) -> Receiver<ExecutorResult<Vec<SignedTransaction>>> {
         unimplemented!();
     }
+
+    fn update_certified_timestamp(&self, _certified_time: u64) {
+        unimplemented!();
+    }
 }
 
 #[tokio::test(flavor = "multi_thread")]
@@ -38,6 +38,9 @@ pub trait PayloadClient: Send + Sync {
         pending_ordering: bool,
         pending_uncommitted_blocks: usize,
         recent_max_fill_fraction: f32,
+        // If set, the pulled payload is put in a canonical order shuffled by this seed, so the
+        // same mempool state and seed always produce the same payload.
+        seed: Option<u64>,
     ) -> Result<Payload, QuorumStoreError>;
 
     fn trace_payloads(&self) {}
@@ -0,0 +1,41 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::quorum_store::batch_store::BatchReader;
+use aptos_consensus_types::proof_of_store::ProofOfStore;
+use aptos_crypto::HashValue;
+use aptos_executor_types::ExecutorResult;
+use aptos_infallible::Mutex;
+use aptos_types::{transaction::SignedTransaction, PeerId};
+use tokio::sync::oneshot;
+
+/// Answers every batch request right away, counting them.
+#[derive(Default)]
+pub struct CountingBatchReader {
+    requested: Mutex<Vec<HashValue>>,
+}
+
+impl CountingBatchReader {
+    /// The digests of the requested batches, in request order.
+    pub fn requested(&self) -> Vec<HashValue> {
+        self.requested.lock().clone()
+    }
+}
+
+impl BatchReader for CountingBatchReader {
+    fn exists(&self, _digest: &HashValue) -> Option<PeerId> {
+        None
+    }
+
+    fn get_batch(
+        &self,
+        proof: ProofOfStore,
+    ) -> oneshot::Receiver<ExecutorResult<Vec<SignedTransaction>>> {
+        self.requested.lock().push(*proof.digest());
+        let (tx, rx) = oneshot::channel();
+        let _ = tx.send(Ok(vec![]));
+        rx
+    }
+
+    fn update_certified_timestamp(&self, _certified_time: u64) {}
+}
@@ -61,6 +61,7 @@ impl PayloadClient for MockPayloadManager {
         _pending_ordering: bool,
         _pending_uncommitted_blocks: usize,
         _recent_fill_fraction: f32,
+        _seed: Option<u64>,
     ) -> Result<Payload, QuorumStoreError> {
         // generate 1k txn is too slow with coverage instrumentation
         Ok(random_payload(10))
@@ -16,6 +16,7 @@ use aptos_types::{ledger_info::LedgerInfo, validator_signer::ValidatorSigner};
 use std::{future::Future, sync::Arc, time::Duration};
 use tokio::{runtime, time::timeout};
 
+mod mock_batch_reader;
 #[cfg(any(test, feature = "fuzzing"))]
 mod mock_payload_manager;
 pub mod mock_quorum_store_sender;
@@ -25,6 +26,7 @@ mod mock_storage;
 use crate::{payload_manager::PayloadManager, util::mock_time_service::SimulatedTimeService};
 use aptos_consensus_types::{block::block_test_utils::gen_test_certificate, common::Payload};
 use aptos_types::block_info::BlockInfo;
+pub use mock_batch_reader::CountingBatchReader;
 pub use mock_payload_manager::MockPayloadManager;
 pub use mock_state_computer::{
     EmptyStateComputer, MockStateComputer, RandomComputeResultStateComputer,
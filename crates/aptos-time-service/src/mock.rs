@@ -175,6 +175,27 @@ impl MockTimeService {
         num_woken
     }
 
+    /// Advance time to the latest deadline of the currently pending waiters and
+    /// wake all of them up. Return the number of waiters that we woke up.
+    ///
+    /// Waiters registered by the woken tasks are left pending, so this always
+    /// terminates even with tasks that sleep in a loop.
+    pub fn flush_pending(&self) -> usize {
+        self.lock().flush_pending()
+    }
+
+    /// Wake all currently pending waiters. See [`flush_pending`](#method.flush_pending).
+    ///
+    /// Yields to the executor to run any freshly awoken tasks (which might also
+    /// create more `Sleep` futures).
+    pub async fn flush_pending_async(&self) -> usize {
+        let num_woken = self.lock().flush_pending();
+        // Imporant: don't hold the lock while yielding. We don't want to block
+        // creating new `Sleep`s or letting existing `Sleep`s run.
+        tokio::task::yield_now().await;
+        num_woken
+    }
+
     /// Advance time by `duration` seconds.
     /// See [`advance_async`](#method.advance_async).
     pub async fn advance_secs_async(&self, duration: u64) -> usize {
@@ -253,6 +274,16 @@ impl Inner {
         num_expired
     }
 
+    fn flush_pending(&mut self) -> usize {
+        match self.pending.keys().next_back() {
+            Some(&(last_deadline, _index)) => {
+                let duration = last_deadline.saturating_sub(self.now);
+                self.advance(duration)
+            },
+            None => 0,
+        }
+    }
+
     fn next_sleep_index(&mut self) -> SleepIndex {
         let index = self.next_sleep_index;
         self.next_sleep_index = self
@@ -650,6 +681,27 @@ mod test {
         assert_eq!(time.now_unix_time(), ms(230));
     }
 
+    #[tokio::test]
+    async fn test_flush_pending() {
+        let time = MockTimeService::new();
+
+        // Nothing to flush.
+        assert_eq!(time.flush_pending_async().await, 0);
+        assert_eq!(time.now_unix_time(), ms(0));
+
+        let mut sleep_5ms = task::spawn(time.sleep(ms(5)));
+        let mut sleep_20ms = task::spawn(time.sleep(ms(20)));
+        assert_pending!(sleep_5ms.poll());
+        assert_pending!(sleep_20ms.poll());
+
+        // Flushing wakes everything and stops at the latest deadline.
+        assert_eq!(time.flush_pending_async().await, 2);
+        assert_eq!(time.num_waiters(), 0);
+        assert_eq!(time.now_unix_time(), ms(20));
+        assert_ready!(sleep_5ms.poll());
+        assert_ready!(sleep_20ms.poll());
+    }
+
     #[test]
     fn test_auto_advance_blocking() {
         let time = MockTimeService::new_auto_advance();
@@ -2,12 +2,24 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use aptos_consensus_types::common::Author;
+use aptos_infallible::Mutex;
 use aptos_time_service::{TimeService, TimeServiceTrait};
 use async_trait::async_trait;
 use futures::{stream::FuturesUnordered, Future, StreamExt};
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use futures_channel::oneshot;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap},
+    sync::Arc,
+    time::Duration,
+};
 
-pub trait RBMessage: Send + Sync + Clone {}
+pub trait RBMessage: Send + Sync + Clone {
+    /// Priority of the message when sends are throttled, higher priority messages are sent first.
+    fn priority(&self) -> u8 {
+        0
+    }
+}
 
 #[async_trait]
 pub trait RBNetworkSender<M: RBMessage>: Send + Sync {
@@ -33,6 +45,7 @@ pub struct ReliableBroadcast<M: RBMessage, TBackoff> {
     backoff_policy: TBackoff,
     time_service: TimeService,
     rpc_timeout_duration: Duration,
+    send_scheduler: Option<Arc<SendScheduler>>,
 }
 
 impl<M, TBackoff> ReliableBroadcast<M, TBackoff>
@@ -53,9 +66,28 @@ where
             backoff_policy,
             time_service,
             rpc_timeout_duration,
+            send_scheduler: None,
         }
     }
 
+    /// Limits the number of RPCs in flight across all broadcasts. Beyond it, sends wait for an RPC
+    /// to complete, and the waiting message with the highest priority is sent first.
+    pub fn with_max_concurrent_sends(mut self, max_concurrent_sends: usize) -> Self {
+        assert!(
+            max_concurrent_sends > 0,
+            "max_concurrent_sends must be positive"
+        );
+        self.send_scheduler = Some(Arc::new(SendScheduler::new(max_concurrent_sends)));
+        self
+    }
+
+    /// Number of sends waiting for an RPC to complete, always 0 if sends are not throttled.
+    pub fn num_queued_sends(&self) -> usize {
+        self.send_scheduler
+            .as_ref()
+            .map_or(0, |scheduler| scheduler.num_waiting())
+    }
+
     pub fn broadcast<S: BroadcastStatus<M>>(
         &self,
         message: S::Message,
@@ -65,6 +97,7 @@ where
         let network_sender = self.network_sender.clone();
         let time_service = self.time_service.clone();
         let rpc_timeout_duration = self.rpc_timeout_duration;
+        let send_scheduler = self.send_scheduler.clone();
         let mut backoff_policies: HashMap<Author, TBackoff> = self
             .validators
             .iter()
@@ -73,13 +106,18 @@ where
             .collect();
         async move {
             let mut fut = FuturesUnordered::new();
-            let send_message = |receiver, message, sleep_duration: Option<Duration>| {
+            let send_message = |receiver, message: M, sleep_duration: Option<Duration>| {
                 let network_sender = network_sender.clone();
                 let time_service = time_service.clone();
+                let send_scheduler = send_scheduler.clone();
                 async move {
                     if let Some(duration) = sleep_duration {
                         time_service.sleep(duration).await;
                     }
+                    let _permit = match send_scheduler {
+                        Some(scheduler) => Some(scheduler.acquire(message.priority()).await),
+                        None => None,
+                    };
                     (
                         receiver,
                         network_sender
@@ -115,5 +153,136 @@ where
     }
 }
 
+/// Hands out a limited number of send slots, the waiting sends get a released slot by priority,
+/// then in order of arrival.
+struct SendScheduler {
+    max_in_flight: usize,
+    state: Mutex<SchedulerState>,
+}
+
+struct SchedulerState {
+    in_flight: usize,
+    next_seq: u64,
+    waiting: BinaryHeap<WaitingSend>,
+}
+
+struct WaitingSend {
+    priority: u8,
+    seq: u64,
+    tx: oneshot::Sender<()>,
+}
+
+impl WaitingSend {
+    fn key(&self) -> (u8, Reverse<u64>) {
+        (self.priority, Reverse(self.seq))
+    }
+}
+
+impl PartialEq for WaitingSend {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for WaitingSend {}
+
+impl PartialOrd for WaitingSend {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for WaitingSend {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl SendScheduler {
+    fn new(max_in_flight: usize) -> Self {
+        Self {
+            max_in_flight,
+            state: Mutex::new(SchedulerState {
+                in_flight: 0,
+                next_seq: 0,
+                waiting: BinaryHeap::new(),
+            }),
+        }
+    }
+
+    async fn acquire(self: Arc<Self>, priority: u8) -> SendPermit {
+        let rx = {
+            let mut state = self.state.lock();
+            if state.in_flight < self.max_in_flight {
+                state.in_flight += 1;
+                None
+            } else {
+                let (tx, rx) = oneshot::channel();
+                let seq = state.next_seq;
+                state.next_seq += 1;
+                state.waiting.push(WaitingSend { priority, seq, tx });
+                Some(rx)
+            }
+        };
+        if let Some(rx) = rx {
+            let mut grant = SlotGrant {
+                rx,
+                scheduler: self.clone(),
+                granted: false,
+            };
+            // the slot is handed over by the released permit, the scheduler outlives the waiters
+            let _ = (&mut grant.rx).await;
+            grant.granted = true;
+        }
+        SendPermit { scheduler: self }
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock();
+        while let Some(waiting) = state.waiting.pop() {
+            // the send may have been dropped while waiting, e.g. when its broadcast is aborted
+            if waiting.tx.send(()).is_ok() {
+                return;
+            }
+        }
+        state.in_flight -= 1;
+    }
+
+    fn num_waiting(&self) -> usize {
+        self.state.lock().waiting.len()
+    }
+}
+
+/// A send slot, released when dropped.
+struct SendPermit {
+    scheduler: Arc<SendScheduler>,
+}
+
+impl Drop for SendPermit {
+    fn drop(&mut self) {
+        self.scheduler.release();
+    }
+}
+
+/// A send waiting for a slot. If dropped after the slot was handed over but before the send took
+/// it, the slot is released again.
+struct SlotGrant {
+    rx: oneshot::Receiver<()>,
+    scheduler: Arc<SendScheduler>,
+    granted: bool,
+}
+
+impl Drop for SlotGrant {
+    fn drop(&mut self) {
+        if self.granted {
+            return;
+        }
+        self.rx.close();
+        if let Ok(Some(())) = self.rx.try_recv() {
+            self.scheduler.release();
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests;
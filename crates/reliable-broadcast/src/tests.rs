@@ -200,3 +200,20 @@ async fn test_abort_reliable_broadcast() {
     abort_handle.abort();
     assert!(rx.await.is_err());
 }
+
+#[test]
+#[should_panic(expected = "max_concurrent_sends must be positive")]
+fn test_zero_max_concurrent_sends() {
+    let (_, validator_verifier) = random_validator_verifier(5, None, false);
+    let validators = validator_verifier.get_ordered_account_addresses();
+    let sender = Arc::new(TestRBSender::<TestRBMessage>::new(HashMap::new()));
+    // no send could ever acquire a permit
+    let _ = ReliableBroadcast::new(
+        validators,
+        sender,
+        FixedInterval::from_millis(10),
+        TimeService::real(),
+        Duration::from_millis(500),
+    )
+    .with_max_concurrent_sends(0);
+}
@@ -0,0 +1,46 @@
+// Copyright © Aptos Foundation
+
+use aptos_types::{
+    block_executor::partitioner::PartitionedTransactions,
+    transaction::analyzed_transaction::{AnalyzedTransaction, StorageLocation},
+};
+use std::collections::HashSet;
+
+/// Whether a candidate txn could run in parallel with a partitioned block, if it were added to it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConflictAnalysis {
+    /// No txn in the block writes what the candidate accesses, or accesses what the candidate writes.
+    Parallel,
+    /// The candidate conflicts with some txns in the block and would be serialized against them.
+    Serialized { num_conflicting_txns: usize },
+}
+
+/// Check the declared read/write hints of `txn` against every txn in `partition`.
+pub fn conflict_analysis(
+    txn: &AnalyzedTransaction,
+    partition: &PartitionedTransactions,
+) -> ConflictAnalysis {
+    let reads: HashSet<&StorageLocation> = txn.read_hints().iter().collect();
+    let writes: HashSet<&StorageLocation> = txn.write_hints().iter().collect();
+    let conflicts_with = |other: &AnalyzedTransaction| {
+        other
+            .write_hints()
+            .iter()
+            .any(|loc| reads.contains(loc) || writes.contains(loc))
+            || other.read_hints().iter().any(|loc| writes.contains(loc))
+    };
+    let num_conflicting_txns = partition
+        .sharded_txns()
+        .iter()
+        .flat_map(|sub_blocks| sub_blocks.iter())
+        .chain(partition.global_txns.iter())
+        .filter(|txn_with_deps| conflicts_with(txn_with_deps.txn()))
+        .count();
+    if num_conflicting_txns == 0 {
+        ConflictAnalysis::Parallel
+    } else {
+        ConflictAnalysis::Serialized {
+            num_conflicting_txns,
+        }
+    }
+}
@@ -2,10 +2,13 @@
 // Parts of the project are originally copyright © Meta Platforms, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod conflict_analysis;
+pub mod report;
 pub mod v2;
 
 pub mod test_utils;
 
+use crate::conflict_analysis::{conflict_analysis, ConflictAnalysis};
 use aptos_types::{
     block_executor::partitioner::{PartitionedTransactions, ShardId},
     transaction::analyzed_transaction::{AnalyzedTransaction, StorageLocation},
@@ -27,11 +30,22 @@ pub trait PartitionerConfig: Debug {
 mod tests;
 
 pub trait BlockPartitioner: Send {
+    /// Partition `transactions` into `num_shards` sub-blocks. Shards beyond the number of txns
+    /// are left idle with empty sub-blocks, see `PartitionReport::num_utilized_shards`.
     fn partition(
         &self,
         transactions: Vec<AnalyzedTransaction>,
         num_shards: usize, //TODO: rethink about whether this is needed as part of `BlockPartitioner` API.
     ) -> PartitionedTransactions;
+
+    /// Report whether `txn` would run in parallel with, or be serialized against, a block partitioned by this partitioner.
+    fn conflict_analysis(
+        &self,
+        txn: &AnalyzedTransaction,
+        partition: &PartitionedTransactions,
+    ) -> ConflictAnalysis {
+        conflict_analysis(txn, partition)
+    }
 }
 
 /// When multiple transactions access the same storage location,
@@ -0,0 +1,40 @@
+// Copyright © Aptos Foundation
+
+use crate::pre_partition::{
+    account_sharding::{hash_account_to_shard, AccountShardingFn, AccountShardingPartitioner},
+    PrePartitioner, PrePartitionerConfig,
+};
+use std::{fmt, sync::Arc};
+
+#[derive(Clone)]
+pub struct AccountShardingPartitionerConfig {
+    /// See the comments of `aptos_block_partitioner::pre_partition::account_sharding::AccountShardingPartitioner`.
+    pub sharding_fn: AccountShardingFn,
+}
+
+impl AccountShardingPartitionerConfig {
+    pub fn new(sharding_fn: AccountShardingFn) -> Self {
+        Self { sharding_fn }
+    }
+}
+
+impl Default for AccountShardingPartitionerConfig {
+    fn default() -> Self {
+        Self::new(Arc::new(hash_account_to_shard))
+    }
+}
+
+impl fmt::Debug for AccountShardingPartitionerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccountShardingPartitionerConfig")
+            .finish_non_exhaustive()
+    }
+}
+
+impl PrePartitionerConfig for AccountShardingPartitionerConfig {
+    fn build(&self) -> Box<dyn PrePartitioner> {
+        Box::new(AccountShardingPartitioner {
+            sharding_fn: self.sharding_fn.clone(),
+        })
+    }
+}
@@ -0,0 +1,81 @@
+// Copyright © Aptos Foundation
+
+use crate::{
+    pre_partition::PrePartitioner,
+    v2::{
+        state::PartitionState,
+        types::{OriginalTxnIdx, PrePartitionedTxnIdx},
+    },
+};
+use aptos_types::block_executor::partitioner::ShardId;
+use move_core_types::account_address::AccountAddress;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+/// Maps an account to the shard its txns are assigned to, given the number of shards.
+pub type AccountShardingFn = Arc<dyn Fn(&AccountAddress, usize) -> ShardId + Send + Sync>;
+
+/// The default `AccountShardingFn`: spreads the accounts over the shards by their hash.
+pub fn hash_account_to_shard(address: &AccountAddress, num_shards: usize) -> ShardId {
+    let mut hasher = DefaultHasher::new();
+    address.hash(&mut hasher);
+    (hasher.finish() % num_shards as u64) as usize
+}
+
+/// A `PrePartitioner` used in `PartitionerV2` that assigns every txn to the shard of its sender, as given by a
+/// pluggable `AccountShardingFn`, e.g. to place the hot accounts of a workload with known access skew.
+///
+/// Txns from the same sender land in the same shard, in their original order. Txns without a sender go to shard 0.
+/// Conflicts between txns assigned to different shards are resolved by the later phases of `PartitionerV2`,
+/// as with any other pre-partitioner, so the function only affects the load balance and the number of cross-shard
+/// dependencies, not correctness.
+pub struct AccountShardingPartitioner {
+    pub sharding_fn: AccountShardingFn,
+}
+
+impl PrePartitioner for AccountShardingPartitioner {
+    fn pre_partition(
+        &self,
+        state: &PartitionState,
+    ) -> (
+        Vec<OriginalTxnIdx>,
+        Vec<PrePartitionedTxnIdx>,
+        Vec<Vec<PrePartitionedTxnIdx>>,
+    ) {
+        let num_shards = state.num_executor_shards;
+        let mut ori_txns_idxs_by_shard: Vec<Vec<OriginalTxnIdx>> = vec![vec![]; num_shards];
+        for ori_txn_idx in 0..state.num_txns() {
+            let sender = state.txns[ori_txn_idx]
+                .read()
+                .unwrap()
+                .as_ref()
+                .unwrap()
+                .sender();
+            let shard_id = sender.map_or(0, |sender| (self.sharding_fn)(&sender, num_shards));
+            assert!(
+                shard_id < num_shards,
+                "Account {:?} mapped to shard {}, but there are only {} shards",
+                sender,
+                shard_id,
+                num_shards
+            );
+            ori_txns_idxs_by_shard[shard_id].push(ori_txn_idx);
+        }
+
+        let mut ori_txn_idxs = Vec::with_capacity(state.num_txns());
+        let mut start_txn_idxs_by_shard = vec![0; num_shards];
+        let mut pre_partitioned = Vec::with_capacity(num_shards);
+        for (shard_id, txn_idxs) in ori_txns_idxs_by_shard.into_iter().enumerate() {
+            let start = ori_txn_idxs.len();
+            start_txn_idxs_by_shard[shard_id] = start;
+            ori_txn_idxs.extend(txn_idxs);
+            pre_partitioned.push((start..ori_txn_idxs.len()).collect());
+        }
+        (ori_txn_idxs, start_txn_idxs_by_shard, pre_partitioned)
+    }
+}
+
+pub mod config;
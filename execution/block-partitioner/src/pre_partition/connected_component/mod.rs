@@ -85,9 +85,11 @@ impl PrePartitioner for ConnectedComponentPartitioner {
         }
 
         // Calculate txn group size limit.
+        // With more shards than txns, groups are single txns and the extra shards get no work.
         let group_size_limit = ((state.num_txns() as f32) * self.load_imbalance_tolerance
             / (state.num_executor_shards as f32))
-            .ceil() as usize;
+            .ceil()
+            .max(1.0) as usize;
 
         // Prepare `group_metadata`, a group_metadata (i, r) will later be converted to a real group that takes `r` txns from set `i`.
         // NOTE: If we create actual txn groups now and then do load-balanced scheduling, we break the relative order of txns from the same sender.
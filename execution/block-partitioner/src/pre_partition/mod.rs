@@ -37,6 +37,7 @@ pub trait PrePartitioner: Send {
     );
 }
 
+pub mod account_sharding;
 pub mod connected_component;
 pub mod uniform_partitioner;
 
@@ -0,0 +1,117 @@
+// Copyright © Aptos Foundation
+
+use aptos_types::{
+    block_executor::partitioner::{
+        PartitionedTransactions, ShardedTxnIndex, TxnIndex, GLOBAL_ROUND_ID, GLOBAL_SHARD_ID,
+    },
+    transaction::analyzed_transaction::StorageLocation,
+};
+use std::collections::HashMap;
+
+/// Summary of how a block was split across the shards, for evaluating the partitioner output.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PartitionReport {
+    pub num_shards: usize,
+    /// Number of txns assigned to each shard, over all rounds.
+    pub num_txns_by_shard: Vec<usize>,
+    pub num_global_txns: usize,
+    /// Number of shards that got at least one txn. With fewer txns than shards, the other shards
+    /// stay idle.
+    pub num_utilized_shards: usize,
+    /// The cross-shard dependency ending the longest chain of txns that had to run one after the
+    /// other, `None` if no txn depends on another shard.
+    pub longest_dependency: Option<CrossShardDependencyReport>,
+}
+
+/// A cross-shard dependency, with the length of the longest chain of dependencies it ends.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CrossShardDependencyReport {
+    /// The txn whose write had to be waited for.
+    pub required_txn: ShardedTxnIndex,
+    /// The txn that waited for it.
+    pub dependent_txn: ShardedTxnIndex,
+    /// The location written by `required_txn` and accessed by `dependent_txn`.
+    pub location: StorageLocation,
+    /// Number of txns in the chain, including both ends of this dependency.
+    pub chain_length: usize,
+}
+
+impl PartitionReport {
+    pub fn new(partition: &PartitionedTransactions) -> Self {
+        let num_txns_by_shard: Vec<usize> = partition
+            .sharded_txns()
+            .iter()
+            .map(|sub_blocks| sub_blocks.num_txns())
+            .collect();
+        let num_utilized_shards = num_txns_by_shard
+            .iter()
+            .filter(|num_txns| **num_txns > 0)
+            .count();
+        Self {
+            num_shards: partition.num_shards(),
+            num_txns_by_shard,
+            num_global_txns: partition.global_txns.len(),
+            num_utilized_shards,
+            longest_dependency: longest_dependency(partition),
+        }
+    }
+}
+
+/// Finds the dependency ending the longest chain. A txn only requires txns of earlier rounds, so
+/// visiting the rounds in order sees every required txn before the txns depending on it.
+fn longest_dependency(partition: &PartitionedTransactions) -> Option<CrossShardDependencyReport> {
+    let num_rounds = partition
+        .sharded_txns()
+        .iter()
+        .map(|sub_blocks| sub_blocks.num_sub_blocks())
+        .max()
+        .unwrap_or(0);
+    let mut txns = vec![];
+    for round_id in 0..num_rounds {
+        for (shard_id, sub_blocks) in partition.sharded_txns().iter().enumerate() {
+            if let Some(sub_block) = sub_blocks.get_sub_block(round_id) {
+                txns.extend(sub_block.txn_with_index_iter().map(|(txn_index, txn)| {
+                    (ShardedTxnIndex::new(txn_index, shard_id, round_id), txn)
+                }));
+            }
+        }
+    }
+    let num_sharded_txns = partition.num_sharded_txns();
+    txns.extend(partition.global_txns.iter().enumerate().map(|(i, txn)| {
+        let txn_index = num_sharded_txns + i;
+        (
+            ShardedTxnIndex::new(txn_index, GLOBAL_SHARD_ID, GLOBAL_ROUND_ID),
+            txn,
+        )
+    }));
+
+    let mut chain_lengths: HashMap<TxnIndex, usize> = HashMap::new();
+    let mut longest: Option<CrossShardDependencyReport> = None;
+    for (dependent_txn, txn) in txns {
+        let mut chain_length = 1;
+        for (required_txn, locations) in txn.cross_shard_dependencies().required_edges_iter() {
+            let length = chain_lengths
+                .get(&required_txn.txn_index)
+                .copied()
+                .unwrap_or(1)
+                + 1;
+            chain_length = chain_length.max(length);
+            if longest
+                .as_ref()
+                .map_or(false, |longest| longest.chain_length >= length)
+            {
+                continue;
+            }
+            if let Some(location) = locations.first() {
+                longest = Some(CrossShardDependencyReport {
+                    required_txn: *required_txn,
+                    dependent_txn,
+                    location: location.clone(),
+                    chain_length: length,
+                });
+            }
+        }
+        chain_lengths.insert(dependent_txn.txn_index, chain_length);
+    }
+    longest
+}
@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    conflict_analysis::ConflictAnalysis,
+    report::PartitionReport,
     test_utils::{
         create_non_conflicting_p2p_transaction, create_signed_p2p_transaction,
         generate_test_account, verify_partitioner_output,
@@ -10,7 +12,10 @@ use crate::{
     PartitionerConfig,
 };
 use aptos_crypto::hash::CryptoHash;
-use aptos_types::{block_executor::partitioner::SubBlocksForShard, transaction::Transaction};
+use aptos_types::{
+    block_executor::partitioner::SubBlocksForShard,
+    transaction::{analyzed_transaction::AnalyzedTransaction, Transaction},
+};
 use move_core_types::account_address::AccountAddress;
 use rand::{rngs::OsRng, Rng};
 use std::{collections::HashMap, sync::Mutex};
@@ -144,3 +149,106 @@ fn test_no_conflict_across_shards_in_non_last_rounds() {
         }
     }
 }
+
+#[test]
+fn test_conflict_analysis() {
+    let num_shards = 2;
+    let mut accounts: Vec<_> = (0..4).map(|_| generate_test_account()).collect();
+    let (senders, receivers) = accounts.split_at_mut(2);
+    let mut transactions = vec![];
+    for (sender, receiver) in senders.iter_mut().zip(receivers.iter()) {
+        transactions.extend(create_signed_p2p_transaction(sender, vec![receiver]));
+    }
+    let partitioner = PartitionerV2Config::default().build();
+    let partitioned_txns = partitioner.partition(transactions, num_shards);
+
+    let independent_txn = create_non_conflicting_p2p_transaction();
+    assert_eq!(
+        partitioner.conflict_analysis(&independent_txn, &partitioned_txns),
+        ConflictAnalysis::Parallel
+    );
+
+    // Sending from an account that's already a sender in the block.
+    let conflicting_txn =
+        create_signed_p2p_transaction(&mut senders[0], vec![&generate_test_account()]).remove(0);
+    assert_eq!(
+        partitioner.conflict_analysis(&conflicting_txn, &partitioned_txns),
+        ConflictAnalysis::Serialized {
+            num_conflicting_txns: 1
+        }
+    );
+}
+
+#[test]
+fn test_more_shards_than_txns() {
+    let num_shards = 8;
+    let transactions: Vec<_> = (0..3)
+        .map(|_| create_non_conflicting_p2p_transaction())
+        .collect();
+    let partitioner = PartitionerV2Config::default().build();
+    let partitioned_txns = partitioner.partition(transactions.clone(), num_shards);
+    verify_partitioner_output(&transactions, &partitioned_txns);
+    assert_eq!(partitioned_txns.num_shards(), num_shards);
+
+    let report = PartitionReport::new(&partitioned_txns);
+    assert_eq!(report.num_shards, num_shards);
+    assert_eq!(report.num_txns_by_shard.len(), num_shards);
+    assert_eq!(
+        report.num_txns_by_shard.iter().sum::<usize>() + report.num_global_txns,
+        3
+    );
+    assert!(report.num_utilized_shards <= 3);
+    assert_eq!(
+        report.num_utilized_shards,
+        report
+            .num_txns_by_shard
+            .iter()
+            .filter(|num_txns| **num_txns > 0)
+            .count()
+    );
+}
+
+#[test]
+fn test_partition_report_longest_dependency() {
+    let num_shards = 4;
+    let hot_receiver = generate_test_account();
+    let mut hot_senders = Vec::new();
+    let mut transactions = Vec::new();
+    for _ in 0..8 {
+        let mut sender = generate_test_account();
+        hot_senders.push(sender.account_address);
+        transactions.extend(create_signed_p2p_transaction(
+            &mut sender,
+            vec![&hot_receiver],
+        ));
+        transactions.push(create_non_conflicting_p2p_transaction());
+    }
+    let partitioner = PartitionerV2Config::default()
+        .partition_last_round(true)
+        .build();
+    let partitioned_txns = partitioner.partition(transactions.clone(), num_shards);
+    verify_partitioner_output(&transactions, &partitioned_txns);
+
+    let report = PartitionReport::new(&partitioned_txns);
+    let longest = report.longest_dependency.unwrap();
+    // only the transfers to the hot receiver conflict, on the receiver's coin store
+    assert_eq!(
+        longest.location,
+        AnalyzedTransaction::coin_store_location(hot_receiver.account_address)
+    );
+    assert!(longest.chain_length >= 2);
+    assert!(longest.required_txn.round_id < longest.dependent_txn.round_id);
+
+    let sender_of = |txn_index| {
+        partitioned_txns
+            .sharded_txns()
+            .iter()
+            .flat_map(|sub_blocks| sub_blocks.sub_block_iter())
+            .flat_map(|sub_block| sub_block.txn_with_index_iter())
+            .find(|(index, _)| *index == txn_index)
+            .and_then(|(_, txn)| txn.txn().sender())
+            .unwrap()
+    };
+    assert!(hot_senders.contains(&sender_of(longest.required_txn.txn_index)));
+    assert!(hot_senders.contains(&sender_of(longest.dependent_txn.txn_index)));
+}
@@ -3,7 +3,8 @@
 use crate::v2::{counters::MISC_TIMERS_SECONDS, state::PartitionState, PartitionerV2};
 use aptos_types::{
     block_executor::partitioner::{
-        PartitionedTransactions, SubBlock, SubBlocksForShard, TransactionWithDependencies,
+        PartitionedTransactions, RoundId, ShardId, SubBlock, SubBlocksForShard,
+        TransactionWithDependencies,
     },
     transaction::analyzed_transaction::AnalyzedTransaction,
 };
@@ -38,14 +39,7 @@ impl PartitionerV2 {
                     (0..state.num_executor_shards)
                         .into_par_iter()
                         .for_each(|shard_id| {
-                            let twds = state.finalized_txn_matrix[round_id][shard_id]
-                                .par_iter()
-                                .map(|&txn_idx1| {
-                                    state.take_txn_with_dep(round_id, shard_id, txn_idx1)
-                                })
-                                .collect();
-                            let sub_block =
-                                SubBlock::new(state.start_index_matrix[round_id][shard_id], twds);
+                            let sub_block = Self::build_sub_block(state, round_id, shard_id);
                             *state.sub_block_matrix[round_id][shard_id].lock().unwrap() =
                                 Some(sub_block);
                         });
@@ -87,4 +81,29 @@ impl PartitionerV2 {
 
         PartitionedTransactions::new(sharded_txns, global_txns)
     }
+
+    /// Build the sub-blocks of a round, indexed by shard id, with the edges of their txns.
+    pub(crate) fn build_sub_blocks_for_round(
+        state: &PartitionState,
+        round_id: RoundId,
+    ) -> Vec<SubBlock<AnalyzedTransaction>> {
+        state.thread_pool.install(|| {
+            (0..state.num_executor_shards)
+                .into_par_iter()
+                .map(|shard_id| Self::build_sub_block(state, round_id, shard_id))
+                .collect()
+        })
+    }
+
+    fn build_sub_block(
+        state: &PartitionState,
+        round_id: RoundId,
+        shard_id: ShardId,
+    ) -> SubBlock<AnalyzedTransaction> {
+        let twds = state.finalized_txn_matrix[round_id][shard_id]
+            .par_iter()
+            .map(|&txn_idx1| state.take_txn_with_dep(round_id, shard_id, txn_idx1))
+            .collect();
+        SubBlock::new(state.start_index_matrix[round_id][shard_id], twds)
+    }
 }
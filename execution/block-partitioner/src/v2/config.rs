@@ -16,6 +16,9 @@ pub struct PartitionerV2Config {
     pub dashmap_num_shards: usize,
     pub partition_last_round: bool,
     pub pre_partitioner_config: Box<dyn PrePartitionerConfig>,
+    /// Route a block with a txn of more hints than this to sequential execution, see
+    /// `PartitionerV2::with_max_hints_per_txn`. Unbounded if not set.
+    pub max_hints_per_txn: Option<usize>,
 }
 
 impl PartitionerV2Config {
@@ -48,6 +51,11 @@ impl PartitionerV2Config {
         self.pre_partitioner_config = val;
         self
     }
+
+    pub fn max_hints_per_txn(mut self, val: Option<usize>) -> Self {
+        self.max_hints_per_txn = val;
+        self
+    }
 }
 
 impl Default for PartitionerV2Config {
@@ -59,6 +67,7 @@ impl Default for PartitionerV2Config {
             dashmap_num_shards: 64,
             partition_last_round: false,
             pre_partitioner_config: Box::<ConnectedComponentPartitionerConfig>::default(),
+            max_hints_per_txn: None,
         }
     }
 }
@@ -66,13 +75,16 @@ impl Default for PartitionerV2Config {
 impl PartitionerConfig for PartitionerV2Config {
     fn build(&self) -> Box<dyn BlockPartitioner> {
         let pre_partitioner = self.pre_partitioner_config.build();
-        Box::new(PartitionerV2::new(
-            self.num_threads,
-            self.max_partitioning_rounds,
-            self.cross_shard_dep_avoid_threshold,
-            self.dashmap_num_shards,
-            self.partition_last_round,
-            pre_partitioner,
-        ))
+        Box::new(
+            PartitionerV2::new(
+                self.num_threads,
+                self.max_partitioning_rounds,
+                self.cross_shard_dep_avoid_threshold,
+                self.dashmap_num_shards,
+                self.partition_last_round,
+                pre_partitioner,
+            )
+            .with_max_hints_per_txn(self.max_hints_per_txn),
+        )
     }
 }
@@ -2,14 +2,21 @@
 
 use crate::{
     pre_partition::PrePartitioner, v2::counters::BLOCK_PARTITIONING_SECONDS, BlockPartitioner,
+    Sender,
 };
 use aptos_types::{
-    block_executor::partitioner::{PartitionedTransactions, RoundId},
+    block_executor::partitioner::{
+        CrossShardDependencies, PartitionedTransactions, RoundId, ShardId, SubBlocksForShard,
+        TransactionWithDependencies,
+    },
     transaction::analyzed_transaction::AnalyzedTransaction,
 };
 use rayon::{ThreadPool, ThreadPoolBuilder};
 use state::PartitionState;
-use std::sync::{Arc, RwLock};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
 
 mod build_edge;
 pub mod config;
@@ -18,7 +25,9 @@ pub mod counters;
 mod init;
 pub(crate) mod load_balance;
 mod partition_to_matrix;
+mod shard_stickiness;
 pub(crate) mod state;
+pub mod streaming;
 #[cfg(test)]
 mod tests;
 pub mod types;
@@ -100,6 +109,7 @@ pub struct PartitionerV2 {
     cross_shard_dep_avoid_threshold: f32,
     dashmap_num_shards: usize,
     partition_last_round: bool,
+    max_hints_per_txn: Option<usize>,
 }
 
 impl PartitionerV2 {
@@ -124,8 +134,38 @@ impl PartitionerV2 {
             cross_shard_dep_avoid_threshold,
             dashmap_num_shards,
             partition_last_round,
+            max_hints_per_txn: None,
         }
     }
+
+    /// Txns with more read and write hints than this are too costly to analyze for conflicts, and
+    /// are treated as conflicting with the whole state: a block containing one is not partitioned,
+    /// all its txns are executed sequentially as global txns.
+    pub fn with_max_hints_per_txn(mut self, max_hints_per_txn: Option<usize>) -> Self {
+        self.max_hints_per_txn = max_hints_per_txn;
+        self
+    }
+
+    fn exceeds_max_hints(&self, txns: &[AnalyzedTransaction]) -> bool {
+        self.max_hints_per_txn.map_or(false, |max_hints_per_txn| {
+            txns.iter().any(|txn| txn.num_hints() > max_hints_per_txn)
+        })
+    }
+
+    /// The partition executing all the txns in order on the global executor.
+    fn sequential_partition(
+        txns: Vec<AnalyzedTransaction>,
+        num_executor_shards: usize,
+    ) -> PartitionedTransactions {
+        let sharded_txns = (0..num_executor_shards)
+            .map(SubBlocksForShard::empty)
+            .collect();
+        let global_txns = txns
+            .into_iter()
+            .map(|txn| TransactionWithDependencies::new(txn, CrossShardDependencies::default()))
+            .collect();
+        PartitionedTransactions::new(sharded_txns, global_txns)
+    }
 }
 
 impl BlockPartitioner for PartitionerV2 {
@@ -135,7 +175,60 @@ impl BlockPartitioner for PartitionerV2 {
         num_executor_shards: usize,
     ) -> PartitionedTransactions {
         let _timer = BLOCK_PARTITIONING_SECONDS.start_timer();
+        if self.exceeds_max_hints(&txns) {
+            return Self::sequential_partition(txns, num_executor_shards);
+        }
+
+        let mut state = self.build_txn_matrix(txns, num_executor_shards, None);
 
+        // Step 6: calculate all the cross-shard dependencies and prepare the input for sharded execution.
+        let ret = Self::add_edges(&mut state);
+
+        // Async clean-up.
+        self.thread_pool.spawn(move || {
+            drop(state);
+        });
+        ret
+    }
+}
+
+impl PartitionerV2 {
+    /// Partition `txns` like `partition`, but relabel the shards so that senders stay on the shard
+    /// they were assigned to in `shard_by_sender` as much as possible, for better cache locality.
+    /// `shard_by_sender` is then replaced by the assignment of this block, to be passed along with
+    /// the next block.
+    ///
+    /// The relabeling changes the order the txns are executed in, so the output depends on
+    /// `shard_by_sender` as much as on `txns`. Every node executing the block must pass the same
+    /// assignment, e.g. the one of the previous committed block, or they compute different results.
+    pub fn partition_with_shard_stickiness(
+        &self,
+        txns: Vec<AnalyzedTransaction>,
+        num_executor_shards: usize,
+        shard_by_sender: &mut HashMap<Sender, ShardId>,
+    ) -> PartitionedTransactions {
+        let _timer = BLOCK_PARTITIONING_SECONDS.start_timer();
+        if self.exceeds_max_hints(&txns) {
+            shard_by_sender.clear();
+            return Self::sequential_partition(txns, num_executor_shards);
+        }
+
+        let mut state = self.build_txn_matrix(txns, num_executor_shards, Some(shard_by_sender));
+        let ret = Self::add_edges(&mut state);
+        self.thread_pool.spawn(move || {
+            drop(state);
+        });
+        ret
+    }
+
+    /// Steps 1 to 5 of the partitioning: the txns are placed into the final txn matrix, before
+    /// their cross-shard dependencies are calculated.
+    fn build_txn_matrix(
+        &self,
+        txns: Vec<AnalyzedTransaction>,
+        num_executor_shards: usize,
+        shard_by_sender: Option<&mut HashMap<Sender, ShardId>>,
+    ) -> PartitionState {
         let mut state = PartitionState::new(
             self.thread_pool.clone(),
             self.dashmap_num_shards,
@@ -155,6 +248,12 @@ impl BlockPartitioner for PartitionerV2 {
             state.pre_partitioned,
         ) = self.pre_partitioner.pre_partition(&state);
 
+        // Step 2.1 (optional): keep senders on the shards they were assigned to in the previous block.
+        if let Some(shard_by_sender) = shard_by_sender {
+            Self::apply_shard_stickiness(&mut state, shard_by_sender);
+            *shard_by_sender = Self::shard_by_sender(&state);
+        }
+
         // Step 3: update trackers.
         for txn_idx1 in 0..state.num_txns() {
             let ori_txn_idx = state.ori_idxs_by_pre_partitioned[txn_idx1];
@@ -180,15 +279,7 @@ impl BlockPartitioner for PartitionerV2 {
 
         // Step 5: build some additional indices of the resulting txn matrix from the previous step.
         Self::build_index_from_txn_matrix(&mut state);
-
-        // Step 6: calculate all the cross-shard dependencies and prepare the input for sharded execution.
-        let ret = Self::add_edges(&mut state);
-
-        // Async clean-up.
-        self.thread_pool.spawn(move || {
-            drop(state);
-        });
-        ret
+        state
     }
 }
 
@@ -0,0 +1,119 @@
+// Copyright © Aptos Foundation
+
+use crate::{
+    v2::{
+        counters::MISC_TIMERS_SECONDS,
+        state::PartitionState,
+        types::{OriginalTxnIdx, SenderIdx},
+        PartitionerV2,
+    },
+    Sender,
+};
+use aptos_types::block_executor::partitioner::ShardId;
+use std::collections::HashMap;
+
+impl PartitionerV2 {
+    /// Relabel the pre-partitioned shards so that senders stay on the shard they were assigned to in the previous block,
+    /// as much as possible.
+    ///
+    /// Only the shard ids are permuted, so the load of every shard stays the same. But the txns are laid out shard by shard
+    /// in the new order, which changes which conflicting txns are moved to later rounds, and so the execution order of the
+    /// block: the output depends on `previous_shard_by_sender`, see `PartitionerV2::partition_with_shard_stickiness`.
+    /// The relabeling is skipped if it would reorder txns from the same sender.
+    pub(crate) fn apply_shard_stickiness(
+        state: &mut PartitionState,
+        previous_shard_by_sender: &HashMap<Sender, ShardId>,
+    ) {
+        let _timer = MISC_TIMERS_SECONDS
+            .with_label_values(&["apply_shard_stickiness"])
+            .start_timer();
+        let num_shards = state.num_executor_shards;
+
+        // Count, for every (current shard, previous shard) pair, the txns whose senders moved between them.
+        let mut overlaps: HashMap<(ShardId, ShardId), usize> = HashMap::new();
+        for (shard_id, txn_idxs) in state.pre_partitioned.iter().enumerate() {
+            for &txn_idx in txn_idxs {
+                let ori_txn_idx = state.ori_idxs_by_pre_partitioned[txn_idx];
+                if let Some(&previous_shard_id) =
+                    previous_shard_by_sender.get(&Self::sender(state, ori_txn_idx))
+                {
+                    if previous_shard_id < num_shards {
+                        *overlaps.entry((shard_id, previous_shard_id)).or_default() += 1;
+                    }
+                }
+            }
+        }
+
+        // Greedily give every shard the id of the previous shard it overlaps the most with.
+        let mut candidates: Vec<((ShardId, ShardId), usize)> = overlaps.into_iter().collect();
+        candidates.sort_by(|(pair_a, count_a), (pair_b, count_b)| {
+            count_b.cmp(count_a).then(pair_a.cmp(pair_b))
+        });
+        let mut new_ids: Vec<Option<ShardId>> = vec![None; num_shards];
+        let mut id_taken = vec![false; num_shards];
+        for ((shard_id, previous_shard_id), _) in candidates {
+            if new_ids[shard_id].is_none() && !id_taken[previous_shard_id] {
+                new_ids[shard_id] = Some(previous_shard_id);
+                id_taken[previous_shard_id] = true;
+            }
+        }
+        let mut free_ids = (0..num_shards).filter(|id| !id_taken[*id]);
+        let mut shard_ids_by_new_id = vec![0; num_shards];
+        for (shard_id, new_id) in new_ids.into_iter().enumerate() {
+            let new_id = new_id.unwrap_or_else(|| free_ids.next().unwrap());
+            shard_ids_by_new_id[new_id] = shard_id;
+        }
+
+        // Lay the txns out again, shard by shard in the new order.
+        let mut ori_idxs_by_pre_partitioned: Vec<OriginalTxnIdx> =
+            Vec::with_capacity(state.num_txns());
+        let mut start_txn_idxs_by_shard = vec![0; num_shards];
+        let mut pre_partitioned = Vec::with_capacity(num_shards);
+        for (new_id, &shard_id) in shard_ids_by_new_id.iter().enumerate() {
+            let start = ori_idxs_by_pre_partitioned.len();
+            start_txn_idxs_by_shard[new_id] = start;
+            ori_idxs_by_pre_partitioned.extend(
+                state.pre_partitioned[shard_id]
+                    .iter()
+                    .map(|&txn_idx| state.ori_idxs_by_pre_partitioned[txn_idx]),
+            );
+            pre_partitioned.push((start..ori_idxs_by_pre_partitioned.len()).collect());
+        }
+
+        let mut last_ori_idx_by_sender: HashMap<SenderIdx, OriginalTxnIdx> = HashMap::new();
+        for &ori_txn_idx in ori_idxs_by_pre_partitioned.iter() {
+            if let Some(last_ori_idx) =
+                last_ori_idx_by_sender.insert(state.sender_idx(ori_txn_idx), ori_txn_idx)
+            {
+                if last_ori_idx > ori_txn_idx {
+                    return;
+                }
+            }
+        }
+
+        state.ori_idxs_by_pre_partitioned = ori_idxs_by_pre_partitioned;
+        state.start_txn_idxs_by_shard = start_txn_idxs_by_shard;
+        state.pre_partitioned = pre_partitioned;
+    }
+
+    /// The shard each sender is pre-partitioned into, to be used as the bias for the next block.
+    pub(crate) fn shard_by_sender(state: &PartitionState) -> HashMap<Sender, ShardId> {
+        let mut shard_by_sender = HashMap::new();
+        for (shard_id, txn_idxs) in state.pre_partitioned.iter().enumerate() {
+            for &txn_idx in txn_idxs {
+                let ori_txn_idx = state.ori_idxs_by_pre_partitioned[txn_idx];
+                shard_by_sender.insert(Self::sender(state, ori_txn_idx), shard_id);
+            }
+        }
+        shard_by_sender
+    }
+
+    fn sender(state: &PartitionState, ori_txn_idx: OriginalTxnIdx) -> Sender {
+        state.txns[ori_txn_idx]
+            .read()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .sender()
+    }
+}
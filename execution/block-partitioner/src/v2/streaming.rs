@@ -0,0 +1,104 @@
+// Copyright © Aptos Foundation
+
+use crate::v2::{counters::BLOCK_PARTITIONING_SECONDS, PartitionerV2};
+use aptos_types::{
+    block_executor::partitioner::{
+        PartitionedTransactions, RoundId, SubBlock, SubBlocksForShard, TransactionWithDependencies,
+    },
+    transaction::analyzed_transaction::AnalyzedTransaction,
+};
+use std::sync::mpsc::{channel, Receiver};
+
+/// A part of a block partitioned in streaming mode.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PartitionedChunk {
+    /// The sub-blocks of a round, indexed by shard id. Its txns only require txns of earlier
+    /// rounds, so the shards can start executing it before the later rounds are emitted.
+    Round(RoundId, Vec<SubBlock<AnalyzedTransaction>>),
+    /// The txns to execute on the global executor after all the rounds, always emitted last.
+    Global(Vec<TransactionWithDependencies<AnalyzedTransaction>>),
+}
+
+/// The chunks of a block partitioned in streaming mode, in the order the shards execute them.
+pub struct PartitionStream {
+    num_executor_shards: usize,
+    rx: Receiver<PartitionedChunk>,
+}
+
+impl Iterator for PartitionStream {
+    type Item = PartitionedChunk;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rx.recv().ok()
+    }
+}
+
+impl PartitionStream {
+    /// Wait for all the chunks and put them together into the output of batch partitioning.
+    pub fn collect_partitioned(self) -> PartitionedTransactions {
+        let mut sharded_txns: Vec<_> = (0..self.num_executor_shards)
+            .map(SubBlocksForShard::empty)
+            .collect();
+        let mut global_txns = vec![];
+        for chunk in self {
+            match chunk {
+                PartitionedChunk::Round(_, sub_blocks) => {
+                    for (shard_id, sub_block) in sub_blocks.into_iter().enumerate() {
+                        sharded_txns[shard_id].add_sub_block(sub_block);
+                    }
+                },
+                PartitionedChunk::Global(txns) => global_txns = txns,
+            }
+        }
+        PartitionedTransactions::new(sharded_txns, global_txns)
+    }
+}
+
+impl PartitionerV2 {
+    /// Partition `txns` in streaming mode. Once the txns are placed into rounds, the sub-blocks of
+    /// each round are emitted as soon as the cross-shard dependencies of their txns are calculated,
+    /// so the shards can start on the early rounds while the later rounds are still being built.
+    /// Put together, the chunks are the same as the output of `partition`, including for blocks
+    /// routed to sequential execution, which are emitted as a single chunk of global txns.
+    pub fn partition_streaming(
+        &self,
+        txns: Vec<AnalyzedTransaction>,
+        num_executor_shards: usize,
+    ) -> PartitionStream {
+        let timer = BLOCK_PARTITIONING_SECONDS.start_timer();
+        let (tx, rx) = channel();
+        if self.exceeds_max_hints(&txns) {
+            let (_, global_txns) = Self::sequential_partition(txns, num_executor_shards).into();
+            tx.send(PartitionedChunk::Global(global_txns))
+                .expect("The receiver is held by the stream");
+            return PartitionStream {
+                num_executor_shards,
+                rx,
+            };
+        }
+        let state = self.build_txn_matrix(txns, num_executor_shards, None);
+
+        self.thread_pool.spawn(move || {
+            let _timer = timer;
+            let num_rounds = state.num_rounds();
+            for round_id in 0..num_rounds {
+                let mut sub_blocks = Self::build_sub_blocks_for_round(&state, round_id);
+                // Without partitioning the last round, all its txns are in the last shard.
+                let chunk = if round_id == num_rounds - 1 && !state.partition_last_round {
+                    let last_sub_block = sub_blocks.pop().unwrap();
+                    PartitionedChunk::Global(last_sub_block.into_transactions_with_deps())
+                } else {
+                    PartitionedChunk::Round(round_id, sub_blocks)
+                };
+                // The stream was dropped, nobody is waiting for the remaining rounds.
+                if tx.send(chunk).is_err() {
+                    break;
+                }
+            }
+        });
+        PartitionStream {
+            num_executor_shards,
+            rx,
+        }
+    }
+}
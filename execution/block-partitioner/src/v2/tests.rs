@@ -4,14 +4,25 @@
 
 use crate::{
     pre_partition::{
-        connected_component::ConnectedComponentPartitioner, uniform_partitioner::UniformPartitioner,
+        account_sharding::AccountShardingPartitioner,
+        connected_component::ConnectedComponentPartitioner,
+        uniform_partitioner::UniformPartitioner,
     },
-    test_utils::{assert_deterministic_result, P2PBlockGenerator},
-    v2::PartitionerV2,
+    test_utils::{
+        assert_deterministic_result, create_non_conflicting_p2p_transaction,
+        create_signed_p2p_transaction, generate_test_account, generate_test_account_for_address,
+        P2PBlockGenerator, TestAccount,
+    },
+    v2::{streaming::PartitionedChunk, PartitionerV2},
     BlockPartitioner,
 };
+use aptos_types::{
+    block_executor::partitioner::{ShardId, SubBlocksForShard},
+    transaction::analyzed_transaction::AnalyzedTransaction,
+};
+use move_core_types::account_address::AccountAddress;
 use rand::{thread_rng, Rng};
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 #[test]
 fn test_partitioner_v2_uniform_correctness() {
@@ -94,3 +105,270 @@ fn test_partitioner_v2_connected_component_determinism() {
         assert_deterministic_result(partitioner);
     }
 }
+
+fn shard_by_sender(
+    sub_blocks: Vec<SubBlocksForShard<AnalyzedTransaction>>,
+) -> HashMap<AccountAddress, ShardId> {
+    let mut shard_by_sender = HashMap::new();
+    for (shard_id, sub_blocks_for_shard) in sub_blocks.into_iter().enumerate() {
+        for txn in sub_blocks_for_shard.iter() {
+            shard_by_sender.insert(txn.txn().sender().unwrap(), shard_id);
+        }
+    }
+    shard_by_sender
+}
+
+#[test]
+fn test_partitioner_v2_shard_stickiness() {
+    let num_shards = 4;
+    // Accounts in different groups never interact, so every group ends up in its own shard.
+    let mut groups: Vec<Vec<TestAccount>> = (0..num_shards)
+        .map(|_| (0..5).map(|_| generate_test_account()).collect())
+        .collect();
+    let mut gen_block = |group_order: &[usize]| {
+        let mut txns = vec![];
+        for &group_id in group_order {
+            let group = &mut groups[group_id];
+            for i in 0..group.len() {
+                let receiver =
+                    generate_test_account_for_address(group[(i + 1) % group.len()].account_address);
+                txns.extend(create_signed_p2p_transaction(
+                    &mut group[i],
+                    vec![&receiver],
+                ));
+            }
+        }
+        txns
+    };
+    let block_1 = gen_block(&[0, 1, 2, 3]);
+    // Same accounts, but the groups show up in a different order.
+    let block_2 = gen_block(&[3, 1, 0, 2]);
+
+    let partitioner = PartitionerV2::new(
+        4,
+        4,
+        0.9,
+        64,
+        false,
+        Box::new(ConnectedComponentPartitioner {
+            load_imbalance_tolerance: 2.0,
+        }),
+    );
+    let mut previous_shards = HashMap::new();
+    let (sub_blocks, _) = partitioner
+        .partition_with_shard_stickiness(block_1, num_shards, &mut previous_shards)
+        .into();
+    let shards_1 = shard_by_sender(sub_blocks);
+    let (sub_blocks, _) = partitioner
+        .partition_with_shard_stickiness(block_2, num_shards, &mut previous_shards)
+        .into();
+    let shards_2 = shard_by_sender(sub_blocks);
+
+    let num_retained = shards_2
+        .iter()
+        .filter(|(sender, shard_id)| shards_1.get(*sender) == Some(*shard_id))
+        .count();
+    assert_eq!(shards_2.len(), 20);
+    assert!(num_retained * 10 >= shards_2.len() * 9);
+}
+
+#[test]
+fn test_partitioner_v2_shard_stickiness_determinism() {
+    let new_partitioner = || {
+        PartitionerV2::new(
+            4,
+            4,
+            0.9,
+            64,
+            false,
+            Box::new(ConnectedComponentPartitioner {
+                load_imbalance_tolerance: 2.0,
+            }),
+        )
+    };
+    let mut rng = thread_rng();
+    let block_generator = P2PBlockGenerator::new(100);
+    let previous_block = block_generator.rand_block(&mut rng, 100);
+    let block = block_generator.rand_block(&mut rng, 100);
+
+    // one partitioner partitioned other blocks before, the other one didn't
+    let partitioner = new_partitioner();
+    let mut unrelated_shards = HashMap::new();
+    for _ in 0..3 {
+        partitioner.partition_with_shard_stickiness(
+            block_generator.rand_block(&mut rng, 100),
+            4,
+            &mut unrelated_shards,
+        );
+    }
+    let fresh_partitioner = new_partitioner();
+
+    // given the same assignment of the previous block, the block is partitioned the same
+    let mut previous_shards = HashMap::new();
+    new_partitioner().partition_with_shard_stickiness(previous_block, 4, &mut previous_shards);
+    let mut shards = previous_shards.clone();
+    let mut fresh_shards = previous_shards;
+    assert_eq!(
+        partitioner.partition_with_shard_stickiness(block.clone(), 4, &mut shards),
+        fresh_partitioner.partition_with_shard_stickiness(block.clone(), 4, &mut fresh_shards)
+    );
+    assert_eq!(shards, fresh_shards);
+
+    // and without stickiness, the earlier blocks make no difference either
+    assert_eq!(
+        partitioner.partition(block.clone(), 4),
+        fresh_partitioner.partition(block, 4)
+    );
+}
+
+fn last_byte_to_shard(address: &AccountAddress, num_shards: usize) -> ShardId {
+    address.into_bytes()[AccountAddress::LENGTH - 1] as usize % num_shards
+}
+
+#[test]
+fn test_partitioner_v2_account_sharding_correctness() {
+    for merge_discarded in [false, true] {
+        let block_generator = P2PBlockGenerator::new(100);
+        let partitioner = PartitionerV2::new(
+            8,
+            4,
+            0.9,
+            64,
+            merge_discarded,
+            Box::new(AccountShardingPartitioner {
+                sharding_fn: Arc::new(last_byte_to_shard),
+            }),
+        );
+        let mut rng = thread_rng();
+        for _run_id in 0..20 {
+            let block_size = 10_u64.pow(rng.gen_range(0, 4)) as usize;
+            let num_shards = rng.gen_range(1, 10);
+            let block = block_generator.rand_block(&mut rng, block_size);
+            let block_clone = block.clone();
+            let partitioned = partitioner.partition(block, num_shards);
+            crate::test_utils::verify_partitioner_output(&block_clone, &partitioned);
+        }
+    }
+}
+
+#[test]
+fn test_partitioner_v2_account_sharding_assignment() {
+    let num_shards = 4;
+    let partitioner = PartitionerV2::new(
+        4,
+        4,
+        0.9,
+        64,
+        false,
+        Box::new(AccountShardingPartitioner {
+            sharding_fn: Arc::new(last_byte_to_shard),
+        }),
+    );
+    // Without conflicts, no txn is moved out of the shard its sender is mapped to.
+    let block: Vec<_> = (0..40)
+        .map(|_| create_non_conflicting_p2p_transaction())
+        .collect();
+    let (sub_blocks, _) = partitioner.partition(block, num_shards).into();
+    let shard_by_sender = shard_by_sender(sub_blocks);
+    assert_eq!(shard_by_sender.len(), 40);
+    for (sender, shard_id) in shard_by_sender {
+        assert_eq!(shard_id, last_byte_to_shard(&sender, num_shards));
+    }
+}
+
+#[test]
+fn test_partitioner_v2_streaming() {
+    for merge_discarded in [false, true] {
+        let block_generator = P2PBlockGenerator::new(100);
+        let partitioner = PartitionerV2::new(
+            8,
+            4,
+            0.9,
+            64,
+            merge_discarded,
+            Box::new(UniformPartitioner {}),
+        );
+        let mut rng = thread_rng();
+        for _run_id in 0..10 {
+            let block_size = 10_u64.pow(rng.gen_range(0, 4)) as usize;
+            let num_shards = rng.gen_range(1, 10);
+            let block = block_generator.rand_block(&mut rng, block_size);
+
+            // The rounds are emitted in order, and only require txns emitted before them.
+            let mut num_emitted_txns = 0;
+            let mut next_round_id = 0;
+            let mut global_emitted = false;
+            for chunk in partitioner.partition_streaming(block.clone(), num_shards) {
+                assert!(!global_emitted);
+                let txns: Vec<_> = match chunk {
+                    PartitionedChunk::Round(round_id, sub_blocks) => {
+                        assert_eq!(round_id, next_round_id);
+                        assert_eq!(sub_blocks.len(), num_shards);
+                        next_round_id += 1;
+                        sub_blocks
+                            .into_iter()
+                            .flat_map(|sub_block| sub_block.into_transactions_with_deps())
+                            .collect()
+                    },
+                    PartitionedChunk::Global(txns) => {
+                        global_emitted = true;
+                        txns
+                    },
+                };
+                for txn in &txns {
+                    for (required_txn, _) in txn.cross_shard_dependencies().required_edges_iter() {
+                        assert!(required_txn.txn_index < num_emitted_txns);
+                    }
+                }
+                num_emitted_txns += txns.len();
+            }
+            assert_eq!(num_emitted_txns, block_size);
+            assert_eq!(global_emitted, !merge_discarded);
+
+            let streamed = partitioner
+                .partition_streaming(block.clone(), num_shards)
+                .collect_partitioned();
+            let batch = partitioner.partition(block, num_shards);
+            assert_eq!(streamed, batch);
+        }
+    }
+}
+
+#[test]
+fn test_partitioner_v2_max_hints_per_txn() {
+    let num_shards = 4;
+    let partitioner = PartitionerV2::new(8, 4, 0.9, 64, false, Box::new(UniformPartitioner {}))
+        .with_max_hints_per_txn(Some(10));
+    let mut block: Vec<_> = (0..20)
+        .map(|_| create_non_conflicting_p2p_transaction())
+        .collect();
+
+    // p2p txns have 8 hints, within the cap
+    let partitioned = partitioner.partition(block.clone(), num_shards);
+    crate::test_utils::verify_partitioner_output(&block, &partitioned);
+    assert!(partitioned.num_sharded_txns() > 0);
+
+    // a txn writing to many accounts exceeds it
+    let p2p_txn = create_non_conflicting_p2p_transaction();
+    let read_hints = p2p_txn.read_hints().to_vec();
+    let write_hints = (0..10)
+        .map(|_| AnalyzedTransaction::coin_store_location(AccountAddress::random()))
+        .collect();
+    let wide_txn = AnalyzedTransaction::new(p2p_txn.into_txn(), read_hints, write_hints);
+    block.insert(10, wide_txn);
+    let partitioned = partitioner.partition(block.clone(), num_shards);
+    crate::test_utils::verify_partitioner_output(&block, &partitioned);
+    assert_eq!(partitioned.num_shards(), num_shards);
+    assert_eq!(partitioned.num_sharded_txns(), 0);
+    let global_txns: Vec<_> = partitioned
+        .global_txns
+        .iter()
+        .map(|txn| txn.txn().clone())
+        .collect();
+    assert_eq!(global_txns, block);
+
+    let streamed = partitioner
+        .partition_streaming(block, num_shards)
+        .collect_partitioned();
+    assert_eq!(streamed, partitioned);
+}
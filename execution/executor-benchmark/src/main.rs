@@ -161,6 +161,7 @@ impl PipelineOpt {
                 dashmap_num_shards: self.partitioner_v2_dashmap_num_shards,
                 partition_last_round: !self.use_global_executor,
                 pre_partitioner_config: self.pre_partitioner_config(),
+                max_hints_per_txn: None,
             },
             None => PartitionerV2Config::default(),
             _ => panic!(
@@ -14,7 +14,10 @@ use aptos_vm::sharded_block_executor::{
 };
 use crossbeam_channel::{Receiver, Sender};
 use rayon::prelude::*;
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    net::SocketAddr,
+    sync::{atomic::AtomicBool, Arc},
+};
 
 pub struct RemoteCoordinatorClient {
     state_view_client: Arc<RemoteStateViewClient>,
@@ -88,6 +91,8 @@ impl CoordinatorClient<RemoteStateViewClient> for RemoteCoordinatorClient {
                     sub_blocks,
                     concurrency,
                     gas_limit,
+                    // the remote coordinator doesn't cancel the blocks
+                    Arc::new(AtomicBool::new(false)),
                 )
             },
         }
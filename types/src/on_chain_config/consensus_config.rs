@@ -208,12 +208,19 @@ pub struct ProposerAndVoterConfig {
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
 pub struct DagConsensusConfigV1 {
     pub dag_ordering_causal_history_window: usize,
+    // Number of committed anchors the anchor election weighs the validators' reputation over,
+    // the anchors are elected round robin if not set.
+    pub anchor_election_reputation_window: Option<usize>,
+    // Shuffles the payload of the ordered DAG nodes.
+    pub shuffle_payloads: bool,
 }
 
 impl Default for DagConsensusConfigV1 {
     fn default() -> Self {
         Self {
             dag_ordering_causal_history_window: 1,
+            anchor_election_reputation_window: None,
+            shuffle_payloads: false,
         }
     }
 }
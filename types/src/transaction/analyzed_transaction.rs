@@ -104,6 +104,10 @@ impl AnalyzedTransaction {
         &self.write_hints
     }
 
+    pub fn num_hints(&self) -> usize {
+        self.read_hints.len() + self.write_hints.len()
+    }
+
     pub fn predictable_transaction(&self) -> bool {
         self.predictable_transaction
     }